@@ -1,9 +1,21 @@
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use cosmwasm_schema::{export_schema, remove_schemas, schema_for, export_schema_with_title};
+use cosmwasm_schema::{export_schema, export_schema_with_title, remove_schemas, schema_for};
 
-use simple_option::msg::{ConfigResponse, HandleMsg, InitMsg, QueryMsg};
+use simple_option::ibc::OptionPacket;
+use simple_option::msg::{
+    AdminConfigResponse, ArchivedResponse, AuctionResponse, BlacklistedResponse, BuybackResponse,
+    CanExecuteResponse, ConfigResponse, ContributionResponse, CrankPoolResponse,
+    CurrentPriceResponse, EscrowsResponse, ExecuteMsg, ExpiringResponse, ExtensionResponse,
+    FailedPayoutsResponse, FairValueResponse, FundingResponse, InstancesResponse, InstantiateMsg,
+    InsuranceFundResponse, IntrinsicValueResponse, LastPriceResponse, MarginResponse, MigrateMsg,
+    OptionsResponse, OrdersResponse, PriceSamplesResponse, QueryMsg, QueuedConfigsResponse,
+    QuotesResponse, RawOptionResponse, ReceiveMsg, ReceiveNftMsg, ReconcileResponse,
+    ReferralStatsResponse, SeriesResponse, SharesResponse, StakeResponse, StatsResponse,
+    StrategyResponse, SudoMsg, TransferOfferResponse, VaultPositionResponse, VaultResponse,
+    VoucherResponse, WritingPoolResponse, YieldDepositResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -11,8 +23,116 @@ fn main() {
     create_dir_all(&out_dir).unwrap();
     remove_schemas(&out_dir).unwrap();
 
-    export_schema(&schema_for!(InitMsg), &out_dir);
-    export_schema(&schema_for!(HandleMsg), &out_dir);
+    export_schema(&schema_for!(InstantiateMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(SudoMsg), &out_dir);
+    export_schema(&schema_for!(OptionPacket), &out_dir);
+    export_schema(&schema_for!(OptionsResponse), &out_dir);
+    export_schema(&schema_for!(ExpiringResponse), &out_dir);
+    export_schema(&schema_for!(ReferralStatsResponse), &out_dir);
+    export_schema(&schema_for!(OrdersResponse), &out_dir);
+    export_schema(&schema_for!(EscrowsResponse), &out_dir);
+    export_schema(&schema_for!(QuotesResponse), &out_dir);
+    export_schema(&schema_for!(InstancesResponse), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
+    export_schema(&schema_for!(ReceiveNftMsg), &out_dir);
+    export_schema(&schema_for!(CanExecuteResponse), &out_dir);
+    export_schema(&schema_for!(IntrinsicValueResponse), &out_dir);
+    export_schema(&schema_for!(FairValueResponse), &out_dir);
+    export_schema(&schema_for!(CurrentPriceResponse), &out_dir);
     export_schema_with_title(&mut schema_for!(ConfigResponse), &out_dir, "ConfigResponse");
+    export_schema_with_title(&mut schema_for!(StatsResponse), &out_dir, "StatsResponse");
+    export_schema_with_title(&mut schema_for!(SharesResponse), &out_dir, "SharesResponse");
+    export_schema_with_title(&mut schema_for!(SeriesResponse), &out_dir, "SeriesResponse");
+    export_schema(&schema_for!(VaultPositionResponse), &out_dir);
+    export_schema_with_title(&mut schema_for!(VaultResponse), &out_dir, "VaultResponse");
+    export_schema(&schema_for!(FundingResponse), &out_dir);
+    export_schema(&schema_for!(PriceSamplesResponse), &out_dir);
+    export_schema(&schema_for!(StrategyResponse), &out_dir);
+    export_schema(&schema_for!(StakeResponse), &out_dir);
+    export_schema_with_title(
+        &mut schema_for!(YieldDepositResponse),
+        &out_dir,
+        "YieldDepositResponse",
+    );
+    export_schema(&schema_for!(MarginResponse), &out_dir);
+    export_schema(&schema_for!(ReconcileResponse), &out_dir);
+    export_schema(&schema_for!(RawOptionResponse), &out_dir);
+    export_schema_with_title(
+        &mut schema_for!(CrankPoolResponse),
+        &out_dir,
+        "CrankPoolResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(BlacklistedResponse),
+        &out_dir,
+        "BlacklistedResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(InsuranceFundResponse),
+        &out_dir,
+        "InsuranceFundResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(LastPriceResponse),
+        &out_dir,
+        "LastPriceResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(FailedPayoutsResponse),
+        &out_dir,
+        "FailedPayoutsResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(QueuedConfigsResponse),
+        &out_dir,
+        "QueuedConfigsResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(WritingPoolResponse),
+        &out_dir,
+        "WritingPoolResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(ContributionResponse),
+        &out_dir,
+        "ContributionResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(AuctionResponse),
+        &out_dir,
+        "AuctionResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(ArchivedResponse),
+        &out_dir,
+        "ArchivedResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(VoucherResponse),
+        &out_dir,
+        "VoucherResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(AdminConfigResponse),
+        &out_dir,
+        "AdminConfigResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(BuybackResponse),
+        &out_dir,
+        "BuybackResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(ExtensionResponse),
+        &out_dir,
+        "ExtensionResponse",
+    );
+    export_schema_with_title(
+        &mut schema_for!(TransferOfferResponse),
+        &out_dir,
+        "TransferOfferResponse",
+    );
 }
@@ -0,0 +1,570 @@
+//! Auctions for selling an option.
+//!
+//! In an English auction the creator puts up the option with a reserve and a duration, and the
+//! contract holds it while bids come in. Every bid must beat the last one, which is refunded
+//! right away, so only the highest bid is ever escrowed. Once the time is up anyone can close
+//! the auction.
+//!
+//! In a Dutch auction the owner keeps the option while its price falls from a start to a floor
+//! price, and the first buyer pays the price at that block.
+
+use cosmwasm_std::{BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult};
+
+use crate::blacklist;
+use crate::contract::{assert_not_paused, change_owner, check_taker, option_attributes, pay_sale};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::msg::{AuctionResponse, CurrentPriceResponse};
+use crate::shares::keep_proceeds;
+use crate::state::{
+    options, remove_held, save_held, save_option, Asset, Auction, Bid, DutchAuction, State,
+    AUCTIONS, CONFIG, DUTCH_AUCTIONS,
+};
+
+/// Creator, still owning the option, opens bidding at `reserve` for `duration` seconds.
+pub fn handle_start_auction(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    reserve: Coin,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    // only the initial sale, later sales go through List and Buy
+    if info.sender != state.creator || info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if duration == 0 || reserve.amount.is_zero() {
+        return Err(ContractError::InvalidAuction {});
+    }
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+
+    // the contract holds the option until the auction is closed
+    let auction = Auction {
+        seller: info.sender,
+        reserve,
+        end: env.block.time.plus_seconds(duration),
+        bid: None,
+    };
+    save_held(deps.storage, &AUCTIONS, option_id, &auction)?;
+    let event = change_owner(&mut state, option_id, env.contract.address);
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "start_auction")
+        .add_event(event.into())
+        .add_attribute("reserve", auction.reserve.to_string())
+        .add_attribute("end", auction.end.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Anyone but the seller can outbid the highest bid, sending it in the reserve's denom.
+pub fn handle_bid(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut auction = AUCTIONS.load(deps.storage, option_id)?;
+    if env.block.time >= auction.end {
+        return Err(ContractError::AuctionEnded {});
+    }
+    let state: State = options().load(deps.storage, option_id)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    if info.sender == auction.seller {
+        return Err(ContractError::Unauthorized {});
+    }
+    check_taker(&state, &info.sender)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "bid",
+        option_id,
+        &[&info.sender],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &info.sender)?;
+
+    let minimum = match &auction.bid {
+        Some(bid) => Coin::new(bid.amount.amount.u128() + 1, &bid.amount.denom),
+        None => auction.reserve.clone(),
+    };
+    let amount = match info.funds.as_slice() {
+        [coin] if coin.denom == minimum.denom && coin.amount >= minimum.amount => coin.clone(),
+        _ => return Err(ContractError::BidTooLow { minimum }),
+    };
+
+    let mut res = Response::new();
+    let outbid = auction.bid.replace(Bid {
+        bidder: info.sender.clone(),
+        amount,
+    });
+    if let Some(outbid) = outbid {
+        res = res.add_message(BankMsg::Send {
+            to_address: outbid.bidder.into_string(),
+            amount: vec![outbid.amount],
+        });
+    }
+    save_held(deps.storage, &AUCTIONS, option_id, &auction)?;
+
+    Ok(res
+        .add_attribute("action", "bid")
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("bidder", info.sender)
+        .add_attribute("amount", info.funds[0].to_string()))
+}
+
+/// Anyone can close an auction after its end, handing the option to the highest bidder and the
+/// bid to the seller. Without bids, if the option expired meanwhile or the bidder was
+/// blacklisted or stopped being eligible since, the seller keeps it and the bid is refunded.
+pub fn handle_close_auction(
+    deps: DepsMut,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let auction = AUCTIONS.load(deps.storage, option_id)?;
+    if env.block.time < auction.end {
+        return Err(ContractError::AuctionNotEnded { end: auction.end });
+    }
+    remove_held(deps.storage, &AUCTIONS, option_id)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "close_auction")
+        .add_attribute("option_id", option_id.to_string());
+    let blocked = auction.bid.as_ref().and_then(|bid| {
+        blacklist::listed(deps.storage, &[&bid.bidder]).map(|address| {
+            blacklist::blocked_event("close_auction", "option_id", option_id, address.as_str())
+        })
+    });
+    let eligible = match &auction.bid {
+        Some(bid) => match assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &bid.bidder)
+        {
+            Err(ContractError::NotEligible { .. }) => false,
+            checked => checked.map(|_| true)?,
+        },
+        None => true,
+    };
+    // it may have been burned after expiring in the auction
+    let state = options().may_load(deps.storage, option_id)?;
+    let live = state
+        .as_ref()
+        .filter(|state| !state.expires.is_expired(&env.block) && blocked.is_none() && eligible);
+    if let Some(event) = blocked {
+        res = res.add_event(event);
+    }
+    let owner = match (auction.bid, live) {
+        (Some(bid), Some(live)) => {
+            res = res.add_attribute("price", bid.amount.to_string());
+            res = pay_sale(
+                deps.storage,
+                &env,
+                res,
+                live,
+                auction.seller,
+                vec![bid.amount],
+                None,
+            )?;
+            bid.bidder
+        }
+        (Some(bid), None) => {
+            res = res.add_message(BankMsg::Send {
+                to_address: bid.bidder.into_string(),
+                amount: vec![bid.amount],
+            });
+            auction.seller
+        }
+        (None, _) => auction.seller,
+    };
+    if let Some(mut state) = state {
+        let event = change_owner(&mut state, option_id, owner);
+        save_option(deps.storage, option_id, &state)?;
+        res = res
+            .add_event(event.into())
+            .add_attributes(option_attributes(option_id, &state));
+    }
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+pub fn query_auction(deps: Deps, option_id: u64) -> StdResult<AuctionResponse> {
+    AUCTIONS.may_load(deps.storage, option_id)
+}
+
+/// Owner lists the option at `start_price`, falling linearly to `floor_price` over `duration`
+/// seconds. The listing lapses if the option changes hands.
+pub fn handle_start_dutch_auction(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    start_price: Coin,
+    floor_price: Coin,
+    duration: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if duration == 0
+        || start_price.amount.is_zero()
+        || start_price.denom != floor_price.denom
+        || floor_price.amount > start_price.amount
+    {
+        return Err(ContractError::InvalidAuction {});
+    }
+
+    let auction = DutchAuction {
+        seller: info.sender,
+        start_price,
+        floor_price,
+        start: env.block.time,
+        end: env.block.time.plus_seconds(duration),
+    };
+    DUTCH_AUCTIONS.save(deps.storage, option_id, &auction)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "start_dutch_auction")
+        .add_attribute("start_price", auction.start_price.to_string())
+        .add_attribute("floor_price", auction.floor_price.to_string())
+        .add_attribute("end", auction.end.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_cancel_dutch_auction(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let auction = DUTCH_AUCTIONS.load(deps.storage, option_id)?;
+    if info.sender != auction.seller {
+        return Err(ContractError::Unauthorized {});
+    }
+    DUTCH_AUCTIONS.remove(deps.storage, option_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_dutch_auction")
+        .add_attribute("option_id", option_id.to_string()))
+}
+
+/// Loads the Dutch auction of the option, as long as the seller still owns it
+fn live_dutch_auction(deps: Deps, option_id: u64) -> Result<(DutchAuction, State), ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    match DUTCH_AUCTIONS.may_load(deps.storage, option_id)? {
+        Some(auction) if auction.seller == state.owner => Ok((auction, state)),
+        _ => Err(ContractError::NotForSale {}),
+    }
+}
+
+/// Price of the auction at `env.block`, rounded up so it never goes below the floor
+fn dutch_price(auction: &DutchAuction, env: &Env) -> Coin {
+    let total = auction.end.seconds() - auction.start.seconds();
+    let elapsed = env
+        .block
+        .time
+        .seconds()
+        .saturating_sub(auction.start.seconds())
+        .min(total);
+    let decay = auction.start_price.amount - auction.floor_price.amount;
+    let amount = auction.start_price.amount - decay.multiply_ratio(elapsed, total);
+    Coin {
+        denom: auction.start_price.denom.clone(),
+        amount,
+    }
+}
+
+/// Anyone can buy the option at the current price, anything paid on top is refunded.
+pub fn handle_buy_dutch(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let (auction, mut state) = live_dutch_auction(deps.as_ref(), option_id)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    check_taker(&state, &info.sender)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "buy_dutch",
+        option_id,
+        &[&info.sender],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &info.sender)?;
+    let price = dutch_price(&auction, &env);
+    let paid = match info.funds.as_slice() {
+        [coin] if coin.denom == price.denom && coin.amount >= price.amount => coin.amount,
+        _ => {
+            return Err(ContractError::PriceMismatch {
+                offer: info.funds,
+                expected: vec![price],
+            })
+        }
+    };
+
+    DUTCH_AUCTIONS.remove(deps.storage, option_id);
+    let event = change_owner(&mut state, option_id, info.sender.clone());
+    save_option(deps.storage, option_id, &state)?;
+
+    let res = Response::new().add_event(event.into());
+    let mut res = pay_sale(
+        deps.storage,
+        &env,
+        res,
+        &state,
+        auction.seller,
+        vec![price.clone()],
+        None,
+    )?;
+    if paid > price.amount {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.into_string(),
+            amount: vec![Coin::new((paid - price.amount).u128(), &price.denom)],
+        });
+    }
+    let res = res
+        .add_attribute("action", "buy_dutch")
+        .add_attribute("price", price.to_string())
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+pub fn query_current_price(
+    deps: Deps,
+    env: Env,
+    option_id: u64,
+) -> StdResult<CurrentPriceResponse> {
+    let (auction, _) = live_dutch_auction(deps, option_id)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    Ok(CurrentPriceResponse {
+        price: dutch_price(&auction, &env),
+        floor_price: auction.floor_price,
+        end: auction.end,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ConfigResponse, ExecuteMsg, QueryMsg};
+    use crate::testing::{blacklist, setup};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coin, coins, from_json, CosmosMsg, OwnedDeps};
+
+    fn owner(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> String {
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 1 }).unwrap();
+        let config: ConfigResponse = from_json(res).unwrap();
+        config.state.owner.into_string()
+    }
+
+    #[test]
+    fn english_auction() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::StartAuction {
+            option_id: 1,
+            reserve: coin(5, "ETH"),
+            duration: 3600,
+        };
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // bids must meet the reserve, then beat the last bid
+        let bid = ExecuteMsg::Bid { option_id: 1 };
+        let info = mock_info("alice", &coins(4, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, bid.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::BidTooLow { .. }));
+        let info = mock_info("alice", &coins(5, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, bid.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        let info = mock_info("bob", &coins(5, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, bid.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::BidTooLow { .. }));
+
+        // the outbid bidder is refunded
+        let info = mock_info("bob", &coins(8, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, bid.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".into(),
+                amount: coins(5, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+
+        // closing waits for the end
+        let close = ExecuteMsg::CloseAuction { option_id: 1 };
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), close.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::AuctionNotEnded { .. }));
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("carol", &coins(9, "ETH")),
+            bid,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::AuctionEnded {}));
+
+        let res = execute(deps.as_mut(), env, info, close).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(8, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!("bob", owner(&deps));
+        assert_eq!(None, query_auction(deps.as_ref(), 1).unwrap());
+    }
+
+    #[test]
+    fn dutch_auction() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::StartDutchAuction {
+            option_id: 1,
+            start_price: coin(100, "ETH"),
+            floor_price: coin(20, "ETH"),
+            duration: 1000,
+        };
+        let info = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // the price falls linearly to the floor
+        let price_at = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>, seconds: u64| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            let res = query(deps.as_ref(), env, QueryMsg::CurrentPrice { option_id: 1 });
+            let res: CurrentPriceResponse = from_json(res.unwrap()).unwrap();
+            res.price
+        };
+        assert_eq!(coin(100, "ETH"), price_at(&deps, 0));
+        assert_eq!(coin(60, "ETH"), price_at(&deps, 500));
+        assert_eq!(coin(20, "ETH"), price_at(&deps, 5000));
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(250);
+        let buy = ExecuteMsg::BuyDutch { option_id: 1 };
+        let info = mock_info("alice", &coins(79, "ETH"));
+        let err = execute(deps.as_mut(), env.clone(), info, buy.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::PriceMismatch { .. }));
+
+        // the seller gets the live price, the rest is refunded
+        let info = mock_info("alice", &coins(90, "ETH"));
+        let res = execute(deps.as_mut(), env.clone(), info, buy.clone()).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(80, "ETH"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "alice".into(),
+                    amount: coins(10, "ETH"),
+                }),
+            ],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        assert_eq!("alice", owner(&deps));
+
+        // and cannot be bought twice
+        let info = mock_info("bob", &coins(90, "ETH"));
+        let err = execute(deps.as_mut(), env, info, buy).unwrap_err();
+        assert!(matches!(err, ContractError::NotForSale {}));
+    }
+
+    fn start_auction(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>) {
+        let msg = ExecuteMsg::StartAuction {
+            option_id: 1,
+            reserve: coin(5, "ETH"),
+            duration: 3600,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    }
+
+    fn refund(to: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.into(),
+            amount: coins(amount, "ETH"),
+        })
+    }
+
+    #[test]
+    fn blacklisted_bidder() {
+        let mut deps = setup();
+        start_auction(&mut deps);
+        blacklist(&mut deps, "sanctioned");
+
+        let bid = ExecuteMsg::Bid { option_id: 1 };
+        let info = mock_info("sanctioned", &coins(5, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, bid).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(refund("sanctioned", 5), res.messages[0].msg);
+        assert_eq!(None, query_auction(deps.as_ref(), 1).unwrap().unwrap().bid);
+    }
+
+    #[test]
+    fn blacklisted_winner() {
+        let mut deps = setup();
+        start_auction(&mut deps);
+        let bid = ExecuteMsg::Bid { option_id: 1 };
+        let info = mock_info("alice", &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, bid).unwrap();
+        blacklist(&mut deps, "alice");
+
+        // listed after bidding, the bid is refunded and the seller keeps the option
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let close = ExecuteMsg::CloseAuction { option_id: 1 };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), close).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(refund("alice", 5), res.messages[0].msg);
+        assert_eq!("creator", owner(&deps));
+    }
+
+    #[test]
+    fn blacklisted_dutch_buyer() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::StartDutchAuction {
+            option_id: 1,
+            start_price: coin(100, "ETH"),
+            floor_price: coin(20, "ETH"),
+            duration: 1000,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        blacklist(&mut deps, "sanctioned");
+
+        let buy = ExecuteMsg::BuyDutch { option_id: 1 };
+        let info = mock_info("sanctioned", &coins(100, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, buy).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(refund("sanctioned", 100), res.messages[0].msg);
+        assert_eq!("creator", owner(&deps));
+    }
+}
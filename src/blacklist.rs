@@ -0,0 +1,239 @@
+//! Blacklist of addresses barred from taking or exercising options.
+//!
+//! Institutional writers need sanctioned or compromised addresses kept away from their options.
+//! The admin manages the list. A transfer, sale or exercise involving a listed address does
+//! nothing but refund what it paid, and succeeds with a `blacklist_blocked` event so the
+//! attempt stays on record, which a failed transaction would not leave. That covers every way
+//! an option changes hands or gets exercised: auctions, escrows, orders, quotes, strategies and
+//! options coming back over IBC, whose packet is refused so the sending chain keeps the voucher.
+
+use cosmwasm_std::{Addr, Deps, DepsMut, Empty, Event, MessageInfo, Response, StdResult, Storage};
+
+use crate::contract::send_asset;
+use crate::error::ContractError;
+use crate::msg::BlacklistedResponse;
+use crate::state::{Asset, BLACKLIST, CONFIG};
+
+/// The first of `addrs` on the blacklist. They have to be validated, as the list is kept by
+/// validated address.
+pub(crate) fn listed<'a>(storage: &dyn Storage, addrs: &[&'a Addr]) -> Option<&'a Addr> {
+    addrs
+        .iter()
+        .copied()
+        .find(|addr| BLACKLIST.has(storage, addr))
+}
+
+/// Records that `address` kept `action` on the option, or whatever `key` names, from happening
+pub(crate) fn blocked_event(action: &str, key: &str, id: u64, address: &str) -> Event {
+    Event::new("blacklist_blocked")
+        .add_attribute("action", action)
+        .add_attribute(key, id.to_string())
+        .add_attribute("address", address)
+}
+
+/// The response to a blocked attempt, refunding `paid` to `payer`
+pub(crate) fn blocked_response(event: Event, payer: &Addr, paid: Asset) -> StdResult<Response> {
+    let mut res = Response::new()
+        .add_attribute("action", "blocked")
+        .add_event(event);
+    if !matches!(&paid, Asset::Native(coins) if coins.is_empty()) {
+        res = res.add_message(send_asset(payer, paid)?);
+    }
+    Ok(res)
+}
+
+/// The response to an attempt at `action` on the option if any of `addrs` is blacklisted,
+/// refunding `paid` to `payer`.
+pub(crate) fn blocked_attempt(
+    storage: &dyn Storage,
+    action: &str,
+    option_id: u64,
+    addrs: &[&Addr],
+    payer: &Addr,
+    paid: Asset,
+) -> StdResult<Option<Response>> {
+    match listed(storage, addrs) {
+        Some(address) => {
+            let event = blocked_event(action, "option_id", option_id, address.as_str());
+            blocked_response(event, payer, paid).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Admin adds addresses to and removes them from the blacklist
+pub fn handle_update_blacklist(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    for addr in &add {
+        BLACKLIST.save(deps.storage, &deps.api.addr_validate(addr)?, &Empty {})?;
+    }
+    for addr in &remove {
+        BLACKLIST.remove(deps.storage, &deps.api.addr_validate(addr)?);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_blacklist")
+        .add_attribute("added", add.join(","))
+        .add_attribute("removed", remove.join(",")))
+}
+
+pub fn query_blacklisted(deps: Deps, address: String) -> StdResult<BlacklistedResponse> {
+    Ok(BLACKLIST.has(deps.storage, &deps.api.addr_validate(&address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query};
+    use crate::msg::{CanExecuteResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
+    use crate::state::{options, State, TRANSFER_OFFERS};
+    use crate::testing::{blacklist, instantiate_msg, setup};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, from_json, BankMsg, CosmosMsg, StdError};
+    use cw_utils::Expiration;
+
+    #[test]
+    fn blocks_listed_addresses() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            premium: coins(5, "ETH"),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateBlacklist {
+            add: vec!["sanctioned".to_string()],
+            remove: vec![],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert!(query_blacklisted(deps.as_ref(), "sanctioned".to_string()).unwrap());
+
+        // a listed buyer gets the price back and the option stays put
+        let info = mock_info("sanctioned", &coins(5, "ETH"));
+        let msg = ExecuteMsg::Buy {
+            option_id: 1,
+            referrer: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sanctioned".into(),
+                amount: coins(5, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        let state: State = options().load(&deps.storage, 1).unwrap();
+        assert_eq!("creator", state.owner);
+
+        // nor can the option be transferred to a listed address, or exercised for one
+        let msg = ExecuteMsg::TransferNft {
+            recipient: "sanctioned".to_string(),
+            token_id: "1".to_string(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert!(res.messages.is_empty());
+        // as long as the transfer would go through otherwise
+        let err = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        // addresses are looked up as validated, not as typed
+        let err = query_blacklisted(deps.as_ref(), "Sanctioned".to_string()).unwrap_err();
+        assert!(matches!(err, StdError::GenericErr { .. }));
+        let msg = ExecuteMsg::OfferTransfer {
+            option_id: 1,
+            recipient: "sanctioned".to_string(),
+            expires: Expiration::Never {},
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert!(!TRANSFER_OFFERS.has(&deps.storage, 1));
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: Some("sanctioned".to_string()),
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert!(options().has(&deps.storage, 1));
+
+        // once removed the address is treated like any other
+        let unlist = ExecuteMsg::UpdateBlacklist {
+            add: vec![],
+            remove: vec!["sanctioned".to_string()],
+        };
+        let creator = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), creator, unlist).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert!(res.events.iter().all(|e| e.ty != "blacklist_blocked"));
+        assert!(!options().has(&deps.storage, 1));
+    }
+
+    #[test]
+    fn blocks_listed_exercisers() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::TransferNft {
+            recipient: "alice".to_string(),
+            token_id: "1".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        blacklist(&mut deps, "alice");
+
+        // a batch gets the funds back, and none of its options are executed
+        let msg = ExecuteMsg::ExecuteBatch {
+            option_ids: vec![1],
+        };
+        let info = mock_info("alice", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".into(),
+                amount: coins(40, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert!(options().has(&deps.storage, 1));
+
+        // which the query tells before trying
+        let msg = QueryMsg::CanExecute {
+            option_id: 1,
+            sender: "alice".to_string(),
+            funds: coins(40, "ETH"),
+        };
+        let res: CanExecuteResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            CanExecuteResponse::Blocked {
+                address: Addr::unchecked("alice"),
+            },
+            res
+        );
+    }
+}
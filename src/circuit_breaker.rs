@@ -0,0 +1,230 @@
+//! Circuit breaker suspending cash settlement when the oracle price jumps.
+//!
+//! The last oracle price of every pair is recorded whenever a cash settled option settles, and
+//! whenever anyone updates it. A settlement at a price more than the configured deviation away
+//! from the last one fails. An update that far away records the new price but halts cash
+//! settlement of the pair, auto-exercise by the crank included, for the cooldown, so a broken
+//! or manipulated oracle can't settle options before anyone can react. The admin can reset the
+//! breaker of a pair at any time.
+
+use cosmwasm_std::{Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+
+use crate::contract::MAX_BPS;
+use crate::error::ContractError;
+use crate::msg::LastPriceResponse;
+use crate::oracle::{price_pair, query_collateral_price};
+use crate::state::{options, Config, LastPrice, Settlement, State, CONFIG, LAST_PRICES};
+
+/// Whether `rate` is further from `last` than the configured deviation allows.
+fn deviates(config: &Config, last: Decimal, rate: Decimal) -> bool {
+    let moved = if rate > last {
+        rate - last
+    } else {
+        last - rate
+    };
+    moved > last * Decimal::from_ratio(config.max_price_deviation_bps, MAX_BPS)
+}
+
+/// Fails if cash settlement of the option at `rate` is halted or too far from the last price.
+/// Options settling otherwise are never stopped.
+pub(crate) fn assert_price(
+    storage: &dyn Storage,
+    env: &Env,
+    config: &Config,
+    state: &State,
+    rate: Decimal,
+) -> Result<(), ContractError> {
+    if config.max_price_deviation_bps == 0 || state.settlement != Settlement::Cash {
+        return Ok(());
+    }
+    let (base, quote) = price_pair(state)?;
+    let last = match LAST_PRICES.may_load(storage, (&base, &quote))? {
+        Some(last) => last,
+        None => return Ok(()),
+    };
+    match last.halted_until {
+        Some(until) if env.block.time < until => Err(ContractError::PriceHalted { until }),
+        _ if deviates(config, last.rate, rate) => Err(ContractError::PriceDeviation {
+            last: last.rate,
+            rate,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the crank can settle the option without the breaker failing it.
+pub(crate) fn settles_now(deps: Deps, env: &Env, config: &Config, state: &State) -> bool {
+    if config.max_price_deviation_bps == 0 || state.settlement != Settlement::Cash {
+        return true;
+    }
+    query_collateral_price(&deps.querier, config.oracle.as_ref(), state)
+        .and_then(|rate| assert_price(deps.storage, env, config, state, rate))
+        .is_ok()
+}
+
+/// Records `rate` as the last price of a cash settled option settling at it.
+pub(crate) fn record_price(
+    storage: &mut dyn Storage,
+    config: &Config,
+    state: &State,
+    rate: Decimal,
+) -> Result<(), ContractError> {
+    if config.max_price_deviation_bps == 0 || state.settlement != Settlement::Cash {
+        return Ok(());
+    }
+    let (base, quote) = price_pair(state)?;
+    let last = LastPrice {
+        rate,
+        halted_until: None,
+    };
+    LAST_PRICES.save(storage, (&base, &quote), &last)?;
+    Ok(())
+}
+
+/// Anyone can record the oracle price of the pair the option is priced in, halting its cash
+/// settlement for the cooldown if it moved more than the deviation allows
+pub fn handle_update_price(
+    deps: DepsMut,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if config.max_price_deviation_bps == 0 {
+        return Err(ContractError::CircuitBreakerDisabled {});
+    }
+    let state: State = options().load(deps.storage, option_id)?;
+    let (base, quote) = price_pair(&state)?;
+    let rate = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)?;
+
+    let mut last = LAST_PRICES
+        .may_load(deps.storage, (&base, &quote))?
+        .unwrap_or(LastPrice {
+            rate,
+            halted_until: None,
+        });
+    // a halt runs its course, even if the price comes back meanwhile
+    let halted = last.halted_until.filter(|until| env.block.time < *until);
+    last.halted_until = match deviates(&config, last.rate, rate) {
+        true => Some(env.block.time.plus_seconds(config.breaker_cooldown)),
+        false => halted,
+    };
+    last.rate = rate;
+    LAST_PRICES.save(deps.storage, (&base, &quote), &last)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "update_price")
+        .add_attribute("base", base)
+        .add_attribute("quote", quote)
+        .add_attribute("rate", rate.to_string());
+    if let Some(until) = last.halted_until {
+        res = res.add_attribute("halted_until", until.to_string());
+    }
+    Ok(res)
+}
+
+/// Admin clears the last price of the pair the option is priced in, lifting any halt
+pub fn handle_reset_circuit_breaker(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let state: State = options().load(deps.storage, option_id)?;
+    let (base, quote) = price_pair(&state)?;
+    LAST_PRICES.remove(deps.storage, (&base, &quote));
+
+    Ok(Response::new()
+        .add_attribute("action", "reset_circuit_breaker")
+        .add_attribute("base", base)
+        .add_attribute("quote", quote))
+}
+
+pub fn query_last_price(deps: Deps, option_id: u64) -> StdResult<LastPriceResponse> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let (base, quote) = match price_pair(&state) {
+        Ok(pair) => pair,
+        Err(_) => return Ok(None),
+    };
+    LAST_PRICES.may_load(deps.storage, (&base, &quote))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::state::Asset;
+    use crate::testing::{instantiate_msg, mock_oracle};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{attr, coins};
+
+    #[test]
+    fn halts_on_deviation() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(4_000));
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(30, "ETH")),
+            settlement: Settlement::Cash,
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let update = ExecuteMsg::UpdatePrice { option_id: 1 };
+        let anyone = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), anyone.clone(), update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CircuitBreakerDisabled {}));
+        let config = UpdateConfigMsg {
+            max_price_deviation_bps: Some(1_000),
+            breaker_cooldown: Some(600),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let admin = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let _ = execute(deps.as_mut(), mock_env(), anyone.clone(), update.clone()).unwrap();
+
+        // a 25% jump can't settle, and halts settlement once recorded
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PriceDeviation { .. }));
+        let res = execute(deps.as_mut(), mock_env(), anyone.clone(), update).unwrap();
+        let until = mock_env().block.time.plus_seconds(600);
+        assert!(res
+            .attributes
+            .contains(&attr("halted_until", until.to_string())));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PriceHalted { .. }));
+
+        // only the admin can lift the halt before the cooldown is over
+        let reset = ExecuteMsg::ResetCircuitBreaker { option_id: 1 };
+        let err = execute(deps.as_mut(), mock_env(), anyone, reset.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), reset).unwrap();
+        assert_eq!(None, query_last_price(deps.as_ref(), 1).unwrap());
+        let _ = execute(deps.as_mut(), mock_env(), admin, execute_msg).unwrap();
+    }
+}
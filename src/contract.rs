@@ -1,353 +1,7827 @@
+use std::convert::TryFrom;
+
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Api, BankMsg, Binary, Context, Env, Extern, HandleResponse, HumanAddr, InitResponse,
-    MessageInfo, Querier, StdError, StdResult, Storage,
+    from_json, to_json_binary, Addr, Api, Attribute, BankMsg, Binary, Coin, Coins, CosmosMsg,
+    Decimal, Deps, DepsMut, Env, Event, MessageInfo, Order, Reply, Response, StdError, StdResult,
+    Storage, Timestamp, Uint128, WasmMsg,
+};
+use cw2::{get_contract_version, set_contract_version};
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw721::{
+    Approval, ApprovalsResponse, Cw721ExecuteMsg, Cw721ReceiveMsg, NftInfoResponse,
+    OperatorsResponse, OwnerOfResponse,
+};
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
+
+use crate::auction;
+use crate::blacklist;
+use crate::circuit_breaker;
+use crate::cowrite;
+use crate::crank;
+use crate::denom::{self, option_denoms, validate_denom};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::escrow;
+use crate::events::{
+    OptionBurned, OptionClosed, OptionCreated, OptionExercised, OptionTransferred,
 };
+use crate::factory;
+use crate::governance;
+use crate::ibc;
+use crate::insurance;
+use crate::limits;
+use crate::margin;
+use crate::msg::{
+    AdminConfigResponse, ArchivedResponse, CanExecuteResponse, ConfigResponse, CreateMsg,
+    ExecuteMsg, ExpiringOption, ExpiringResponse, FairValueResponse, InstantiateMsg,
+    IntrinsicValueResponse, MigrateMsg, OptionInfo, OptionsResponse, QueryMsg, ReceiveMsg,
+    ReceiveNftMsg, ReferralStatsResponse, StatsResponse, SudoMsg, UpdateConfigMsg, VoucherResponse,
+};
+use crate::oracle::{price_pair, query_collateral_price};
+use crate::orderbook;
+use crate::payouts;
+use crate::perpetual;
+use crate::pricing;
+use crate::raw;
+use crate::recovery;
+use crate::rfq;
+use crate::series;
+use crate::shares::{self, keep_proceeds};
+use crate::staking;
+use crate::state::{
+    archive, expiration_key, next_id, options, remove_held, remove_option, save_held, save_option,
+    Asset, AssetList, Barrier, Buyback, Closure, Config, Extension, Ledger, NftCollateral,
+    OptionStyle, Settlement, SettlementRecord, State, TransferFee, TransferOffer, BUYBACKS, CONFIG,
+    EXTENSIONS, FUNDING, NOTIONALS, OPERATORS, OPTION_COUNT, REFERRALS, STAKES, STATS,
+    STATS_REBUILD, TERMINATIONS, TRACKED, TRANSFER_OFFERS, VOUCHERS, YIELD_DEPOSITS,
+};
+use crate::strategy;
+use crate::timelock;
+use crate::tokenfactory::{self, take_token};
+use crate::twap;
+use crate::vault;
+use crate::yield_strategy;
+
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:simple-option";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub(crate) const MAX_BPS: u64 = 10_000;
 
-use crate::msg::{ConfigResponse, HandleMsg, InitMsg, QueryMsg};
-use crate::state::{config, config_read, State};
+// most options a split creates or a merge combines
+pub(crate) const MAX_PARTS: u32 = 30;
 
-// Note, you can use StdResult in some functions where you do not
-// make use of the custom errors
-pub fn init<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
+// how long European options stay exercisable after expiry, unless they set their own window
+const SETTLEMENT_WINDOW_BLOCKS: u64 = 100;
+const SETTLEMENT_WINDOW_SECONDS: u64 = 600;
+
+// longest the config timelock can be set to, a year
+const MAX_CONFIG_TIMELOCK: u64 = 365 * 24 * 3600;
+
+// settings for pagination
+pub(crate) const MAX_LIMIT: u32 = 30;
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    msg: InitMsg,
-) -> StdResult<InitResponse> {
-    if msg.expires <= env.block.height {
-        return Err(StdError::generic_err("Cannot create expired option"));
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let admin = match msg.admin {
+        Some(addr) => deps.api.addr_validate(&addr)?,
+        None => info.sender.clone(),
+    };
+    let guardian = match msg.guardian {
+        Some(addr) => deps.api.addr_validate(&addr)?,
+        None => admin.clone(),
+    };
+    let fee_collector = match msg.fee_collector {
+        Some(addr) => deps.api.addr_validate(&addr)?,
+        None => info.sender.clone(),
+    };
+    let config = Config {
+        guardian,
+        dao: msg
+            .dao
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        paused: false,
+        burn_reward_bps: validate_bps(msg.burn_reward_bps)?,
+        fee_bps: validate_bps(msg.fee_bps)?,
+        fee_collector,
+        allowed_denoms: validate_denoms(msg.allowed_denoms)?,
+        oracle: msg
+            .oracle
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?,
+        archive_retention: msg.archive_retention,
+        tokenfactory: msg.tokenfactory,
+        ..Config::new(admin)
+    };
+    CONFIG.save(deps.storage, &config)?;
+
+    let terms = CreateMsg {
+        counter_offer: msg.counter_offer,
+        alt_counter_offers: msg.alt_counter_offers,
+        strike: msg.strike,
+        expires: msg.expires,
+        premium: msg.premium,
+        settlement: msg.settlement,
+        style: msg.style,
+        royalty_bps: msg.royalty_bps,
+        roll_on_expiry: msg.roll_on_expiry,
+        barrier: msg.barrier,
+        settlement_window: msg.settlement_window,
+        exercisable_from: msg.exercisable_from,
+        allowed_taker: msg.allowed_taker,
+        owner: msg.owner,
+    };
+    let info = match msg.creator {
+        Some(creator) => MessageInfo {
+            sender: deps.api.addr_validate(&creator)?,
+            funds: info.funds,
+        },
+        None => info,
+    };
+    let (option_id, state) = create_option(deps, &env, info, terms)?;
+
+    Ok(Response::new()
+        .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+        .add_attribute("action", "instantiate")
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+fn validate_denoms(denoms: Vec<String>) -> Result<Vec<String>, ContractError> {
+    for denom in &denoms {
+        validate_denom(denom)?;
+    }
+    Ok(denoms)
+}
+
+fn validate_bps(bps: u64) -> Result<u64, ContractError> {
+    if bps > MAX_BPS {
+        return Err(ContractError::InvalidBps { bps });
+    }
+    Ok(bps)
+}
+
+/// Validates the terms and stores a new option written by the sender, returning its id.
+pub(crate) fn create_option(
+    deps: DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    msg: CreateMsg,
+) -> Result<(u64, State), ContractError> {
+    if let Expiration::Never {} = msg.expires {
+        return Err(ContractError::NeverExpires {});
+    }
+    write_option(deps, env, info, msg, None, vec![])
+}
+
+/// Stores a new option on any terms, including ones that never expire. The collateral is the
+/// funds sent along with `cw20_collateral`, or `nft` alone if given.
+pub(crate) fn write_option(
+    deps: DepsMut,
+    env: &Env,
+    info: MessageInfo,
+    msg: CreateMsg,
+    nft: Option<NftCollateral>,
+    cw20_collateral: Vec<Cw20CoinVerified>,
+) -> Result<(u64, State), ContractError> {
+    if msg.expires.is_expired(&env.block) {
+        return Err(ContractError::CreateExpired {});
+    }
+    let mut tokens: Vec<&Addr> = cw20_collateral.iter().map(|c| &c.address).collect();
+    tokens.sort_unstable();
+    tokens.dedup();
+    let no_assets = info.funds.is_empty() && cw20_collateral.is_empty();
+    if no_assets == nft.is_none()
+        || info.funds.iter().any(|c| c.amount.is_zero())
+        || cw20_collateral.iter().any(|c| c.amount.is_zero())
+        || tokens.len() != cw20_collateral.len()
+    {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    // there is nothing to swap for the alternatives when cash settled
+    let counter_offers: Vec<&Asset> = std::iter::once(&msg.counter_offer)
+        .chain(&msg.alt_counter_offers)
+        .collect();
+    let empty_counter_offer = counter_offers.iter().any(|asset| match asset {
+        Asset::Native(coins) => coins.is_empty() || coins.iter().any(|c| c.amount.is_zero()),
+        Asset::Cw20(coin) => coin.amount.is_zero(),
+    });
+    if empty_counter_offer
+        || (!msg.alt_counter_offers.is_empty() && msg.settlement != Settlement::Physical)
+    {
+        return Err(ContractError::InvalidCounterOffer {});
+    }
+    // the strike prices a single collateral coin in a single counter_offer coin
+    if let Some(price) = msg.strike {
+        let single_coin = matches!(&msg.counter_offer, Asset::Native(coins) if coins.len() == 1);
+        if price.is_zero()
+            || !single_coin
+            || info.funds.len() != 1
+            || nft.is_some()
+            || !cw20_collateral.is_empty()
+            || !msg.alt_counter_offers.is_empty()
+        {
+            return Err(ContractError::InvalidStrike {});
+        }
+    }
+    let native: Vec<&Coin> = counter_offers
+        .iter()
+        .flat_map(|asset| match asset {
+            Asset::Native(coins) => coins.as_slice(),
+            Asset::Cw20(_) => &[],
+        })
+        .collect();
+    for coin in native.into_iter().chain(&msg.premium) {
+        validate_denom(&coin.denom)?;
     }
 
-    let state = State {
-        creator: info.sender.clone(),
-        owner: info.sender.clone(),
-        collateral: info.sent_funds,
+    // cw20 tokens and NFT collections are listed by contract address
+    let config = CONFIG.load(deps.storage)?;
+    if !config.allowed_denoms.is_empty() {
+        let mut denoms: Vec<String> = info.funds.iter().map(|c| c.denom.clone()).collect();
+        denoms.extend(nft.iter().map(|nft| nft.collection.to_string()));
+        denoms.extend(cw20_collateral.iter().map(|c| c.address.to_string()));
+        for counter_offer in &counter_offers {
+            match counter_offer {
+                Asset::Native(coins) => denoms.extend(coins.iter().map(|c| c.denom.clone())),
+                Asset::Cw20(coin) => denoms.push(coin.address.to_string()),
+            }
+        }
+        if let Some(denom) = denoms
+            .into_iter()
+            .find(|d| !config.allowed_denoms.contains(d))
+        {
+            return Err(ContractError::DenomNotAllowed { denom });
+        }
+    }
+    limits::assert_min_collateral(&deps.querier, &config, &info.funds, &cw20_collateral)?;
+    limits::assert_open_limit(deps.storage, &config, &info.sender)?;
+
+    // tokenized options are sold by trading the token, not listed here, which is minted to the
+    // creator and can go to anyone
+    let allowed_taker = msg
+        .allowed_taker
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let restricted = allowed_taker.is_some() || msg.owner.is_some();
+    if config.tokenfactory && (!msg.premium.is_empty() || restricted) {
+        return Err(ContractError::TokenizedOption {});
+    }
+
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+    let mut state = State {
+        creator: info.sender,
+        owner,
+        collateral: info.funds,
         counter_offer: msg.counter_offer,
+        alt_counter_offers: msg.alt_counter_offers,
+        strike: msg.strike,
         expires: msg.expires,
+        price: msg.premium,
+        settlement: msg.settlement,
+        style: msg.style,
+        royalty_bps: validate_bps(msg.royalty_bps)?,
+        roll_on_expiry: msg.roll_on_expiry,
+        barrier: msg.barrier,
+        settlement_window: msg.settlement_window,
+        exercisable_from: msg.exercisable_from,
+        allowed_taker,
+        approvals: vec![],
+        denom: None,
+        nft,
+        cw20_collateral,
+    };
+    state.apply_strike();
+    check_taker(&state, &state.owner)?;
+    if state.settlement != Settlement::Physical {
+        price_pair(&state)?;
+    }
+    match state.settlement_window {
+        Some(window) if window == 0 || state.style != OptionStyle::European => {
+            return Err(ContractError::InvalidSettlementWindow {});
+        }
+        _ => {}
+    }
+    if let Some(from) = &state.exercisable_from {
+        // different kinds of expiration don't compare
+        let before_expiry = from.partial_cmp(&state.expires) == Some(std::cmp::Ordering::Less);
+        if !before_expiry || state.style != OptionStyle::American {
+            return Err(ContractError::InvalidExercisableFrom {});
+        }
+    }
+    if let Settlement::Average { window } = state.settlement {
+        if window == 0 || state.style != OptionStyle::European {
+            return Err(ContractError::InvalidAveraging {});
+        }
+    }
+    if let Some(barrier) = &state.barrier {
+        if barrier.price.is_zero() {
+            return Err(ContractError::InvalidBarrier {});
+        }
+        price_pair(&state)?;
+    }
+    let option_id = next_id(deps.storage, &OPTION_COUNT)?;
+    if config.tokenfactory {
+        // the token holder could not be paid out
+        if let Settlement::Binary { .. } = state.settlement {
+            return Err(ContractError::TokenizedOption {});
+        }
+        state.owner = env.contract.address.clone();
+        state.denom = Some(tokenfactory::option_denom(env, option_id));
+    }
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok((option_id, state))
+}
+
+/// Keeps a record of an option being closed, before it is deleted, returning the event
+/// announcing it.
+pub(crate) fn archive_option(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+    state: &State,
+    closed_by: Option<&Addr>,
+    closure: Closure,
+    payment: Option<Asset>,
+) -> Result<OptionClosed, ContractError> {
+    staking::release(storage, env, option_id)?;
+    yield_strategy::assert_withdrawn(storage, option_id)?;
+    margin::release(storage, option_id);
+    let record = SettlementRecord {
+        creator: state.creator.clone(),
+        owner: state.owner.clone(),
+        closed_by: closed_by.cloned(),
+        closure,
+        height: env.block.height,
+        time: env.block.time,
+        collateral: state.collateral.clone(),
+        payment,
+    };
+    archive().save(storage, option_id, &record)?;
+    Ok(OptionClosed { option_id, closure })
+}
+
+/// Terms of the option after the action, emitted on every response about an option
+/// so indexers can follow its state from events alone.
+pub(crate) fn option_attributes(option_id: u64, state: &State) -> Vec<Attribute> {
+    let fmt_asset = |asset: &Asset| match asset {
+        Asset::Native(coins) => fmt_coins(coins),
+        Asset::Cw20(coin) => format!("{}{}", coin.amount, coin.address),
+    };
+    let mut attributes = vec![
+        Attribute::new("option_id", option_id.to_string()),
+        Attribute::new("creator", &state.creator),
+        Attribute::new("owner", &state.owner),
+        Attribute::new("collateral", fmt_coins(&state.collateral)),
+        Attribute::new("counter_offer", fmt_asset(&state.counter_offer)),
+        Attribute::new("expires", state.expires.to_string()),
+    ];
+    if let Some(strike) = state.strike {
+        attributes.push(Attribute::new("strike", strike.to_string()));
+    }
+    if !state.alt_counter_offers.is_empty() {
+        let alts: Vec<String> = state.alt_counter_offers.iter().map(fmt_asset).collect();
+        attributes.push(Attribute::new("alt_counter_offers", alts.join("|")));
+    }
+    if !state.cw20_collateral.is_empty() {
+        let tokens: Vec<String> = state
+            .cw20_collateral
+            .iter()
+            .map(|coin| format!("{}{}", coin.amount, coin.address))
+            .collect();
+        attributes.push(Attribute::new("cw20_collateral", tokens.join(",")));
+    }
+    if let Some(nft) = &state.nft {
+        attributes.push(Attribute::new(
+            "nft_collateral",
+            format!("{}/{}", nft.collection, nft.token_id),
+        ));
+    }
+    attributes
+}
+
+/// Releases `collateral` to `to`, along with the NFT of an option collateralized by one.
+pub(crate) fn collateral_msgs(
+    collateral: AssetList,
+    nft: Option<&NftCollateral>,
+    to: &Addr,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = send_assets(to, collateral)?;
+    if let Some(nft) = nft {
+        let transfer = Cw721ExecuteMsg::TransferNft {
+            recipient: to.to_string(),
+            token_id: nft.token_id.clone(),
+        };
+        msgs.push(
+            WasmMsg::Execute {
+                contract_addr: nft.collection.to_string(),
+                msg: to_json_binary(&transfer)?,
+                funds: vec![],
+            }
+            .into(),
+        );
+    }
+    Ok(msgs)
+}
+
+pub(crate) fn fmt_coins(coins: &[Coin]) -> String {
+    coins
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::Create(msg) => handle_create(deps, info, env, msg),
+        ExecuteMsg::Spawn(msg) => factory::handle_spawn(deps, info, env, msg),
+        ExecuteMsg::List { option_id, price } => handle_list(deps, info, env, option_id, price),
+        ExecuteMsg::Buy {
+            option_id,
+            referrer,
+        } => handle_buy(deps, info, env, option_id, referrer),
+        ExecuteMsg::StartAuction {
+            option_id,
+            reserve,
+            duration,
+        } => auction::handle_start_auction(deps, info, env, option_id, reserve, duration),
+        ExecuteMsg::Bid { option_id } => auction::handle_bid(deps, info, env, option_id),
+        ExecuteMsg::CloseAuction { option_id } => {
+            auction::handle_close_auction(deps, env, option_id)
+        }
+        ExecuteMsg::StartDutchAuction {
+            option_id,
+            start_price,
+            floor_price,
+            duration,
+        } => auction::handle_start_dutch_auction(
+            deps,
+            info,
+            env,
+            option_id,
+            start_price,
+            floor_price,
+            duration,
+        ),
+        ExecuteMsg::CancelDutchAuction { option_id } => {
+            auction::handle_cancel_dutch_auction(deps, info, option_id)
+        }
+        ExecuteMsg::BuyDutch { option_id } => auction::handle_buy_dutch(deps, info, env, option_id),
+        ExecuteMsg::PlaceBid { option_id, expires } => {
+            orderbook::handle_place_bid(deps, info, env, option_id, expires)
+        }
+        ExecuteMsg::PlaceAsk {
+            option_id,
+            price,
+            expires,
+        } => orderbook::handle_place_ask(deps, info, env, option_id, price, expires),
+        ExecuteMsg::FillOrder { order_id } => {
+            orderbook::handle_fill_order(deps, info, env, order_id)
+        }
+        ExecuteMsg::CancelOrder { order_id } => {
+            orderbook::handle_cancel_order(deps, info, env, order_id)
+        }
+        ExecuteMsg::EscrowPremium {
+            option_id,
+            deadline,
+        } => escrow::handle_escrow_premium(deps, info, env, option_id, deadline),
+        ExecuteMsg::AcceptEscrow { escrow_id } => {
+            escrow::handle_accept_escrow(deps, info, env, escrow_id)
+        }
+        ExecuteMsg::RefundEscrow { escrow_id } => {
+            escrow::handle_refund_escrow(deps, info, env, escrow_id)
+        }
+        ExecuteMsg::RefundExpiredEscrows { limit } => {
+            escrow::handle_refund_expired_escrows(deps, env, limit)
+        }
+        ExecuteMsg::RequestQuote {
+            counter_offer,
+            collateral,
+            expires,
+            settlement,
+            style,
+            deadline,
+        } => rfq::handle_request_quote(
+            deps,
+            info,
+            env,
+            counter_offer,
+            collateral,
+            expires,
+            settlement,
+            style,
+            deadline,
+        ),
+        ExecuteMsg::CancelQuoteRequest { request_id } => {
+            rfq::handle_cancel_quote_request(deps, info, env, request_id)
+        }
+        ExecuteMsg::SubmitQuote {
+            request_id,
+            premium,
+        } => rfq::handle_submit_quote(deps, info, env, request_id, premium),
+        ExecuteMsg::WithdrawQuote { quote_id } => {
+            rfq::handle_withdraw_quote(deps, info, env, quote_id)
+        }
+        ExecuteMsg::AcceptQuote { quote_id } => rfq::handle_accept_quote(deps, info, env, quote_id),
+        ExecuteMsg::TransferNft {
+            recipient,
+            token_id,
+        } => handle_transfer_nft(deps, info, env, recipient, token_id),
+        ExecuteMsg::SendNft {
+            contract,
+            token_id,
+            msg,
+        } => handle_send_nft(deps, info, env, contract, token_id, msg),
+        ExecuteMsg::TransferFrom {
+            owner,
+            recipient,
+            token_id,
+        } => handle_transfer_from(deps, info, env, owner, recipient, token_id),
+        ExecuteMsg::TransferBatch { transfers } => {
+            handle_transfer_batch(deps, info, env, transfers)
+        }
+        ExecuteMsg::IbcTransfer {
+            option_id,
+            channel_id,
+            receiver,
+            timeout,
+        } => ibc::handle_ibc_transfer(deps, info, env, option_id, channel_id, receiver, timeout),
+        ExecuteMsg::TransferVoucher {
+            channel_id,
+            option_id,
+            recipient,
+        } => ibc::handle_transfer_voucher(deps, info, channel_id, option_id, recipient),
+        ExecuteMsg::ReturnVoucher {
+            channel_id,
+            option_id,
+            receiver,
+            timeout,
+        } => ibc::handle_return_voucher(deps, info, env, channel_id, option_id, receiver, timeout),
+        ExecuteMsg::OfferTransfer {
+            option_id,
+            recipient,
+            expires,
+        } => handle_offer_transfer(deps, info, env, option_id, recipient, expires),
+        ExecuteMsg::ClaimTransfer { option_id } => {
+            handle_claim_transfer(deps, info, env, option_id)
+        }
+        ExecuteMsg::Approve {
+            spender,
+            token_id,
+            expires,
+        } => handle_approve(deps, info, env, spender, token_id, expires),
+        ExecuteMsg::Revoke { spender, token_id } => {
+            handle_revoke(deps, info, env, spender, token_id)
+        }
+        ExecuteMsg::ApproveAll { operator, expires } => {
+            handle_approve_all(deps, info, env, operator, expires)
+        }
+        ExecuteMsg::RevokeAll { operator } => handle_revoke_all(deps, info, operator),
+        ExecuteMsg::Execute {
+            option_id,
+            recipient,
+            referrer,
+            deadline,
+            min_payout,
+        } => {
+            check_deadline(&env, deadline)?;
+            handle_execute(deps, info, env, option_id, recipient, referrer, min_payout)
+        }
+        ExecuteMsg::ExecutePartial {
+            option_id,
+            fraction,
+            recipient,
+        } => handle_execute_partial(deps, info, env, option_id, fraction, recipient),
+        ExecuteMsg::ExecuteBatch { option_ids } => {
+            handle_execute_batch(deps, info, env, option_ids)
+        }
+        ExecuteMsg::Split { option_id, parts } => handle_split(deps, info, option_id, parts),
+        ExecuteMsg::Merge { option_ids } => handle_merge(deps, info, option_ids),
+        ExecuteMsg::AddCollateral { option_id } => {
+            handle_add_collateral(deps, info, env, option_id)
+        }
+        ExecuteMsg::WithdrawCollateral { option_id, amount } => {
+            handle_withdraw_collateral(deps, info, env, option_id, amount)
+        }
+        ExecuteMsg::Cancel { option_id } => handle_cancel(deps, info, env, option_id),
+        ExecuteMsg::ProposeTermination { option_id } => {
+            handle_propose_termination(deps, info, option_id)
+        }
+        ExecuteMsg::AcceptTermination { option_id } => {
+            handle_accept_termination(deps, info, env, option_id)
+        }
+        ExecuteMsg::ProposeExtension { option_id, expires } => {
+            handle_propose_extension(deps, info, option_id, expires)
+        }
+        ExecuteMsg::AcceptExtension { option_id } => handle_accept_extension(deps, info, option_id),
+        ExecuteMsg::CancelExtension { option_id } => handle_cancel_extension(deps, info, option_id),
+        ExecuteMsg::OfferBuyback { option_id, price } => {
+            handle_offer_buyback(deps, info, option_id, price)
+        }
+        ExecuteMsg::AcceptBuyback { option_id } => {
+            handle_accept_buyback(deps, info, env, option_id)
+        }
+        ExecuteMsg::CancelBuyback { option_id } => handle_cancel_buyback(deps, info, option_id),
+        ExecuteMsg::PruneArchive { older_than, limit } => {
+            handle_prune_archive(deps, env, older_than, limit)
+        }
+        ExecuteMsg::Burn { option_id } => handle_burn(deps, info, env, option_id),
+        ExecuteMsg::Roll { option_id } => handle_roll(deps, info, env, option_id),
+        ExecuteMsg::Crank { limit } => crank::handle_crank(deps, info, env, limit),
+        ExecuteMsg::FundCrankPool {} => crank::handle_fund_crank_pool(deps, info),
+        ExecuteMsg::KnockOut { option_id } => handle_knock_out(deps, info, env, option_id),
+        ExecuteMsg::SettleBinary { option_id } => handle_settle_binary(deps, info, env, option_id),
+        ExecuteMsg::SamplePrice { option_id } => twap::handle_sample_price(deps, env, option_id),
+        ExecuteMsg::CreatePerpetual {
+            counter_offer,
+            funding_rate,
+            premium,
+            settlement,
+        } => perpetual::handle_create_perpetual(
+            deps,
+            info,
+            env,
+            counter_offer,
+            funding_rate,
+            premium,
+            settlement,
+        ),
+        ExecuteMsg::CreateBasket {
+            terms,
+            cw20_collateral,
+        } => handle_create_basket(deps, info, env, terms, cw20_collateral),
+        ExecuteMsg::CreateStaked { terms, validator } => {
+            staking::handle_create_staked(deps, info, env, terms, validator)
+        }
+        ExecuteMsg::CreateMargined { terms, notional } => {
+            margin::handle_create_margined(deps, info, env, terms, notional)
+        }
+        ExecuteMsg::LiquidateMargin { option_id } => {
+            margin::handle_liquidate_margin(deps, info, env, option_id)
+        }
+        ExecuteMsg::WithdrawInsurance { amount, recipient } => {
+            insurance::handle_withdraw_insurance(deps, info, amount, recipient)
+        }
+        ExecuteMsg::RecoverFunds {
+            denom,
+            amount,
+            recipient,
+        } => recovery::handle_recover_funds(deps, info, env, denom, amount, recipient),
+        ExecuteMsg::RetryPayout { claim_id } => payouts::handle_retry_payout(deps, claim_id),
+        ExecuteMsg::SweepSurplus {} => recovery::handle_sweep_surplus(deps, info, env),
+        ExecuteMsg::RebuildStats { limit } => handle_rebuild_stats(deps, limit),
+        ExecuteMsg::UpdateBlacklist { add, remove } => {
+            blacklist::handle_update_blacklist(deps, info, add, remove)
+        }
+        ExecuteMsg::UpdatePrice { option_id } => {
+            circuit_breaker::handle_update_price(deps, env, option_id)
+        }
+        ExecuteMsg::ResetCircuitBreaker { option_id } => {
+            circuit_breaker::handle_reset_circuit_breaker(deps, info, option_id)
+        }
+        ExecuteMsg::Unstake { option_id } => staking::handle_unstake(deps, info, env, option_id),
+        ExecuteMsg::ClaimStakingRewards { option_id } => {
+            staking::handle_claim_staking_rewards(deps, env, option_id)
+        }
+        ExecuteMsg::DepositYield { option_id } => {
+            yield_strategy::handle_deposit_yield(deps, info, env, option_id)
+        }
+        ExecuteMsg::WithdrawYield { option_id } => {
+            yield_strategy::handle_withdraw_yield(deps, info, env, option_id)
+        }
+        ExecuteMsg::DepositFunding { option_id } => {
+            perpetual::handle_deposit_funding(deps, info, env, option_id)
+        }
+        ExecuteMsg::WithdrawFunding { option_id, amount } => {
+            perpetual::handle_withdraw_funding(deps, info, env, option_id, amount)
+        }
+        ExecuteMsg::SettleFunding { option_id } => {
+            perpetual::handle_settle_funding(deps, env, option_id)
+        }
+        ExecuteMsg::Liquidate { option_id } => {
+            perpetual::handle_liquidate(deps, info, env, option_id)
+        }
+        ExecuteMsg::Fractionalize { option_id, shares } => {
+            shares::handle_fractionalize(deps, info, env, option_id, shares)
+        }
+        ExecuteMsg::CoWrite { option_id } => cowrite::handle_co_write(deps, info, env, option_id),
+        ExecuteMsg::ClaimCoWritten { option_id } => {
+            cowrite::handle_claim_co_written(deps, info, option_id)
+        }
+        ExecuteMsg::CreateSeries {
+            strikes,
+            expiries,
+            style,
+        } => series::handle_create_series(deps, info, env, strikes, expiries, style),
+        ExecuteMsg::CreateStrategy {
+            legs,
+            expires,
+            style,
+            owner,
+        } => strategy::handle_create_strategy(deps, info, env, legs, expires, style, owner),
+        ExecuteMsg::TransferStrategy {
+            strategy_id,
+            recipient,
+        } => strategy::handle_transfer_strategy(deps, info, strategy_id, recipient),
+        ExecuteMsg::ExecuteStrategy { strategy_id } => {
+            strategy::handle_execute_strategy(deps, info, env, strategy_id)
+        }
+        ExecuteMsg::CreateVault {
+            writer,
+            denom,
+            strike_denom,
+            strike,
+        } => vault::handle_create_vault(deps, info, writer, denom, strike_denom, strike),
+        ExecuteMsg::SetVaultStrike { vault_id, strike } => {
+            vault::handle_set_vault_strike(deps, info, vault_id, strike)
+        }
+        ExecuteMsg::DepositVault { vault_id } => vault::handle_deposit_vault(deps, info, vault_id),
+        ExecuteMsg::WithdrawVault { vault_id, shares } => {
+            vault::handle_withdraw_vault(deps, info, vault_id, shares)
+        }
+        ExecuteMsg::ClaimVaultEarnings { vault_id } => {
+            vault::handle_claim_vault_earnings(deps, info, vault_id)
+        }
+        ExecuteMsg::WriteVaultOption {
+            vault_id,
+            amount,
+            expires,
+            premium,
+        } => vault::handle_write_vault_option(deps, info, env, vault_id, amount, expires, premium),
+        ExecuteMsg::Receive(msg) => handle_receive(deps, info, env, msg),
+        ExecuteMsg::ReceiveNft(msg) => handle_receive_nft(deps, info, env, msg),
+        ExecuteMsg::UpdateConfig(msg) => handle_update_config(deps, info, env, msg),
+        ExecuteMsg::UpdateFees {
+            fee_bps,
+            fee_collector,
+            transfer_fee,
+            referral_bps,
+            insurance_bps,
+        } => {
+            let changes = UpdateConfigMsg {
+                fee_bps,
+                fee_collector,
+                transfer_fee,
+                referral_bps,
+                insurance_bps,
+                ..UpdateConfigMsg::default()
+            };
+            governance::handle_governance_update(deps, info, env, "update_fees", changes)
+        }
+        ExecuteMsg::UpdateOracle { oracle } => {
+            let changes = UpdateConfigMsg {
+                oracle: Some(oracle),
+                ..UpdateConfigMsg::default()
+            };
+            governance::handle_governance_update(deps, info, env, "update_oracle", changes)
+        }
+        ExecuteMsg::UpdateWhitelist { allowed_denoms } => {
+            let changes = UpdateConfigMsg {
+                allowed_denoms: Some(allowed_denoms),
+                ..UpdateConfigMsg::default()
+            };
+            governance::handle_governance_update(deps, info, env, "update_whitelist", changes)
+        }
+        ExecuteMsg::UpdateTimelock { config_timelock } => {
+            let changes = UpdateConfigMsg {
+                config_timelock: Some(config_timelock),
+                ..UpdateConfigMsg::default()
+            };
+            governance::handle_governance_update(deps, info, env, "update_timelock", changes)
+        }
+        ExecuteMsg::ExecuteQueued { queued_id } => {
+            timelock::handle_execute_queued(deps, env, queued_id)
+        }
+        ExecuteMsg::CancelQueued { queued_id } => {
+            timelock::handle_cancel_queued(deps, info, queued_id)
+        }
+        ExecuteMsg::RegisterDenomTrace { path, base_denom } => {
+            denom::handle_register_denom_trace(deps, info, path, base_denom)
+        }
+        ExecuteMsg::Pause {} => handle_set_paused(deps, info, true),
+        ExecuteMsg::Unpause {} => handle_set_paused(deps, info, false),
+    }
+}
+
+pub fn handle_create(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    msg: CreateMsg,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let (option_id, state) = create_option(deps, &env, info, msg)?;
+
+    Ok(Response::new()
+        .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+        .add_attribute("action", "create")
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Writes an option collateralized by the coins sent along with cw20 tokens, which the contract
+/// takes from the creator under the allowances they granted it.
+pub fn handle_create_basket(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    msg: CreateMsg,
+    cw20_collateral: Vec<Cw20Coin>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    if let Expiration::Never {} = msg.expires {
+        return Err(ContractError::NeverExpires {});
+    }
+    let tokens = cw20_collateral
+        .into_iter()
+        .map(|coin| {
+            Ok(Cw20CoinVerified {
+                address: deps.api.addr_validate(&coin.address)?,
+                amount: coin.amount,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    let creator = info.sender.clone();
+    let (option_id, state) = write_option(deps, &env, info, msg, None, tokens)?;
+
+    let mut pulls = vec![];
+    for coin in &state.cw20_collateral {
+        pulls.push(WasmMsg::Execute {
+            contract_addr: coin.address.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: creator.to_string(),
+                recipient: env.contract.address.to_string(),
+                amount: coin.amount,
+            })?,
+            funds: vec![],
+        });
+    }
+    Ok(Response::new()
+        .add_messages(pulls)
+        .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+        .add_attribute("action", "create")
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_transfer_nft(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    recipient: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let option_id = parse_token_id(&token_id)?;
+    let (state, to) = authorize_transfer(deps.as_ref(), &env, &info, option_id, &recipient)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "transfer_nft",
+        option_id,
+        &[&info.sender, &to],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let (state, event, fee) = transfer_option(deps, &env, &info, option_id, state, to)?;
+
+    Ok(Response::new()
+        .add_messages(fee)
+        .add_event(event.into())
+        .add_attribute("action", "transfer_nft")
+        .add_attribute("sender", info.sender)
+        .add_attribute("recipient", recipient)
+        .add_attribute("token_id", token_id)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_send_nft(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    contract: String,
+    token_id: String,
+    msg: Binary,
+) -> Result<Response, ContractError> {
+    let option_id = parse_token_id(&token_id)?;
+    let (state, to) = authorize_transfer(deps.as_ref(), &env, &info, option_id, &contract)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "send_nft",
+        option_id,
+        &[&info.sender, &to],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let (state, event, fee) = transfer_option(deps, &env, &info, option_id, state, to)?;
+
+    // let the receiving contract know it now holds the option
+    let callback = Cw721ReceiveMsg {
+        sender: info.sender.to_string(),
+        token_id: token_id.clone(),
+        msg,
+    }
+    .into_cosmos_msg(contract.clone())?;
+
+    Ok(Response::new()
+        .add_message(callback)
+        .add_messages(fee)
+        .add_event(event.into())
+        .add_attribute("action", "send_nft")
+        .add_attribute("sender", info.sender)
+        .add_attribute("recipient", contract)
+        .add_attribute("token_id", token_id)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_transfer_from(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    owner: String,
+    recipient: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let option_id = parse_token_id(&token_id)?;
+    let (state, to) = authorize_transfer(deps.as_ref(), &env, &info, option_id, &recipient)?;
+
+    // ensure the approval is still used for the owner that granted it
+    if state.owner != owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "transfer_from",
+        option_id,
+        &[&info.sender, &state.owner, &to],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let (state, event, fee) = transfer_option(deps, &env, &info, option_id, state, to)?;
+
+    Ok(Response::new()
+        .add_messages(fee)
+        .add_event(event.into())
+        .add_attribute("action", "transfer_from")
+        .add_attribute("sender", info.sender)
+        .add_attribute("recipient", recipient)
+        .add_attribute("token_id", token_id)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_transfer_batch(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    transfers: Vec<(u64, String)>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+
+    // check every entry before moving any option
+    let config = CONFIG.load(deps.storage)?;
+    let mut moves: Vec<(u64, State, Addr)> = vec![];
+    let mut paying = 0;
+    for (option_id, recipient) in transfers {
+        if moves.iter().any(|(id, _, _)| *id == option_id) {
+            return Err(ContractError::DuplicateOption { option_id });
+        }
+        let state: State = options().load(deps.storage, option_id)?;
+        check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+        let recipient = deps.api.addr_validate(&recipient)?;
+        let blocked = blacklist::blocked_attempt(
+            deps.storage,
+            "transfer_batch",
+            option_id,
+            &[&info.sender, &recipient],
+            &info.sender,
+            Asset::Native(info.funds.clone()),
+        )?;
+        if let Some(res) = blocked {
+            return Ok(res);
+        }
+        check_taker(&state, &recipient)?;
+        assert_eligible(&deps.querier, &config, &recipient)?;
+        if owes_transfer_fee(&config, &env, &state.owner, &recipient) {
+            paying += 1;
+        }
+        moves.push((option_id, state, recipient));
+    }
+    let fee = collect_transfer_fee(&config, paying, &info.funds)?;
+
+    let mut res = Response::new()
+        .add_messages(fee)
+        .add_attribute("action", "transfer_batch")
+        .add_attribute("sender", info.sender);
+    for (option_id, mut state, recipient) in moves {
+        let event = change_owner(&mut state, option_id, recipient);
+        save_option(deps.storage, option_id, &state)?;
+        res = res
+            .add_event(event.into())
+            .add_attributes(option_attributes(option_id, &state));
+    }
+    Ok(res)
+}
+
+/// Checks the sender can move the option, returning it along with the validated `recipient`.
+fn authorize_transfer(
+    deps: Deps,
+    env: &Env,
+    info: &MessageInfo,
+    option_id: u64,
+    recipient: &str,
+) -> Result<(State, Addr), ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+
+    // ensure msg.sender is the owner or approved
+    check_can_send(deps, env, &info.sender, &state)?;
+
+    // a mistyped recipient would lose the option for good
+    let recipient = deps.api.addr_validate(recipient)?;
+    Ok((state, recipient))
+}
+
+/// Moves ownership of an option checked by `authorize_transfer` to `recipient`.
+/// Returns the transfer event and the fee the sender paid, to forward to the fee collector.
+fn transfer_option(
+    deps: DepsMut,
+    env: &Env,
+    info: &MessageInfo,
+    option_id: u64,
+    mut state: State,
+    recipient: Addr,
+) -> Result<(State, OptionTransferred, Option<BankMsg>), ContractError> {
+    check_taker(&state, &recipient)?;
+    let config = CONFIG.load(deps.storage)?;
+    assert_eligible(&deps.querier, &config, &recipient)?;
+    let paying = owes_transfer_fee(&config, env, &state.owner, &recipient) as u128;
+    let fee = collect_transfer_fee(&config, paying, &info.funds)?;
+    let event = change_owner(&mut state, option_id, recipient);
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok((state, event, fee))
+}
+
+/// Whether an option moving from `from` to `to` pays the transfer fee.
+/// Moves into and out of the contract's own escrow, or involving exempt addresses, do not.
+fn owes_transfer_fee(config: &Config, env: &Env, from: &Addr, to: &Addr) -> bool {
+    [from, to]
+        .iter()
+        .all(|addr| **addr != env.contract.address && !config.fee_exempt.contains(addr))
+}
+
+/// Checks the sender paid the flat fee for `count` transfers, to be sent to the fee collector.
+/// Funds are left alone when no fee is due.
+fn collect_transfer_fee(
+    config: &Config,
+    count: u128,
+    funds: &[Coin],
+) -> Result<Option<BankMsg>, ContractError> {
+    let fee = config
+        .transfer_fee
+        .flat
+        .iter()
+        .map(|c| {
+            Ok(Coin::new(
+                c.amount.checked_mul(count.into())?.u128(),
+                &c.denom,
+            ))
+        })
+        .filter(|c| !matches!(c, Ok(c) if c.amount.is_zero()))
+        .collect::<StdResult<Vec<_>>>()?;
+    if fee.is_empty() {
+        return Ok(None);
+    }
+    if funds != fee.as_slice() {
+        return Err(ContractError::TransferFeeRequired { fee });
+    }
+    Ok(Some(BankMsg::Send {
+        to_address: config.fee_collector.to_string(),
+        amount: fee,
+    }))
+}
+
+/// Ensures `taker` may become the owner of an option restricted to a counterparty.
+/// The creator can always take it back.
+pub(crate) fn check_taker(state: &State, taker: &Addr) -> Result<(), ContractError> {
+    match &state.allowed_taker {
+        Some(allowed) if taker != allowed && *taker != state.creator => {
+            Err(ContractError::TakerNotAllowed {})
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Sets the new owner on state, any listing or approval was made by the previous owner.
+/// Returns the event recording the move.
+pub(crate) fn change_owner(state: &mut State, option_id: u64, owner: Addr) -> OptionTransferred {
+    let from = std::mem::replace(&mut state.owner, owner);
+    state.price = vec![];
+    state.approvals = vec![];
+    OptionTransferred {
+        option_id,
+        from,
+        to: state.owner.clone(),
+    }
+}
+
+pub fn handle_offer_transfer(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    recipient: String,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OfferExpired {});
+    }
+    let recipient_addr = deps.api.addr_validate(&recipient)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "offer_transfer",
+        option_id,
+        &[&state.owner, &recipient_addr],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let config = CONFIG.load(deps.storage)?;
+    assert_eligible(&deps.querier, &config, &recipient_addr)?;
+
+    let offer = TransferOffer {
+        from: state.owner.clone(),
+        recipient: recipient_addr,
+        expires,
+    };
+    TRANSFER_OFFERS.save(deps.storage, option_id, &offer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "offer_transfer")
+        .add_attribute("recipient", recipient)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_claim_transfer(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+
+    // ensure the offer is for the sender, still open, and made by the current owner
+    let offer = match TRANSFER_OFFERS.may_load(deps.storage, option_id)? {
+        Some(offer) if !offer.expires.is_expired(&env.block) && offer.from == state.owner => offer,
+        _ => return Err(ContractError::OfferExpired {}),
     };
-    config(&mut deps.storage).save(&state)?;
+    if info.sender != offer.recipient {
+        return Err(ContractError::Unauthorized {});
+    }
+    check_taker(&state, &offer.recipient)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "claim_transfer",
+        option_id,
+        &[&offer.from, &info.sender],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    assert_eligible(&deps.querier, &config, &offer.recipient)?;
+    let paying = owes_transfer_fee(&config, &env, &offer.from, &offer.recipient) as u128;
+    let fee = collect_transfer_fee(&config, paying, &info.funds)?;
+
+    TRANSFER_OFFERS.remove(deps.storage, option_id);
+    let event = change_owner(&mut state, option_id, offer.recipient);
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_messages(fee)
+        .add_event(event.into())
+        .add_attribute("action", "claim_transfer")
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Owners and their operators can approve spenders, as well as transfer or execute the option.
+fn check_can_approve(
+    deps: Deps,
+    env: &Env,
+    sender: &Addr,
+    state: &State,
+) -> Result<(), ContractError> {
+    if *sender == state.owner {
+        return Ok(());
+    }
+    match OPERATORS.may_load(deps.storage, (&state.owner, sender))? {
+        Some(expires) if !expires.is_expired(&env.block) => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+/// Approved spenders can transfer or execute the option as well.
+pub(crate) fn check_can_send(
+    deps: Deps,
+    env: &Env,
+    sender: &Addr,
+    state: &State,
+) -> Result<(), ContractError> {
+    let approved = state
+        .approvals
+        .iter()
+        .any(|a| a.spender == sender.as_str() && !a.expires.is_expired(&env.block));
+    if approved {
+        return Ok(());
+    }
+    check_can_approve(deps, env, sender, state)
+}
+
+pub fn handle_approve(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    spender: String,
+    token_id: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let (option_id, state) =
+        update_approvals(deps, &info, &env, &spender, &token_id, Some(expires))?;
+
+    Ok(Response::new()
+        .add_attribute("action", "approve")
+        .add_attribute("sender", info.sender)
+        .add_attribute("spender", spender)
+        .add_attribute("token_id", token_id)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_revoke(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    spender: String,
+    token_id: String,
+) -> Result<Response, ContractError> {
+    let (option_id, state) = update_approvals(deps, &info, &env, &spender, &token_id, None)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "revoke")
+        .add_attribute("sender", info.sender)
+        .add_attribute("spender", spender)
+        .add_attribute("token_id", token_id)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Replaces the approval of `spender` with one expiring at `add`, or just removes it if `None`.
+fn update_approvals(
+    deps: DepsMut,
+    info: &MessageInfo,
+    env: &Env,
+    spender: &str,
+    token_id: &str,
+    add: Option<Option<Expiration>>,
+) -> Result<(u64, State), ContractError> {
+    let option_id = parse_token_id(token_id)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    check_can_approve(deps.as_ref(), env, &info.sender, &state)?;
+
+    let spender = deps.api.addr_validate(spender)?;
+    state.approvals.retain(|a| a.spender != spender.as_str());
+    if let Some(expires) = add {
+        let expires = expires.unwrap_or_default();
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::ApprovalExpired {});
+        }
+        state.approvals.push(Approval {
+            spender: spender.into_string(),
+            expires,
+        });
+    }
+    save_option(deps.storage, option_id, &state)?;
+    Ok((option_id, state))
+}
+
+pub fn handle_approve_all(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    operator: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::ApprovalExpired {});
+    }
+    let operator_addr = deps.api.addr_validate(&operator)?;
+    OPERATORS.save(deps.storage, (&info.sender, &operator_addr), &expires)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "approve_all")
+        .add_attribute("sender", info.sender)
+        .add_attribute("operator", operator))
+}
+
+pub fn handle_revoke_all(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    let operator_addr = deps.api.addr_validate(&operator)?;
+    OPERATORS.remove(deps.storage, (&info.sender, &operator_addr));
+
+    Ok(Response::new()
+        .add_attribute("action", "revoke_all")
+        .add_attribute("sender", info.sender)
+        .add_attribute("operator", operator))
+}
+
+/// Options are exposed as cw721 tokens whose token_id is the option id.
+fn parse_token_id(token_id: &str) -> StdResult<u64> {
+    token_id
+        .parse()
+        .map_err(|_| StdError::parse_err("u64", format!("invalid token_id: {}", token_id)))
+}
+
+pub fn handle_list(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    price: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+
+    // ensure msg.sender is the owner
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+
+    state.price = price;
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "list")
+        .add_attribute("price", fmt_coins(&state.price))
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_buy(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    referrer: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "buy",
+        option_id,
+        &[&info.sender],
+        &info.sender,
+        Asset::Native(info.funds.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let referrer = validate_referrer(deps.as_ref(), &info.sender, referrer)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+
+    // ensure it is listed
+    if state.price.is_empty() {
+        return Err(ContractError::NotForSale {});
+    }
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    check_taker(&state, &info.sender)?;
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &info.sender)?;
+    // ensure sending proper price
+    if info.funds != state.price {
+        return Err(ContractError::PriceMismatch {
+            offer: info.funds,
+            expected: state.price,
+        });
+    }
+
+    // hand over the option and forward the price to the seller
+    let price = std::mem::take(&mut state.price);
+    let event = change_owner(&mut state, option_id, info.sender);
+    save_option(deps.storage, option_id, &state)?;
+
+    let seller = event.from.clone();
+    let res = Response::new()
+        .add_attribute("action", "buy")
+        .add_event(event.into());
+    let res = pay_sale(
+        deps.storage,
+        &env,
+        res,
+        &state,
+        seller,
+        price,
+        referrer.as_ref(),
+    )?
+    .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+/// Pays the sale `price` of the option, now owned by the buyer, to the seller. The transfer fee
+/// is taken first, then the creator's royalty when the option is resold.
+/// The `referrer` gets their share of the fee.
+/// A fractionalized option's royalty is paid to the contract, call `keep_proceeds` after.
+pub(crate) fn pay_sale(
+    storage: &mut dyn Storage,
+    env: &Env,
+    res: Response,
+    state: &State,
+    seller: Addr,
+    price: Vec<Coin>,
+    referrer: Option<&Addr>,
+) -> StdResult<Response> {
+    let config = CONFIG.load(storage)?;
+    let (fee, price) = if owes_transfer_fee(&config, env, &seller, &state.owner) {
+        split_bps(price, config.transfer_fee.bps)
+    } else {
+        (vec![], price)
+    };
+    let (royalty, rest) = if seller == state.creator {
+        (vec![], price)
+    } else {
+        split_bps(price, state.royalty_bps)
+    };
+    let mut res = res;
+    if !rest.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: seller.into_string(),
+            amount: rest,
+        });
+    }
+    if !royalty.is_empty() {
+        res = res
+            .add_attribute("royalty", fmt_coins(&royalty))
+            .add_message(BankMsg::Send {
+                to_address: state.creator.to_string(),
+                amount: royalty,
+            });
+    }
+    if !fee.is_empty() {
+        res = res
+            .add_attribute("transfer_fee", fmt_coins(&fee))
+            .add_messages(protocol_fee_msgs(
+                storage,
+                &config,
+                referrer,
+                Some(Asset::Native(fee)),
+            )?);
+    }
+    Ok(res)
+}
+
+/// Errors once the block is past `deadline`, if any.
+fn check_deadline(env: &Env, deadline: Option<Expiration>) -> Result<(), ContractError> {
+    match deadline {
+        Some(deadline) if deadline.is_expired(&env.block) => {
+            Err(ContractError::DeadlinePassed { deadline })
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn handle_execute(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    recipient: Option<String>,
+    referrer: Option<String>,
+    min_payout: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let payment = Asset::Native(info.funds);
+    execute_option(
+        deps,
+        env,
+        option_id,
+        info.sender,
+        payment,
+        Decimal::one(),
+        recipient,
+        referrer,
+        min_payout,
+    )
+}
+
+pub fn handle_execute_partial(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    fraction: Decimal,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let payment = Asset::Native(info.funds);
+    execute_option(
+        deps,
+        env,
+        option_id,
+        info.sender,
+        payment,
+        fraction,
+        recipient,
+        None,
+        None,
+    )
+}
+
+pub fn handle_execute_batch(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_ids: Vec<u64>,
+) -> Result<Response, ContractError> {
+    // check every option and take its counter_offer out of the funds before settling any
+    let mut payment = Some(Asset::Native(info.funds.clone()));
+    let mut batch: Vec<(u64, State)> = vec![];
+    for option_id in option_ids {
+        if batch.iter().any(|(id, _)| *id == option_id) {
+            return Err(ContractError::DuplicateOption { option_id });
+        }
+        let mut state: State = options().load(deps.storage, option_id)?;
+        check_exercisable(deps.as_ref(), &env, &info.sender, option_id, &state)?;
+        let blocked = blacklist::blocked_attempt(
+            deps.storage,
+            "execute_batch",
+            option_id,
+            &[&info.sender, &holder(&state, &info.sender)],
+            &info.sender,
+            Asset::Native(info.funds.clone()),
+        )?;
+        if let Some(res) = blocked {
+            return Ok(res);
+        }
+        state.apply_strike();
+        let paid = take_token(&state, payment.unwrap_or(Asset::Native(vec![])))?;
+        payment = match state.settlement {
+            Settlement::Physical => accept_counter_offer(&mut state, paid)?,
+            _ => Some(paid),
+        };
+        batch.push((option_id, state));
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let mut res = Response::new().add_attribute("action", "execute_batch");
+    for (option_id, state) in batch {
+        let attributes = option_attributes(option_id, &state);
+        let payment =
+            (state.settlement == Settlement::Physical).then(|| state.counter_offer.clone());
+        let notional = margin::notional(deps.storage, option_id)?;
+        let archived = archive_option(
+            deps.storage,
+            &env,
+            option_id,
+            &state,
+            Some(&info.sender),
+            Closure::Executed,
+            payment,
+        )?;
+        remove_option(deps.storage, option_id)?;
+        let funding = perpetual::settle_funding(deps.storage, &env, option_id, &state)?;
+        let recipient = holder(&state, &info.sender);
+        if let Some(denom) = &state.denom {
+            res = res.add_message(tokenfactory::burn_msg(&env, denom));
+        }
+        let closed = state.clone();
+        let (settled, fee, payout) = match state.settlement {
+            Settlement::Physical => settle_physical(&config, state, recipient.clone())?,
+            Settlement::Cash | Settlement::Average { .. } => settle_cash(
+                deps.branch(),
+                &env,
+                &config,
+                option_id,
+                state,
+                recipient.clone(),
+                None,
+                notional,
+            )?,
+            Settlement::Binary { .. } => unreachable!("binary options are not executed"),
+        };
+        // the settled response is merged without its events
+        let exercised = OptionExercised::new(option_id, &info.sender, &recipient, &closed, payout);
+        res = res.add_event(exercised.into()).add_event(archived.into());
+        let settled = settled
+            .add_messages(protocol_fee_msgs(deps.storage, &config, None, fee)?)
+            .add_messages(funding);
+        let settled = keep_proceeds(deps.storage, &env, option_id, settled)?;
+        let settled = payouts::guard_payouts(deps.storage, &env, option_id, settled)?;
+        let details = settled.attributes.into_iter().filter(|a| a.key != "action");
+        res = res
+            .add_submessages(settled.messages)
+            .add_attributes(details)
+            .add_attributes(attributes);
+    }
+    if let Some(refund) = payment {
+        res = res.add_message(send_asset(&info.sender, refund)?);
+    }
+    Ok(res)
+}
+
+/// Handles cw20 `Send` hooks, where `info.sender` is the token contract and
+/// `wrapper.sender` is the account that sent the tokens.
+pub fn handle_receive(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let msg: ReceiveMsg = from_json(&wrapper.msg)?;
+    let sender = deps.api.addr_validate(&wrapper.sender)?;
+    let payment = Asset::Cw20(Cw20CoinVerified {
+        address: info.sender.clone(),
+        amount: wrapper.amount,
+    });
+    match msg {
+        ReceiveMsg::Execute {
+            option_id,
+            recipient,
+            referrer,
+            deadline,
+            min_payout,
+        } => {
+            check_deadline(&env, deadline)?;
+            execute_option(
+                deps,
+                env,
+                option_id,
+                sender,
+                payment,
+                Decimal::one(),
+                recipient,
+                referrer,
+                min_payout,
+            )
+        }
+        ReceiveMsg::ExecutePartial {
+            option_id,
+            fraction,
+            recipient,
+        } => execute_option(
+            deps, env, option_id, sender, payment, fraction, recipient, None, None,
+        ),
+        ReceiveMsg::ClaimShares { option_id } => {
+            shares::handle_claim(deps, info.sender, sender, wrapper.amount, option_id)
+        }
+    }
+}
+
+/// Handles cw721 `SendNft` hooks, where `info.sender` is the collection and `wrapper.sender`
+/// is the account that sent the NFT.
+pub fn handle_receive_nft(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    wrapper: Cw721ReceiveMsg,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    // options written here don't back other options
+    if info.sender == env.contract.address {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    let msg: ReceiveNftMsg = from_json(&wrapper.msg)?;
+    match msg {
+        ReceiveNftMsg::Create(terms) => {
+            if let Expiration::Never {} = terms.expires {
+                return Err(ContractError::NeverExpires {});
+            }
+            let writer = MessageInfo {
+                sender: deps.api.addr_validate(&wrapper.sender)?,
+                funds: vec![],
+            };
+            let nft = NftCollateral {
+                collection: info.sender,
+                token_id: wrapper.token_id,
+            };
+            let (option_id, state) = write_option(deps, &env, writer, terms, Some(nft), vec![])?;
+
+            Ok(Response::new()
+                .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+                .add_attribute("action", "create")
+                .add_event(OptionCreated::new(option_id, &state).into())
+                .add_attributes(option_attributes(option_id, &state)))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_option(
+    mut deps: DepsMut,
+    env: Env,
+    option_id: u64,
+    sender: Addr,
+    payment: Asset,
+    fraction: Decimal,
+    recipient: Option<String>,
+    referrer: Option<String>,
+    min_payout: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    // the collateral goes to the recipient, the holder unless another is named
+    let paid_to = match &recipient {
+        Some(recipient) => deps.api.addr_validate(recipient)?,
+        None => holder(&state, &sender),
+    };
+    check_exercisable(deps.as_ref(), &env, &sender, option_id, &state)?;
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "execute",
+        option_id,
+        &[&sender, &paid_to],
+        &sender,
+        payment.clone(),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res);
+    }
+    let referrer = validate_referrer(deps.as_ref(), &sender, referrer)?;
+    let payment = take_token(&state, payment)?;
+    if state.denom.is_some() && fraction != Decimal::one() {
+        return Err(ContractError::TokenizedOption {});
+    }
+    if state.nft.is_some() && fraction != Decimal::one() {
+        return Err(ContractError::NftCollateral {});
+    }
+    if !state.cw20_collateral.is_empty() && fraction != Decimal::one() {
+        return Err(ContractError::Cw20Collateral {});
+    }
+    if !state.alt_counter_offers.is_empty() && fraction != Decimal::one() {
+        return Err(ContractError::AltCounterOffers {});
+    }
+    if NOTIONALS.has(deps.storage, option_id) && fraction != Decimal::one() {
+        return Err(ContractError::MarginedOption {});
+    }
+    let (mut state, remaining) = split_option(state, fraction)?;
+    let refund = check_payment(&mut state, payment)?;
+
+    let recipient = paid_to;
+    let burn = state
+        .denom
+        .as_ref()
+        .map(|denom| tokenfactory::burn_msg(&env, denom));
+    let config = CONFIG.load(deps.storage)?;
+    let attributes = option_attributes(option_id, &state);
+    let notional = margin::notional(deps.storage, option_id)?;
+    let mut archived = None;
+    if remaining.is_none() {
+        let payment =
+            (state.settlement == Settlement::Physical).then(|| state.counter_offer.clone());
+        archived = Some(archive_option(
+            deps.storage,
+            &env,
+            option_id,
+            &state,
+            Some(&sender),
+            Closure::Executed,
+            payment,
+        )?);
+    }
+    let closed = state.clone();
+    let (res, fee, payout) = match state.settlement {
+        Settlement::Physical => settle_physical(&config, state, recipient.clone())?,
+        Settlement::Cash | Settlement::Average { .. } => settle_cash(
+            deps.branch(),
+            &env,
+            &config,
+            option_id,
+            state,
+            recipient.clone(),
+            min_payout,
+            notional,
+        )?,
+        Settlement::Binary { .. } => unreachable!("binary options are not executed"),
+    };
+    let exercised = OptionExercised::new(option_id, &sender, &recipient, &closed, payout);
+    let fee = protocol_fee_msgs(deps.storage, &config, referrer.as_ref(), fee)?;
+    let mut res = res
+        .add_messages(fee)
+        .add_event(exercised.into())
+        .add_events(archived.map(Event::from))
+        .add_attributes(attributes);
+
+    // delete the option, or keep what was not executed
+    match remaining {
+        Some(remaining) => save_option(deps.storage, option_id, &remaining)?,
+        None => {
+            remove_option(deps.storage, option_id)?;
+            let funding = perpetual::settle_funding(deps.storage, &env, option_id, &closed)?;
+            res = res.add_messages(funding);
+        }
+    }
+    res = keep_proceeds(deps.storage, &env, option_id, res)?;
+    res = payouts::guard_payouts(deps.storage, &env, option_id, res)?;
+    if let Some(refund) = refund {
+        res = res.add_message(send_asset(&sender, refund)?);
+    }
+    Ok(res.add_messages(burn))
+}
+
+/// Ensures `sender` may execute the option right now.
+pub(crate) fn check_exercisable(
+    deps: Deps,
+    env: &Env,
+    sender: &Addr,
+    option_id: u64,
+    state: &State,
+) -> Result<(), ContractError> {
+    assert_not_paused(deps.storage)?;
+    perpetual::assert_funded(deps.storage, env, option_id, state)?;
+    staking::assert_liquid(deps.storage, env, option_id)?;
+    yield_strategy::assert_withdrawn(deps.storage, option_id)?;
+    if let Settlement::Binary { .. } = state.settlement {
+        return Err(ContractError::BinaryOption {});
+    }
+
+    // ensure message sender is the owner or approved,
+    // tokenized options are for whoever sends in the token instead
+    if state.denom.is_none() {
+        check_can_send(deps, env, sender, state)?;
+    }
+
+    // ensure inside the exercise period
+    match state.style {
+        OptionStyle::American if state.expires.is_expired(&env.block) => {
+            return Err(ContractError::OptionExpired {
+                expired: state.expires,
+            });
+        }
+        OptionStyle::American => match state.exercisable_from {
+            Some(from) if !from.is_expired(&env.block) => {
+                return Err(ContractError::NotYetExercisable { from });
+            }
+            _ => {}
+        },
+        OptionStyle::European if !state.expires.is_expired(&env.block) => {
+            return Err(ContractError::OptionNotExpired {
+                expires: state.expires,
+            });
+        }
+        OptionStyle::European if settlement_window_end(state).is_expired(&env.block) => {
+            return Err(ContractError::OptionExpired {
+                expired: settlement_window_end(state),
+            });
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Whoever gets the collateral by default, the token holder sending in a tokenized option
+fn holder(state: &State, sender: &Addr) -> Addr {
+    match state.denom {
+        Some(_) => sender.clone(),
+        None => state.owner.clone(),
+    }
+}
+
+/// Ensures `payment` is what executing the option takes, returning anything to refund.
+fn check_payment(state: &mut State, payment: Asset) -> Result<Option<Asset>, ContractError> {
+    match state.settlement {
+        // ensure sending enough counter_offer
+        Settlement::Physical => accept_counter_offer(state, payment),
+        // nothing is swapped, so nothing should be paid
+        _ if payment != Asset::Native(vec![]) => Err(ContractError::FundsSentWithCashSettlement {}),
+        _ => Ok(None),
+    }
+}
+
+/// Ensures `payment` covers the counter_offer or else one of its alternatives, which then
+/// replaces the counter_offer as what the creator is paid.
+fn accept_counter_offer(state: &mut State, payment: Asset) -> Result<Option<Asset>, ContractError> {
+    let mismatch = match overpayment(payment.clone(), &state.counter_offer) {
+        Ok(extra) => return Ok(extra),
+        Err(err) => err,
+    };
+    for alt in &state.alt_counter_offers {
+        if let Ok(extra) = overpayment(payment.clone(), alt) {
+            state.counter_offer = alt.clone();
+            return Ok(extra);
+        }
+    }
+    Err(mismatch)
+}
+
+/// Ensures `payment` covers `due`, returning whatever was paid on top of it.
+fn overpayment(payment: Asset, due: &Asset) -> Result<Option<Asset>, ContractError> {
+    let mismatch = |offer: Asset| ContractError::CounterOfferMismatch {
+        offer,
+        expected: due.clone(),
+    };
+    match (&payment, due) {
+        (Asset::Native(paid), Asset::Native(due_coins)) => {
+            let mut extra = match Coins::try_from(paid.clone()) {
+                Ok(coins) => coins,
+                Err(_) => return Err(mismatch(payment)),
+            };
+            for coin in due_coins {
+                if extra.sub(coin.clone()).is_err() {
+                    return Err(mismatch(payment));
+                }
+            }
+            Ok((!extra.is_empty()).then(|| Asset::Native(extra.into_vec())))
+        }
+        (Asset::Cw20(paid), Asset::Cw20(due_coin))
+            if paid.address == due_coin.address && paid.amount >= due_coin.amount =>
+        {
+            let extra = paid.amount - due_coin.amount;
+            Ok((!extra.is_zero()).then(|| {
+                Asset::Cw20(Cw20CoinVerified {
+                    address: paid.address.clone(),
+                    amount: extra,
+                })
+            }))
+        }
+        _ => Err(mismatch(payment)),
+    }
+}
+
+/// Splits `fraction` off an option, returning the part to execute and what remains, if anything.
+/// Released collateral is rounded down and the counter_offer due is rounded up,
+/// so rounding always favours the creator.
+fn split_option(
+    mut state: State,
+    fraction: Decimal,
+) -> Result<(State, Option<State>), ContractError> {
+    if fraction == Decimal::one() {
+        state.apply_strike();
+        return Ok((state, None));
+    }
+    if fraction.is_zero() || fraction > Decimal::one() {
+        return Err(ContractError::InvalidFraction { fraction });
+    }
+
+    let mut part = state.clone();
+    let mut rest = state;
+    part.collateral = vec![];
+    for coin in std::mem::take(&mut rest.collateral) {
+        let released = coin.amount.mul_floor(fraction);
+        if !released.is_zero() {
+            part.collateral
+                .push(Coin::new(released.u128(), &coin.denom));
+        }
+        if coin.amount > released {
+            rest.collateral
+                .push(Coin::new((coin.amount - released).u128(), coin.denom));
+        }
+    }
+    if part.collateral.is_empty() {
+        return Err(ContractError::InvalidFraction { fraction });
+    }
+
+    match (&mut part.counter_offer, &mut rest.counter_offer) {
+        (Asset::Native(due), Asset::Native(left)) => {
+            for (due, left) in due.iter_mut().zip(left.iter_mut()) {
+                due.amount = left.amount.mul_ceil(fraction);
+                left.amount -= due.amount;
+            }
+            left.retain(|coin| !coin.amount.is_zero());
+        }
+        (Asset::Cw20(due), Asset::Cw20(left)) => {
+            due.amount = left.amount.mul_ceil(fraction);
+            left.amount -= due.amount;
+        }
+        _ => unreachable!("both parts are cloned from the same option"),
+    }
+    // a strike prices each part exactly
+    part.apply_strike();
+    rest.apply_strike();
+
+    // nothing left to execute once all collateral is released
+    let rest = (!rest.collateral.is_empty()).then_some(rest);
+    Ok((part, rest))
+}
+
+/// European options can be executed from their expiry until this point.
+fn settlement_window_end(state: &State) -> Expiration {
+    match state.expires {
+        Expiration::AtHeight(height) => {
+            let window = state.settlement_window.unwrap_or(SETTLEMENT_WINDOW_BLOCKS);
+            Expiration::AtHeight(height + window)
+        }
+        Expiration::AtTime(time) => {
+            let window = state.settlement_window.unwrap_or(SETTLEMENT_WINDOW_SECONDS);
+            Expiration::AtTime(time.plus_seconds(window))
+        }
+        Expiration::Never {} => Expiration::Never {},
+    }
+}
+
+/// Point from which the option can be burned: its expiry, or the end of the settlement window of
+/// European options, plus the grace period.
+pub(crate) fn burnable_from(state: &State, grace: u64) -> Expiration {
+    let end = match state.style {
+        OptionStyle::American => state.expires,
+        OptionStyle::European => settlement_window_end(state),
+    };
+    match end {
+        Expiration::AtHeight(height) => Expiration::AtHeight(height + grace),
+        Expiration::AtTime(time) => Expiration::AtTime(time.plus_seconds(grace)),
+        Expiration::Never {} => Expiration::Never {},
+    }
+}
+
+/// Settles the option, returning the protocol fee taken for `protocol_fee_msgs` to pay out
+/// Response settling an option, with the protocol fee and what the recipient was paid
+type Settled = (Response, Option<Asset>, AssetList);
+
+fn settle_physical(
+    config: &Config,
+    state: State,
+    recipient: Addr,
+) -> Result<Settled, ContractError> {
+    let collateral = state.collateral_list();
+    let (fee, counter_offer) = split_asset(state.counter_offer, config.fee_bps);
+
+    // release counter_offer to creator, and collateral to recipient
+    let res = Response::new()
+        .add_message(send_asset(&state.creator, counter_offer)?)
+        .add_messages(collateral_msgs(
+            collateral.clone(),
+            state.nft.as_ref(),
+            &recipient,
+        )?)
+        .add_attribute("action", "execute");
+    Ok((res, fee, collateral))
+}
+
+/// Pays the owner `max(spot - strike, 0)` per unit of collateral, in collateral,
+/// where the strike is the counter_offer per unit of collateral. The creator gets the rest.
+/// Fails if the payout comes to less than `min_payout`.
+#[allow(clippy::too_many_arguments)]
+fn settle_cash(
+    deps: DepsMut,
+    env: &Env,
+    config: &Config,
+    option_id: u64,
+    state: State,
+    recipient: Addr,
+    min_payout: Option<Uint128>,
+    notional: Option<Uint128>,
+) -> Result<Settled, ContractError> {
+    let spot = twap::settlement_price(deps.as_ref(), config, option_id, &state)?;
+    circuit_breaker::assert_price(deps.storage, env, config, &state, spot)?;
+    circuit_breaker::record_price(deps.storage, config, &state, spot)?;
+    let collateral = &state.collateral[0];
+    let strike = priced_amount(&state.counter_offer);
+    // margined options pay out on their notional, up to the margin held
+    let notional = notional.unwrap_or(collateral.amount);
+    // collateral worth the strike stays with the creator, rounding in their favour
+    let kept = strike
+        .checked_div_ceil(spot)
+        .unwrap_or(notional)
+        .min(notional);
+    let owed = notional - kept;
+    let paid_out = owed.min(collateral.amount);
+    // the insurance fund makes up what the margin falls short of
+    let covered = insurance::cover(deps.storage, owed - paid_out, &collateral.denom)?;
+    let payout = coins_nonzero(paid_out + covered, &collateral.denom);
+    let remainder = coins_nonzero(collateral.amount - paid_out, &collateral.denom);
+    let (fee, payout) = split_bps(payout, config.fee_bps);
+    let paid = payout.first().map_or(Uint128::zero(), |coin| coin.amount);
+    match min_payout {
+        Some(min_payout) if paid < min_payout => {
+            return Err(ContractError::PayoutTooLow {
+                payout: paid,
+                min_payout,
+            });
+        }
+        _ => {}
+    }
+
+    let mut res = Response::new()
+        .add_attribute("action", "execute")
+        .add_attribute("spot", spot.to_string());
+    if !remainder.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: state.creator.into_string(),
+            amount: remainder,
+        });
+    }
+    if !payout.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: recipient.into_string(),
+            amount: payout.clone(),
+        });
+    }
+    let fee = (!fee.is_empty()).then_some(Asset::Native(fee));
+    let payout = AssetList {
+        native: payout,
+        cw20: vec![],
+    };
+    Ok((res, fee, payout))
+}
+
+/// Pays the protocol fee to the fee collector, less the referrer's share
+pub(crate) fn protocol_fee_msgs(
+    storage: &mut dyn Storage,
+    config: &Config,
+    referrer: Option<&Addr>,
+    fee: Option<Asset>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = vec![];
+    let (cut, fee) = match (referrer, fee) {
+        (Some(referrer), Some(fee)) => {
+            let (cut, fee) = split_asset(fee, config.referral_bps);
+            (cut.map(|cut| (referrer, cut)), Some(fee))
+        }
+        (_, fee) => (None, fee),
+    };
+    if let Some((referrer, cut)) = cut {
+        let mut stats = REFERRALS.may_load(storage, referrer)?.unwrap_or_default();
+        stats.referrals += 1;
+        match &cut {
+            Asset::Native(coins) => {
+                let mut earned = Coins::try_from(stats.earned)?;
+                for coin in coins {
+                    earned.add(coin.clone())?;
+                }
+                stats.earned = earned.into_vec();
+            }
+            Asset::Cw20(coin) => match stats
+                .earned_cw20
+                .iter_mut()
+                .find(|c| c.address == coin.address)
+            {
+                Some(earned) => earned.amount += coin.amount,
+                None => stats.earned_cw20.push(coin.clone()),
+            },
+        }
+        REFERRALS.save(storage, referrer, &stats)?;
+        msgs.push(send_asset(referrer, cut)?);
+    }
+    // the insurance fund takes its share of what is left of native fees
+    let fee = match fee {
+        Some(Asset::Native(coins)) => Some(Asset::Native(insurance::take_share(
+            storage, config, coins,
+        )?)),
+        fee => fee,
+    };
+    // the referrer may get all of it
+    let left = match &fee {
+        Some(Asset::Native(coins)) => !coins.is_empty(),
+        Some(Asset::Cw20(coin)) => !coin.amount.is_zero(),
+        None => false,
+    };
+    if let (true, Some(fee)) = (left, fee) {
+        msgs.push(send_asset(&config.fee_collector, fee)?);
+    }
+    Ok(msgs)
+}
+
+/// Referrers cannot be the trader themselves, which would just be a discount
+fn validate_referrer(
+    deps: Deps,
+    sender: &Addr,
+    referrer: Option<String>,
+) -> Result<Option<Addr>, ContractError> {
+    let referrer = referrer
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    if referrer.as_ref() == Some(sender) {
+        return Err(ContractError::SelfReferral {});
+    }
+    Ok(referrer)
+}
+
+/// Amount of a priceable counter_offer, which holds a single coin.
+pub(crate) fn priced_amount(asset: &Asset) -> Uint128 {
+    match asset {
+        Asset::Native(coins) => coins[0].amount,
+        Asset::Cw20(coin) => coin.amount,
+    }
+}
+
+fn coins_nonzero(amount: Uint128, denom: &str) -> Vec<Coin> {
+    if amount.is_zero() {
+        vec![]
+    } else {
+        vec![Coin::new(amount.u128(), denom)]
+    }
+}
+
+/// Like `split_bps`, but for either kind of asset. The cut is `None` if it rounds to zero.
+fn split_asset(asset: Asset, bps: u64) -> (Option<Asset>, Asset) {
+    match asset {
+        Asset::Native(coins) => {
+            let (cut, remainder) = split_bps(coins, bps);
+            let cut = (!cut.is_empty()).then_some(Asset::Native(cut));
+            (cut, Asset::Native(remainder))
+        }
+        Asset::Cw20(coin) => {
+            let part = coin.amount.multiply_ratio(bps, MAX_BPS);
+            let cut = (!part.is_zero()).then_some(Asset::Cw20(Cw20CoinVerified {
+                address: coin.address.clone(),
+                amount: part,
+            }));
+            let remainder = Cw20CoinVerified {
+                address: coin.address,
+                amount: coin.amount - part,
+            };
+            (cut, Asset::Cw20(remainder))
+        }
+    }
+}
+
+/// Builds the message moving `asset` held by this contract to `recipient`.
+pub(crate) fn send_asset(recipient: &Addr, asset: Asset) -> StdResult<CosmosMsg> {
+    match asset {
+        Asset::Native(amount) => Ok(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount,
+        }
+        .into()),
+        Asset::Cw20(coin) => Ok(WasmMsg::Execute {
+            contract_addr: coin.address.into_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: coin.amount,
+            })?,
+            funds: vec![],
+        }
+        .into()),
+    }
+}
+
+/// Sends every asset of the list to `recipient`, the coins in one bank message and every
+/// token in its own transfer.
+fn send_assets(recipient: &Addr, assets: AssetList) -> StdResult<Vec<CosmosMsg>> {
+    let mut msgs = vec![];
+    if !assets.native.is_empty() {
+        msgs.push(send_asset(recipient, Asset::Native(assets.native))?);
+    }
+    for coin in assets.cw20 {
+        msgs.push(send_asset(recipient, Asset::Cw20(coin))?);
+    }
+    Ok(msgs)
+}
+
+pub fn handle_prune_archive(
+    deps: DepsMut,
+    env: Env,
+    older_than: Timestamp,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    let retained_from = env.block.time.minus_seconds(config.archive_retention);
+    if config.archive_retention == 0 || older_than > retained_from {
+        return Err(ContractError::WithinRetention {});
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let pruned = archive()
+        .idx
+        .time
+        .range(
+            deps.storage,
+            None,
+            Some(Bound::exclusive((older_than.nanos(), u64::MIN))),
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(option_id, _)| option_id))
+        .collect::<StdResult<Vec<_>>>()?;
+    for option_id in &pruned {
+        archive().remove(deps.storage, *option_id)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "prune_archive")
+        .add_attribute("pruned", pruned.len().to_string()))
+}
+
+pub fn handle_burn(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    // ensure is expired, and European owners had their chance to execute
+    let burnable = burnable_from(&state, CONFIG.load(deps.storage)?.burn_grace_period);
+    if !burnable.is_expired(&env.block) {
+        return Err(ContractError::OptionNotExpired { expires: burnable });
+    }
+
+    // ensure not sending the counter_offer
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsSentWithBurn {});
+    }
+    if let Settlement::Binary { .. } = state.settlement {
+        return Err(ContractError::BinaryOption {});
+    }
+    // keepers roll these over, the creator can still end them
+    if state.roll_on_expiry && info.sender != state.creator {
+        return Err(ContractError::OptionRolls {});
+    }
+    // an expired cash option is worth its in-the-money part to the owner however it is
+    // cleared, as the crank does. Tokenized ones have no single owner to pay and are burned.
+    let cash = matches!(
+        state.settlement,
+        Settlement::Cash | Settlement::Average { .. }
+    );
+    if cash && state.denom.is_none() {
+        return exercise_expired(deps, &env, &info.sender, option_id, state);
+    }
+
+    // delete the option
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Burned,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let attributes = option_attributes(option_id, &state);
+
+    // reward keepers cleaning up someone else's option
+    let (reward, collateral) = if info.sender == state.creator {
+        (AssetList::default(), state.collateral_list())
+    } else {
+        let config = CONFIG.load(deps.storage)?;
+        split_assets(state.collateral_list(), config.burn_reward_bps)
+    };
+
+    // release collateral to creator
+    let mut res = Response::new()
+        .add_messages(collateral_msgs(
+            collateral,
+            state.nft.as_ref(),
+            &state.creator,
+        )?)
+        .add_attribute("action", "burn")
+        .add_event(OptionBurned::new(option_id, &info.sender, &state).into())
+        .add_event(archived.into())
+        .add_attributes(attributes);
+    res = res.add_messages(send_assets(&info.sender, reward)?);
+    let res = keep_proceeds(deps.storage, &env, option_id, res)?;
+    Ok(payouts::guard_payouts(deps.storage, &env, option_id, res)?)
+}
+
+/// Voids an option whose barrier was crossed, returning the collateral to the creator less
+/// the burn reward for the keeper
+pub fn handle_knock_out(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let barrier = state
+        .barrier
+        .as_ref()
+        .ok_or(ContractError::Unauthorized {})?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)?;
+    if !barrier.crossed(spot) {
+        return Err(ContractError::BarrierNotCrossed {});
+    }
+
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::KnockedOut,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let attributes = option_attributes(option_id, &state);
+    let (reward, collateral) = if info.sender == state.creator {
+        (AssetList::default(), state.collateral_list())
+    } else {
+        split_assets(state.collateral_list(), config.burn_reward_bps)
+    };
+
+    let res = Response::new()
+        .add_messages(collateral_msgs(
+            collateral,
+            state.nft.as_ref(),
+            &state.creator,
+        )?)
+        .add_messages(send_assets(&info.sender, reward)?)
+        .add_attribute("action", "knock_out")
+        .add_event(archived.into())
+        .add_attribute("spot", spot.to_string())
+        .add_attributes(attributes);
+    let res = keep_proceeds(deps.storage, &env, option_id, res)?;
+    Ok(payouts::guard_payouts(deps.storage, &env, option_id, res)?)
+}
+
+/// Executes an expired cash settled option for its owner, who gets the in-the-money part of the
+/// collateral at the current price
+pub(crate) fn exercise_expired(
+    mut deps: DepsMut,
+    env: &Env,
+    keeper: &Addr,
+    option_id: u64,
+    state: State,
+) -> Result<Response, ContractError> {
+    let notional = margin::notional(deps.storage, option_id)?;
+    let archived = archive_option(
+        deps.storage,
+        env,
+        option_id,
+        &state,
+        Some(keeper),
+        Closure::Executed,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let attributes = option_attributes(option_id, &state);
+    let owner = state.owner.clone();
+    let closed = state.clone();
+    let (res, fee, payout) = settle_cash(
+        deps.branch(),
+        env,
+        &config,
+        option_id,
+        state,
+        owner.clone(),
+        None,
+        notional,
+    )?;
+    let exercised = OptionExercised::new(option_id, keeper, &owner, &closed, payout);
+    let res = res
+        .add_messages(protocol_fee_msgs(deps.storage, &config, None, fee)?)
+        .add_event(exercised.into())
+        .add_event(archived.into())
+        .add_attributes(attributes);
+    let res = keep_proceeds(deps.storage, env, option_id, res)?;
+    Ok(payouts::guard_payouts(deps.storage, env, option_id, res)?)
+}
+
+/// Settles an expired binary option at the current oracle price, paying the whole collateral
+/// to the owner if in the money, less the protocol fee, and back to the creator otherwise
+pub fn handle_settle_binary(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let direction = match state.settlement {
+        Settlement::Binary { direction } => direction,
+        _ => return Err(ContractError::Unauthorized {}),
+    };
+    if !state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionNotExpired {
+            expires: state.expires,
+        });
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)?;
+    let strike = Barrier {
+        price: Decimal::from_ratio(
+            priced_amount(&state.counter_offer),
+            state.collateral[0].amount,
+        ),
+        direction,
+    };
+    let in_the_money = strike.crossed(spot);
+
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Settled,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let attributes = option_attributes(option_id, &state);
+
+    let mut res = Response::new()
+        .add_attribute("action", "settle_binary")
+        .add_event(archived.into())
+        .add_attribute("spot", spot.to_string())
+        .add_attribute("in_the_money", in_the_money.to_string())
+        .add_attributes(attributes);
+    if in_the_money {
+        let (fee, payout) = split_bps(state.collateral, config.fee_bps);
+        let fee = (!fee.is_empty()).then_some(Asset::Native(fee));
+        res = res
+            .add_message(BankMsg::Send {
+                to_address: state.owner.into_string(),
+                amount: payout,
+            })
+            .add_messages(protocol_fee_msgs(deps.storage, &config, None, fee)?);
+    } else {
+        res = res.add_message(BankMsg::Send {
+            to_address: state.creator.into_string(),
+            amount: state.collateral,
+        });
+    }
+    let res = keep_proceeds(deps.storage, &env, option_id, res)?;
+    Ok(payouts::guard_payouts(deps.storage, &env, option_id, res)?)
+}
+
+/// Sets an expired option marked to roll over to expire the configured duration from now,
+/// paying the keeper the burn reward out of its collateral
+pub fn handle_roll(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if !state.roll_on_expiry {
+        return Err(ContractError::Unauthorized {});
+    }
+    let config = CONFIG.load(deps.storage)?;
+    if config.roll_duration == 0 {
+        return Err(ContractError::RollingNotEnabled {});
+    }
+    let rollable = match state.style {
+        OptionStyle::American => state.expires,
+        OptionStyle::European => settlement_window_end(&state),
+    };
+    if !rollable.is_expired(&env.block) {
+        return Err(ContractError::OptionNotExpired { expires: rollable });
+    }
+    // rolling pays, it never takes funds
+    if !info.funds.is_empty() {
+        return Err(ContractError::FundsSentWithRoll {});
+    }
+
+    let (reward, collateral) = if info.sender == state.creator {
+        (AssetList::default(), state.collateral_list())
+    } else {
+        split_assets(state.collateral_list(), config.burn_reward_bps)
+    };
+    if collateral.native.is_empty() && collateral.cw20.is_empty() && state.nft.is_none() {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    state.collateral = collateral.native;
+    state.cw20_collateral = collateral.cw20;
+    state.apply_strike();
+    state.expires = match state.expires {
+        Expiration::AtHeight(_) => Expiration::AtHeight(env.block.height + config.roll_duration),
+        _ => Expiration::AtTime(env.block.time.plus_seconds(config.roll_duration)),
+    };
+    // a listing was priced for the old expiry
+    state.price = vec![];
+    save_option(deps.storage, option_id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "roll")
+        .add_event(
+            Event::new("rolled")
+                .add_attribute("option_id", option_id.to_string())
+                .add_attribute("expires", state.expires.to_string()),
+        )
+        .add_attributes(option_attributes(option_id, &state))
+        .add_messages(send_assets(&info.sender, reward)?);
+    Ok(res)
+}
+
+/// Splits `bps` basis points off every coin, returning `(cut, remainder)` without zero coins.
+pub(crate) fn split_bps(coins: Vec<Coin>, bps: u64) -> (Vec<Coin>, Vec<Coin>) {
+    let mut cut = vec![];
+    let mut remainder = vec![];
+    for coin in coins {
+        let part = coin.amount.multiply_ratio(bps, MAX_BPS);
+        if !part.is_zero() {
+            cut.push(Coin::new(part.u128(), &coin.denom));
+        }
+        if coin.amount > part {
+            remainder.push(Coin::new((coin.amount - part).u128(), coin.denom));
+        }
+    }
+    (cut, remainder)
+}
+
+/// Like `split_bps`, for every asset of the list.
+pub(crate) fn split_assets(assets: AssetList, bps: u64) -> (AssetList, AssetList) {
+    let (native_cut, native) = split_bps(assets.native, bps);
+    let mut cut = AssetList {
+        native: native_cut,
+        cw20: vec![],
+    };
+    let mut remainder = AssetList {
+        native,
+        cw20: vec![],
+    };
+    for coin in assets.cw20 {
+        let part = coin.amount.multiply_ratio(bps, MAX_BPS);
+        if !part.is_zero() {
+            cut.cw20.push(Cw20CoinVerified {
+                address: coin.address.clone(),
+                amount: part,
+            });
+        }
+        if coin.amount > part {
+            remainder.cw20.push(Cw20CoinVerified {
+                address: coin.address,
+                amount: coin.amount - part,
+            });
+        }
+    }
+    (cut, remainder)
+}
+
+pub fn handle_propose_termination(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator && info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    TERMINATIONS.save(deps.storage, option_id, &info.sender)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose_termination")
+        .add_attribute("sender", info.sender)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_accept_termination(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+
+    // the proposal must come from a side that is still part of the option,
+    // and be accepted by the other one
+    let counterparty = match TERMINATIONS.may_load(deps.storage, option_id)? {
+        Some(proposer) if proposer == state.creator => &state.owner,
+        Some(proposer) if proposer == state.owner => &state.creator,
+        _ => return Err(ContractError::NoTerminationProposed {}),
+    };
+    if info.sender != *counterparty {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // delete the option, returning the collateral to creator
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Terminated,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let funding = perpetual::settle_funding(deps.storage, &env, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "terminate")
+        .add_event(archived.into())
+        .add_attributes(option_attributes(option_id, &state))
+        .add_messages(funding)
+        .add_messages(collateral_msgs(
+            state.collateral_list(),
+            state.nft.as_ref(),
+            &state.creator,
+        )?))
+}
+
+pub fn handle_propose_extension(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    let later = expires.partial_cmp(&state.expires) == Some(std::cmp::Ordering::Greater);
+    if !later || matches!(expires, Expiration::Never {}) {
+        return Err(ContractError::InvalidExtension {});
+    }
+
+    // only one proposal at a time, the previous fee goes back
+    let mut res = Response::new();
+    if let Some(previous) = EXTENSIONS.may_load(deps.storage, option_id)? {
+        if !previous.fee.is_empty() {
+            res = res.add_message(BankMsg::Send {
+                to_address: previous.creator.into_string(),
+                amount: previous.fee,
+            });
+        }
+    }
+    let extension = Extension {
+        creator: info.sender,
+        expires,
+        fee: info.funds,
+    };
+    save_held(deps.storage, &EXTENSIONS, option_id, &extension)?;
+
+    Ok(res
+        .add_attribute("action", "propose_extension")
+        .add_attribute("new_expires", extension.expires.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_accept_extension(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let extension = EXTENSIONS
+        .may_load(deps.storage, option_id)?
+        .ok_or(ContractError::NoExtensionProposed {})?;
+
+    remove_held(deps.storage, &EXTENSIONS, option_id)?;
+    state.expires = extension.expires;
+    save_option(deps.storage, option_id, &state)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "accept_extension")
+        .add_attributes(option_attributes(option_id, &state));
+    if !extension.fee.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: state.owner.into_string(),
+            amount: extension.fee,
+        });
+    }
+    Ok(res)
+}
+
+/// Also works once the option is gone, so a fee is never stuck.
+pub fn handle_cancel_extension(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let extension = EXTENSIONS
+        .may_load(deps.storage, option_id)?
+        .ok_or(ContractError::NoExtensionProposed {})?;
+    if info.sender != extension.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_held(deps.storage, &EXTENSIONS, option_id)?;
+
+    let mut res = Response::new().add_attribute("action", "cancel_extension");
+    res = match options().may_load(deps.storage, option_id)? {
+        Some(state) => res.add_attributes(option_attributes(option_id, &state)),
+        None => res.add_attribute("option_id", option_id.to_string()),
+    };
+    if !extension.fee.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: extension.creator.into_string(),
+            amount: extension.fee,
+        });
+    }
+    Ok(res)
+}
+
+pub fn handle_offer_buyback(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+    price: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    // ensure escrowing the offered price
+    if info.funds != price {
+        return Err(ContractError::PriceMismatch {
+            offer: info.funds,
+            expected: price,
+        });
+    }
+
+    // only one offer at a time, the previous price goes back
+    let mut res = Response::new();
+    if let Some(previous) = BUYBACKS.may_load(deps.storage, option_id)? {
+        if !previous.price.is_empty() {
+            res = res.add_message(BankMsg::Send {
+                to_address: previous.creator.into_string(),
+                amount: previous.price,
+            });
+        }
+    }
+    let buyback = Buyback {
+        creator: info.sender,
+        price,
+    };
+    save_held(deps.storage, &BUYBACKS, option_id, &buyback)?;
+
+    Ok(res
+        .add_attribute("action", "offer_buyback")
+        .add_attribute("price", fmt_coins(&buyback.price))
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn handle_accept_buyback(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let buyback = BUYBACKS
+        .may_load(deps.storage, option_id)?
+        .ok_or(ContractError::NoBuybackOffered {})?;
+
+    // delete the option, the owner is paid and the creator gets the collateral back
+    remove_held(deps.storage, &BUYBACKS, option_id)?;
+    let payment = (!buyback.price.is_empty()).then(|| Asset::Native(buyback.price.clone()));
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::BoughtBack,
+        payment,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let funding = perpetual::settle_funding(deps.storage, &env, option_id, &state)?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "accept_buyback")
+        .add_event(archived.into())
+        .add_attributes(option_attributes(option_id, &state))
+        .add_messages(funding)
+        .add_messages(collateral_msgs(
+            state.collateral_list(),
+            state.nft.as_ref(),
+            &state.creator,
+        )?);
+    if !buyback.price.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: state.owner.into_string(),
+            amount: buyback.price,
+        });
+    }
+    Ok(res)
+}
+
+/// Also works once the option is gone, so the price is never stuck.
+pub fn handle_cancel_buyback(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let buyback = BUYBACKS
+        .may_load(deps.storage, option_id)?
+        .ok_or(ContractError::NoBuybackOffered {})?;
+    if info.sender != buyback.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_held(deps.storage, &BUYBACKS, option_id)?;
+
+    let mut res = Response::new().add_attribute("action", "cancel_buyback");
+    res = match options().may_load(deps.storage, option_id)? {
+        Some(state) => res.add_attributes(option_attributes(option_id, &state)),
+        None => res.add_attribute("option_id", option_id.to_string()),
+    };
+    if !buyback.price.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: buyback.creator.into_string(),
+            amount: buyback.price,
+        });
+    }
+    Ok(res)
+}
+
+pub fn handle_split(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+    parts: u32,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+
+    // ensure msg.sender is the owner, and the proceeds and funding booked under the id stay with it
+    if info.sender != state.owner
+        || shares::books_proceeds(deps.storage, option_id)
+        || FUNDING.has(deps.storage, option_id)
+        || STAKES.has(deps.storage, option_id)
+        || YIELD_DEPOSITS.has(deps.storage, option_id)
+        || NOTIONALS.has(deps.storage, option_id)
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.nft.is_some() {
+        return Err(ContractError::NftCollateral {});
+    }
+    if !state.cw20_collateral.is_empty() {
+        return Err(ContractError::Cw20Collateral {});
+    }
+    if !state.alt_counter_offers.is_empty() {
+        return Err(ContractError::AltCounterOffers {});
+    }
+
+    // every part needs some of every amount
+    let mut amounts: Vec<Uint128> = state.collateral.iter().map(|c| c.amount).collect();
+    match &state.counter_offer {
+        Asset::Native(coins) => amounts.extend(coins.iter().map(|c| c.amount)),
+        Asset::Cw20(coin) => amounts.push(coin.amount),
+    }
+    if !(2..=MAX_PARTS).contains(&parts) || amounts.iter().any(|a| *a < Uint128::from(parts)) {
+        return Err(ContractError::InvalidParts { parts });
+    }
+
+    remove_option(deps.storage, option_id)?;
+    let closed = OptionClosed {
+        option_id,
+        closure: Closure::Split,
+    };
+    let mut res = Response::new()
+        .add_attribute("action", "split")
+        .add_event(closed.into());
+    let mut option_ids = vec![];
+    for index in 0..parts {
+        let share = |amount: Uint128| split_share(amount, parts, index);
+        let mut part = state.clone();
+        // the listing and approvals were for the whole option
+        part.price = vec![];
+        part.approvals = vec![];
+        for coin in part.collateral.iter_mut() {
+            coin.amount = share(coin.amount);
+        }
+        match &mut part.counter_offer {
+            Asset::Native(coins) => coins.iter_mut().for_each(|c| c.amount = share(c.amount)),
+            Asset::Cw20(coin) => coin.amount = share(coin.amount),
+        }
+        let id = next_id(deps.storage, &OPTION_COUNT)?;
+        save_option(deps.storage, id, &part)?;
+        res = res.add_event(OptionCreated::new(id, &part).into());
+        option_ids.push(id.to_string());
+    }
+
+    Ok(res
+        .add_attribute("option_ids", option_ids.join(","))
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Share of `amount` for the part at `index`, the first part also takes the rounding remainder.
+pub(crate) fn split_share(amount: Uint128, parts: u32, index: u32) -> Uint128 {
+    let share = amount / Uint128::from(parts);
+    if index == 0 {
+        amount - share * Uint128::from(parts - 1)
+    } else {
+        share
+    }
+}
+
+pub fn handle_merge(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_ids: Vec<u64>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut unique = option_ids.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.len() != option_ids.len() || !(2..=MAX_PARTS as usize).contains(&unique.len()) {
+        return Err(ContractError::CannotMerge {});
+    }
+    // proceeds and funding booked under an option id must stay with it
+    if option_ids.iter().any(|id| {
+        shares::books_proceeds(deps.storage, *id)
+            || FUNDING.has(deps.storage, *id)
+            || STAKES.has(deps.storage, *id)
+            || YIELD_DEPOSITS.has(deps.storage, *id)
+            || NOTIONALS.has(deps.storage, *id)
+    }) {
+        return Err(ContractError::CannotMerge {});
+    }
+
+    let mut merged: State = options().load(deps.storage, option_ids[0])?;
+    // ensure msg.sender is the owner, the others must match it
+    if info.sender != merged.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let others = option_ids[1..]
+        .iter()
+        .map(|id| options().load(deps.storage, *id))
+        .collect::<StdResult<Vec<State>>>()?;
+    if !others.iter().all(|state| mergeable(&merged, state)) {
+        return Err(ContractError::CannotMerge {});
+    }
+
+    for (&option_id, state) in option_ids[1..].iter().zip(others) {
+        for (total, coin) in merged.collateral.iter_mut().zip(state.collateral) {
+            total.amount += coin.amount;
+        }
+        match (&mut merged.counter_offer, state.counter_offer) {
+            (Asset::Native(total), Asset::Native(coins)) => {
+                for (total, coin) in total.iter_mut().zip(coins) {
+                    total.amount += coin.amount;
+                }
+            }
+            (Asset::Cw20(total), Asset::Cw20(coin)) => total.amount += coin.amount,
+            _ => unreachable!("mergeable options have the same kind of counter_offer"),
+        }
+        remove_option(deps.storage, option_id)?;
+    }
+
+    // any listing or approval was for a single option
+    merged.price = vec![];
+    merged.approvals = vec![];
+    merged.apply_strike();
+    save_option(deps.storage, option_ids[0], &merged)?;
+
+    // the first option takes on the merged terms, as if written anew
+    let closed = option_ids.iter().map(|&option_id| {
+        Event::from(OptionClosed {
+            option_id,
+            closure: Closure::Merged,
+        })
+    });
+    Ok(Response::new()
+        .add_attribute("action", "merge")
+        .add_events(closed)
+        .add_event(OptionCreated::new(option_ids[0], &merged).into())
+        .add_attributes(option_attributes(option_ids[0], &merged)))
+}
+
+/// Creator can add the funds sent to the collateral of a live option
+pub fn handle_add_collateral(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    // an NFT is the whole collateral of its option, and pays out with nothing else
+    if state.nft.is_some() {
+        return Err(ContractError::NftCollateral {});
+    }
+    if info.funds.is_empty() || info.funds.iter().any(|c| c.amount.is_zero()) {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    let config = CONFIG.load(deps.storage)?;
+    if !config.allowed_denoms.is_empty() {
+        if let Some(coin) = info
+            .funds
+            .iter()
+            .find(|c| !config.allowed_denoms.contains(&c.denom))
+        {
+            return Err(ContractError::DenomNotAllowed {
+                denom: coin.denom.clone(),
+            });
+        }
+    }
+
+    let mut collateral = Coins::try_from(state.collateral).map_err(StdError::from)?;
+    for coin in info.funds.iter().cloned() {
+        collateral.add(coin)?;
+    }
+    state.collateral = collateral.into_vec();
+    // cash and binary settlement can only value a single collateral denom, as can a barrier
+    if state.settlement != Settlement::Physical || state.barrier.is_some() {
+        price_pair(&state)?;
+    }
+    // so can the strike
+    if state.strike.is_some() && state.collateral.len() != 1 {
+        return Err(ContractError::InvalidStrike {});
+    }
+    state.apply_strike();
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_collateral")
+        .add_attribute("added", fmt_coins(&info.funds))
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Creator can reduce the collateral of an option nobody else holds yet.
+/// Some collateral must remain, to take all of it back the option is cancelled.
+pub fn handle_withdraw_collateral(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    amount: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator || state.owner != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    if amount.is_empty() || amount.iter().any(|c| c.amount.is_zero()) {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    staking::assert_liquid(deps.storage, &env, option_id)?;
+    yield_strategy::assert_withdrawn(deps.storage, option_id)?;
+
+    let mut collateral = Coins::try_from(state.collateral).map_err(StdError::from)?;
+    for coin in amount.iter().cloned() {
+        collateral
+            .sub(coin)
+            .map_err(|_| ContractError::InvalidCollateral {})?;
+    }
+    if collateral.is_empty() {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    state.collateral = collateral.into_vec();
+    // a listing was priced for the old collateral
+    state.price = vec![];
+    state.apply_strike();
+    margin::assert_margin(deps.as_ref(), option_id, &state)?;
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: state.creator.to_string(),
+            amount: amount.clone(),
+        })
+        .add_attribute("action", "withdraw_collateral")
+        .add_attribute("withdrawn", fmt_coins(&amount))
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Creator can close an option nobody else holds yet, before or after expiry
+pub fn handle_cancel(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator || state.owner != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // delete the option, returning the collateral to creator
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Cancelled,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let funding = perpetual::settle_funding(deps.storage, &env, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel")
+        .add_event(archived.into())
+        .add_attributes(option_attributes(option_id, &state))
+        .add_messages(funding)
+        .add_messages(collateral_msgs(
+            state.collateral_list(),
+            state.nft.as_ref(),
+            &state.creator,
+        )?))
+}
+
+/// Options can be merged when they only differ in amounts.
+fn mergeable(a: &State, b: &State) -> bool {
+    let denoms = |coins: &[Coin]| coins.iter().map(|c| c.denom.clone()).collect::<Vec<_>>();
+    let same_counter_offer = match (&a.counter_offer, &b.counter_offer) {
+        (Asset::Native(x), Asset::Native(y)) => denoms(x) == denoms(y),
+        (Asset::Cw20(x), Asset::Cw20(y)) => x.address == y.address,
+        _ => false,
+    };
+    a.owner == b.owner
+        && a.creator == b.creator
+        && a.expires == b.expires
+        && a.settlement == b.settlement
+        && a.style == b.style
+        && a.royalty_bps == b.royalty_bps
+        && a.roll_on_expiry == b.roll_on_expiry
+        && a.barrier == b.barrier
+        && a.settlement_window == b.settlement_window
+        && a.exercisable_from == b.exercisable_from
+        && a.allowed_taker == b.allowed_taker
+        && a.nft.is_none()
+        && b.nft.is_none()
+        && a.cw20_collateral.is_empty()
+        && b.cw20_collateral.is_empty()
+        && a.alt_counter_offers.is_empty()
+        && a.strike == b.strike
+        && b.alt_counter_offers.is_empty()
+        && denoms(&a.collateral) == denoms(&b.collateral)
+        && same_counter_offer
+}
+
+pub fn handle_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    msg: UpdateConfigMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin || (config.dao.is_some() && governance::governed(&msg)) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let attributes = update_config(deps, &env, config, msg)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_config")
+        .add_attributes(attributes))
+}
+
+/// Applies `msg` to the config, queueing the changes behind the timelock while there is one.
+/// Returns the attributes describing what was queued.
+pub(crate) fn update_config(
+    deps: DepsMut,
+    env: &Env,
+    mut config: Config,
+    mut msg: UpdateConfigMsg,
+) -> Result<Vec<Attribute>, ContractError> {
+    let mut attributes = vec![];
+    if config.config_timelock > 0 {
+        if let Some(changes) = timelock::take_timelocked(&mut msg) {
+            attributes = timelock::queue(deps.storage, deps.api, env, &config, changes)?;
+        }
+    }
+    apply_config_update(deps.api, &mut config, msg)?;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(attributes)
+}
+
+/// Applies the set fields of `msg` to `config`, validating them
+pub(crate) fn apply_config_update(
+    api: &dyn Api,
+    config: &mut Config,
+    msg: UpdateConfigMsg,
+) -> Result<(), ContractError> {
+    if let Some(admin) = msg.admin {
+        config.admin = api.addr_validate(&admin)?;
+    }
+    if let Some(guardian) = msg.guardian {
+        config.guardian = api.addr_validate(&guardian)?;
+    }
+    if let Some(bps) = msg.burn_reward_bps {
+        config.burn_reward_bps = validate_bps(bps)?;
+    }
+    if let Some(bps) = msg.fee_bps {
+        config.fee_bps = validate_bps(bps)?;
+    }
+    if let Some(fee_collector) = msg.fee_collector {
+        config.fee_collector = api.addr_validate(&fee_collector)?;
+    }
+    if let Some(allowed_denoms) = msg.allowed_denoms {
+        config.allowed_denoms = validate_denoms(allowed_denoms)?;
+    }
+    if let Some(oracle) = msg.oracle {
+        config.oracle = Some(api.addr_validate(&oracle)?);
+    }
+    if let Some(retention) = msg.archive_retention {
+        config.archive_retention = retention;
+    }
+    if let Some(tokenfactory) = msg.tokenfactory {
+        config.tokenfactory = tokenfactory;
+    }
+    if let Some(code_id) = msg.share_code_id {
+        config.share_code_id = Some(code_id);
+    }
+    if let Some(fee) = msg.transfer_fee {
+        // funds arrive sorted by denom, so the fee is kept that way to compare against them
+        config.transfer_fee = TransferFee {
+            flat: Coins::try_from(fee.flat)
+                .map_err(StdError::from)?
+                .into_vec(),
+            bps: validate_bps(fee.bps)?,
+        };
+    }
+    if let Some(bps) = msg.referral_bps {
+        config.referral_bps = validate_bps(bps)?;
+    }
+    if let Some(duration) = msg.roll_duration {
+        config.roll_duration = duration;
+    }
+    if let Some(grace) = msg.burn_grace_period {
+        config.burn_grace_period = grace;
+    }
+    if let Some(reward) = msg.crank_reward {
+        config.crank_reward = Coins::try_from(reward).map_err(StdError::from)?.into_vec();
+    }
+    if let Some(period) = msg.unbonding_period {
+        config.unbonding_period = period;
+    }
+    if let Some(strategy) = msg.yield_strategy {
+        config.yield_strategy = Some(api.addr_validate(&strategy)?);
+    }
+    if let Some(bps) = msg.maintenance_margin_bps {
+        config.maintenance_margin_bps = validate_bps(bps)?;
+    }
+    if let Some(bps) = msg.insurance_bps {
+        config.insurance_bps = validate_bps(bps)?;
+    }
+    if let Some(bps) = msg.max_price_deviation_bps {
+        config.max_price_deviation_bps = bps;
+    }
+    if let Some(cooldown) = msg.breaker_cooldown {
+        config.breaker_cooldown = cooldown;
+    }
+    if let Some(timelock) = msg.config_timelock {
+        if timelock > MAX_CONFIG_TIMELOCK {
+            return Err(ContractError::InvalidTimelock {
+                max: MAX_CONFIG_TIMELOCK,
+            });
+        }
+        config.config_timelock = timelock;
+    }
+    if let Some(allowlist) = msg.allowlist {
+        config.allowlist = Some(api.addr_validate(&allowlist)?);
+    }
+    if let Some(min) = msg.min_collateral {
+        config.min_collateral = Some(min).filter(|min| !min.amount.is_zero());
+    }
+    if let Some(max) = msg.max_open_options {
+        config.max_open_options = max;
+    }
+    if let Some(exempt) = msg.fee_exempt {
+        config.fee_exempt = exempt
+            .iter()
+            .map(|addr| api.addr_validate(addr))
+            .collect::<StdResult<_>>()?;
+    }
+    Ok(())
+}
+
+pub fn handle_set_paused(
+    deps: DepsMut,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.guardian {
+        return Err(ContractError::Unauthorized {});
+    }
+    set_paused(deps, paused)
+}
+
+fn set_paused(deps: DepsMut, paused: bool) -> Result<Response, ContractError> {
+    CONFIG.update(deps.storage, |mut config| -> StdResult<_> {
+        config.paused = paused;
+        Ok(config)
+    })?;
+
+    let action = if paused { "pause" } else { "unpause" };
+    Ok(Response::new().add_attribute("action", action))
+}
+
+pub(crate) fn assert_not_paused(storage: &dyn Storage) -> Result<(), ContractError> {
+    if CONFIG.load(storage)?.paused {
+        return Err(ContractError::Paused {});
+    }
+    Ok(())
+}
+
+/// Lets the chain's governance intervene in emergencies, without the admin or guardian keys.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::ForceSettle { option_id } => sudo_force_settle(deps, env, option_id),
+        SudoMsg::SetPaused { paused } => set_paused(deps, paused),
+    }
+}
+
+/// Works while paused, as a pause is usually what leaves options stuck.
+fn sudo_force_settle(deps: DepsMut, env: Env, option_id: u64) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+
+    // delete the option, returning the collateral to creator
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        None,
+        Closure::ForceSettled,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let funding = perpetual::settle_funding(deps.storage, &env, option_id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "force_settle")
+        .add_event(archived.into())
+        .add_attributes(option_attributes(option_id, &state))
+        .add_messages(funding)
+        .add_messages(collateral_msgs(
+            state.collateral_list(),
+            state.nft.as_ref(),
+            &state.creator,
+        )?);
+    let res = keep_proceeds(deps.storage, &env, option_id, res)?;
+    Ok(payouts::guard_payouts(deps.storage, &env, option_id, res)?)
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        factory::SPAWN_REPLY_ID => factory::handle_spawn_reply(deps, msg),
+        shares::SHARES_REPLY_ID => shares::handle_shares_reply(deps, msg),
+        yield_strategy::YIELD_DEPOSIT_REPLY_ID => yield_strategy::handle_deposit_reply(deps, msg),
+        yield_strategy::YIELD_WITHDRAWAL_REPLY_ID => {
+            yield_strategy::handle_withdrawal_reply(deps, env, msg)
+        }
+        id if id >= payouts::PAYOUT_REPLY_ID => payouts::handle_payout_reply(deps, msg),
+        id => Err(StdError::generic_err(format!("unknown reply id: {}", id)).into()),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    // only allow upgrades from this same contract, storage layout changes go here
+    let version = get_contract_version(deps.storage)?;
+    if version.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: version.contract,
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // deployments from before the global config pay no rewards or fees,
+    // and are administered by whoever can migrate the contract
+    if CONFIG.may_load(deps.storage)?.is_none() {
+        let info = deps
+            .querier
+            .query_wasm_contract_info(env.contract.address.as_str())?;
+        let admin = info
+            .admin
+            .map(Addr::unchecked)
+            .unwrap_or(env.contract.address);
+        let config = Config::new(admin);
+        CONFIG.save(deps.storage, &config)?;
+    }
+
+    // the stats and tracked funds are rebuilt from the entries stored, as earlier deployments
+    // either had none or left cw20 collateral out of the totals. That takes a `RebuildStats`
+    // per page.
+    STATS.remove(deps.storage);
+    TRACKED.remove(deps.storage);
+    STATS_REBUILD.save(deps.storage, &(Ledger::Options, 0))?;
+
+    Ok(Response::default())
+}
+
+/// Anyone can count up to `limit` more options into the stats being rebuilt after a migration,
+/// until every stored option is.
+pub fn handle_rebuild_stats(deps: DepsMut, limit: u32) -> Result<Response, ContractError> {
+    let (mut ledger, mut last) = STATS_REBUILD
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NothingToRebuild {})?;
+    let mut left = limit.clamp(1, MAX_LIMIT);
+    let done = loop {
+        let count = ledger.counter().may_load(deps.storage)?.unwrap_or_default();
+        while left > 0 && last < count {
+            last += 1;
+            left -= 1;
+            recovery::count_entry(deps.storage, ledger, last)?;
+        }
+        if last < count {
+            break false;
+        }
+        match ledger.next() {
+            Some(next) => (ledger, last) = (next, 0),
+            None => break true,
+        }
+    };
+    if done {
+        STATS_REBUILD.remove(deps.storage);
+    } else {
+        STATS_REBUILD.save(deps.storage, &(ledger, last))?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "rebuild_stats")
+        .add_attribute("ledger", ledger.as_str())
+        .add_attribute("last_id", last.to_string())
+        .add_attribute("done", done.to_string()))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::AdminConfig {} => to_json_binary(&query_admin_config(deps)?),
+        QueryMsg::Stats {} => to_json_binary(&query_stats(deps)?),
+        QueryMsg::Archived { option_id } => to_json_binary(&query_archived(deps, option_id)?),
+        QueryMsg::Auction { option_id } => {
+            to_json_binary(&auction::query_auction(deps, option_id)?)
+        }
+        QueryMsg::Orders {
+            option_id,
+            start_after,
+            limit,
+        } => to_json_binary(&orderbook::query_orders(
+            deps,
+            option_id,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Escrows {
+            option_id,
+            start_after,
+            limit,
+        } => to_json_binary(&escrow::query_escrows(deps, option_id, start_after, limit)?),
+        QueryMsg::Quotes {
+            request_id,
+            start_after,
+            limit,
+        } => to_json_binary(&rfq::query_quotes(deps, request_id, start_after, limit)?),
+        QueryMsg::ReferralStats { address } => {
+            to_json_binary(&query_referral_stats(deps, address)?)
+        }
+        QueryMsg::CurrentPrice { option_id } => {
+            to_json_binary(&auction::query_current_price(deps, env, option_id)?)
+        }
+        QueryMsg::Shares { option_id } => to_json_binary(&shares::query_shares(deps, option_id)?),
+        QueryMsg::WritingPool { option_id } => {
+            to_json_binary(&cowrite::query_writing_pool(deps, option_id)?)
+        }
+        QueryMsg::Contribution { option_id, writer } => {
+            to_json_binary(&cowrite::query_contribution(deps, option_id, writer)?)
+        }
+        QueryMsg::Series { id } => to_json_binary(&series::query_series(deps, id)?),
+        QueryMsg::PriceSamples { option_id } => {
+            to_json_binary(&twap::query_price_samples(deps, option_id)?)
+        }
+        QueryMsg::Funding { option_id } => {
+            to_json_binary(&perpetual::query_funding(deps, env, option_id)?)
+        }
+        QueryMsg::Strategy { strategy_id } => {
+            to_json_binary(&strategy::query_strategy(deps, strategy_id)?)
+        }
+        QueryMsg::Stake { option_id } => {
+            to_json_binary(&staking::query_stake(deps, env, option_id)?)
+        }
+        QueryMsg::YieldDeposit { option_id } => {
+            to_json_binary(&yield_strategy::query_yield_deposit(deps, option_id)?)
+        }
+        QueryMsg::Margin { option_id } => to_json_binary(&margin::query_margin(deps, option_id)?),
+        QueryMsg::LastPrice { option_id } => {
+            to_json_binary(&circuit_breaker::query_last_price(deps, option_id)?)
+        }
+        QueryMsg::InsuranceFund {} => to_json_binary(&insurance::query_insurance_fund(deps)?),
+        QueryMsg::FailedPayouts {} => to_json_binary(&payouts::query_failed_payouts(deps)?),
+        QueryMsg::RawOption { option_id } => {
+            to_json_binary(&raw::query_raw_option(deps, option_id)?)
+        }
+        QueryMsg::Reconcile {} => to_json_binary(&recovery::query_reconcile(deps, env)?),
+        QueryMsg::Blacklisted { address } => {
+            to_json_binary(&blacklist::query_blacklisted(deps, address)?)
+        }
+        QueryMsg::QueuedConfigs {} => to_json_binary(&timelock::query_queued_configs(deps)?),
+        QueryMsg::CrankPool {} => to_json_binary(&crank::query_crank_pool(deps)?),
+        QueryMsg::Vault { vault_id } => to_json_binary(&vault::query_vault(deps, vault_id)?),
+        QueryMsg::VaultPosition {
+            vault_id,
+            depositor,
+        } => to_json_binary(&vault::query_vault_position(deps, vault_id, depositor)?),
+        QueryMsg::Voucher {
+            channel_id,
+            option_id,
+        } => to_json_binary(&query_voucher(deps, channel_id, option_id)?),
+        QueryMsg::Config { option_id } => to_json_binary(&query_config(deps, option_id)?),
+        QueryMsg::OwnerOf {
+            token_id,
+            include_expired,
+        } => to_json_binary(&query_owner_of(deps, env, token_id, include_expired)?),
+        QueryMsg::TransferOffer { option_id } => {
+            to_json_binary(&TRANSFER_OFFERS.may_load(deps.storage, option_id)?)
+        }
+        QueryMsg::Extension { option_id } => {
+            to_json_binary(&EXTENSIONS.may_load(deps.storage, option_id)?)
+        }
+        QueryMsg::Buyback { option_id } => {
+            to_json_binary(&BUYBACKS.may_load(deps.storage, option_id)?)
+        }
+        QueryMsg::CanExecute {
+            option_id,
+            sender,
+            funds,
+        } => to_json_binary(&query_can_execute(deps, env, option_id, sender, funds)?),
+        QueryMsg::IntrinsicValue { option_id } => {
+            to_json_binary(&query_intrinsic_value(deps, option_id)?)
+        }
+        QueryMsg::FairValue {
+            option_id,
+            volatility,
+        } => to_json_binary(&query_fair_value(deps, env, option_id, volatility)?),
+        QueryMsg::Approvals {
+            token_id,
+            include_expired,
+        } => to_json_binary(&query_approvals(deps, env, token_id, include_expired)?),
+        QueryMsg::AllOperators {
+            owner,
+            include_expired,
+            start_after,
+            limit,
+        } => to_json_binary(&query_all_operators(
+            deps,
+            env,
+            owner,
+            include_expired,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::NftInfo { token_id } => to_json_binary(&query_nft_info(deps, token_id)?),
+        QueryMsg::OptionsByOwner {
+            owner,
+            start_after,
+            limit,
+        } => to_json_binary(&query_options_by_owner(deps, owner, start_after, limit)?),
+        QueryMsg::ExpiringBetween { start, end, limit } => {
+            to_json_binary(&query_expiring_between(deps, start, end, limit)?)
+        }
+        QueryMsg::OptionsByCreator {
+            creator,
+            start_after,
+            limit,
+        } => to_json_binary(&query_options_by_creator(
+            deps,
+            creator,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::InstancesByCreator {
+            creator,
+            start_after,
+            limit,
+        } => to_json_binary(&factory::query_instances_by_creator(
+            deps,
+            creator,
+            start_after,
+            limit,
+        )?),
+    }
+}
+
+fn query_admin_config(deps: Deps) -> StdResult<AdminConfigResponse> {
+    CONFIG.load(deps.storage)
+}
+
+fn query_referral_stats(deps: Deps, address: String) -> StdResult<ReferralStatsResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    Ok(REFERRALS
+        .may_load(deps.storage, &address)?
+        .unwrap_or_default())
+}
+
+fn query_stats(deps: Deps) -> StdResult<StatsResponse> {
+    Ok(STATS.may_load(deps.storage)?.unwrap_or_default())
+}
+
+fn query_archived(deps: Deps, option_id: u64) -> StdResult<ArchivedResponse> {
+    archive().may_load(deps.storage, option_id)
+}
+
+fn query_voucher(deps: Deps, channel_id: String, option_id: u64) -> StdResult<VoucherResponse> {
+    VOUCHERS.may_load(deps.storage, (&channel_id, option_id))
+}
+
+fn query_config(deps: Deps, option_id: u64) -> StdResult<ConfigResponse> {
+    let state = options().load(deps.storage, option_id)?;
+    let denoms = option_denoms(deps.storage, &state)?;
+    Ok(ConfigResponse { state, denoms })
+}
+
+fn query_owner_of(
+    deps: Deps,
+    env: Env,
+    token_id: String,
+    include_expired: Option<bool>,
+) -> StdResult<OwnerOfResponse> {
+    let state = options().load(deps.storage, parse_token_id(&token_id)?)?;
+    Ok(OwnerOfResponse {
+        owner: state.owner.to_string(),
+        approvals: live_approvals(&env, state, include_expired),
+    })
+}
+
+fn query_can_execute(
+    deps: Deps,
+    env: Env,
+    option_id: u64,
+    sender: String,
+    funds: Vec<Coin>,
+) -> StdResult<CanExecuteResponse> {
+    let mut state: State = options().load(deps.storage, option_id)?;
+    state.apply_strike();
+    let sender = deps.api.addr_validate(&sender)?;
+    let recipient = holder(&state, &sender);
+    let check = check_exercisable(deps, &env, &sender, option_id, &state);
+    if check.is_ok() {
+        if let Some(address) = blacklist::listed(deps.storage, &[&sender, &recipient]) {
+            return Ok(CanExecuteResponse::Blocked {
+                address: address.clone(),
+            });
+        }
+    }
+    let check = check
+        .and_then(|_| take_token(&state, Asset::Native(funds)))
+        .and_then(|payment| check_payment(&mut state, payment));
+    Ok(match check {
+        Ok(_) => CanExecuteResponse::Ok {},
+        Err(ContractError::Paused {}) => CanExecuteResponse::Paused {},
+        Err(ContractError::Unauthorized {}) => CanExecuteResponse::NotOwner {},
+        Err(ContractError::OptionTokenRequired { .. }) => CanExecuteResponse::NotOwner {},
+        Err(ContractError::OptionExpired { expired }) => CanExecuteResponse::Expired { expired },
+        Err(ContractError::OptionNotExpired { expires }) => {
+            CanExecuteResponse::NotYetExercisable { expires }
+        }
+        Err(ContractError::NotYetExercisable { from }) => {
+            CanExecuteResponse::NotYetExercisable { expires: from }
+        }
+        Err(ContractError::CounterOfferMismatch { expected, .. }) => {
+            CanExecuteResponse::WrongFunds { expected }
+        }
+        Err(ContractError::FundsSentWithCashSettlement {}) => CanExecuteResponse::WrongFunds {
+            expected: Asset::Native(vec![]),
+        },
+        Err(err) => CanExecuteResponse::Other {
+            reason: err.to_string(),
+        },
+    })
+}
+
+fn query_intrinsic_value(deps: Deps, option_id: u64) -> StdResult<IntrinsicValueResponse> {
+    let state = options().load(deps.storage, option_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    let collateral = state.collateral[0].amount;
+    let counter_offer = priced_amount(&state.counter_offer);
+    let payoff = collateral.mul_floor(spot).saturating_sub(counter_offer);
+    Ok(IntrinsicValueResponse {
+        spot,
+        strike: Decimal::from_ratio(counter_offer, collateral),
+        payoff,
+        in_the_money: !payoff.is_zero(),
+    })
+}
+
+fn query_fair_value(
+    deps: Deps,
+    env: Env,
+    option_id: u64,
+    volatility: Decimal,
+) -> StdResult<FairValueResponse> {
+    let state = options().load(deps.storage, option_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    let collateral = state.collateral[0].amount;
+    let strike = Decimal::from_ratio(priced_amount(&state.counter_offer), collateral);
+    let years = pricing::years_to_expiry(&state.expires, &env.block);
+    let price_per_unit = pricing::call_value(spot, strike, volatility, years);
+    Ok(FairValueResponse {
+        spot,
+        strike,
+        years,
+        price_per_unit,
+        fair_value: pricing::option_value(collateral, price_per_unit),
+    })
+}
+
+fn query_approvals(
+    deps: Deps,
+    env: Env,
+    token_id: String,
+    include_expired: Option<bool>,
+) -> StdResult<ApprovalsResponse> {
+    let state = options().load(deps.storage, parse_token_id(&token_id)?)?;
+    Ok(ApprovalsResponse {
+        approvals: live_approvals(&env, state, include_expired),
+    })
+}
+
+fn live_approvals(env: &Env, state: State, include_expired: Option<bool>) -> Vec<Approval> {
+    let include_expired = include_expired.unwrap_or(false);
+    state
+        .approvals
+        .into_iter()
+        .filter(|a| include_expired || !a.expires.is_expired(&env.block))
+        .collect()
+}
+
+fn query_all_operators(
+    deps: Deps,
+    env: Env,
+    owner: String,
+    include_expired: Option<bool>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OperatorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let include_expired = include_expired.unwrap_or(false);
+    let owner = deps.api.addr_validate(&owner)?;
+    let start_after = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+    let operators = OPERATORS
+        .prefix(&owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .filter(|item| match item {
+            Ok((_, expires)) => include_expired || !expires.is_expired(&env.block),
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|item| {
+            item.map(|(spender, expires)| Approval {
+                spender: spender.into_string(),
+                expires,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(OperatorsResponse { operators })
+}
+
+fn query_nft_info(deps: Deps, token_id: String) -> StdResult<NftInfoResponse<ConfigResponse>> {
+    Ok(NftInfoResponse {
+        token_uri: None,
+        extension: query_config(deps, parse_token_id(&token_id)?)?,
+    })
+}
+
+fn query_options_by_owner(
+    deps: Deps,
+    owner: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let start = start_after.map(Bound::exclusive);
+    let iter = options()
+        .idx
+        .owner
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending);
+    options_page(deps.storage, iter, limit)
+}
+
+fn query_options_by_creator(
+    deps: Deps,
+    creator: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let creator = deps.api.addr_validate(&creator)?;
+    let start = start_after.map(Bound::exclusive);
+    let iter =
+        options()
+            .idx
+            .creator
+            .prefix(creator)
+            .range(deps.storage, start, None, Order::Ascending);
+    options_page(deps.storage, iter, limit)
+}
+
+fn query_expiring_between(
+    deps: Deps,
+    start: Expiration,
+    end: Expiration,
+    limit: Option<u32>,
+) -> StdResult<ExpiringResponse> {
+    let (start, end) = (expiration_key(&start), expiration_key(&end));
+    if start.0 != end.0 || start.0 > 1 {
+        return Err(StdError::generic_err(
+            "start and end must both be heights or both be times",
+        ));
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let options = options()
+        .idx
+        .expires
+        .range(
+            deps.storage,
+            Some(Bound::inclusive((start, u64::MIN))),
+            Some(Bound::inclusive((end, u64::MAX))),
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            item.map(|(option_id, option)| ExpiringOption {
+                option_id,
+                creator: option.creator,
+                expires: option.expires,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ExpiringResponse { options })
+}
+
+fn options_page(
+    storage: &dyn Storage,
+    iter: impl Iterator<Item = StdResult<(u64, State)>>,
+    limit: Option<u32>,
+) -> StdResult<OptionsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let options = iter
+        .take(limit)
+        .map(|item| {
+            let (option_id, option) = item?;
+            let denoms = option_denoms(storage, &option)?;
+            Ok(OptionInfo {
+                option_id,
+                option,
+                denoms,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(OptionsResponse { options })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{update_stats, BarrierDirection, Stats};
+    use crate::testing::{instantiate_msg, mock_oracle, setup};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{attr, coin, coins, Uint128};
+
+    #[test]
+    fn proper_initialization() {
+        let msg = instantiate_msg();
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let env = mock_env();
+
+        // we can jut call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert_eq!(
+            res.attributes,
+            [
+                attr("action", "instantiate"),
+                attr("option_id", "1"),
+                attr("creator", "creator"),
+                attr("owner", "creator"),
+                attr("collateral", "1BTC"),
+                attr("counter_offer", "40ETH"),
+                attr("expires", "expiration height: 100000"),
+            ]
+        );
+
+        // It worked, let's query the state
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(Expiration::AtHeight(100_000), res.state.expires);
+        assert_eq!("creator", res.state.owner.as_str());
+        assert_eq!("creator", res.state.creator.as_str());
+        assert_eq!(coins(1, "BTC"), res.state.collateral);
+        assert_eq!(Asset::Native(coins(40, "ETH")), res.state.counter_offer);
+    }
+
+    #[test]
+    fn create_invalid_amounts() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+
+        // no collateral
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+
+        // zero collateral
+        let info = mock_info("creator", &[coin(1, "BTC"), coin(0, "ATOM")]);
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+
+        // nothing asked in return
+        for counter_offer in [
+            Asset::Native(vec![]),
+            Asset::Native(coins(0, "ETH")),
+            Asset::Cw20(Cw20CoinVerified {
+                address: Addr::unchecked("token"),
+                amount: Uint128::zero(),
+            }),
+        ] {
+            let info = mock_info("creator", &coins(1, "BTC"));
+            let msg = InstantiateMsg {
+                counter_offer,
+                ..msg.clone()
+            };
+            let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidCounterOffer {}));
+        }
+    }
+
+    #[test]
+    fn ibc_denom_names() {
+        let mut deps = mock_dependencies();
+        let atom = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(40, atom)),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // a malformed voucher could never be paid
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ibc/atom")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::InvalidDenom { denom } => assert_eq!("ibc/atom", denom),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // only the admin can name vouchers
+        let msg = ExecuteMsg::RegisterDenomTrace {
+            path: "transfer/channel-0".to_string(),
+            base_denom: "uatom".to_string(),
+        };
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 1 }).unwrap();
+        let res: ConfigResponse = from_json(res).unwrap();
+        assert_eq!("creator", res.state.owner.as_str());
+        let names: Vec<_> = res.denoms.iter().map(|d| d.display.as_str()).collect();
+        assert_eq!(vec!["BTC", "uatom"], names);
+        assert_eq!(atom, res.denoms[1].denom);
+    }
+
+    #[test]
+    fn tokenized_option() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            tokenfactory: true,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let type_urls: Vec<_> = res
+            .messages
+            .iter()
+            .map(|m| match &m.msg {
+                CosmosMsg::Stargate { type_url, .. } => type_url.as_str(),
+                msg => panic!("unexpected message: {:?}", msg),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "/osmosis.tokenfactory.v1beta1.MsgCreateDenom",
+                "/osmosis.tokenfactory.v1beta1.MsgMint"
+            ],
+            type_urls
+        );
+
+        // the contract holds the option, the token moves through the bank
+        let denom = "factory/cosmos2contract/option1";
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(mock_env().contract.address, res.state.owner);
+        assert_eq!(Some(denom.to_string()), res.state.denom);
+        let msg = ExecuteMsg::TransferNft {
+            recipient: "someone".to_string(),
+            token_id: "1".to_string(),
+        };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // executing takes the token
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("holder", &coins(40, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OptionTokenRequired { .. }));
+
+        let funds = vec![coin(40, "ETH"), coin(1, denom)];
+        let info = mock_info("holder", &funds);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "holder".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert!(matches!(
+            &res.messages.last().unwrap().msg,
+            CosmosMsg::Stargate { type_url, .. } if type_url.ends_with("MsgBurn")
+        ));
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn allowed_denoms() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            allowed_denoms: vec!["BTC".to_string(), "ETH".to_string(), "token".to_string()],
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let create = |collateral: &[Coin], counter_offer: Asset| {
+            let msg = ExecuteMsg::Create(CreateMsg {
+                counter_offer,
+                expires: Expiration::AtHeight(100_000),
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            });
+            (mock_info("creator", collateral), msg)
+        };
+
+        // collateral outside the list
+        let (info, msg) = create(&coins(1, "ATOM"), Asset::Native(coins(40, "ETH")));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::DenomNotAllowed { denom } => assert_eq!("ATOM", denom),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // counter_offer outside the list
+        let (info, msg) = create(&coins(1, "BTC"), Asset::Native(coins(40, "DOGE")));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::DenomNotAllowed { denom } => assert_eq!("DOGE", denom),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // cw20 tokens are listed by address
+        let token = Asset::Cw20(Cw20CoinVerified {
+            address: Addr::unchecked("token"),
+            amount: Uint128::new(40),
+        });
+        let (info, msg) = create(&coins(1, "BTC"), token);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    #[test]
+    fn transfer() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+        let env = mock_env();
+        let info = mock_info("creator", &coins(1, "BTC"));
+
+        let res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // random cannot transfer
+        let info = mock_info("anyone", &[]);
+        let err = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "anyone".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // recipient must be a valid address
+        let info = mock_info("creator", &[]);
+        let err = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "Someone".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::Std(StdError::GenericErr { .. })
+        ));
+
+        // owner can transfer
+        let info = mock_info("creator", &[]);
+        let res = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "someone".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(res.attributes.len(), 10);
+        assert_eq!(res.attributes[0], attr("action", "transfer_nft"));
+        // along with the terms after the transfer
+        assert_eq!(
+            res.attributes[4..],
+            [
+                attr("option_id", "1"),
+                attr("creator", "creator"),
+                attr("owner", "someone"),
+                attr("collateral", "1BTC"),
+                attr("counter_offer", "40ETH"),
+                attr("expires", "expiration height: 100000"),
+            ]
+        );
+
+        // visible through the cw721 owner query
+        let res = query_owner_of(deps.as_ref(), mock_env(), "1".to_string(), None).unwrap();
+        assert_eq!("someone", res.owner);
+
+        // check updated properly
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(Expiration::AtHeight(100_000), res.state.expires);
+        assert_eq!("someone", res.state.owner.as_str());
+        assert_eq!("creator", res.state.creator.as_str());
+        assert_eq!(coins(1, "BTC"), res.state.collateral);
+        assert_eq!(Asset::Native(coins(40, "ETH")), res.state.counter_offer);
+    }
+
+    #[test]
+    fn send_nft() {
+        let mut deps = setup();
+
+        // token ids must be option ids
+        let info = mock_info("creator", &[]);
+        let msg = ExecuteMsg::TransferNft {
+            recipient: "someone".to_string(),
+            token_id: "first".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+
+        // the receiving contract must be a valid address
+        let info = mock_info("creator", &[]);
+        let err = handle_send_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "".to_string(),
+            "1".to_string(),
+            Binary::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+
+        // sending notifies the receiving contract
+        let info = mock_info("creator", &[]);
+        let payload = Binary::from(b"vault".to_vec());
+        let res = handle_send_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "vault".to_string(),
+            "1".to_string(),
+            payload.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            Cw721ReceiveMsg {
+                sender: "creator".to_string(),
+                token_id: "1".to_string(),
+                msg: payload,
+            }
+            .into_cosmos_msg("vault")
+            .unwrap()
+        );
+
+        // nft info carries the option terms
+        let res = query_nft_info(deps.as_ref(), "1".to_string()).unwrap();
+        assert_eq!("vault", res.extension.state.owner.as_str());
+        assert_eq!(coins(1, "BTC"), res.extension.state.collateral);
+    }
+
+    #[test]
+    fn approvals() {
+        let mut deps = setup();
+
+        // a stranger cannot approve or transfer
+        let info = mock_info("market", &[]);
+        let err = handle_approve(
+            deps.as_mut(),
+            info.clone(),
+            mock_env(),
+            "market".to_string(),
+            "1".to_string(),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "market".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // approvals cannot start out expired
+        let info = mock_info("creator", &[]);
+        let expires = Some(Expiration::AtHeight(1));
+        let err = handle_approve(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "market".to_string(),
+            "1".to_string(),
+            expires,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ApprovalExpired {}));
+
+        // an approved spender can transfer, which clears the approvals
+        let info = mock_info("creator", &[]);
+        let expires = Some(Expiration::AtHeight(50_000));
+        let _ = handle_approve(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "market".to_string(),
+            "1".to_string(),
+            expires,
+        )
+        .unwrap();
+        let res = query_approvals(deps.as_ref(), mock_env(), "1".to_string(), None).unwrap();
+        assert_eq!(
+            vec![Approval {
+                spender: "market".to_string(),
+                expires: Expiration::AtHeight(50_000),
+            }],
+            res.approvals
+        );
+        let info = mock_info("market", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "buyer".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+        let res = query_owner_of(deps.as_ref(), mock_env(), "1".to_string(), None).unwrap();
+        assert_eq!("buyer", res.owner);
+        assert!(res.approvals.is_empty());
+
+        // operators can execute for the owner, collateral still goes to the owner
+        let info = mock_info("buyer", &[]);
+        let _ =
+            handle_approve_all(deps.as_mut(), info, mock_env(), "bot".to_string(), None).unwrap();
+        let res = query_all_operators(
+            deps.as_ref(),
+            mock_env(),
+            "buyer".to_string(),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(1, res.operators.len());
+
+        let info = mock_info("buyer", &[]);
+        let _ = handle_revoke_all(deps.as_mut(), info, "bot".to_string()).unwrap();
+        let info = mock_info("bot", &coins(40, "ETH"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let info = mock_info("buyer", &[]);
+        let _ =
+            handle_approve_all(deps.as_mut(), info, mock_env(), "bot".to_string(), None).unwrap();
+        let info = mock_info("bot", &coins(40, "ETH"));
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "buyer".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+    }
+
+    #[test]
+    fn archive() {
+        let mut deps = setup();
+        let msg = CreateMsg {
+            counter_offer: Asset::Native(coins(30, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(2, "BTC"));
+        let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // live options are not archived
+        assert_eq!(None, query_archived(deps.as_ref(), 1).unwrap());
+
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        let env = mock_env();
+        assert_eq!(
+            Some(SettlementRecord {
+                creator: Addr::unchecked("creator"),
+                owner: Addr::unchecked("owner"),
+                closed_by: Some(Addr::unchecked("owner")),
+                closure: Closure::Executed,
+                height: env.block.height,
+                time: env.block.time,
+                collateral: coins(1, "BTC"),
+                payment: Some(Asset::Native(coins(40, "ETH"))),
+            }),
+            query_archived(deps.as_ref(), 1).unwrap()
+        );
+
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let info = mock_info("keeper", &[]);
+        let _ = handle_burn(deps.as_mut(), info, env.clone(), 2).unwrap();
+        let record = query_archived(deps.as_ref(), 2).unwrap().unwrap();
+        assert_eq!(Closure::Burned, record.closure);
+        assert_eq!(Some(Addr::unchecked("keeper")), record.closed_by);
+        assert_eq!(200_000, record.height);
+        assert_eq!(None, record.payment);
+    }
+
+    #[test]
+    fn prune_archive() {
+        let mut deps = setup();
+        for _ in 0..2 {
+            let msg = CreateMsg {
+                counter_offer: Asset::Native(coins(40, "ETH")),
+                expires: Expiration::AtHeight(100_000),
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            };
+            let info = mock_info("creator", &coins(1, "BTC"));
+            let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+        }
+
+        // options 1 and 2 close now, 3 a day later
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ =
+            handle_execute(deps.as_mut(), info.clone(), mock_env(), 1, None, None, None).unwrap();
+        let _ =
+            handle_execute(deps.as_mut(), info.clone(), mock_env(), 2, None, None, None).unwrap();
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(86_400);
+        let _ = handle_execute(deps.as_mut(), info, env.clone(), 3, None, None, None).unwrap();
+
+        // records are kept forever by default
+        let older_than = mock_env().block.time.plus_seconds(1);
+        let err = handle_prune_archive(deps.as_mut(), env.clone(), older_than, None).unwrap_err();
+        assert!(matches!(err, ContractError::WithinRetention {}));
+
+        // and can only be pruned once the retention period passed
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            archive_retention: Some(3_600),
+            ..UpdateConfigMsg::default()
+        });
+        let info = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let err =
+            handle_prune_archive(deps.as_mut(), env.clone(), env.block.time, None).unwrap_err();
+        assert!(matches!(err, ContractError::WithinRetention {}));
+
+        // anyone can prune old records, a page at a time
+        let res = handle_prune_archive(deps.as_mut(), env.clone(), older_than, Some(1)).unwrap();
+        assert_eq!(res.attributes[1], attr("pruned", "1"));
+        assert_eq!(None, query_archived(deps.as_ref(), 1).unwrap());
+        assert!(query_archived(deps.as_ref(), 2).unwrap().is_some());
+        let res = handle_prune_archive(deps.as_mut(), env, older_than, None).unwrap();
+        assert_eq!(res.attributes[1], attr("pruned", "1"));
+        assert_eq!(None, query_archived(deps.as_ref(), 2).unwrap());
+        assert!(query_archived(deps.as_ref(), 3).unwrap().is_some());
+    }
+
+    #[test]
+    fn stats() {
+        let mut deps = setup();
+        let msg = CreateMsg {
+            counter_offer: Asset::Cw20(Cw20CoinVerified {
+                address: Addr::unchecked("token"),
+                amount: Uint128::new(500),
+            }),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &[coin(2, "BTC"), coin(7, "ATOM")]);
+        let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+
+        let res = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(
+            StatsResponse {
+                active_options: 2,
+                collateral: vec![coin(7, "ATOM"), coin(3, "BTC")],
+                counter_offer: vec![coin(40, "ETH"), coin(500, "token")],
+            },
+            res
+        );
+
+        // executing releases the totals of the option
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        let res = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(1, res.active_options);
+        assert_eq!(vec![coin(7, "ATOM"), coin(2, "BTC")], res.collateral);
+        assert_eq!(vec![coin(500, "token")], res.counter_offer);
+
+        // splitting keeps the totals, only the count changes
+        let info = mock_info("creator", &[]);
+        let _ = handle_split(deps.as_mut(), info, 2, 2).unwrap();
+        let res = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(2, res.active_options);
+        assert_eq!(vec![coin(7, "ATOM"), coin(2, "BTC")], res.collateral);
+
+        // burning empties it
+        let info = mock_info("creator", &[]);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let _ = handle_burn(deps.as_mut(), info.clone(), env.clone(), 3).unwrap();
+        let _ = handle_burn(deps.as_mut(), info, env, 4).unwrap();
+        assert_eq!(
+            StatsResponse::default(),
+            query_stats(deps.as_ref()).unwrap()
+        );
+
+        // cw20 collateral is counted by token address, like cw20 counter_offers
+        let msg = ExecuteMsg::CreateBasket {
+            terms: CreateMsg {
+                counter_offer: Asset::Native(coins(40, "ETH")),
+                expires: Expiration::AtHeight(300_000),
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            },
+            cw20_collateral: vec![Cw20Coin {
+                address: "token".to_string(),
+                amount: Uint128::new(100),
+            }],
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(vec![coin(1, "BTC"), coin(100, "token")], res.collateral);
+
+        // taking out an option the stats never counted fails rather than wrapping around
+        let state = options().load(&deps.storage, 5).unwrap();
+        STATS.save(&mut deps.storage, &Stats::default()).unwrap();
+        let err = update_stats(&mut deps.storage, Some(&state), None).unwrap_err();
+        assert!(matches!(err, StdError::GenericErr { .. }));
+    }
+
+    #[test]
+    fn transfer_batch() {
+        let mut deps = setup();
+        let msg = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+
+        // one bad entry fails the whole batch
+        let transfers = vec![(1, "alice".to_string()), (3, "bob".to_string())];
+        let info = mock_info("creator", &[]);
+        let err = handle_transfer_batch(deps.as_mut(), info, mock_env(), transfers).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::NotFound { .. })));
+
+        let transfers = vec![(1, "alice".to_string()), (2, "bob".to_string())];
+        let info = mock_info("creator", &[]);
+        let res = handle_transfer_batch(deps.as_mut(), info, mock_env(), transfers).unwrap();
+        assert_eq!(res.attributes[0], attr("action", "transfer_batch"));
+        assert_eq!("alice", query_config(deps.as_ref(), 1).unwrap().state.owner);
+        assert_eq!("bob", query_config(deps.as_ref(), 2).unwrap().state.owner);
+
+        // each option at most once
+        let transfers = vec![(1, "bob".to_string()), (1, "carol".to_string())];
+        let info = mock_info("alice", &[]);
+        let err = handle_transfer_batch(deps.as_mut(), info, mock_env(), transfers).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::DuplicateOption { option_id: 1 }
+        ));
+
+        // every entry needs the sender to be allowed
+        let transfers = vec![(1, "bob".to_string())];
+        let info = mock_info("creator", &[]);
+        let err = handle_transfer_batch(deps.as_mut(), info, mock_env(), transfers).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn transfer_from() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let _ = handle_approve_all(deps.as_mut(), info, mock_env(), "market".to_string(), None)
+            .unwrap();
+
+        // the named owner must still hold the option
+        let info = mock_info("market", &[]);
+        let err = handle_transfer_from(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "someone".to_string(),
+            "buyer".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // and the sender must be approved
+        let info = mock_info("anyone", &[]);
+        let err = handle_transfer_from(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "creator".to_string(),
+            "buyer".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let info = mock_info("market", &[]);
+        let _ = handle_transfer_from(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "creator".to_string(),
+            "buyer".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+        let res = query_owner_of(deps.as_ref(), mock_env(), "1".to_string(), None).unwrap();
+        assert_eq!("buyer", res.owner);
+    }
+
+    #[test]
+    fn offer_and_claim_transfer() {
+        let mut deps = setup();
+
+        // only the owner can offer
+        let offer = |deps: DepsMut, sender: &str| {
+            let info = mock_info(sender, &[]);
+            let expires = Expiration::AtHeight(mock_env().block.height + 10);
+            handle_offer_transfer(deps, info, mock_env(), 1, "friend".to_string(), expires)
+        };
+        let err = offer(deps.as_mut(), "anyone").unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = offer(deps.as_mut(), "creator").unwrap();
+
+        // ownership only moves once the recipient claims
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("creator", res.state.owner.as_str());
+        let info = mock_info("anyone", &[]);
+        let err = handle_claim_transfer(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // the offer lapses
+        let mut env = mock_env();
+        env.block.height += 10;
+        let info = mock_info("friend", &[]);
+        let err = handle_claim_transfer(deps.as_mut(), info, env, 1).unwrap_err();
+        assert!(matches!(err, ContractError::OfferExpired {}));
+
+        let _ = offer(deps.as_mut(), "creator").unwrap();
+        let info = mock_info("friend", &[]);
+        let _ = handle_claim_transfer(deps.as_mut(), info, mock_env(), 1).unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("friend", res.state.owner.as_str());
+
+        // and cannot be claimed twice
+        let info = mock_info("friend", &[]);
+        let err = handle_claim_transfer(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::OfferExpired {}));
+    }
+
+    #[test]
+    fn pending_requests_go_with_the_option() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let expires = Expiration::Never {};
+        let _ = handle_offer_transfer(
+            deps.as_mut(),
+            info.clone(),
+            mock_env(),
+            1,
+            "friend".to_string(),
+            expires,
+        )
+        .unwrap();
+        let _ = handle_propose_termination(deps.as_mut(), info, 1).unwrap();
+
+        // closing the option clears both, whichever way it closes
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        assert!(!TRANSFER_OFFERS.has(&deps.storage, 1));
+        assert!(!TERMINATIONS.has(&deps.storage, 1));
+    }
+
+    #[test]
+    fn buy() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            premium: coins(2, "ETH"),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // wrong premium cannot buy
+        let info = mock_info("buyer", &coins(1, "ETH"));
+        let err = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap_err();
+        match err {
+            ContractError::PriceMismatch { offer, expected } => {
+                assert_eq!(coins(1, "ETH"), offer);
+                assert_eq!(coins(2, "ETH"), expected);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // nor can an expired option be bought, or listed again
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let err = handle_buy(deps.as_mut(), info, env.clone(), 1, None).unwrap_err();
+        assert!(matches!(err, ContractError::OptionExpired { .. }));
+        let info = mock_info("creator", &[]);
+        let err = handle_list(deps.as_mut(), info, env, 1, coins(2, "ETH")).unwrap_err();
+        assert!(matches!(err, ContractError::OptionExpired { .. }));
+
+        // paying the premium transfers ownership and pays the creator
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let res = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+        assert_eq!(res.attributes[0], attr("action", "buy"));
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "ETH"),
+            })
+        );
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("buyer", res.state.owner.as_str());
+        assert!(res.state.price.is_empty());
+
+        // it can only be sold once
+        let info = mock_info("other", &coins(2, "ETH"));
+        let err = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap_err();
+        match err {
+            ContractError::NotForSale {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn list_and_resell() {
+        let mut deps = setup();
+
+        // not listed without a premium
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let err = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap_err();
+        match err {
+            ContractError::NotForSale {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // only the owner can list
+        let info = mock_info("creator", &[]);
+        let err = handle_list(deps.as_mut(), info, mock_env(), 1, coins(5, "ATOM")).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("owner", &[]);
+        let _ = handle_list(deps.as_mut(), info, mock_env(), 1, coins(5, "ATOM")).unwrap();
+
+        // buying pays the seller, not the creator
+        let info = mock_info("buyer", &coins(5, "ATOM"));
+        let res = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(5, "ATOM"),
+            })
+        );
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("buyer", res.state.owner.as_str());
+        assert!(res.state.price.is_empty());
+
+        // a listing does not survive a transfer
+        let info = mock_info("buyer", &[]);
+        let _ = handle_list(deps.as_mut(), info, mock_env(), 1, coins(9, "ATOM")).unwrap();
+        let info = mock_info("buyer", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "friend".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert!(res.state.price.is_empty());
+    }
+
+    #[test]
+    fn initial_owner() {
+        let mut deps = mock_dependencies();
+        let mut msg = InstantiateMsg {
+            allowed_taker: Some("desk".to_string()),
+            owner: Some("".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+
+        // the owner has to be a counterparty the option allows
+        msg.owner = Some("buyer".to_string());
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::TakerNotAllowed {}));
+
+        msg.owner = Some("desk".to_string());
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("creator", res.state.creator.as_str());
+        assert_eq!("desk", res.state.owner.as_str());
+    }
+
+    #[test]
+    fn allowed_taker() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            premium: coins(2, "ETH"),
+            allowed_taker: Some("desk".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // nobody else can buy it or be sent it
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let err = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap_err();
+        assert!(matches!(err, ContractError::TakerNotAllowed {}));
+        let info = mock_info("creator", &[]);
+        let err = handle_transfer_nft(deps.as_mut(), info, mock_env(), "other".into(), "1".into())
+            .unwrap_err();
+        assert!(matches!(err, ContractError::TakerNotAllowed {}));
+
+        let info = mock_info("desk", &coins(2, "ETH"));
+        let _ = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+
+        // the taker can only pass it back to the creator
+        let info = mock_info("desk", &[]);
+        let err = handle_transfer_nft(deps.as_mut(), info, mock_env(), "other".into(), "1".into())
+            .unwrap_err();
+        assert!(matches!(err, ContractError::TakerNotAllowed {}));
+        let info = mock_info("desk", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "creator".into(),
+            "1".into(),
+        )
+        .unwrap();
+        assert_eq!(
+            "creator",
+            query_config(deps.as_ref(), 1).unwrap().state.owner.as_str()
+        );
+    }
+
+    #[test]
+    fn royalty_on_resale() {
+        let mut deps = mock_dependencies();
+        let mut msg = InstantiateMsg {
+            premium: coins(20, "ETH"),
+            royalty_bps: 10_001,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBps { bps: 10_001 }));
+        msg.royalty_bps = 1_000;
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // the creator's own sale pays no royalty
+        let info = mock_info("owner", &coins(20, "ETH"));
+        let res = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // resales pay 10% to the creator
+        let info = mock_info("owner", &[]);
+        let _ = handle_list(deps.as_mut(), info, mock_env(), 1, coins(55, "ETH")).unwrap();
+        let info = mock_info("buyer", &coins(55, "ETH"));
+        let res = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".into(),
+                    amount: coins(50, "ETH"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(5, "ETH"),
+                }),
+            ],
+            res.messages
+                .iter()
+                .map(|m| m.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(res.attributes.contains(&attr("royalty", "5ETH")));
+        assert_eq!(
+            "buyer",
+            query_config(deps.as_ref(), 1).unwrap().state.owner.as_str()
+        );
+    }
+
+    #[test]
+    fn transfer_fee() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            fee_collector: Some("collector".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msg = UpdateConfigMsg {
+            transfer_fee: Some(TransferFee {
+                flat: coins(1, "ATOM"),
+                bps: 500,
+            }),
+            fee_exempt: Some(vec!["market".to_string()]),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = handle_update_config(deps.as_mut(), mock_info("creator", &[]), mock_env(), msg)
+            .unwrap();
+
+        // transfers pay the flat fee to the collector
+        let info = mock_info("creator", &[]);
+        let err = handle_transfer_nft(deps.as_mut(), info, mock_env(), "alice".into(), "1".into())
+            .unwrap_err();
+        assert!(matches!(err, ContractError::TransferFeeRequired { .. }));
+        let info = mock_info("creator", &coins(1, "ATOM"));
+        let res = handle_transfer_nft(deps.as_mut(), info, mock_env(), "alice".into(), "1".into())
+            .unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(1, "ATOM"),
+            }),
+            res.messages[0].msg
+        );
+
+        // exempt addresses move options for free
+        let info = mock_info("alice", &[]);
+        let res = handle_transfer_nft(deps.as_mut(), info, mock_env(), "market".into(), "1".into())
+            .unwrap();
+        assert!(res.messages.is_empty());
+
+        // sales pay 5% of the price
+        let info = mock_info("market", &[]);
+        let _ =
+            handle_transfer_nft(deps.as_mut(), info, mock_env(), "bob".into(), "1".into()).unwrap();
+        let _ = handle_list(
+            deps.as_mut(),
+            mock_info("bob", &[]),
+            mock_env(),
+            1,
+            coins(100, "ETH"),
+        )
+        .unwrap();
+        let info = mock_info("carol", &coins(100, "ETH"));
+        let res = handle_buy(deps.as_mut(), info, mock_env(), 1, None).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "bob".into(),
+                    amount: coins(95, "ETH"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "collector".into(),
+                    amount: coins(5, "ETH"),
+                }),
+            ],
+            res.messages
+                .iter()
+                .map(|m| m.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(res.attributes.contains(&attr("transfer_fee", "5ETH")));
+    }
+
+    #[test]
+    fn options_by_owner() {
+        let mut deps = setup();
+        for _ in 0..4 {
+            let info = mock_info("creator", &coins(1, "BTC"));
+            let msg = ExecuteMsg::Create(CreateMsg {
+                counter_offer: Asset::Native(coins(40, "ETH")),
+                expires: Expiration::AtHeight(100_000),
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            });
+            let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        // hand every other option to someone else
+        for token_id in &["2", "4"] {
+            let info = mock_info("creator", &[]);
+            let _ = handle_transfer_nft(
+                deps.as_mut(),
+                info,
+                mock_env(),
+                "holder".to_string(),
+                token_id.to_string(),
+            )
+            .unwrap();
+        }
+
+        let ids = |res: OptionsResponse| -> Vec<u64> {
+            res.options.into_iter().map(|o| o.option_id).collect()
+        };
+        let res = query_options_by_owner(deps.as_ref(), "creator".to_string(), None, None).unwrap();
+        assert_eq!(vec![1, 3, 5], ids(res));
+        let res = query_options_by_owner(deps.as_ref(), "holder".to_string(), None, None).unwrap();
+        assert_eq!("holder", res.options[0].option.owner.as_str());
+        assert_eq!(vec![2, 4], ids(res));
+
+        // paginate
+        let res =
+            query_options_by_owner(deps.as_ref(), "creator".to_string(), None, Some(2)).unwrap();
+        assert_eq!(vec![1, 3], ids(res));
+        let res =
+            query_options_by_owner(deps.as_ref(), "creator".to_string(), Some(3), Some(2)).unwrap();
+        assert_eq!(vec![5], ids(res));
+
+        // settled options drop out of the index
+        let info = mock_info("holder", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 2, None, None, None).unwrap();
+        let res = query_options_by_owner(deps.as_ref(), "holder".to_string(), None, None).unwrap();
+        assert_eq!(vec![4], ids(res));
+    }
+
+    #[test]
+    fn options_by_creator() {
+        let mut deps = setup();
+        for writer in &["writer", "creator", "writer"] {
+            let info = mock_info(writer, &coins(2, "BTC"));
+            let msg = ExecuteMsg::Create(CreateMsg {
+                counter_offer: Asset::Native(coins(80, "ETH")),
+                expires: Expiration::AtHeight(120_000),
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            });
+            let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        // selling an option keeps it listed under its creator
+        let info = mock_info("writer", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "buyer".to_string(),
+            "4".to_string(),
+        )
+        .unwrap();
+
+        let res =
+            query_options_by_creator(deps.as_ref(), "writer".to_string(), None, None).unwrap();
+        let ids: Vec<u64> = res.options.iter().map(|o| o.option_id).collect();
+        assert_eq!(vec![2, 4], ids);
+        let option = &res.options[1].option;
+        assert_eq!("buyer", option.owner.as_str());
+        assert_eq!(coins(2, "BTC"), option.collateral);
+        assert_eq!(Asset::Native(coins(80, "ETH")), option.counter_offer);
+        assert_eq!(Expiration::AtHeight(120_000), option.expires);
+
+        let res =
+            query_options_by_creator(deps.as_ref(), "creator".to_string(), Some(1), None).unwrap();
+        assert_eq!(1, res.options.len());
+        assert_eq!(3, res.options[0].option_id);
+    }
+
+    #[test]
+    fn expiring_between() {
+        let mut deps = mock_dependencies();
+        let now = mock_env().block.time;
+        let msg = InstantiateMsg {
+            expires: Expiration::AtHeight(300_000),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let expiries = [
+            Expiration::AtHeight(100_000),
+            Expiration::AtTime(now.plus_seconds(500)),
+            Expiration::AtHeight(200_000),
+            Expiration::AtHeight(100_000),
+        ];
+        for expires in &expiries {
+            let info = mock_info("writer", &coins(1, "BTC"));
+            let msg = ExecuteMsg::Create(CreateMsg {
+                counter_offer: Asset::Native(coins(40, "ETH")),
+                expires: *expires,
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            });
+            let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        // heights come back ordered by expiry, times are not mixed in
+        let res = query_expiring_between(
+            deps.as_ref(),
+            Expiration::AtHeight(0),
+            Expiration::AtHeight(200_000),
+            None,
+        )
+        .unwrap();
+        let ids: Vec<u64> = res.options.iter().map(|o| o.option_id).collect();
+        assert_eq!(vec![2, 5, 4], ids);
+        assert_eq!("writer", res.options[0].creator.as_str());
+
+        let res = query_expiring_between(
+            deps.as_ref(),
+            Expiration::AtHeight(0),
+            Expiration::AtHeight(200_000),
+            Some(1),
+        )
+        .unwrap();
+        assert_eq!(1, res.options.len());
+
+        let res = query_expiring_between(
+            deps.as_ref(),
+            Expiration::AtTime(now),
+            Expiration::AtTime(now.plus_seconds(1_000)),
+            None,
+        )
+        .unwrap();
+        assert_eq!(1, res.options.len());
+        assert_eq!(3, res.options[0].option_id);
+
+        // mixed bounds are rejected
+        let _ = query_expiring_between(
+            deps.as_ref(),
+            Expiration::AtHeight(0),
+            Expiration::AtTime(now),
+            None,
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn execute_native() {
+        let mut deps = mock_dependencies();
+
+        let counter_offer = coins(40, "ETH");
+        let collateral = coins(1, "BTC");
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(counter_offer.clone()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &collateral);
+
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // set a new owner
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // random person cannot execute
+        let info = mock_info("anyone", &counter_offer);
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error : {}", e),
+        }
+
+        // expired cannot execute
+        let info = mock_info("owner", &counter_offer);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let err = handle_execute(deps.as_mut(), info, env, 1, None, None, None).unwrap_err();
+        match err {
+            ContractError::OptionExpired { expired } => {
+                assert_eq!(Expiration::AtHeight(100_000), expired)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // bad counter_offer cannot execute
+        let info = mock_info("owner", &coins(39, "ETH"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { offer, expected } => {
+                assert_eq!(Asset::Native(coins(39, "ETH")), offer);
+                assert_eq!(Asset::Native(counter_offer.clone()), expected);
+            }
+            e => panic!("unexpected error : {}", e),
+        }
+
+        // proper execution
+        let info = mock_info("owner", &counter_offer);
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: counter_offer,
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: collateral,
+            })
+        );
+
+        // check deleted
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn execute_cash() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+
+        // 10 BTC at a strike of 40 ETH each
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            settlement: Settlement::Cash,
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // cash options can only hold one collateral denom
+        let info = mock_info("creator", &[coin(10, "BTC"), coin(5, "ATOM")]);
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Cash,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+
+        // nothing is paid to exercise
+        let info = mock_info("creator", &coins(400, "ETH"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        assert!(matches!(err, ContractError::FundsSentWithCashSettlement {}));
+
+        // at a spot of 50 ETH the owner gets 2 BTC worth 100 ETH, the creator keeps 8
+        let info = mock_info("creator", &[]);
+        let min_payout = Some(Uint128::new(3));
+        let err = handle_execute(
+            deps.as_mut(),
+            info.clone(),
+            mock_env(),
+            1,
+            None,
+            None,
+            min_payout,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::PayoutTooLow { payout, .. } if payout == Uint128::new(2)
+        ));
+        let min_payout = Some(Uint128::new(2));
+        let res =
+            handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, min_payout).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(8, "BTC"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "BTC"),
+            })
+        );
+        // the event reports the payout, with nothing paid for it
+        let exercised = res
+            .events
+            .iter()
+            .find(|e| e.ty == "simple_option.execute")
+            .unwrap();
+        assert_eq!(
+            exercised.attributes[4..],
+            [attr("payout_amount", "2"), attr("payout_denom", "BTC")]
+        );
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn burn_expired_cash() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            settlement: Settlement::Cash,
+            oracle: Some("oracle".to_string()),
+            owner: Some("owner".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // burning settles it like the crank would, the owner still gets the 2 BTC in the money
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let res = handle_burn(deps.as_mut(), mock_info("creator", &[]), env, 1).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(8, "BTC"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".into(),
+                    amount: coins(2, "BTC"),
+                }),
+            ],
+            msgs
+        );
+        let record = query_archived(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Closure::Executed, record.closure);
+    }
+
+    #[test]
+    fn execute_european() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            style: OptionStyle::European,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // cannot execute before expiry
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let mut env = mock_env();
+        env.block.height = 99_999;
+        let err = handle_execute(
+            deps.as_mut(),
+            info.clone(),
+            env.clone(),
+            1,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::OptionNotExpired { expires } => {
+                assert_eq!(Expiration::AtHeight(100_000), expires)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // nor after the settlement window
+        env.block.height = 100_000 + SETTLEMENT_WINDOW_BLOCKS;
+        let err = handle_execute(
+            deps.as_mut(),
+            info.clone(),
+            env.clone(),
+            1,
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::OptionExpired { .. }));
+
+        // the creator cannot burn while the window is open
+        env.block.height = 100_000;
+        let err =
+            handle_burn(deps.as_mut(), mock_info("creator", &[]), env.clone(), 1).unwrap_err();
+        match err {
+            ContractError::OptionNotExpired { expires } => assert_eq!(
+                Expiration::AtHeight(100_000 + SETTLEMENT_WINDOW_BLOCKS),
+                expires
+            ),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // but the owner can execute
+        let res = handle_execute(deps.as_mut(), info, env, 1, None, None, None).unwrap();
+        assert_eq!(res.messages.len(), 2);
+    }
+
+    #[test]
+    fn execute_refund() {
+        let mut deps = setup();
+
+        // the wrong denom is not enough
+        let info = mock_info("creator", &coins(40, "ATOM"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        assert!(matches!(err, ContractError::CounterOfferMismatch { .. }));
+
+        // overpaying, including dust in other denoms, is sent back
+        let info = mock_info("creator", &[coin(42, "ETH"), coin(3, "ATOM")]);
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(40, "ETH"),
+            })
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(3, "ATOM"), coin(2, "ETH")],
+            })
+        );
+    }
+
+    #[test]
+    fn execute_to_recipient() {
+        let mut deps = setup();
+
+        // the collateral can be sent to a cold wallet instead of the owner
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: Some("cold".to_string()),
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "cold".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+    }
+
+    #[test]
+    fn can_execute() {
+        let mut deps = setup();
+
+        let check = |deps: Deps, env: Env, sender: &str, funds: Vec<Coin>| {
+            query_can_execute(deps, env, 1, sender.to_string(), funds).unwrap()
+        };
+        assert_eq!(
+            CanExecuteResponse::Ok {},
+            check(deps.as_ref(), mock_env(), "creator", coins(40, "ETH"))
+        );
+        assert_eq!(
+            CanExecuteResponse::NotOwner {},
+            check(deps.as_ref(), mock_env(), "anyone", coins(40, "ETH"))
+        );
+        assert_eq!(
+            CanExecuteResponse::WrongFunds {
+                expected: Asset::Native(coins(40, "ETH"))
+            },
+            check(deps.as_ref(), mock_env(), "creator", coins(39, "ETH"))
+        );
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        assert_eq!(
+            CanExecuteResponse::Expired {
+                expired: Expiration::AtHeight(100_000)
+            },
+            check(deps.as_ref(), env, "creator", coins(40, "ETH"))
+        );
+
+        // any other failure is reported with its reason rather than failing the query
+        let mut state = options().load(&deps.storage, 1).unwrap();
+        state.settlement = Settlement::Binary {
+            direction: BarrierDirection::Up,
+        };
+        options().save(&mut deps.storage, 1, &state).unwrap();
+        assert_eq!(
+            CanExecuteResponse::Other {
+                reason: ContractError::BinaryOption {}.to_string()
+            },
+            check(deps.as_ref(), mock_env(), "creator", coins(40, "ETH"))
+        );
+    }
+
+    #[test]
+    fn intrinsic_value() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // 10 BTC worth 500 ETH for 400 ETH
+        let res = query_intrinsic_value(deps.as_ref(), 1).unwrap();
+        assert_eq!(
+            IntrinsicValueResponse {
+                spot: Decimal::percent(5_000),
+                strike: Decimal::percent(4_000),
+                payoff: Uint128::new(100),
+                in_the_money: true,
+            },
+            res
+        );
+
+        // out of the money is worth nothing
+        mock_oracle(&mut deps.querier, Decimal::percent(3_000));
+        let res = query_intrinsic_value(deps.as_ref(), 1).unwrap();
+        assert_eq!(Uint128::zero(), res.payoff);
+        assert!(!res.in_the_money);
+    }
+
+    #[test]
+    fn fair_value() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtTime(env.block.time.plus_seconds(31_557_600)),
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // a year out, time value comes on top of the 100 ETH intrinsic value
+        let res = query_fair_value(deps.as_ref(), env.clone(), 1, Decimal::percent(50)).unwrap();
+        assert_eq!(Decimal::percent(5_000), res.spot);
+        assert_eq!(Decimal::percent(4_000), res.strike);
+        assert_eq!(Decimal::one(), res.years);
+        assert!(res.fair_value > Uint128::new(100));
+
+        // worthless without volatility when out of the money
+        mock_oracle(&mut deps.querier, Decimal::percent(3_000));
+        let res = query_fair_value(deps.as_ref(), env, 1, Decimal::zero()).unwrap();
+        assert_eq!(Uint128::zero(), res.fair_value);
+    }
+
+    #[test]
+    fn execute_batch() {
+        let mut deps = setup();
+        let msg = CreateMsg {
+            counter_offer: Asset::Native(coins(30, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(2, "BTC"));
+        let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+
+        // funds must cover both counter_offers
+        let info = mock_info("creator", &coins(60, "ETH"));
+        let err = handle_execute_batch(deps.as_mut(), info, mock_env(), vec![1, 2]).unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { offer, expected } => {
+                assert_eq!(Asset::Native(coins(20, "ETH")), offer);
+                assert_eq!(Asset::Native(coins(30, "ETH")), expected);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // and each option can only be executed once
+        let info = mock_info("creator", &coins(80, "ETH"));
+        let err = handle_execute_batch(deps.as_mut(), info, mock_env(), vec![1, 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::DuplicateOption { option_id: 1 }
+        ));
+
+        // everything settles at once, with the rest refunded
+        let info = mock_info("creator", &coins(100, "ETH"));
+        let res = handle_execute_batch(deps.as_mut(), info, mock_env(), vec![1, 2]).unwrap();
+        assert_eq!(5, res.messages.len());
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert_eq!(
+            res.messages[3].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "BTC"),
+            })
+        );
+        assert_eq!(
+            res.messages[4].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(30, "ETH"),
+            })
+        );
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+        let _ = query_config(deps.as_ref(), 2).unwrap_err();
+    }
+
+    #[test]
+    fn execute_partial() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // fraction must be positive and at most the whole option
+        for fraction in [Decimal::zero(), Decimal::percent(101), Decimal::percent(5)] {
+            let info = mock_info("creator", &[]);
+            let err = handle_execute_partial(deps.as_mut(), info, mock_env(), 1, fraction, None)
+                .unwrap_err();
+            match err {
+                ContractError::InvalidFraction { fraction: f } => assert_eq!(fraction, f),
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+
+        // must pay the matching share of the counter_offer
+        let info = mock_info("creator", &coins(99, "ETH"));
+        let err = handle_execute_partial(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            1,
+            Decimal::percent(25),
+            None,
+        )
+        .unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { expected, .. } => {
+                assert_eq!(Asset::Native(coins(100, "ETH")), expected)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // a quarter releases 2 BTC, rounded down, for 100 ETH
+        let info = mock_info("creator", &coins(100, "ETH"));
+        let res = handle_execute_partial(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            1,
+            Decimal::percent(25),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "BTC"),
+            })
+        );
+        let state = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(coins(8, "BTC"), state.state.collateral);
+        assert_eq!(Asset::Native(coins(300, "ETH")), state.state.counter_offer);
+
+        // executing the rest closes the option
+        let info = mock_info("creator", &coins(300, "ETH"));
+        let _ = handle_execute_partial(deps.as_mut(), info, mock_env(), 1, Decimal::one(), None)
+            .unwrap();
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn terminate() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // nothing to accept yet
+        let info = mock_info("creator", &[]);
+        let err = handle_accept_termination(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::NoTerminationProposed {}));
+
+        // only the two sides can propose
+        let info = mock_info("anyone", &[]);
+        let err = handle_propose_termination(deps.as_mut(), info, 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // the proposer cannot accept on their own
+        let info = mock_info("owner", &[]);
+        let _ = handle_propose_termination(deps.as_mut(), info, 1).unwrap();
+        let info = mock_info("owner", &[]);
+        let err = handle_accept_termination(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // the other side accepting unwinds the option
+        let info = mock_info("creator", &[]);
+        let res = handle_accept_termination(deps.as_mut(), info, mock_env(), 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn extend_expiry() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // only the creator proposes, and only later expiries
+        let later = Expiration::AtHeight(150_000);
+        let info = mock_info("owner", &[]);
+        let err = handle_propose_extension(deps.as_mut(), info, 1, later).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        for expires in [
+            Expiration::AtHeight(90_000),
+            Expiration::AtTime(mock_env().block.time),
+            Expiration::Never {},
+        ] {
+            let info = mock_info("creator", &[]);
+            let err = handle_propose_extension(deps.as_mut(), info, 1, expires).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidExtension {}));
+        }
+
+        // a new proposal refunds the previous fee
+        let info = mock_info("creator", &coins(1, "ETH"));
+        let _ = handle_propose_extension(deps.as_mut(), info, 1, later).unwrap();
+        let info = mock_info("creator", &coins(2, "ETH"));
+        let res = handle_propose_extension(deps.as_mut(), info, 1, later).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1, "ETH"),
+            })
+        );
+
+        // nothing changes until the owner accepts
+        assert_eq!(
+            Expiration::AtHeight(100_000),
+            query_config(deps.as_ref(), 1).unwrap().state.expires
+        );
+        let info = mock_info("creator", &[]);
+        let err = handle_accept_extension(deps.as_mut(), info, 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let info = mock_info("owner", &[]);
+        let res = handle_accept_extension(deps.as_mut(), info, 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(2, "ETH"),
+            })
+        );
+        assert_eq!(later, query_config(deps.as_ref(), 1).unwrap().state.expires);
+
+        // a withdrawn proposal returns the fee
+        let info = mock_info("creator", &coins(3, "ETH"));
+        let _ = handle_propose_extension(deps.as_mut(), info, 1, Expiration::AtHeight(200_000))
+            .unwrap();
+        let info = mock_info("creator", &[]);
+        let res = handle_cancel_extension(deps.as_mut(), info, 1).unwrap();
+        assert_eq!(1, res.messages.len());
+        let info = mock_info("owner", &[]);
+        let err = handle_accept_extension(deps.as_mut(), info, 1).unwrap_err();
+        assert!(matches!(err, ContractError::NoExtensionProposed {}));
+    }
+
+    #[test]
+    fn buyback() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // the price has to be escrowed by the creator
+        let info = mock_info("owner", &coins(3, "ETH"));
+        let err = handle_offer_buyback(deps.as_mut(), info, 1, coins(3, "ETH")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &coins(2, "ETH"));
+        let err = handle_offer_buyback(deps.as_mut(), info, 1, coins(3, "ETH")).unwrap_err();
+        assert!(matches!(err, ContractError::PriceMismatch { .. }));
+
+        // nothing to accept yet
+        let info = mock_info("owner", &[]);
+        let err = handle_accept_buyback(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::NoBuybackOffered {}));
+
+        let info = mock_info("creator", &coins(3, "ETH"));
+        let res = handle_offer_buyback(deps.as_mut(), info, 1, coins(3, "ETH")).unwrap();
+        assert_eq!(res.attributes[1], attr("price", "3ETH"));
+
+        // only the owner can accept
+        let info = mock_info("creator", &[]);
+        let err = handle_accept_buyback(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let info = mock_info("owner", &[]);
+        let res = handle_accept_buyback(deps.as_mut(), info, mock_env(), 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(3, "ETH"),
+            })
+        );
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+    }
+
+    #[test]
+    fn split() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            premium: coins(5, "ETH"),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // only the owner can split
+        let info = mock_info("anyone", &[]);
+        let err = handle_split(deps.as_mut(), info, 1, 2).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // every part needs collateral
+        let info = mock_info("creator", &[]);
+        let err = handle_split(deps.as_mut(), info, 1, 11).unwrap_err();
+        match err {
+            ContractError::InvalidParts { parts } => assert_eq!(11, parts),
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the first part takes the remainder
+        let info = mock_info("creator", &[]);
+        let res = handle_split(deps.as_mut(), info, 1, 3).unwrap();
+        assert_eq!(res.attributes[1], attr("option_ids", "2,3,4"));
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
+
+        let first = query_config(deps.as_ref(), 2).unwrap();
+        assert_eq!(coins(4, "BTC"), first.state.collateral);
+        assert_eq!(Asset::Native(coins(134, "ETH")), first.state.counter_offer);
+        assert!(first.state.price.is_empty());
+        for id in [3, 4] {
+            let part = query_config(deps.as_ref(), id).unwrap();
+            assert_eq!(coins(3, "BTC"), part.state.collateral);
+            assert_eq!(Asset::Native(coins(133, "ETH")), part.state.counter_offer);
+            assert_eq!(first.state.expires, part.state.expires);
+            assert_eq!("creator", part.state.owner.as_str());
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let _ = handle_split(deps.as_mut(), info, 1, 2).unwrap();
+
+        // a different expiry cannot be merged in
+        let info = mock_info("creator", &coins(5, "BTC"));
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(200, "ETH")),
+            expires: Expiration::AtHeight(200_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let err = handle_merge(deps.as_mut(), info, vec![2, 3, 4]).unwrap_err();
+        assert!(matches!(err, ContractError::CannotMerge {}));
+
+        // nor the same option twice
+        let info = mock_info("creator", &[]);
+        let err = handle_merge(deps.as_mut(), info, vec![2, 2]).unwrap_err();
+        assert!(matches!(err, ContractError::CannotMerge {}));
+
+        // only the owner can merge
+        let info = mock_info("anyone", &[]);
+        let err = handle_merge(deps.as_mut(), info, vec![2, 3]).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // merging undoes the split
+        let info = mock_info("creator", &[]);
+        let _ = handle_merge(deps.as_mut(), info, vec![3, 2]).unwrap();
+        let _ = query_config(deps.as_ref(), 2).unwrap_err();
+        let merged = query_config(deps.as_ref(), 3).unwrap();
+        assert_eq!(coins(10, "BTC"), merged.state.collateral);
+        assert_eq!(Asset::Native(coins(400, "ETH")), merged.state.counter_offer);
+    }
+
+    #[test]
+    fn add_collateral() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            owner: Some("owner".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("owner", &coins(1, "BTC"));
+        let err = handle_add_collateral(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &[]);
+        let err = handle_add_collateral(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+
+        let info = mock_info("creator", &[coin(2, "BTC"), coin(5, "ATOM")]);
+        let _ = handle_add_collateral(deps.as_mut(), info, mock_env(), 1).unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(vec![coin(5, "ATOM"), coin(3, "BTC")], res.state.collateral);
+        assert_eq!("owner", res.state.owner.as_str());
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(vec![coin(5, "ATOM"), coin(3, "BTC")], stats.collateral);
+
+        // expired options cannot be topped up
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = handle_add_collateral(deps.as_mut(), info, env, 1).unwrap_err();
+        assert!(matches!(err, ContractError::OptionExpired { .. }));
+    }
+
+    #[test]
+    fn withdraw_collateral() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &[coin(3, "BTC"), coin(5, "ATOM")]);
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("creator", &[]);
+        let _ = handle_list(deps.as_mut(), info, mock_env(), 1, coins(10, "ETH")).unwrap();
+
+        // more than the collateral, or all of it, is refused
+        let info = mock_info("creator", &[]);
+        let err =
+            handle_withdraw_collateral(deps.as_mut(), info.clone(), mock_env(), 1, coins(4, "BTC"))
+                .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+        let all = vec![coin(5, "ATOM"), coin(3, "BTC")];
+        let err = handle_withdraw_collateral(deps.as_mut(), info.clone(), mock_env(), 1, all)
+            .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+
+        let res = handle_withdraw_collateral(deps.as_mut(), info, mock_env(), 1, coins(5, "ATOM"))
+            .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(5, "ATOM"),
+            })
+        );
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(coins(3, "BTC"), res.state.collateral);
+        assert!(res.state.price.is_empty());
+        let stats = query_stats(deps.as_ref()).unwrap();
+        assert_eq!(coins(3, "BTC"), stats.collateral);
+
+        // once someone else holds the option its collateral is locked
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info.clone(),
+            mock_env(),
+            "owner".into(),
+            "1".into(),
+        )
+        .unwrap();
+        let err = handle_withdraw_collateral(deps.as_mut(), info, mock_env(), 1, coins(1, "BTC"))
+            .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn cancel() {
+        let mut deps = setup();
+
+        let err =
+            handle_cancel(deps.as_mut(), mock_info("anyone", &[]), mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // a sold option cannot be cancelled, until it comes back to the creator
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info.clone(),
+            mock_env(),
+            "owner".into(),
+            "1".into(),
+        )
+        .unwrap();
+        let err = handle_cancel(deps.as_mut(), info.clone(), mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let owner = mock_info("owner", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            owner,
+            mock_env(),
+            "creator".into(),
+            "1".into(),
+        )
+        .unwrap();
+
+        let res = handle_cancel(deps.as_mut(), info, mock_env(), 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert!(query_config(deps.as_ref(), 1).is_err());
+        let record = query_archived(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Closure::Cancelled, record.closure);
+    }
+
+    #[test]
+    fn roll_on_expiry() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            roll_on_expiry: true,
+            owner: Some("owner".to_string()),
+            burn_reward_bps: 100,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(100, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let keeper = mock_info("keeper", &[]);
+        let err = handle_roll(deps.as_mut(), keeper.clone(), env.clone(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::RollingNotEnabled {}));
+        let config = UpdateConfigMsg {
+            roll_duration: Some(1_000),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = handle_update_config(deps.as_mut(), mock_info("creator", &[]), mock_env(), config)
+            .unwrap();
+        let err = handle_roll(deps.as_mut(), keeper.clone(), mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::OptionNotExpired { .. }));
+
+        // keepers roll it instead of burning it, earning the burn reward
+        let err = handle_burn(deps.as_mut(), keeper.clone(), env.clone(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::OptionRolls {}));
+        let paying = mock_info("keeper", &coins(1, "BTC"));
+        let err = handle_roll(deps.as_mut(), paying, env.clone(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::FundsSentWithRoll {}));
+        let mut state = options().load(&deps.storage, 1).unwrap();
+        state.price = coins(5, "ETH");
+        save_option(&mut deps.storage, 1, &state).unwrap();
+        let res = handle_roll(deps.as_mut(), keeper, env.clone(), 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "keeper".into(),
+                amount: coins(1, "BTC"),
+            })
+        );
+        assert_eq!("rolled", res.events[0].ty);
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(Expiration::AtHeight(101_000), res.state.expires);
+        assert_eq!(coins(99, "BTC"), res.state.collateral);
+        assert_eq!("owner", res.state.owner.as_str());
+        // and whatever it was listed for no longer holds
+        assert!(res.state.price.is_empty());
+
+        // the creator can still end it once expired
+        env.block.height = 101_000;
+        let info = mock_info("creator", &[]);
+        let res = handle_burn(deps.as_mut(), info, env, 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(99, "BTC"),
+            })
+        );
+    }
+
+    #[test]
+    fn knock_out() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let barrier = |price: Decimal| Barrier {
+            price,
+            direction: BarrierDirection::Up,
+        };
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(4_000, "ETH")),
+            barrier: Some(barrier(Decimal::percent(6_000))),
+            burn_reward_bps: 100,
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(100, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: Some(barrier(Decimal::zero())),
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Create(terms.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBarrier {}));
+
+        // the barrier has to be watched through a price for the collateral
+        let msg = ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+            sender: "creator".to_string(),
+            token_id: "7".to_string(),
+            msg: to_json_binary(&ReceiveNftMsg::Create(CreateMsg {
+                barrier: Some(barrier(Decimal::percent(6_000))),
+                ..terms
+            }))
+            .unwrap(),
+        });
+        let err = execute(deps.as_mut(), mock_env(), mock_info("punks", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+        let info = mock_info("creator", &coins(5, "ATOM"));
+        let err = handle_add_collateral(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+
+        // only once the price reaches the barrier
+        let msg = ExecuteMsg::KnockOut { option_id: 1 };
+        let info = mock_info("keeper", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::BarrierNotCrossed {}));
+        mock_oracle(&mut deps.querier, Decimal::percent(6_000));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(99, "BTC"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "keeper".into(),
+                    amount: coins(1, "BTC"),
+                }),
+            ],
+            msgs
+        );
+        assert!(!options().has(&deps.storage, 1));
+        let record = query_archived(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Closure::KnockedOut, record.closure);
+
+        // a full reward leaves nothing to send the creator
+        let config = UpdateConfigMsg {
+            burn_reward_bps: Some(10_000),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: Some(barrier(Decimal::percent(6_000))),
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(100, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(terms)).unwrap();
+        let msg = ExecuteMsg::KnockOut { option_id: 2 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("keeper", &[]), msg).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "keeper".into(),
+                amount: coins(100, "BTC"),
+            })],
+            msgs
+        );
+    }
+    #[test]
+    fn binary_settlement() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let binary = Settlement::Binary {
+            direction: BarrierDirection::Up,
+        };
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            settlement: binary,
+            owner: Some("owner".to_string()),
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: binary,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: Some("owner".to_string()),
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(terms)).unwrap();
+
+        // neither executed nor burned, and only settled once expired
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, execute_msg).unwrap_err();
+        assert!(matches!(err, ContractError::BinaryOption {}));
+        let settle = |option_id| ExecuteMsg::SettleBinary { option_id };
+        let keeper = mock_info("keeper", &[]);
+        let err = execute(deps.as_mut(), mock_env(), keeper.clone(), settle(1)).unwrap_err();
+        assert!(matches!(err, ContractError::OptionNotExpired { .. }));
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let burn = ExecuteMsg::Burn { option_id: 1 };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, burn).unwrap_err();
+        assert!(matches!(err, ContractError::BinaryOption {}));
+
+        // 50 ETH per BTC is above the strike of 40, the owner takes the collateral
+        let paid =
+            |res: Response| -> Vec<CosmosMsg> { res.messages.into_iter().map(|m| m.msg).collect() };
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), settle(1)).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(10, "BTC"),
+            })],
+            paid(res)
+        );
+        let record = query_archived(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Closure::Settled, record.closure);
+        let err = execute(deps.as_mut(), env.clone(), keeper.clone(), settle(1)).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::NotFound { .. })));
+
+        // below it, the creator keeps it
+        mock_oracle(&mut deps.querier, Decimal::percent(3_000));
+        let res = execute(deps.as_mut(), env, keeper, settle(2)).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(10, "BTC"),
+            })],
+            paid(res)
+        );
+    }
+    #[test]
+    fn custom_settlement_window() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            style: OptionStyle::European,
+            settlement_window: Some(10),
+            owner: Some("owner".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // American options are exercisable until expiry, they have no window
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: Some(10),
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(terms)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSettlementWindow {}));
+
+        // the window closes 10 blocks past expiry, the creator can burn from then on
+        let mut env = mock_env();
+        env.block.height = 100_009;
+        let burn = ExecuteMsg::Burn { option_id: 1 };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), burn.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OptionNotExpired { .. }));
+        env.block.height = 100_010;
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let owner = mock_info("owner", &coins(40, "ETH"));
+        let err = execute(deps.as_mut(), env.clone(), owner, execute_msg).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::OptionExpired {
+                expired: Expiration::AtHeight(100_010)
+            }
+        ));
+        let _ = execute(deps.as_mut(), env, info, burn).unwrap();
+    }
+    #[test]
+    fn exercisable_from() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            exercisable_from: Some(Expiration::AtHeight(90_000)),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // a start that isn't comparable to the expiry is never reached
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: Some(Expiration::AtTime(mock_env().block.time)),
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(terms)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidExercisableFrom {}));
+
+        // still transferable before the start
+        let info = mock_info("creator", &[]);
+        let transfer = ExecuteMsg::TransferNft {
+            recipient: "owner".to_string(),
+            token_id: "1".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, transfer).unwrap();
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err =
+            execute(deps.as_mut(), mock_env(), info.clone(), execute_msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotYetExercisable { .. }));
+        let check = query_can_execute(
+            deps.as_ref(),
+            mock_env(),
+            1,
+            "owner".to_string(),
+            coins(40, "ETH"),
+        )
+        .unwrap();
+        assert_eq!(
+            CanExecuteResponse::NotYetExercisable {
+                expires: Expiration::AtHeight(90_000)
+            },
+            check
+        );
+
+        let mut env = mock_env();
+        env.block.height = 90_000;
+        let _ = execute(deps.as_mut(), env, info, execute_msg).unwrap();
+    }
+    #[test]
+    fn burn_grace_period() {
+        let mut deps = setup();
+        let config = UpdateConfigMsg {
+            burn_grace_period: Some(50),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = handle_update_config(deps.as_mut(), mock_info("creator", &[]), mock_env(), config)
+            .unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 100_049;
+        let info = mock_info("creator", &[]);
+        let err = handle_burn(deps.as_mut(), info.clone(), env.clone(), 1).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::OptionNotExpired {
+                expires: Expiration::AtHeight(100_050)
+            }
+        ));
+        env.block.height = 100_050;
+        let _ = handle_burn(deps.as_mut(), info, env, 1).unwrap();
+    }
+    #[test]
+    fn execute_deadline() {
+        let mut deps = setup();
+
+        let env = mock_env();
+        let execute_by = |height: u64| ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: Some(Expiration::AtHeight(height)),
+            min_payout: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let msg = execute_by(env.block.height);
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::DeadlinePassed { .. }));
+        let msg = execute_by(env.block.height + 1);
+        let _ = execute(deps.as_mut(), env, info, msg).unwrap();
+    }
+    #[test]
+    fn nft_collateral() {
+        let mut deps = setup();
+
+        let terms = |settlement, owner: &str| CreateMsg {
+            counter_offer: Asset::Native(coins(500, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: Some(owner.to_string()),
+        };
+        let send = |token_id: &str, terms: CreateMsg| {
+            ExecuteMsg::ReceiveNft(Cw721ReceiveMsg {
+                sender: "writer".to_string(),
+                token_id: token_id.to_string(),
+                msg: to_json_binary(&ReceiveNftMsg::Create(terms)).unwrap(),
+            })
+        };
+        let collection = mock_info("punks", &[]);
+        // an NFT can't be priced
+        let msg = send("7", terms(Settlement::Cash, "owner"));
+        let err = execute(deps.as_mut(), mock_env(), collection.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+        let msg = send("7", terms(Settlement::Physical, "owner"));
+        let _ = execute(deps.as_mut(), mock_env(), collection.clone(), msg).unwrap();
+        let msg = send("8", terms(Settlement::Physical, "writer"));
+        let _ = execute(deps.as_mut(), mock_env(), collection, msg).unwrap();
+        let state = options().load(&deps.storage, 2).unwrap();
+        assert!(state.collateral.is_empty());
+        assert_eq!(
+            Some(NftCollateral {
+                collection: Addr::unchecked("punks"),
+                token_id: "7".to_string(),
+            }),
+            state.nft
+        );
+        let err = handle_split(deps.as_mut(), mock_info("owner", &[]), 2, 2).unwrap_err();
+        assert!(matches!(err, ContractError::NftCollateral {}));
+        let info = mock_info("writer", &coins(1, "BTC"));
+        let err = handle_add_collateral(deps.as_mut(), info, mock_env(), 2).unwrap_err();
+        assert!(matches!(err, ContractError::NftCollateral {}));
+
+        // the exerciser gets the NFT, the creator the counter_offer
+        let info = mock_info("owner", &coins(500, "ETH"));
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 2, None, None, None).unwrap();
+        let transfer = |to: &str, token_id: &str| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "punks".to_string(),
+                msg: to_json_binary(&Cw721ExecuteMsg::TransferNft {
+                    recipient: to.to_string(),
+                    token_id: token_id.to_string(),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        };
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "writer".into(),
+                    amount: coins(500, "ETH"),
+                }),
+                transfer("owner", "7"),
+            ],
+            msgs
+        );
+
+        // and it goes back to the creator on burn
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let res = handle_burn(deps.as_mut(), mock_info("keeper", &[]), env, 3).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(vec![transfer("writer", "8")], msgs);
+    }
+
+    #[test]
+    fn basket_collateral() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            burn_reward_bps: 1_000,
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let terms = |settlement| CreateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: Some("owner".to_string()),
+        };
+        let basket = |settlement, tokens: &[&str]| ExecuteMsg::CreateBasket {
+            terms: terms(settlement),
+            cw20_collateral: tokens
+                .iter()
+                .map(|token| Cw20Coin {
+                    address: token.to_string(),
+                    amount: Uint128::new(100),
+                })
+                .collect(),
+        };
+        // the tokens can't be priced, and each is listed once
+        let info = mock_info("writer", &coins(10, "BTC"));
+        let msg = basket(Settlement::Cash, &["token"]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+        let msg = basket(Settlement::Physical, &["token", "token"]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+
+        let transfer = |contract: &str, msg: Cw20ExecuteMsg| {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_json_binary(&msg).unwrap(),
+                funds: vec![],
+            })
+        };
+        let msg = basket(Settlement::Physical, &["token", "other"]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        let pull = |token: &str| {
+            transfer(
+                token,
+                Cw20ExecuteMsg::TransferFrom {
+                    owner: "writer".to_string(),
+                    recipient: mock_env().contract.address.to_string(),
+                    amount: Uint128::new(100),
+                },
+            )
+        };
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(vec![pull("token"), pull("other")], msgs);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let err = handle_split(deps.as_mut(), mock_info("owner", &[]), 2, 2).unwrap_err();
+        assert!(matches!(err, ContractError::Cw20Collateral {}));
+
+        // the exerciser gets every asset of the basket
+        let send = |to: &str, token: &str, amount: u128| {
+            transfer(
+                token,
+                Cw20ExecuteMsg::Transfer {
+                    recipient: to.to_string(),
+                    amount: Uint128::new(amount),
+                },
+            )
+        };
+        let bank = |to: &str, amount: u128| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: coins(amount, "BTC"),
+            })
+        };
+        let info = mock_info("owner", &coins(400, "ETH"));
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 2, None, None, None).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().skip(1).map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                bank("owner", 10),
+                send("owner", "token", 100),
+                send("owner", "other", 100),
+            ],
+            msgs
+        );
+
+        // keepers get the burn reward out of each of them
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let res = handle_burn(deps.as_mut(), mock_info("keeper", &[]), env, 3).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                bank("writer", 9),
+                send("writer", "token", 90),
+                send("writer", "other", 90),
+                bank("keeper", 1),
+                send("keeper", "token", 10),
+                send("keeper", "other", 10),
+            ],
+            msgs
+        );
+    }
+
+    #[test]
+    fn burn() {
+        let mut deps = mock_dependencies();
+
+        let counter_offer = coins(40, "ETH");
+        let collateral = coins(1, "BTC");
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(counter_offer.clone()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &collateral);
+
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // set a new owner
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // non-expired cannot execute
+        let info = mock_info("owner", &counter_offer);
+        let err = handle_burn(deps.as_mut(), info, mock_env(), 1).unwrap_err();
+        match err {
+            ContractError::OptionNotExpired { expires } => {
+                assert_eq!(Expiration::AtHeight(100_000), expires)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // with funds cannot execute
+        let info = mock_info("owner", &counter_offer);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let err = handle_burn(deps.as_mut(), info, env, 1).unwrap_err();
+        match err {
+            ContractError::FundsSentWithBurn {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
 
-    Ok(InitResponse::default())
-}
+        // expired returns funds
+        let info = mock_info("owner", &[]);
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let res = handle_burn(deps.as_mut(), info, env, 1).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: collateral,
+            })
+        );
 
-// And declare a custom Error variant for the ones where you will want to make use of it
-pub fn handle<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    info: MessageInfo,
-    env: Env,
-    msg: HandleMsg,
-) -> StdResult<HandleResponse> {
-    match msg {
-        HandleMsg::Transfer { recipient } => handle_transfer(deps, info, recipient),
-        HandleMsg::Execute {} => handle_execute(deps, info, env),
-        HandleMsg::Burn {} => handle_burn(deps, info, env),
+        // check deleted
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
     }
-}
-pub fn handle_transfer<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    info: MessageInfo,
-    recipient: HumanAddr,
-) -> StdResult<HandleResponse> {
-    let mut state: State = config(&mut deps.storage).load()?;
 
-    // ensure msg.sender is the owner
-    if info.sender != state.owner {
-        return Err(StdError::generic_err("Sender must be owner"));
-    }
+    #[test]
+    fn burn_reward() {
+        let mut deps = mock_dependencies();
 
-    // set ne owner on state
-    state.owner = recipient.clone();
-    config(&mut deps.storage).save(&state)?;
+        // rewards are capped at 100%
+        let msg = InstantiateMsg {
+            burn_reward_bps: 10_001,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1_000, "BTC"));
+        let err = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::InvalidBps { bps } => assert_eq!(10_001, bps),
+            e => panic!("unexpected error: {}", e),
+        }
 
-    let mut res = Context::new();
-    res.add_attribute("action", "transfer");
-    res.add_attribute("owner", recipient);
-    Ok(res.into())
-}
+        let msg = InstantiateMsg {
+            burn_reward_bps: 150,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &[coin(1_000, "BTC"), coin(10, "ATOM")]);
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000, "BTC"));
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-pub fn handle_execute<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    info: MessageInfo,
-    env: Env,
-) -> StdResult<HandleResponse> {
-    // ensure message sender is the owner
-    let state: State = config(&mut deps.storage).load()?;
-    if info.sender != state.owner {
-        return Err(StdError::generic_err("Sender must be owner"));
-    }
-
-    // ensure not expired
-    if env.block.height >= state.expires {
-        return Err(StdError::generic_err("option expired"));
-    }
-    // ensure sending proper counter_offer
-    if info.sent_funds != state.counter_offer {
-        return Err(StdError::generic_err(format!(
-            "must send exact counter_offer: {:?}",
-            state.counter_offer
-        )));
-    }
-    // release counter_offer to creator
-    let mut res = Context::new();
-    res.add_message(BankMsg::Send {
-        from_address: env.contract.address.clone(),
-        to_address: state.creator,
-        amount: state.counter_offer,
-    });
+        let mut env = mock_env();
+        env.block.height = 200_000;
 
-    // release collateral to sender
-    res.add_message(BankMsg::Send {
-        from_address: env.contract.address,
-        to_address: state.owner,
-        amount: state.collateral,
-    });
+        // a keeper gets 1.5% of the collateral, dust rounds down to the creator
+        let info = mock_info("keeper", &[]);
+        let res = handle_burn(deps.as_mut(), info, env.clone(), 1).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(985, "BTC"), coin(10, "ATOM")],
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "keeper".into(),
+                amount: coins(15, "BTC"),
+            })
+        );
 
-    // delete the option
-    config(&mut deps.storage).remove();
+        // creators burning their own option get everything back
+        let info = mock_info("creator", &[]);
+        let res = handle_burn(deps.as_mut(), info, env, 2).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(1_000, "BTC"),
+            })
+        );
+    }
 
-    res.add_attribute("action", "execute");
-    Ok(res.into())
-}
+    #[test]
+    fn execute_fee() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(vec![coin(1_000, "ETH"), coin(50, "DAI")]),
+            fee_bps: 30,
+            fee_collector: Some("collector".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-pub fn handle_burn<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    info: MessageInfo,
-    env: Env,
-) -> StdResult<HandleResponse> {
-    let state: State = config(&mut deps.storage).load()?;
-    // ensure is expired
-    if env.block.height < state.expires {
-        return Err(StdError::generic_err("option not yet expired"));
-    }
+        let info = mock_info("creator", &[coin(1_000, "ETH"), coin(50, "DAI")]);
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(997, "ETH"), coin(50, "DAI")],
+            })
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(3, "ETH"),
+            })
+        );
 
-    // ensure not sending the counter_offer
-    if !info.sent_funds.is_empty() {
-        return Err(StdError::generic_err("don't send funds with burn"));
+        // cw20 fees are paid in the token
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let counter_offer = Cw20CoinVerified {
+            address: Addr::unchecked("token"),
+            amount: Uint128::new(10_000),
+        };
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Cw20(counter_offer),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("token", &[]);
+        let wrapper = Cw20ReceiveMsg {
+            sender: "creator".to_string(),
+            amount: Uint128::new(10_000),
+            msg: to_json_binary(&ReceiveMsg::Execute {
+                option_id: 2,
+                recipient: None,
+                referrer: None,
+                deadline: None,
+                min_payout: None,
+            })
+            .unwrap(),
+        };
+        let res = handle_receive(deps.as_mut(), info, mock_env(), wrapper).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".into(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "collector".into(),
+                    amount: Uint128::new(30),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
     }
 
-    // release collateral to creator
-    let mut res = Context::new();
-    res.add_message(BankMsg::Send {
-        from_address: env.contract.address,
-        to_address: state.creator,
-        amount: state.collateral,
-    });
-
-    // delete the option
-    config(&mut deps.storage).remove();
-    res.add_attribute("action", "burn");
-    Ok(res.into())
-}
+    #[test]
+    fn referral_fee() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(1_000, "ETH")),
+            fee_bps: 100,
+            fee_collector: Some("collector".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msg = UpdateConfigMsg {
+            referral_bps: Some(4_000),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = handle_update_config(deps.as_mut(), mock_info("creator", &[]), mock_env(), msg)
+            .unwrap();
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    _env: Env,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Config {} => to_binary(&query_config(deps)?),
-    }
-}
+        let info = mock_info("creator", &coins(1_000, "ETH"));
+        let me = Some("creator".to_string());
+        let err =
+            handle_execute(deps.as_mut(), info.clone(), mock_env(), 1, None, me, None).unwrap_err();
+        assert!(matches!(err, ContractError::SelfReferral {}));
 
-fn query_config<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<ConfigResponse> {
-    let state = config_read(&deps.storage).load()?;
-    Ok(state)
-}
+        // the referrer gets 40% of the fee, the collector the rest
+        let referrer = Some("wallet".to_string());
+        let res = handle_execute(deps.as_mut(), info, mock_env(), 1, None, referrer, None).unwrap();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "wallet".into(),
+                    amount: coins(4, "ETH"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "collector".into(),
+                    amount: coins(6, "ETH"),
+                }),
+            ],
+            res.messages[2..]
+                .iter()
+                .map(|m| m.msg.clone())
+                .collect::<Vec<_>>()
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
-    use cosmwasm_std::{coins, attr, CosmosMsg};
+        let msg = QueryMsg::ReferralStats {
+            address: "wallet".to_string(),
+        };
+        let stats: ReferralStatsResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, stats.referrals);
+        assert_eq!(coins(4, "ETH"), stats.earned);
+    }
 
     #[test]
-    fn proper_initialization() {
-        let msg = InitMsg {
-            counter_offer: coins(40, "ETH"),
-            expires: 100_000,
+    fn update_config() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            admin: Some("admin".to_string()),
+            ..instantiate_msg()
         };
-        let mut deps = mock_dependencies(&[]);
         let info = mock_info("creator", &coins(1, "BTC"));
-        let env = mock_env();
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-        // we can jut call .unwrap() to assert this was a success
-        let res = init(&mut deps, env, info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        let res = query_admin_config(deps.as_ref()).unwrap();
+        assert_eq!("admin", res.admin.as_str());
+        assert_eq!("creator", res.fee_collector.as_str());
 
-        // It worked, let's query the state
-        let res = query_config(&deps).unwrap();
-        assert_eq!(100_000, res.expires);
-        assert_eq!("creator", res.owner.as_str());
-        assert_eq!("creator", res.creator.as_str());
-        assert_eq!(coins(1, "BTC"), res.collateral);
-        assert_eq!(coins(40, "ETH"), res.counter_offer);
+        // only the admin can update
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            fee_bps: Some(25),
+            fee_collector: Some("treasury".to_string()),
+            allowed_denoms: Some(vec!["BTC".to_string()]),
+            oracle: Some("oracle".to_string()),
+            ..UpdateConfigMsg::default()
+        });
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        match err {
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
+        }
+        let info = mock_info("admin", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query_admin_config(deps.as_ref()).unwrap();
+        assert_eq!("admin", res.admin.as_str());
+        assert_eq!(0, res.burn_reward_bps);
+        assert_eq!(25, res.fee_bps);
+        assert_eq!("treasury", res.fee_collector.as_str());
+        assert_eq!(vec!["BTC".to_string()], res.allowed_denoms);
+        assert_eq!(Some(Addr::unchecked("oracle")), res.oracle);
 
+        // fees are still bounded
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            burn_reward_bps: Some(20_000),
+            ..UpdateConfigMsg::default()
+        });
+        let info = mock_info("admin", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        match err {
+            ContractError::InvalidBps { bps } => assert_eq!(20_000, bps),
+            e => panic!("unexpected error: {}", e),
+        }
     }
 
     #[test]
-    fn transfer() {
-        let mut deps = mock_dependencies(&[]);
-        let msg = InitMsg {
-            counter_offer: coins(40, "ETH"),
-            expires: 100_000,
+    fn pause() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            guardian: Some("guardian".to_string()),
+            ..instantiate_msg()
         };
-        let env = mock_env();
         let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-        let res = init(&mut deps, env, info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
-
-        // random cannot transfer
-        let info = mock_info("anyone", &[]);
-        let err = handle_transfer(&mut deps, info, HumanAddr::from("anyone")).unwrap_err();
+        // only the guardian can pause, not even the admin
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Pause {}).unwrap_err();
         match err {
-            StdError::GenericErr { .. } => {}
+            ContractError::Unauthorized {} => {}
             e => panic!("unexpected error: {}", e),
         }
+        let info = mock_info("guardian", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Pause {}).unwrap();
+        assert!(query_admin_config(deps.as_ref()).unwrap().paused);
 
-        // owner can transfer
+        // creating, transferring and executing are halted
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
         let info = mock_info("creator", &[]);
-        let res = handle_transfer(&mut deps,info, HumanAddr::from("someone")).unwrap();
-        assert_eq!(res.attributes.len(), 2);
-        assert_eq!(res.attributes[0], attr("action", "transfer"));
+        let err = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "other".to_string(),
+            "1".to_string(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let msg = ExecuteMsg::OfferTransfer {
+            option_id: 1,
+            recipient: "other".to_string(),
+            expires: Expiration::Never {},
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let msg = ExecuteMsg::Merge {
+            option_ids: vec![1, 2],
+        };
+        let err = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
 
-        // check updated properly
-        let res = query_config(&deps).unwrap();
-        assert_eq!(100_000, res.expires);
-        assert_eq!("someone", res.owner.as_str());
-        assert_eq!("creator", res.creator.as_str());
-        assert_eq!(coins(1, "BTC"), res.collateral);
-        assert_eq!(coins(40, "ETH"), res.counter_offer);
+        // expired options can still be burned
+        let mut env = mock_env();
+        env.block.height = 200_000;
+        let info = mock_info("creator", &[]);
+        let _ = handle_burn(deps.as_mut(), info, env, 1).unwrap();
 
+        let info = mock_info("guardian", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Unpause {}).unwrap();
+        assert!(!query_admin_config(deps.as_ref()).unwrap().paused);
     }
 
     #[test]
-    fn execute() {
-        let mut deps = mock_dependencies(&[]);
+    fn multiple_options() {
+        let mut deps = setup();
 
-        let counter_offer = coins(40, "ETH");
+        // anyone can write another option next to the first one
+        let info = mock_info("writer", &coins(5, "ATOM"));
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(10, "OSMO")),
+            expires: Expiration::AtHeight(150_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.attributes[0], attr("action", "create"));
+        assert_eq!(res.attributes[1], attr("option_id", "2"));
+
+        let res = query_config(deps.as_ref(), 2).unwrap();
+        assert_eq!("writer", res.state.creator.as_str());
+        assert_eq!(coins(5, "ATOM"), res.state.collateral);
+        assert_eq!(Expiration::AtHeight(150_000), res.state.expires);
+
+        // settling one option leaves the other untouched
+        let info = mock_info("writer", &coins(10, "OSMO"));
+        let msg = ExecuteMsg::Execute {
+            option_id: 2,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let _ = query_config(deps.as_ref(), 2).unwrap_err();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("creator", res.state.owner.as_str());
+
+        // unknown ids cannot be executed
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 3, None, None, None).unwrap_err();
+    }
+
+    #[test]
+    fn execute_cw20() {
+        let mut deps = mock_dependencies();
+
+        let counter_offer = Cw20CoinVerified {
+            address: Addr::unchecked("token"),
+            amount: Uint128::new(40),
+        };
         let collateral = coins(1, "BTC");
-        let msg = InitMsg {
-            counter_offer: counter_offer.clone(),
-            expires: 100_000
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Cw20(counter_offer.clone()),
+            ..instantiate_msg()
         };
         let info = mock_info("creator", &collateral);
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-        let _ = init(&mut deps, mock_env(), info, msg).unwrap();
-
-        // set a new owner
         let info = mock_info("creator", &[]);
-        let _ = handle_transfer(&mut deps, info, HumanAddr::from("owner")).unwrap();
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
 
-        // random person cannot execute
-        let info = mock_info("anyone", &counter_offer);
-        let err = handle_execute(&mut deps, info, mock_env()).unwrap_err();
-        match err {
-            StdError::GenericErr { msg,.. } => assert_eq!("Sender must be owner", msg.as_str()),
-            e => panic!("unexpected error : {}", e),
-        }
+        let hook = |sender: &str, amount: u128| Cw20ReceiveMsg {
+            sender: sender.to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::Execute {
+                option_id: 1,
+                recipient: None,
+                referrer: None,
+                deadline: None,
+                min_payout: None,
+            })
+            .unwrap(),
+        };
 
-        // expired cannot execute
-        let info = mock_info("owner", &counter_offer);
-        let mut env = mock_env();
-        env.block.height = 200_000;
-        let err = handle_execute(&mut deps, info, env).unwrap_err();
+        // native funds cannot execute a cw20 counter_offer
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err = handle_execute(deps.as_mut(), info, mock_env(), 1, None, None, None).unwrap_err();
         match err {
-            StdError::GenericErr { msg, .. } => {
-                assert_eq!("option expired", msg.as_str())
-            },
+            ContractError::CounterOfferMismatch { .. } => {}
             e => panic!("unexpected error: {}", e),
         }
 
-        // bad counter_offer cannot execute
-        let info = mock_info("owner", &coins(39, "ETH"));
-        let err = handle_execute(&mut deps, info, mock_env()).unwrap_err();
+        // only the owner's tokens count
+        let info = mock_info("token", &[]);
+        let err = handle_receive(deps.as_mut(), info, mock_env(), hook("anyone", 40)).unwrap_err();
         match err {
-            StdError::GenericErr {msg, ..} => assert_eq!(format!("must send exact counter_offer: {:?}", &counter_offer), msg.as_str()),
-            e => panic!("unexpected error : {}", e),
+            ContractError::Unauthorized {} => {}
+            e => panic!("unexpected error: {}", e),
         }
 
+        // the wrong token cannot execute
+        let info = mock_info("other_token", &[]);
+        let _ = handle_receive(deps.as_mut(), info, mock_env(), hook("owner", 40)).unwrap_err();
 
-        // proper execution
-        let info = mock_info("owner", &counter_offer);
-        let res = handle_execute(&mut deps, info, mock_env()).unwrap();
+        // the wrong amount cannot execute
+        let info = mock_info("token", &[]);
+        let _ = handle_receive(deps.as_mut(), info, mock_env(), hook("owner", 39)).unwrap_err();
+
+        // proper execution pays the creator in tokens
+        let info = mock_info("token", &[]);
+        let res = handle_receive(deps.as_mut(), info, mock_env(), hook("owner", 40)).unwrap();
         assert_eq!(res.messages.len(), 2);
-        assert_eq!(res.messages[0], CosmosMsg::Bank(BankMsg::Send {
-            from_address: MOCK_CONTRACT_ADDR.into(),
-            to_address: "creator".into(),
-            amount: counter_offer,
-        }));
-        assert_eq!(res.messages[1], CosmosMsg::Bank(BankMsg::Send {
-            from_address: MOCK_CONTRACT_ADDR.into(),
-            to_address: "owner".into(),
-            amount: collateral,
-        }));
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".into(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "creator".into(),
+                    amount: Uint128::new(40),
+                })
+                .unwrap(),
+                funds: vec![],
+            })
+        );
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: collateral,
+            })
+        );
 
         // check deleted
-        let _ = query_config(&deps).unwrap_err();
-
-
-
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
     }
 
     #[test]
-    fn burn() {
-        let mut deps = mock_dependencies(&[]);
-
-        let counter_offer = coins(40, "ETH");
-        let collateral = coins(1, "BTC");
-        let msg = InitMsg {
-            counter_offer: counter_offer.clone(),
-            expires: 100_000
-        };
-        let info = mock_info("creator", &collateral);
+    fn time_expiration() {
+        let mut deps = mock_dependencies();
+        let now = mock_env().block.time;
 
-        let _ = init(&mut deps, mock_env(), info, msg).unwrap();
+        // cannot create options that are already expired or never expire
+        for expires in &[Expiration::AtTime(now), Expiration::Never {}] {
+            let msg = InstantiateMsg {
+                expires: *expires,
+                ..instantiate_msg()
+            };
+            let info = mock_info("creator", &coins(1, "BTC"));
+            let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        }
 
-        // set a new owner
-        let info = mock_info("creator", &[]);
-        let _ = handle_transfer(&mut deps, info, HumanAddr::from("owner")).unwrap();
+        let msg = InstantiateMsg {
+            expires: Expiration::AtTime(now.plus_seconds(1_000)),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
-        // non-expired cannot execute
-        let info = mock_info("owner", &counter_offer);
-        let err = handle_burn(&mut deps, info, mock_env()).unwrap_err();
+        // burning is evaluated against block time, not height
+        let mut env = mock_env();
+        env.block.height += 1_000_000;
+        let info = mock_info("anyone", &[]);
+        let err = handle_burn(deps.as_mut(), info, env, 1).unwrap_err();
         match err {
-            StdError::GenericErr { msg, .. } => {
-                assert_eq!("option not yet expired", msg.as_str())
-            },
+            ContractError::OptionNotExpired { .. } => {}
             e => panic!("unexpected error: {}", e),
         }
 
-        // with funds cannot execute
-        let info = mock_info("owner", &counter_offer);
+        // once the time passes, it can no longer be executed
         let mut env = mock_env();
-        env.block.height = 200_000;
-        let err = handle_burn(&mut deps, info, env).unwrap_err();
+        env.block.time = now.plus_seconds(1_000);
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let err =
+            handle_execute(deps.as_mut(), info, env.clone(), 1, None, None, None).unwrap_err();
         match err {
-            StdError::GenericErr { msg, .. } => {
-                assert_eq!("don't send funds with burn", msg.as_str())
+            ContractError::OptionExpired { expired } => {
+                assert_eq!(Expiration::AtTime(now.plus_seconds(1_000)), expired)
             }
             e => panic!("unexpected error: {}", e),
         }
 
-        // expired returns funds
-        let info = mock_info("owner", &[]);
-        let mut env = mock_env();
-        env.block.height = 200_000;
-        let res = handle_burn(&mut deps, info, env).unwrap();
+        // but it can be burned
+        let info = mock_info("anyone", &[]);
+        let res = handle_burn(deps.as_mut(), info, env, 1).unwrap();
         assert_eq!(res.messages.len(), 1);
+    }
+
+    #[test]
+    fn migration() {
+        let mut deps = setup();
+
+        let version = get_contract_version(&deps.storage).unwrap();
+        assert_eq!(CONTRACT_NAME, version.contract);
+        assert_eq!(CONTRACT_VERSION, version.version);
+
+        // migrating keeps the options around
+        let _ = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!("creator", res.state.creator.as_str());
+
+        // cannot migrate from a different contract
+        set_contract_version(&mut deps.storage, "crates.io:cw20-base", "0.3.2").unwrap();
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        match err {
+            ContractError::CannotMigrate { previous_contract } => {
+                assert_eq!("crates.io:cw20-base", previous_contract)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn stats_rebuilt_in_pages() {
+        let mut deps = setup();
+        let msg = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = handle_create(deps.as_mut(), info.clone(), mock_env(), msg.clone()).unwrap();
+
+        // migrating starts the stats over rather than counting every option at once
+        let _ = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(
+            StatsResponse::default(),
+            query_stats(deps.as_ref()).unwrap()
+        );
+
+        // options written meanwhile are left to the rebuild
+        let _ = handle_create(deps.as_mut(), info, mock_env(), msg).unwrap();
+        assert_eq!(0, query_stats(deps.as_ref()).unwrap().active_options);
+
+        // so are the funds the contract tracks, which can't be recovered until they are counted
+        let recover = ExecuteMsg::RecoverFunds {
+            denom: "BTC".to_string(),
+            amount: Uint128::new(1),
+            recipient: "creator".to_string(),
+        };
+        let admin = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), admin, recover).unwrap_err();
+        assert!(matches!(err, ContractError::StatsRebuilding {}));
+
+        let res = handle_rebuild_stats(deps.as_mut(), 2).unwrap();
+        assert_eq!(res.attributes[1], attr("ledger", "options"));
+        assert_eq!(res.attributes[2], attr("last_id", "2"));
+        assert_eq!(res.attributes[3], attr("done", "false"));
+        assert_eq!(2, query_stats(deps.as_ref()).unwrap().active_options);
+
+        let res = handle_rebuild_stats(deps.as_mut(), 2).unwrap();
+        assert_eq!(res.attributes[1], attr("ledger", "vaults"));
+        assert_eq!(res.attributes[3], attr("done", "true"));
+        assert_eq!(
+            coins(3, "BTC"),
+            recovery::query_reconcile(deps.as_ref(), mock_env())
+                .unwrap()
+                .tracked
+        );
+        assert_eq!(
+            StatsResponse {
+                active_options: 3,
+                collateral: coins(3, "BTC"),
+                counter_offer: coins(120, "ETH"),
+            },
+            query_stats(deps.as_ref()).unwrap()
+        );
+
+        // once done, options are counted as they are written again
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = handle_execute(deps.as_mut(), info, mock_env(), 3, None, None, None).unwrap();
+        assert_eq!(2, query_stats(deps.as_ref()).unwrap().active_options);
+        let err = handle_rebuild_stats(deps.as_mut(), 2).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToRebuild {}));
+    }
+
+    #[test]
+    fn sudo_intervention() {
+        let mut deps = setup();
+        let info = mock_info("creator", &[]);
+        let _ = handle_transfer_nft(
+            deps.as_mut(),
+            info,
+            mock_env(),
+            "owner".to_string(),
+            "1".to_string(),
+        )
+        .unwrap();
+
+        // governance can pause without the guardian
+        let msg = SudoMsg::SetPaused { paused: true };
+        let res = sudo(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[0], attr("action", "pause"));
+        assert!(query_admin_config(deps.as_ref()).unwrap().paused);
+
+        // and settle options even while paused
+        let msg = SudoMsg::ForceSettle { option_id: 1 };
+        let res = sudo(deps.as_mut(), mock_env(), msg).unwrap();
         assert_eq!(
-            res.messages[0],
+            res.messages[0].msg,
             CosmosMsg::Bank(BankMsg::Send {
-                from_address: MOCK_CONTRACT_ADDR.into(),
                 to_address: "creator".into(),
-                amount: collateral,
+                amount: coins(1, "BTC"),
             })
         );
+        let _ = query_config(deps.as_ref(), 1).unwrap_err();
 
-        // check deleted
-        let _ = query_config(&deps).unwrap_err();
+        let msg = SudoMsg::SetPaused { paused: false };
+        let _ = sudo(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(!query_admin_config(deps.as_ref()).unwrap().paused);
+    }
+    #[test]
+    fn alt_counter_offers() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(40, "USDC")),
+            alt_counter_offers: vec![Asset::Native(coins(38, "USDT"))],
+            owner: Some("owner".to_string()),
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert!(res
+            .attributes
+            .contains(&attr("alt_counter_offers", "38USDT")));
+
+        // cash settled options only have a strike
+        let msg = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(40, "USDC")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Cash,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![Asset::Native(coins(38, "USDT"))],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCounterOffer {}));
+
+        // the parts would have to split every alternative
+        let owner = mock_info("owner", &[]);
+        let msg = ExecuteMsg::Split {
+            option_id: 1,
+            parts: 2,
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner, msg).unwrap_err();
+        assert!(matches!(err, ContractError::AltCounterOffers {}));
+
+        // paying neither is a mismatch with the counter_offer
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(30, "USDT"));
+        let err = execute(deps.as_mut(), mock_env(), info, execute_msg.clone()).unwrap_err();
+        match err {
+            ContractError::CounterOfferMismatch { expected, .. } => {
+                assert_eq!(Asset::Native(coins(40, "USDC")), expected)
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        // the creator is paid whichever alternative was sent
+        let info = mock_info("owner", &coins(38, "USDT"));
+        let res = execute(deps.as_mut(), mock_env(), info, execute_msg).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(38, "USDT"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".into(),
+                    amount: coins(1, "BTC"),
+                }),
+            ],
+            msgs
+        );
+    }
+    #[test]
+    fn strike_price() {
+        let mut deps = mock_dependencies();
+        let msg = |counter_offer: Vec<Coin>| InstantiateMsg {
+            counter_offer: Asset::Native(counter_offer),
+            strike: Some(Decimal::percent(400)),
+            ..instantiate_msg()
+        };
+        // the strike can only price one coin in another
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let counter_offer = vec![coin(1, "ETH"), coin(1, "USDC")];
+        let err =
+            instantiate(deps.as_mut(), mock_env(), info.clone(), msg(counter_offer)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStrike {}));
+
+        // only the denom of the counter_offer counts, the amount follows the collateral
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg(coins(1, "ETH"))).unwrap();
+        assert!(res.attributes.contains(&attr("counter_offer", "40ETH")));
+        assert!(res.attributes.contains(&attr("strike", "4")));
+        let msg = ExecuteMsg::AddCollateral { option_id: 1 };
+        let info = mock_info("creator", &coins(5, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(Asset::Native(coins(60, "ETH")), res.state.counter_offer);
+        let msg = ExecuteMsg::AddCollateral { option_id: 1 };
+        let info = mock_info("creator", &coins(5, "ATOM"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStrike {}));
+
+        // part of the collateral costs the strike for it, the rest keeps the same strike
+        let msg = ExecuteMsg::ExecutePartial {
+            option_id: 1,
+            fraction: Decimal::percent(40),
+            recipient: None,
+        };
+        let info = mock_info("creator", &coins(24, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(6, "BTC"),
+            }),
+            res.messages[1].msg
+        );
+        let res = query_config(deps.as_ref(), 1).unwrap();
+        assert_eq!(coins(9, "BTC"), res.state.collateral);
+        assert_eq!(Asset::Native(coins(36, "ETH")), res.state.counter_offer);
     }
 }
@@ -0,0 +1,223 @@
+//! Options written by several writers pooling their collateral.
+//!
+//! While its creator still owns the option, anyone can add to its collateral and become one of
+//! its writers. The first contribution hands the creator role to the contract, so whatever the
+//! option would have paid its creator, the counter_offer on execution or the collateral on burn,
+//! stays in the contract as proceeds. Once the option is closed, each writer claims the part of
+//! the proceeds matching their part of the collateral.
+
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128};
+
+use crate::contract::{assert_not_paused, option_attributes};
+use crate::error::ContractError;
+use crate::msg::{ContributionResponse, WritingPoolResponse};
+use crate::shares::pay_portion;
+use crate::state::{
+    options, remove_held, save_held, save_option, State, WritingPool, CONTRIBUTIONS, WRITING_POOLS,
+};
+use crate::yield_strategy;
+
+/// Adds the single coin sent to the collateral, booking it as the sender's contribution.
+pub fn handle_co_write(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    // the deposit was for the collateral it has now
+    yield_strategy::assert_withdrawn(deps.storage, option_id)?;
+    let pool = WRITING_POOLS.may_load(deps.storage, option_id)?;
+    // fractionalized options already pay the contract
+    if pool.is_none() && state.creator == env.contract.address {
+        return Err(ContractError::InvalidContribution {});
+    }
+    let lead = pool.as_ref().map_or(&state.creator, |pool| &pool.lead);
+    if state.owner != *lead {
+        return Err(ContractError::InvalidContribution {});
+    }
+    let coin = match (info.funds.as_slice(), state.collateral.as_mut_slice()) {
+        ([coin], [collateral]) if coin.denom == collateral.denom && !coin.amount.is_zero() => {
+            collateral.amount += coin.amount;
+            coin
+        }
+        _ => return Err(ContractError::InvalidContribution {}),
+    };
+
+    let mut pool = match pool {
+        Some(pool) => pool,
+        None => {
+            // the creator's collateral is the first contribution
+            let written = state.collateral[0].amount - coin.amount;
+            CONTRIBUTIONS.save(deps.storage, (option_id, &state.creator), &written)?;
+            let pool = WritingPool {
+                lead: state.creator.clone(),
+                total: written,
+                proceeds: vec![],
+                cw20_proceeds: vec![],
+            };
+            state.creator = env.contract.address.clone();
+            pool
+        }
+    };
+    pool.total += coin.amount;
+    CONTRIBUTIONS.update(
+        deps.storage,
+        (option_id, &info.sender),
+        |written| -> StdResult<_> { Ok(written.unwrap_or_default() + coin.amount) },
+    )?;
+    save_held(deps.storage, &WRITING_POOLS, option_id, &pool)?;
+    save_option(deps.storage, option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "co_write")
+        .add_attribute("writer", info.sender)
+        .add_attribute("contributed", coin.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Pays the sender their part of the proceeds of a closed co-written option.
+pub fn handle_claim_co_written(
+    deps: DepsMut,
+    info: MessageInfo,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    if options().has(deps.storage, option_id) {
+        return Err(ContractError::OptionStillOpen {});
+    }
+    let mut pool = WRITING_POOLS.load(deps.storage, option_id)?;
+    let written = CONTRIBUTIONS
+        .may_load(deps.storage, (option_id, &info.sender))?
+        .ok_or(ContractError::Unauthorized {})?;
+
+    let res = pay_portion(
+        &mut pool.proceeds,
+        &mut pool.cw20_proceeds,
+        written,
+        pool.total,
+        &info.sender,
+    )?;
+    CONTRIBUTIONS.remove(deps.storage, (option_id, &info.sender));
+    pool.total -= written;
+    if pool.total.is_zero() {
+        remove_held(deps.storage, &WRITING_POOLS, option_id)?;
+    } else {
+        save_held(deps.storage, &WRITING_POOLS, option_id, &pool)?;
+    }
+
+    Ok(res
+        .add_attribute("action", "claim_co_written")
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("writer", info.sender)
+        .add_attribute("contribution", written))
+}
+
+pub fn query_writing_pool(deps: Deps, option_id: u64) -> StdResult<WritingPoolResponse> {
+    WRITING_POOLS.may_load(deps.storage, option_id)
+}
+
+pub fn query_contribution(
+    deps: Deps,
+    option_id: u64,
+    writer: String,
+) -> StdResult<ContributionResponse> {
+    let writer = deps.api.addr_validate(&writer)?;
+    Ok(CONTRIBUTIONS
+        .may_load(deps.storage, (option_id, &writer))?
+        .unwrap_or(Uint128::zero()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::ExecuteMsg;
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, BankMsg, CosmosMsg};
+
+    #[test]
+    fn co_write_and_claim() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // only the collateral denom is taken
+        let msg = ExecuteMsg::CoWrite { option_id: 1 };
+        let info = mock_info("alice", &coins(5, "ATOM"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidContribution {}));
+        let info = mock_info("alice", &coins(30, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap();
+
+        let state = options().load(&deps.storage, 1).unwrap();
+        assert_eq!(coins(40, "BTC"), state.collateral);
+        assert_eq!(mock_env().contract.address, state.creator);
+        let contribution = |deps: Deps, writer: &str| {
+            query_contribution(deps, 1, writer.to_string())
+                .unwrap()
+                .u128()
+        };
+        assert_eq!(10, contribution(deps.as_ref(), "creator"));
+        assert_eq!(30, contribution(deps.as_ref(), "alice"));
+
+        // executing keeps the counter_offer for the writers, who can't claim before it closes
+        let claim = ExecuteMsg::ClaimCoWritten { option_id: 1 };
+        let info = mock_info("alice", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::OptionStillOpen {}));
+        let info = mock_info("creator", &[]);
+        let transfer = ExecuteMsg::TransferNft {
+            recipient: "owner".to_string(),
+            token_id: "1".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, transfer).unwrap();
+        let info = mock_info("bob", &coins(10, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidContribution {}));
+
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, execute_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let pool = query_writing_pool(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(coins(40, "ETH"), pool.proceeds);
+
+        let paid =
+            |res: Response| -> Vec<CosmosMsg> { res.messages.into_iter().map(|m| m.msg).collect() };
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), claim.clone()).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".into(),
+                amount: coins(30, "ETH"),
+            })],
+            paid(res)
+        );
+        let err = execute(deps.as_mut(), mock_env(), info, claim.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, claim).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(10, "ETH")],
+            })],
+            paid(res)
+        );
+        assert_eq!(None, query_writing_pool(deps.as_ref(), 1).unwrap());
+    }
+}
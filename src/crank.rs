@@ -0,0 +1,337 @@
+//! Clearing expired options in bulk, for a reward out of a pool anyone can fund.
+
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    BankMsg, Coin, Coins, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult,
+    Uint128,
+};
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
+
+use crate::circuit_breaker;
+use crate::contract::{
+    assert_not_paused, burnable_from, exercise_expired, handle_burn, handle_settle_binary,
+    MAX_LIMIT,
+};
+use crate::error::ContractError;
+use crate::msg::CrankPoolResponse;
+use crate::staking;
+use crate::state::{expiration_key, options, Settlement, State, CONFIG, CRANK_CURSOR, CRANK_POOL};
+use crate::yield_strategy;
+
+/// Most expired options a crank looks at, settled or not
+const MAX_SCAN: usize = 3 * MAX_LIMIT as usize;
+
+/// Whether the crank settles the option: once it can be burned, unless keepers roll it over or
+/// a token holder would be owed the payout.
+fn crankable(state: &State, grace: u64, env: &Env) -> bool {
+    let cash = matches!(
+        state.settlement,
+        Settlement::Cash | Settlement::Average { .. }
+    );
+    burnable_from(state, grace).is_expired(&env.block)
+        && !state.roll_on_expiry
+        && !(cash && state.denom.is_some())
+}
+
+/// Anyone can add the funds sent to the pool crank rewards are paid from
+pub fn handle_fund_crank_pool(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    if info.funds.is_empty() {
+        return Err(ContractError::InvalidCrankFunds {});
+    }
+    let mut pool = Coins::try_from(CRANK_POOL.may_load(deps.storage)?.unwrap_or_default())
+        .map_err(StdError::from)?;
+    for coin in info.funds {
+        pool.add(coin)?;
+    }
+    CRANK_POOL.save(deps.storage, &pool.to_vec())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_crank_pool")
+        .add_attribute("pool", pool.to_string()))
+}
+
+/// Settles up to `limit` expired options, the earliest first: physical ones are burned, cash
+/// settled ones executed for their owner, and binary ones settled. The caller gets the crank
+/// reward for each out of the pool, as far as it goes, on top of any burn reward.
+/// Each crank looks at no more than `MAX_SCAN` options, continuing after the last one the
+/// previous crank looked at until it reaches the latest expired option and starts over.
+pub fn handle_crank(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    limit: u32,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let limit = limit.clamp(1, MAX_LIMIT) as usize;
+    let now = [
+        expiration_key(&Expiration::AtHeight(env.block.height)),
+        expiration_key(&Expiration::AtTime(env.block.time)),
+    ];
+    let started_from = CRANK_CURSOR.may_load(deps.storage)?;
+    let mut cursor = started_from;
+    let mut scanned = 0;
+    let mut finished = true;
+    let mut due: Vec<(u64, State)> = vec![];
+    'ranges: for end in now {
+        let start = match cursor {
+            Some(last) if last.0 .0 > end.0 => continue,
+            Some(last) if last.0 .0 == end.0 => Bound::exclusive(last),
+            _ => Bound::inclusive(((end.0, u64::MIN), u64::MIN)),
+        };
+        let expired = options().idx.expires.range(
+            deps.storage,
+            Some(start),
+            Some(Bound::inclusive((end, u64::MAX))),
+            Order::Ascending,
+        );
+        for item in expired {
+            if due.len() == limit || scanned == MAX_SCAN {
+                finished = false;
+                break 'ranges;
+            }
+            let (id, state) = item?;
+            scanned += 1;
+            cursor = Some((expiration_key(&state.expires), id));
+            if crankable(&state, config.burn_grace_period, &env)
+                && staking::assert_liquid(deps.storage, &env, id).is_ok()
+                && yield_strategy::assert_withdrawn(deps.storage, id).is_ok()
+                && circuit_breaker::settles_now(deps.as_ref(), &env, &config, &state)
+            {
+                due.push((id, state));
+            }
+        }
+    }
+    // only a look through every expired option can come up with nothing
+    if due.is_empty() && finished && started_from.is_none() {
+        return Err(ContractError::NothingToCrank {});
+    }
+    match cursor {
+        Some(last) if !finished => CRANK_CURSOR.save(deps.storage, &last)?,
+        _ => CRANK_CURSOR.remove(deps.storage),
+    }
+
+    let keeper = MessageInfo {
+        sender: info.sender.clone(),
+        funds: vec![],
+    };
+    let mut res = Response::new().add_attribute("action", "crank");
+    let mut option_ids = vec![];
+    for (option_id, state) in due {
+        let settled = match state.settlement {
+            Settlement::Physical => {
+                handle_burn(deps.branch(), keeper.clone(), env.clone(), option_id)?
+            }
+            Settlement::Binary { .. } => {
+                handle_settle_binary(deps.branch(), keeper.clone(), env.clone(), option_id)?
+            }
+            Settlement::Cash | Settlement::Average { .. } => {
+                exercise_expired(deps.branch(), &env, &info.sender, option_id, state)?
+            }
+        };
+        res = res
+            .add_submessages(settled.messages)
+            .add_events(settled.events);
+        option_ids.push(option_id.to_string());
+    }
+
+    // as much of the reward as the pool holds
+    let pool = CRANK_POOL.may_load(deps.storage)?.unwrap_or_default();
+    let mut left = Coins::try_from(pool).map_err(StdError::from)?;
+    let mut reward = vec![];
+    for coin in &config.crank_reward {
+        let owed = coin.amount * Uint128::from(option_ids.len() as u64);
+        let paid = owed.min(left.amount_of(&coin.denom));
+        if !paid.is_zero() {
+            left.sub(Coin::new(paid.u128(), &coin.denom))?;
+            reward.push(Coin::new(paid.u128(), &coin.denom));
+        }
+    }
+    CRANK_POOL.save(deps.storage, &left.to_vec())?;
+    if !reward.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: reward,
+        });
+    }
+    Ok(res.add_attribute("option_ids", option_ids.join(",")))
+}
+
+pub fn query_crank_pool(deps: Deps) -> StdResult<CrankPoolResponse> {
+    Ok(CRANK_POOL.may_load(deps.storage)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CreateMsg, ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::state::{Asset, OptionStyle};
+    use crate::testing::{instantiate_msg, mock_oracle};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, CosmosMsg, Decimal};
+
+    #[test]
+    fn crank_expired() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let msg = InstantiateMsg {
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // 10 BTC at 50 ETH is 100 ETH over the strike, worth 2 BTC
+        let cash = CreateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Cash,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: Some("owner".to_string()),
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Create(cash.clone()),
+        )
+        .unwrap();
+        // keepers roll these instead
+        let rolling = CreateMsg {
+            roll_on_expiry: true,
+            ..cash
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(rolling)).unwrap();
+
+        let config = UpdateConfigMsg {
+            crank_reward: Some(coins(5, "uatom")),
+            ..UpdateConfigMsg::default()
+        };
+        let info = mock_info("creator", &[]);
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::UpdateConfig(config),
+        )
+        .unwrap();
+        let info = mock_info("funder", &coins(7, "uatom"));
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::FundCrankPool {},
+        )
+        .unwrap();
+
+        let keeper = mock_info("keeper", &[]);
+        let crank = ExecuteMsg::Crank { limit: 10 };
+        let err = execute(deps.as_mut(), mock_env(), keeper.clone(), crank.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToCrank {}));
+        let mut env = mock_env();
+        env.block.height = 100_000;
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), crank.clone()).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        let sent = |to: &str, amount| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.into(),
+                amount,
+            })
+        };
+        // the pool only covers part of the second reward
+        assert_eq!(
+            vec![
+                sent("creator", coins(1, "BTC")),
+                sent("creator", coins(8, "BTC")),
+                sent("owner", coins(2, "BTC")),
+                sent("keeper", coins(7, "uatom")),
+            ],
+            msgs
+        );
+        assert!(query_crank_pool(deps.as_ref()).unwrap().is_empty());
+        assert!(options().has(&deps.storage, 3));
+        let err = execute(deps.as_mut(), env, keeper, crank).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToCrank {}));
+    }
+
+    #[test]
+    fn crank_looks_at_a_bounded_page() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            roll_on_expiry: true,
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // a page worth of options the crank leaves alone, all expiring before one it settles
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: true,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        for _ in 1..MAX_SCAN {
+            let info = mock_info("creator", &coins(1, "BTC"));
+            let msg = ExecuteMsg::Create(terms.clone());
+            let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let settled = CreateMsg {
+            roll_on_expiry: false,
+            expires: Expiration::AtHeight(100_001),
+            ..terms
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(settled)).unwrap();
+
+        // the first crank gets through the page without settling anything
+        let mut env = mock_env();
+        env.block.height = 100_001;
+        let keeper = mock_info("keeper", &[]);
+        let crank = ExecuteMsg::Crank { limit: 10 };
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), crank.clone()).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(
+            Some(((0, 100_000), MAX_SCAN as u64)),
+            CRANK_CURSOR.may_load(&deps.storage).unwrap()
+        );
+
+        // the next one carries on from there
+        let res = execute(deps.as_mut(), env.clone(), keeper.clone(), crank.clone()).unwrap();
+        assert_eq!(
+            res.attributes[1],
+            ("option_ids", (MAX_SCAN as u64 + 1).to_string())
+        );
+        let closed = res
+            .events
+            .iter()
+            .find(|e| e.ty == "simple_option.close")
+            .unwrap();
+        assert_eq!(("closure", "burned"), closed.attributes[2]);
+        assert!(!CRANK_CURSOR.exists(&deps.storage));
+        let err = execute(deps.as_mut(), env, keeper, crank).unwrap_err();
+        assert!(matches!(err, ContractError::NothingToCrank {}));
+    }
+}
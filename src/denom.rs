@@ -0,0 +1,164 @@
+//! Human readable names for the denoms an option holds or asks for.
+//!
+//! IBC vouchers show up as `ibc/HASH`, where the hash is the sha256 of the trace
+//! `{path}/{base_denom}`. The admin registers traces for the vouchers in use, and since the
+//! hash is recomputed from the trace, a registered name cannot point to the wrong voucher.
+
+use cosmwasm_std::{DepsMut, MessageInfo, Response, StdResult, Storage};
+use sha2::{Digest, Sha256};
+
+use crate::error::ContractError;
+use crate::msg::DenomInfo;
+use crate::state::{Asset, DenomTrace, State, CONFIG, DENOM_TRACES};
+
+const IBC_PREFIX: &str = "ibc/";
+
+/// The `ibc/HASH` voucher denom for `trace`
+pub fn ibc_denom(trace: &DenomTrace) -> String {
+    let hash = Sha256::digest(format!("{}/{}", trace.path, trace.base_denom).as_bytes());
+    let hex: String = hash.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("{}{}", IBC_PREFIX, hex)
+}
+
+/// Rejects malformed IBC denoms, which could never be paid and would make the option unusable
+pub fn validate_denom(denom: &str) -> Result<(), ContractError> {
+    if let Some(hash) = denom.strip_prefix(IBC_PREFIX) {
+        let valid = hash.len() == 64
+            && hash
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('A'..='F').contains(&c));
+        if !valid {
+            return Err(ContractError::InvalidDenom {
+                denom: denom.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_trace(trace: &DenomTrace) -> Result<(), ContractError> {
+    // the path is made of port/channel pairs, one for every hop
+    let segments: Vec<&str> = trace.path.split('/').collect();
+    if trace.base_denom.is_empty()
+        || segments.len() % 2 != 0
+        || segments.iter().any(|s| s.is_empty())
+    {
+        return Err(ContractError::InvalidDenomTrace {});
+    }
+    Ok(())
+}
+
+pub fn denom_info(storage: &dyn Storage, denom: &str) -> StdResult<DenomInfo> {
+    let trace = DENOM_TRACES.may_load(storage, denom)?;
+    let display = match &trace {
+        Some(trace) => trace.base_denom.clone(),
+        None => denom.to_string(),
+    };
+    Ok(DenomInfo {
+        denom: denom.to_string(),
+        display,
+        trace,
+    })
+}
+
+/// Every native denom in the collateral and counter_offer, in that order and without duplicates
+pub fn option_denoms(storage: &dyn Storage, state: &State) -> StdResult<Vec<DenomInfo>> {
+    let mut denoms: Vec<&str> = state.collateral.iter().map(|c| c.denom.as_str()).collect();
+    if let Asset::Native(coins) = &state.counter_offer {
+        denoms.extend(coins.iter().map(|c| c.denom.as_str()));
+    }
+    let mut seen = vec![];
+    denoms
+        .into_iter()
+        .filter(|denom| {
+            let new = !seen.contains(denom);
+            seen.push(*denom);
+            new
+        })
+        .map(|denom| denom_info(storage, denom))
+        .collect()
+}
+
+/// Admin can register the trace of an IBC voucher, so queries can show where it comes from
+pub fn handle_register_denom_trace(
+    deps: DepsMut,
+    info: MessageInfo,
+    path: String,
+    base_denom: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    let trace = DenomTrace { path, base_denom };
+    validate_trace(&trace)?;
+    let denom = ibc_denom(&trace);
+    DENOM_TRACES.save(deps.storage, &denom, &trace)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_denom_trace")
+        .add_attribute("denom", denom)
+        .add_attribute("path", trace.path)
+        .add_attribute("base_denom", trace.base_denom))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_dependencies;
+
+    fn trace(path: &str, base_denom: &str) -> DenomTrace {
+        DenomTrace {
+            path: path.to_string(),
+            base_denom: base_denom.to_string(),
+        }
+    }
+
+    #[test]
+    fn ibc_denoms() {
+        // ATOM on Osmosis
+        assert_eq!(
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+            ibc_denom(&trace("transfer/channel-0", "uatom"))
+        );
+
+        validate_denom("uosmo").unwrap();
+        validate_denom(&ibc_denom(&trace("transfer/channel-0", "uatom"))).unwrap();
+        for denom in [
+            "ibc/27394FB0",
+            "ibc/27394fb092d2eccd56123c74f36e4c1f926001ceada9ca97ea622b25f41e5eb2",
+        ] {
+            let err = validate_denom(denom).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidDenom { .. }));
+        }
+
+        validate_trace(&trace("transfer/channel-0/transfer/channel-141", "uatom")).unwrap();
+        for bad in [
+            trace("transfer", "uatom"),
+            trace("transfer/", "uatom"),
+            trace("transfer/channel-0", ""),
+        ] {
+            let err = validate_trace(&bad).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidDenomTrace {}));
+        }
+    }
+
+    #[test]
+    fn resolve_registered() {
+        let mut deps = mock_dependencies();
+        let atom = trace("transfer/channel-0", "uatom");
+        let denom = ibc_denom(&atom);
+        DENOM_TRACES
+            .save(deps.as_mut().storage, &denom, &atom)
+            .unwrap();
+
+        let info = denom_info(&deps.storage, &denom).unwrap();
+        assert_eq!("uatom", info.display);
+        assert_eq!(Some(atom), info.trace);
+
+        // unknown denoms are shown as they are
+        let info = denom_info(&deps.storage, "uosmo").unwrap();
+        assert_eq!("uosmo", info.display);
+        assert_eq!(None, info.trace);
+    }
+}
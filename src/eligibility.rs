@@ -0,0 +1,319 @@
+//! Eligibility hook gating who can take options.
+//!
+//! With an allowlist contract configured, every transfer or sale asks it whether the address
+//! taking the option is allowed, and fails if it isn't. That includes bids and fills in the
+//! markets, accepted escrows and quotes, strategy transfers and options returned over IBC. An
+//! auction whose winner is no longer allowed closes with the bid refunded instead. Compliance checks such as KYC live in
+//! that contract, while this one enforces them.
+
+use cosmwasm_std::{Addr, QuerierWrapper};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContractError;
+use crate::state::Config;
+
+/// Query interface the configured allowlist contract has to implement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowlistQueryMsg {
+    /// Returns whether `addr` may take options
+    IsAllowed { addr: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsAllowedResponse {
+    pub allowed: bool,
+}
+
+/// Fails unless the configured allowlist, if any, allows `taker` to take an option.
+pub(crate) fn assert_eligible(
+    querier: &QuerierWrapper,
+    config: &Config,
+    taker: &Addr,
+) -> Result<(), ContractError> {
+    let allowlist = match &config.allowlist {
+        Some(allowlist) => allowlist,
+        None => return Ok(()),
+    };
+    let msg = AllowlistQueryMsg::IsAllowed {
+        addr: taker.to_string(),
+    };
+    let res: IsAllowedResponse = querier.query_wasm_smart(allowlist, &msg)?;
+    if !res.allowed {
+        return Err(ContractError::NotEligible {
+            addr: taker.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::ibc::{ibc_channel_connect, ibc_packet_receive, Ack, OptionPacket, IBC_VERSION};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, LegTerms, UpdateConfigMsg};
+    use crate::state::{options, Asset, OptionStyle, Settlement, State};
+    use crate::testing::{instantiate_msg, setup};
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_ibc_channel_connect_ack, mock_ibc_packet_recv, mock_info,
+        MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_json, to_json_binary, BankMsg, Coin, ContractResult, CosmosMsg, IbcOrder,
+        OwnedDeps, SystemResult, WasmQuery,
+    };
+    use cw_utils::Expiration;
+
+    /// Answers the `allowlist` contract, allowing the addresses starting with `prefix`
+    fn mock_allowlist(querier: &mut MockQuerier, prefix: &'static str) {
+        querier.update_wasm(move |query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "allowlist" => {
+                let AllowlistQueryMsg::IsAllowed { addr } = from_json(msg).unwrap();
+                let res = IsAllowedResponse {
+                    allowed: addr.starts_with(prefix),
+                };
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+            }
+            _ => panic!("unexpected query: {:?}", query),
+        });
+    }
+
+    fn use_allowlist(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>) {
+        mock_allowlist(&mut deps.querier, "verified");
+        let config = UpdateConfigMsg {
+            allowlist: Some("allowlist".to_string()),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+    }
+
+    fn not_eligible(
+        deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        sender: &str,
+        funds: &[Coin],
+        msg: ExecuteMsg,
+    ) -> bool {
+        let err = execute(deps.as_mut(), mock_env(), mock_info(sender, funds), msg).unwrap_err();
+        matches!(err, ContractError::NotEligible { .. })
+    }
+
+    #[test]
+    fn gates_transfers_and_sales() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            premium: coins(5, "ETH"),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        use_allowlist(&mut deps);
+
+        let buy = ExecuteMsg::Buy {
+            option_id: 1,
+            referrer: None,
+        };
+        assert!(not_eligible(
+            &mut deps,
+            "anon",
+            &coins(5, "ETH"),
+            buy.clone()
+        ));
+        let info = mock_info("verified_buyer", &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, buy).unwrap();
+
+        let transfer = |recipient: &str| ExecuteMsg::TransferNft {
+            recipient: recipient.to_string(),
+            token_id: "1".to_string(),
+        };
+        assert!(not_eligible(
+            &mut deps,
+            "verified_buyer",
+            &[],
+            transfer("anon")
+        ));
+        let offer = ExecuteMsg::OfferTransfer {
+            option_id: 1,
+            recipient: "anon".to_string(),
+            expires: Expiration::Never {},
+        };
+        assert!(not_eligible(&mut deps, "verified_buyer", &[], offer));
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("verified_buyer", &[]),
+            transfer("verified_friend"),
+        )
+        .unwrap();
+        let state: State = options().load(&deps.storage, 1).unwrap();
+        assert_eq!("verified_friend", state.owner);
+    }
+
+    #[test]
+    fn gates_auctions() {
+        let mut deps = setup();
+        use_allowlist(&mut deps);
+        let msg = ExecuteMsg::StartAuction {
+            option_id: 1,
+            reserve: coin(5, "ETH"),
+            duration: 3600,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let bid = ExecuteMsg::Bid { option_id: 1 };
+        assert!(not_eligible(
+            &mut deps,
+            "anon",
+            &coins(5, "ETH"),
+            bid.clone()
+        ));
+        let info = mock_info("verified_bidder", &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, bid).unwrap();
+
+        // a winner no longer allowed by the close gets the bid back
+        mock_allowlist(&mut deps.querier, "verified_friend");
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let close = ExecuteMsg::CloseAuction { option_id: 1 };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), close).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "verified_bidder".into(),
+                amount: coins(5, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        let state: State = options().load(&deps.storage, 1).unwrap();
+        assert_eq!("creator", state.owner);
+
+        let msg = ExecuteMsg::StartDutchAuction {
+            option_id: 1,
+            start_price: coin(100, "ETH"),
+            floor_price: coin(20, "ETH"),
+            duration: 1000,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let buy = ExecuteMsg::BuyDutch { option_id: 1 };
+        assert!(not_eligible(&mut deps, "anon", &coins(100, "ETH"), buy));
+    }
+
+    #[test]
+    fn gates_escrows_orders_and_quotes() {
+        let mut deps = setup();
+        use_allowlist(&mut deps);
+        let expires = Expiration::AtHeight(mock_env().block.height + 100);
+
+        let msg = ExecuteMsg::EscrowPremium {
+            option_id: 1,
+            deadline: expires,
+        };
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anon", &coins(5, "ETH")),
+            msg,
+        )
+        .unwrap();
+        let accept = ExecuteMsg::AcceptEscrow { escrow_id: 1 };
+        assert!(not_eligible(&mut deps, "creator", &[], accept));
+
+        // on either side of the book, the buyer has to be allowed
+        let bid = ExecuteMsg::PlaceBid {
+            option_id: 1,
+            expires,
+        };
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anon", &coins(7, "ETH")),
+            bid,
+        )
+        .unwrap();
+        assert!(not_eligible(
+            &mut deps,
+            "creator",
+            &[],
+            ExecuteMsg::FillOrder { order_id: 1 }
+        ));
+        let ask = ExecuteMsg::PlaceAsk {
+            option_id: 1,
+            price: coin(9, "ETH"),
+            expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ask).unwrap();
+        let fill = ExecuteMsg::FillOrder { order_id: 2 };
+        assert!(not_eligible(&mut deps, "anon", &coins(9, "ETH"), fill));
+
+        let msg = ExecuteMsg::RequestQuote {
+            counter_offer: Asset::Native(coins(30, "ETH")),
+            collateral: coins(1, "BTC"),
+            expires: Expiration::AtHeight(100_000),
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            deadline: expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("anon", &[]), msg).unwrap();
+        let msg = ExecuteMsg::SubmitQuote {
+            request_id: 1,
+            premium: coins(2, "ETH"),
+        };
+        let info = mock_info("writer", &coins(1, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let accept = ExecuteMsg::AcceptQuote { quote_id: 1 };
+        assert!(not_eligible(&mut deps, "anon", &coins(2, "ETH"), accept));
+    }
+
+    #[test]
+    fn gates_strategies_and_ibc() {
+        let mut deps = setup();
+        use_allowlist(&mut deps);
+
+        let msg = ExecuteMsg::CreateStrategy {
+            legs: vec![
+                LegTerms {
+                    collateral: coins(1, "BTC"),
+                    counter_offer: coins(40, "ETH"),
+                },
+                LegTerms {
+                    collateral: coins(50, "ETH"),
+                    counter_offer: coins(1, "BTC"),
+                },
+            ],
+            expires: Expiration::AtHeight(100_000),
+            style: OptionStyle::American,
+            owner: None,
+        };
+        let info = mock_info("creator", &[coin(1, "BTC"), coin(50, "ETH")]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let transfer = ExecuteMsg::TransferStrategy {
+            strategy_id: 1,
+            recipient: "anon".to_string(),
+        };
+        assert!(not_eligible(&mut deps, "creator", &[], transfer));
+
+        // an option returning to someone not allowed gets an error ack, undoing the return
+        let channel = mock_ibc_channel_connect_ack("channel-0", IbcOrder::Unordered, IBC_VERSION);
+        let _ = ibc_channel_connect(deps.as_mut(), mock_env(), channel).unwrap();
+        let msg = ExecuteMsg::IbcTransfer {
+            option_id: 1,
+            channel_id: "channel-0".to_string(),
+            receiver: "remote".to_string(),
+            timeout: None,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let packet = OptionPacket::Return {
+            option_id: 1,
+            sender: "remote".to_string(),
+            receiver: "anon".to_string(),
+        };
+        let msg = mock_ibc_packet_recv("channel-0", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(matches!(
+            from_json(res.acknowledgement).unwrap(),
+            Ack::Error(_)
+        ));
+        let state: State = options().load(&deps.storage, 1).unwrap();
+        assert_eq!(mock_env().contract.address, state.owner);
+    }
+}
@@ -1,6 +1,9 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Coin, Decimal, StdError, Timestamp, Uint128};
+use cw_utils::Expiration;
 use thiserror::Error;
 
+use crate::state::Asset;
+
 #[derive(Error, Debug)]
 pub enum ContractError {
     #[error("{0}")]
@@ -8,6 +11,338 @@ pub enum ContractError {
 
     #[error("Unauthorized")]
     Unauthorized {},
-    // Add any other custom errors you like here.
-    // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
+
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Basis points must not exceed 10000, got {bps}")]
+    InvalidBps { bps: u64 },
+
+    #[error("The config timelock must not exceed {max} seconds")]
+    InvalidTimelock { max: u64 },
+
+    #[error("Must send some collateral, and no zero amounts")]
+    InvalidCollateral {},
+
+    #[error("Counter offer must not be empty or contain zero amounts")]
+    InvalidCounterOffer {},
+
+    #[error("Denom {denom} is not allowed")]
+    DenomNotAllowed { denom: String },
+
+    #[error("Invalid denom {denom}")]
+    InvalidDenom { denom: String },
+
+    #[error("Denom trace path must be port and channel pairs, and base denom must be set")]
+    InvalidDenomTrace {},
+
+    #[error("Must send the option token {denom} to execute it")]
+    OptionTokenRequired { denom: String },
+
+    #[error("Tokenized options are traded by sending their token, and cannot be split or listed")]
+    TokenizedOption {},
+
+    #[error("Cannot create expired option")]
+    CreateExpired {},
+
+    #[error("Option must expire at some height or time")]
+    NeverExpires {},
+
+    #[error("Option expired ({expired})")]
+    OptionExpired { expired: Expiration },
+
+    #[error("Invalid staked option")]
+    InvalidStaking {},
+
+    #[error("Collateral is staked, it must be unstaked and back first")]
+    CollateralStaked {},
+
+    #[error("Collateral can't be deposited for yield")]
+    InvalidYieldDeposit {},
+
+    #[error("The yield strategy redeemed {missing} short of the collateral, send that along")]
+    YieldShortfall { missing: Coin },
+
+    #[error("Collateral is deposited for yield, it must be withdrawn first")]
+    CollateralDeposited {},
+
+    #[error("Not possible for options collateralized by an NFT")]
+    NftCollateral {},
+
+    #[error("Not possible for options with cw20 collateral")]
+    Cw20Collateral {},
+
+    #[error("Not possible for options accepting alternative counter offers")]
+    AltCounterOffers {},
+
+    #[error("Strike needs a single collateral coin and counter_offer coin")]
+    InvalidStrike {},
+
+    #[error("Margined options are cash settled, expire and are written on a notional above the single coin of margin")]
+    InvalidMargin {},
+
+    #[error("Margin of {margin} is below the requirement of {requirement}")]
+    MarginTooLow {
+        margin: Uint128,
+        requirement: Uint128,
+    },
+
+    #[error("Margin covers the maintenance requirement")]
+    MarginCovered {},
+
+    #[error("Not possible for margined options")]
+    MarginedOption {},
+
+    #[error("Insurance withdrawals must be non-zero amounts the fund holds")]
+    InvalidInsuranceWithdrawal {},
+
+    #[error("Cash settlement at this price is halted until {until}")]
+    PriceHalted { until: Timestamp },
+
+    #[error("Price of {rate} moved too far from the last price of {last}")]
+    PriceDeviation { last: Decimal, rate: Decimal },
+
+    #[error("Circuit breaker is disabled")]
+    CircuitBreakerDisabled {},
+
+    #[error("Collateral has to be worth at least {min}")]
+    CollateralTooSmall { min: Coin },
+
+    #[error("Cannot have more than {max} options open")]
+    TooManyOpenOptions { max: u64 },
+
+    #[error("Only {surplus} is held beyond the funds of options")]
+    ExceedsSurplus { surplus: Coin },
+
+    #[error("No such payout")]
+    PayoutNotFound {},
+
+    #[error("Nothing is held beyond the funds of options")]
+    NoSurplus {},
+
+    #[error("{addr} is not allowed to take options")]
+    NotEligible { addr: String },
+
+    #[error("Queued config change can't be executed before {executable_at}")]
+    TimelockNotElapsed { executable_at: Timestamp },
+
+    #[error("Payout of {payout} is below the minimum of {min_payout}")]
+    PayoutTooLow {
+        payout: Uint128,
+        min_payout: Uint128,
+    },
+
+    #[error("Deadline passed ({deadline})")]
+    DeadlinePassed { deadline: Expiration },
+
+    #[error("Option not yet expired ({expires})")]
+    OptionNotExpired { expires: Expiration },
+
+    #[error("Must send at least the counter_offer: {expected:?}, received {offer:?}")]
+    CounterOfferMismatch { offer: Asset, expected: Asset },
+
+    #[error("Cannot approve with an expiration in the past")]
+    ApprovalExpired {},
+
+    #[error("Transfer offer expired or was never made")]
+    OfferExpired {},
+
+    #[error("No termination was proposed by the other side")]
+    NoTerminationProposed {},
+
+    #[error("Extension must move expiry later, keeping heights as heights and times as times")]
+    InvalidExtension {},
+
+    #[error("No extension was proposed")]
+    NoExtensionProposed {},
+
+    #[error("No buyback was offered")]
+    NoBuybackOffered {},
+
+    #[error("Option is not for sale")]
+    NotForSale {},
+
+    #[error("Must send exact price: {expected:?}, received {offer:?}")]
+    PriceMismatch {
+        offer: Vec<Coin>,
+        expected: Vec<Coin>,
+    },
+
+    #[error("Must send the transfer fee: {fee:?}")]
+    TransferFeeRequired { fee: Vec<Coin> },
+
+    #[error("Option can only go to its allowed taker")]
+    TakerNotAllowed {},
+
+    #[error("Cannot refer one's own trade")]
+    SelfReferral {},
+
+    #[error("No price oracle configured")]
+    NoOracle {},
+
+    #[error("Only options with a single collateral and counter_offer denom can be priced")]
+    NotPriceable {},
+
+    #[error("Barriers need a nonzero price")]
+    InvalidBarrier {},
+
+    #[error("The oracle price has not crossed the barrier")]
+    BarrierNotCrossed {},
+
+    #[error("Binary options are settled by anyone after expiry, not executed or burned")]
+    BinaryOption {},
+
+    #[error("Averaging settlement needs a European option and a nonzero window")]
+    InvalidAveraging {},
+
+    #[error("Only European options take a settlement window, and it can't be empty")]
+    InvalidSettlementWindow {},
+
+    #[error("No expired options to crank")]
+    NothingToCrank {},
+
+    #[error("The stats are not being rebuilt")]
+    NothingToRebuild {},
+
+    #[error("Not while the stats are being rebuilt")]
+    StatsRebuilding {},
+
+    #[error("Must send funds to add to the crank pool")]
+    InvalidCrankFunds {},
+
+    #[error("Only American options take an exercise start, before their expiry")]
+    InvalidExercisableFrom {},
+
+    #[error("Option can only be exercised from {from}")]
+    NotYetExercisable { from: Expiration },
+
+    #[error("Prices are sampled from {start} until expiry at {end}")]
+    OutsideSamplingWindow { start: Expiration, end: Expiration },
+
+    #[error("Price was already sampled this block")]
+    AlreadySampled {},
+
+    #[error("Fraction must be above 0 and at most 1, and release some collateral, got {fraction}")]
+    InvalidFraction { fraction: Decimal },
+
+    #[error("Cannot split into {parts} parts, need 2 to 30 parts and enough of every amount")]
+    InvalidParts { parts: u32 },
+
+    #[error("Option {option_id} appears more than once")]
+    DuplicateOption { option_id: u64 },
+
+    #[error("Only supports channel with ibc version {expected}, got {version}")]
+    InvalidIbcVersion { expected: String, version: String },
+
+    #[error("Only supports unordered channels")]
+    OnlyUnorderedChannel {},
+
+    #[error("Unknown channel {channel_id}")]
+    UnknownChannel { channel_id: String },
+
+    #[error("Voucher for this option already exists")]
+    VoucherExists {},
+
+    #[error("Auction needs a duration and a reserve")]
+    InvalidAuction {},
+
+    #[error("Must bid a single coin of at least {minimum}")]
+    BidTooLow { minimum: Coin },
+
+    #[error("Auction ended")]
+    AuctionEnded {},
+
+    #[error("Auction does not end before {end}")]
+    AuctionNotEnded { end: Timestamp },
+
+    #[error(
+        "Orders take a single nonzero coin as price, and cannot be placed on one's own option"
+    )]
+    InvalidOrder {},
+
+    #[error("Order expired")]
+    OrderExpired {},
+
+    #[error("Escrows take a nonzero premium and a deadline at some height or time, and cannot be made on one's own option")]
+    InvalidEscrow {},
+
+    #[error("Escrow deadline passed")]
+    EscrowExpired {},
+
+    #[error("Quote request was accepted, cancelled or is past its deadline")]
+    QuoteRequestClosed {},
+
+    #[error("Share tokens are not enabled")]
+    SharesNotEnabled {},
+
+    #[error("Must split the option into some shares")]
+    InvalidShares {},
+
+    #[error("Proceeds can only be claimed once the option is closed")]
+    OptionStillOpen {},
+
+    #[error("Co-writing takes a single coin of the option's only collateral denom, while its first writer owns it")]
+    InvalidContribution {},
+
+    #[error("Series need 1 to 30 legs with nonzero strikes, and enough of every collateral coin for each")]
+    InvalidSeries {},
+
+    #[error("Strategies need 2 to 30 legs, and exactly the collateral of all of them")]
+    InvalidStrategy {},
+
+    #[error("A leg of the strategy is already closed")]
+    StrategyClosed {},
+
+    #[error("Vaults need distinct asset and strike denoms, and a nonzero strike")]
+    InvalidVault {},
+
+    #[error("Deposits take a single coin of the vault asset, and an emptied vault must be withdrawn first")]
+    InvalidDeposit {},
+
+    #[error("Not enough vault shares")]
+    InsufficientShares {},
+
+    #[error("Vault options are open, deposits and withdrawals wait until they close")]
+    VaultRoundOpen {},
+
+    #[error("Vault options need some of the idle asset, worth a nonzero counter_offer at the strike, and a premium")]
+    InvalidVaultWrite {},
+
+    #[error(
+        "Funding needs a nonzero rate, and deposits and withdrawals a nonzero amount of its denom"
+    )]
+    InvalidFunding {},
+
+    #[error("Funding ran out, the option can only be liquidated")]
+    FundingExhausted {},
+
+    #[error("Funding still covers the option, it can't be liquidated")]
+    FundingCovered {},
+
+    #[error("Perpetual options are funded here and cannot leave the chain")]
+    PerpetualOption {},
+
+    #[error("Can only prune settlement records older than the retention period")]
+    WithinRetention {},
+
+    #[error("Can only merge 2 to 30 distinct options with the same creator, expiry and denoms")]
+    CannotMerge {},
+
+    #[error("Don't send funds to execute a cash settled option")]
+    FundsSentWithCashSettlement {},
+
+    #[error("Option rolls over on expiry, only its creator can burn it")]
+    OptionRolls {},
+
+    #[error("Rolling options is not enabled")]
+    RollingNotEnabled {},
+
+    #[error("Don't send funds with roll")]
+    FundsSentWithRoll {},
+
+    #[error("Don't send funds with burn")]
+    FundsSentWithBurn {},
+
+    #[error("Cannot migrate from different contract type: {previous_contract}")]
+    CannotMigrate { previous_contract: String },
 }
@@ -0,0 +1,315 @@
+//! Premium offers escrowed against a specific option.
+//!
+//! A buyer sends the premium they offer along with a deadline. Until then the owner can accept,
+//! selling the option for the escrowed premium. The buyer can take the offer back at any time,
+//! and once the deadline passed anyone can refund it, one by one or by sweeping the expired
+//! escrows in deadline order.
+
+use cosmwasm_std::{BankMsg, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult};
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
+
+use crate::blacklist;
+use crate::contract::{
+    assert_not_paused, change_owner, check_can_send, check_taker, option_attributes, pay_sale,
+    DEFAULT_LIMIT, MAX_LIMIT,
+};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::msg::{EscrowInfo, EscrowsResponse};
+use crate::shares::keep_proceeds;
+use crate::state::{
+    expiration_key, next_id, options, premium_escrows, remove_indexed, save_indexed, save_option,
+    Asset, PremiumEscrow, State, CONFIG, ESCROW_COUNT,
+};
+
+fn refund(escrow: PremiumEscrow) -> BankMsg {
+    BankMsg::Send {
+        to_address: escrow.buyer.into_string(),
+        amount: escrow.premium,
+    }
+}
+
+/// Anyone but the owner can escrow the premium they offer for the option, until `deadline`
+pub fn handle_escrow_premium(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    deadline: Expiration,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let no_premium = info.funds.is_empty() || info.funds.iter().any(|c| c.amount.is_zero());
+    let never = matches!(deadline, Expiration::Never {});
+    if info.sender == state.owner || no_premium || never {
+        return Err(ContractError::InvalidEscrow {});
+    }
+    if deadline.is_expired(&env.block) {
+        return Err(ContractError::EscrowExpired {});
+    }
+    check_taker(&state, &info.sender)?;
+
+    let escrow = PremiumEscrow {
+        buyer: info.sender,
+        option_id,
+        premium: info.funds,
+        deadline,
+    };
+    let escrow_id = next_id(deps.storage, &ESCROW_COUNT)?;
+    save_indexed(deps.storage, &premium_escrows(), escrow_id, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "escrow_premium")
+        .add_attribute("escrow_id", escrow_id.to_string())
+        .add_attribute("buyer", escrow.buyer)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Owner, or an approved spender, sells the option to the buyer for the escrowed premium
+pub fn handle_accept_escrow(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    escrow_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let escrow = premium_escrows().load(deps.storage, escrow_id)?;
+    if escrow.deadline.is_expired(&env.block) {
+        return Err(ContractError::EscrowExpired {});
+    }
+    let mut state: State = options().load(deps.storage, escrow.option_id)?;
+    check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+
+    remove_indexed(deps.storage, &premium_escrows(), escrow_id)?;
+    // the sale cannot happen, so the buyer gets the premium back
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "accept_escrow",
+        escrow.option_id,
+        &[&info.sender, &escrow.buyer],
+        &escrow.buyer,
+        Asset::Native(escrow.premium.clone()),
+    )?;
+    if let Some(res) = blocked {
+        return Ok(res.add_attribute("escrow_id", escrow_id.to_string()));
+    }
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &escrow.buyer)?;
+    let event = change_owner(&mut state, escrow.option_id, escrow.buyer);
+    save_option(deps.storage, escrow.option_id, &state)?;
+
+    let seller = event.from.clone();
+    let res = Response::new()
+        .add_attribute("action", "accept_escrow")
+        .add_attribute("escrow_id", escrow_id.to_string())
+        .add_event(event.into());
+    let res = pay_sale(
+        deps.storage,
+        &env,
+        res,
+        &state,
+        seller,
+        escrow.premium,
+        None,
+    )?
+    .add_attributes(option_attributes(escrow.option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, escrow.option_id, res)?)
+}
+
+/// Buyer can take their escrow back at any time, anyone once the deadline passed
+pub fn handle_refund_escrow(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    escrow_id: u64,
+) -> Result<Response, ContractError> {
+    let escrow = premium_escrows().load(deps.storage, escrow_id)?;
+    if info.sender != escrow.buyer && !escrow.deadline.is_expired(&env.block) {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_indexed(deps.storage, &premium_escrows(), escrow_id)?;
+
+    Ok(Response::new()
+        .add_message(refund(escrow))
+        .add_attribute("action", "refund_escrow")
+        .add_attribute("escrow_id", escrow_id.to_string()))
+}
+
+/// Anyone can refund up to `limit` escrows past their deadline, the longest expired first
+pub fn handle_refund_expired_escrows(
+    deps: DepsMut,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let now = [
+        expiration_key(&Expiration::AtHeight(env.block.height)),
+        expiration_key(&Expiration::AtTime(env.block.time)),
+    ];
+    let mut expired = vec![];
+    for key in now {
+        // deadlines at or before the current block have passed
+        let start = Bound::inclusive(((key.0, u64::MIN), u64::MIN));
+        let end = Bound::inclusive((key, u64::MAX));
+        let found = premium_escrows()
+            .idx
+            .deadline
+            .range(deps.storage, Some(start), Some(end), Order::Ascending)
+            .take(limit - expired.len())
+            .collect::<StdResult<Vec<_>>>()?;
+        expired.extend(found);
+    }
+
+    let mut res = Response::new()
+        .add_attribute("action", "refund_expired_escrows")
+        .add_attribute("refunded", expired.len().to_string());
+    for (escrow_id, escrow) in expired {
+        remove_indexed(deps.storage, &premium_escrows(), escrow_id)?;
+        res = res.add_message(refund(escrow));
+    }
+    Ok(res)
+}
+
+/// Escrows offered for the option, by escrow id
+pub fn query_escrows(
+    deps: Deps,
+    option_id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<EscrowsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let escrows = premium_escrows()
+        .idx
+        .option
+        .prefix(option_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(escrow_id, escrow)| EscrowInfo { escrow_id, escrow }))
+        .collect::<StdResult<_>>()?;
+    Ok(EscrowsResponse { escrows })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ConfigResponse, ExecuteMsg, QueryMsg};
+    use crate::testing::{blacklist, setup};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coins, from_json, CosmosMsg, OwnedDeps};
+
+    fn escrow(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, buyer: &str, blocks: u64) {
+        let msg = ExecuteMsg::EscrowPremium {
+            option_id: 1,
+            deadline: Expiration::AtHeight(mock_env().block.height + blocks),
+        };
+        let info = mock_info(buyer, &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    fn premium_to(buyer: &str) -> CosmosMsg {
+        BankMsg::Send {
+            to_address: buyer.into(),
+            amount: coins(5, "ETH"),
+        }
+        .into()
+    }
+
+    #[test]
+    fn accept_before_deadline() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::EscrowPremium {
+            option_id: 1,
+            deadline: Expiration::Never {},
+        };
+        let info = mock_info("alice", &coins(5, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidEscrow {}));
+        escrow(&mut deps, "alice", 10);
+
+        let accept = ExecuteMsg::AcceptEscrow { escrow_id: 1 };
+        let info = mock_info("alice", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        // past the deadline it can only be refunded
+        let mut env = mock_env();
+        env.block.height += 10;
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), env, info.clone(), accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::EscrowExpired {}));
+
+        let res = execute(deps.as_mut(), mock_env(), info, accept).unwrap();
+        assert_eq!(premium_to("creator"), res.messages[0].msg);
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 1 }).unwrap();
+        let config: ConfigResponse = from_json(res).unwrap();
+        assert_eq!("alice", config.state.owner.as_str());
+    }
+
+    #[test]
+    fn blacklisted_buyer() {
+        let mut deps = setup();
+        escrow(&mut deps, "alice", 10);
+        blacklist(&mut deps, "alice");
+
+        // the escrow closes with the premium refunded, the option stays put
+        let accept = ExecuteMsg::AcceptEscrow { escrow_id: 1 };
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, accept).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(premium_to("alice"), res.messages[0].msg);
+        assert!(!premium_escrows().has(&deps.storage, 1));
+        let state: State = options().load(&deps.storage, 1).unwrap();
+        assert_eq!("creator", state.owner);
+    }
+
+    #[test]
+    fn refund_expired() {
+        let mut deps = setup();
+        escrow(&mut deps, "alice", 10);
+        escrow(&mut deps, "bob", 5);
+        escrow(&mut deps, "carol", 20);
+
+        // the buyer can take it back, others wait for the deadline
+        let msg = ExecuteMsg::RefundEscrow { escrow_id: 3 };
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("carol", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(premium_to("carol"), res.messages[0].msg);
+
+        // sweeping refunds the expired escrows, earliest deadline first
+        let mut env = mock_env();
+        env.block.height += 10;
+        escrow(&mut deps, "dave", 30);
+        let info = mock_info("anyone", &[]);
+        let msg = ExecuteMsg::RefundExpiredEscrows { limit: None };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            vec![premium_to("bob"), premium_to("alice")],
+            res.messages
+                .iter()
+                .map(|m| m.msg.clone())
+                .collect::<Vec<_>>()
+        );
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        let msg = QueryMsg::Escrows {
+            option_id: 1,
+            start_after: None,
+            limit: None,
+        };
+        let escrows: EscrowsResponse =
+            from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, escrows.escrows.len());
+        assert_eq!("dave", escrows.escrows[0].escrow.buyer.as_str());
+    }
+}
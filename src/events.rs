@@ -0,0 +1,368 @@
+//! Typed events of the option lifecycle.
+//!
+//! Handlers emit these on top of their attributes, so the same change to an option is described
+//! the same way whichever handler made it. Every event carries the `version` of its layout,
+//! bumped whenever its attributes change.
+//!
+//! The layout is meant for indexers: event types are `simple_option.<action>` (seen on chain
+//! as `wasm-simple_option.<action>`), and values are never debug formatted. Every coin of a
+//! field is one `<field>_amount` attribute, an integer, followed by its `<field>_denom`, the
+//! token address for cw20 tokens. An NFT of a field is its `<field>_collection` followed by its
+//! `<field>_token_id`. Expirations are an `expires_kind` of `height`, `time` or `never`,
+//! followed by `expires_at`, the height or the time in nanoseconds, unless never.
+//!
+//! Every way an option is closed ends with a `close` event saying how. Splitting or merging
+//! options closes them, and creates the options they became.
+
+use cosmwasm_std::{Addr, Coin, Event};
+use cw_utils::Expiration;
+
+use crate::state::{Asset, AssetList, Closure, NftCollateral, Settlement, State};
+
+/// Layout version of the events below
+pub const EVENT_VERSION: &str = "3";
+
+fn add_coins(event: Event, field: &str, coins: &[Coin]) -> Event {
+    coins.iter().fold(event, |event, coin| {
+        event
+            .add_attribute(format!("{}_amount", field), coin.amount.to_string())
+            .add_attribute(format!("{}_denom", field), &coin.denom)
+    })
+}
+
+fn add_asset(event: Event, field: &str, asset: &Asset) -> Event {
+    match asset {
+        Asset::Native(coins) => add_coins(event, field, coins),
+        Asset::Cw20(coin) => event
+            .add_attribute(format!("{}_amount", field), coin.amount.to_string())
+            .add_attribute(format!("{}_denom", field), &coin.address),
+    }
+}
+
+fn add_assets(event: Event, field: &str, assets: &AssetList, nft: Option<&NftCollateral>) -> Event {
+    let event = add_coins(event, field, &assets.native);
+    let event = assets.cw20.iter().fold(event, |event, coin| {
+        add_asset(event, field, &Asset::Cw20(coin.clone()))
+    });
+    match nft {
+        Some(nft) => event
+            .add_attribute(format!("{}_collection", field), &nft.collection)
+            .add_attribute(format!("{}_token_id", field), &nft.token_id),
+        None => event,
+    }
+}
+
+fn add_expiration(event: Event, expires: &Expiration) -> Event {
+    match expires {
+        Expiration::AtHeight(height) => event
+            .add_attribute("expires_kind", "height")
+            .add_attribute("expires_at", height.to_string()),
+        Expiration::AtTime(time) => event
+            .add_attribute("expires_kind", "time")
+            .add_attribute("expires_at", time.nanos().to_string()),
+        Expiration::Never {} => event.add_attribute("expires_kind", "never"),
+    }
+}
+
+/// A new option was written
+pub struct OptionCreated {
+    pub option_id: u64,
+    pub creator: Addr,
+    pub owner: Addr,
+    pub collateral: AssetList,
+    pub nft: Option<NftCollateral>,
+    pub counter_offer: Asset,
+    pub expires: Expiration,
+}
+
+impl OptionCreated {
+    pub fn new(option_id: u64, state: &State) -> Self {
+        OptionCreated {
+            option_id,
+            creator: state.creator.clone(),
+            owner: state.owner.clone(),
+            collateral: state.collateral_list(),
+            nft: state.nft.clone(),
+            counter_offer: state.counter_offer.clone(),
+            expires: state.expires,
+        }
+    }
+}
+
+impl From<OptionCreated> for Event {
+    fn from(event: OptionCreated) -> Self {
+        let res = Event::new("simple_option.create")
+            .add_attribute("version", EVENT_VERSION)
+            .add_attribute("option_id", event.option_id.to_string())
+            .add_attribute("creator", event.creator)
+            .add_attribute("owner", event.owner);
+        let res = add_assets(res, "collateral", &event.collateral, event.nft.as_ref());
+        let res = add_asset(res, "counter_offer", &event.counter_offer);
+        add_expiration(res, &event.expires)
+    }
+}
+
+/// An option changed hands, by transfer or sale
+pub struct OptionTransferred {
+    pub option_id: u64,
+    pub from: Addr,
+    pub to: Addr,
+}
+
+impl From<OptionTransferred> for Event {
+    fn from(event: OptionTransferred) -> Self {
+        Event::new("simple_option.transfer")
+            .add_attribute("version", EVENT_VERSION)
+            .add_attribute("option_id", event.option_id.to_string())
+            .add_attribute("from", event.from)
+            .add_attribute("to", event.to)
+    }
+}
+
+/// An option, or part of it, was executed, paying the recipient `payout`. The counter_offer is
+/// what the exerciser paid for it, none when settled in cash.
+pub struct OptionExercised {
+    pub option_id: u64,
+    pub exerciser: Addr,
+    pub recipient: Addr,
+    pub payout: AssetList,
+    pub nft: Option<NftCollateral>,
+    pub counter_offer: Option<Asset>,
+}
+
+impl OptionExercised {
+    /// `payout` is the collateral of a physically settled option, or what it came to in cash
+    pub fn new(
+        option_id: u64,
+        exerciser: &Addr,
+        recipient: &Addr,
+        state: &State,
+        payout: AssetList,
+    ) -> Self {
+        OptionExercised {
+            option_id,
+            exerciser: exerciser.clone(),
+            recipient: recipient.clone(),
+            payout,
+            nft: state.nft.clone(),
+            counter_offer: (state.settlement == Settlement::Physical)
+                .then(|| state.counter_offer.clone()),
+        }
+    }
+}
+
+impl From<OptionExercised> for Event {
+    fn from(event: OptionExercised) -> Self {
+        let res = Event::new("simple_option.execute")
+            .add_attribute("version", EVENT_VERSION)
+            .add_attribute("option_id", event.option_id.to_string())
+            .add_attribute("exerciser", event.exerciser)
+            .add_attribute("recipient", event.recipient);
+        let res = add_assets(res, "payout", &event.payout, event.nft.as_ref());
+        match &event.counter_offer {
+            Some(counter_offer) => add_asset(res, "counter_offer", counter_offer),
+            None => res,
+        }
+    }
+}
+
+/// An expired option was burned, returning the collateral to its creator
+pub struct OptionBurned {
+    pub option_id: u64,
+    pub burner: Addr,
+    pub creator: Addr,
+    pub collateral: Vec<Coin>,
+}
+
+impl OptionBurned {
+    pub fn new(option_id: u64, burner: &Addr, state: &State) -> Self {
+        OptionBurned {
+            option_id,
+            burner: burner.clone(),
+            creator: state.creator.clone(),
+            collateral: state.collateral.clone(),
+        }
+    }
+}
+
+impl From<OptionBurned> for Event {
+    fn from(event: OptionBurned) -> Self {
+        let res = Event::new("simple_option.burn")
+            .add_attribute("version", EVENT_VERSION)
+            .add_attribute("option_id", event.option_id.to_string())
+            .add_attribute("burner", event.burner)
+            .add_attribute("creator", event.creator);
+        add_coins(res, "collateral", &event.collateral)
+    }
+}
+
+/// An option was closed, and is no longer live
+#[must_use]
+pub struct OptionClosed {
+    pub option_id: u64,
+    pub closure: Closure,
+}
+
+impl From<OptionClosed> for Event {
+    fn from(event: OptionClosed) -> Self {
+        Event::new("simple_option.close")
+            .add_attribute("version", EVENT_VERSION)
+            .add_attribute("option_id", event.option_id.to_string())
+            .add_attribute("closure", event.closure.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::ExecuteMsg;
+    use crate::state::options;
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{attr, coins, Uint128};
+    use cw20::Cw20CoinVerified;
+
+    #[test]
+    fn lifecycle_events() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let created = &res.events[0];
+        assert_eq!("simple_option.create", created.ty);
+        assert_eq!(attr("version", EVENT_VERSION), created.attributes[0]);
+        assert_eq!(
+            created.attributes[1..],
+            [
+                attr("option_id", "1"),
+                attr("creator", "creator"),
+                attr("owner", "creator"),
+                attr("collateral_amount", "1"),
+                attr("collateral_denom", "BTC"),
+                attr("counter_offer_amount", "40"),
+                attr("counter_offer_denom", "ETH"),
+                attr("expires_kind", "height"),
+                attr("expires_at", "100000"),
+            ]
+        );
+
+        let msg = ExecuteMsg::TransferNft {
+            recipient: "owner".to_string(),
+            token_id: "1".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!("simple_option.transfer", res.events[0].ty);
+        assert_eq!(
+            res.events[0].attributes[1..],
+            [
+                attr("option_id", "1"),
+                attr("from", "creator"),
+                attr("to", "owner")
+            ]
+        );
+
+        // the same event whichever way the option is exercised
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: Some("payee".to_string()),
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let exercised = res
+            .events
+            .iter()
+            .find(|e| e.ty == "simple_option.execute")
+            .unwrap();
+        assert_eq!(
+            exercised.attributes[1..],
+            [
+                attr("option_id", "1"),
+                attr("exerciser", "owner"),
+                attr("recipient", "payee"),
+                attr("payout_amount", "1"),
+                attr("payout_denom", "BTC"),
+                attr("counter_offer_amount", "40"),
+                attr("counter_offer_denom", "ETH"),
+            ]
+        );
+        let closed = res
+            .events
+            .iter()
+            .find(|e| e.ty == "simple_option.close")
+            .unwrap();
+        assert_eq!(
+            closed.attributes[1..],
+            [attr("option_id", "1"), attr("closure", "executed")]
+        );
+    }
+
+    #[test]
+    fn split_and_merge_events() {
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &coins(2, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg()).unwrap();
+
+        // cw20 collateral is listed after the coins
+        let state = State {
+            cw20_collateral: vec![Cw20CoinVerified {
+                address: Addr::unchecked("token"),
+                amount: Uint128::new(100),
+            }],
+            ..options().load(&deps.storage, 1).unwrap()
+        };
+        let created = Event::from(OptionCreated::new(1, &state));
+        assert_eq!(
+            created.attributes[4..8],
+            [
+                attr("collateral_amount", "2"),
+                attr("collateral_denom", "BTC"),
+                attr("collateral_amount", "100"),
+                attr("collateral_denom", "token"),
+            ]
+        );
+
+        let owner = mock_info("creator", &[]);
+        let split = ExecuteMsg::Split {
+            option_id: 1,
+            parts: 2,
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), split).unwrap();
+        let events: Vec<(&str, &str)> = res
+            .events
+            .iter()
+            .map(|e| (e.ty.as_str(), e.attributes[1].value.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("simple_option.close", "1"),
+                ("simple_option.create", "2"),
+                ("simple_option.create", "3"),
+            ],
+            events
+        );
+        assert_eq!(attr("closure", "split"), res.events[0].attributes[2]);
+
+        let merge = ExecuteMsg::Merge {
+            option_ids: vec![2, 3],
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner, merge).unwrap();
+        let events: Vec<(&str, &str)> = res
+            .events
+            .iter()
+            .map(|e| (e.ty.as_str(), e.attributes[1].value.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("simple_option.close", "2"),
+                ("simple_option.close", "3"),
+                ("simple_option.create", "2"),
+            ],
+            events
+        );
+        assert_eq!(attr("closure", "merged"), res.events[0].attributes[2]);
+    }
+}
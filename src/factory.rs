@@ -0,0 +1,215 @@
+use cosmwasm_std::{
+    to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Order, Reply, Response, StdError,
+    StdResult, SubMsg, WasmMsg,
+};
+use cw_storage_plus::Bound;
+
+use crate::contract::{assert_not_paused, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::error::ContractError;
+use crate::msg::{CreateMsg, InstanceInfo, InstancesResponse, InstantiateMsg};
+use crate::state::{next_id, CONFIG, INSTANCES, INSTANCE_COUNT, PENDING_INSTANCE};
+
+pub const SPAWN_REPLY_ID: u64 = 1;
+
+/// Instantiates a copy of this contract holding a single option written by the sender.
+/// Instances share the factory config and get deterministic addresses, salted by instance id.
+pub fn handle_spawn(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    msg: CreateMsg,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let code_id = deps
+        .querier
+        .query_wasm_contract_info(&env.contract.address)?
+        .code_id;
+
+    let instance_id = next_id(deps.storage, &INSTANCE_COUNT)?;
+    let init = InstantiateMsg {
+        counter_offer: msg.counter_offer,
+        alt_counter_offers: msg.alt_counter_offers,
+        strike: msg.strike,
+        expires: msg.expires,
+        premium: msg.premium,
+        settlement: msg.settlement,
+        style: msg.style,
+        royalty_bps: msg.royalty_bps,
+        roll_on_expiry: msg.roll_on_expiry,
+        barrier: msg.barrier,
+        settlement_window: msg.settlement_window,
+        exercisable_from: msg.exercisable_from,
+        allowed_taker: msg.allowed_taker,
+        owner: msg.owner,
+        burn_reward_bps: config.burn_reward_bps,
+        fee_bps: config.fee_bps,
+        fee_collector: Some(config.fee_collector.to_string()),
+        admin: Some(config.admin.to_string()),
+        guardian: Some(config.guardian.to_string()),
+        dao: config.dao.map(String::from),
+        allowed_denoms: config.allowed_denoms,
+        oracle: config.oracle.map(String::from),
+        archive_retention: config.archive_retention,
+        tokenfactory: config.tokenfactory,
+        creator: Some(info.sender.to_string()),
+    };
+    let spawn = WasmMsg::Instantiate2 {
+        admin: Some(config.admin.to_string()),
+        code_id,
+        label: format!("simple-option instance {}", instance_id),
+        msg: to_json_binary(&init)?,
+        funds: info.funds,
+        salt: Binary::from(instance_id.to_be_bytes()),
+    };
+    // the address is only known once the instance is created
+    PENDING_INSTANCE.save(deps.storage, &(info.sender.clone(), instance_id))?;
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(spawn, SPAWN_REPLY_ID))
+        .add_attribute("action", "spawn")
+        .add_attribute("instance_id", instance_id.to_string())
+        .add_attribute("creator", info.sender))
+}
+
+/// Registers the instance created by `handle_spawn` under its creator.
+pub fn handle_spawn_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let (creator, instance_id) = PENDING_INSTANCE.load(deps.storage)?;
+    PENDING_INSTANCE.remove(deps.storage);
+
+    let res = msg.result.into_result().map_err(StdError::generic_err)?;
+    let address = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "_contract_address")
+        .ok_or_else(|| StdError::generic_err("instance address not found"))?;
+    let address = deps.api.addr_validate(&address.value)?;
+    INSTANCES.save(deps.storage, (&creator, instance_id), &address)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_instance")
+        .add_attribute("instance_id", instance_id.to_string())
+        .add_attribute("contract_address", address))
+}
+
+pub fn query_instances_by_creator(
+    deps: Deps,
+    creator: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<InstancesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let creator = deps.api.addr_validate(&creator)?;
+    let start = start_after.map(Bound::exclusive);
+    let instances = INSTANCES
+        .prefix(&creator)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(instance_id, address)| InstanceInfo {
+                instance_id,
+                address,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(InstancesResponse { instances })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, reply};
+    use crate::msg::ExecuteMsg;
+    use crate::state::{Asset, OptionStyle, Settlement};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{
+        coins, ContractInfoResponse, ContractResult, CosmosMsg, Event, SubMsgResponse,
+        SubMsgResult, SystemResult, WasmQuery,
+    };
+    use cw_utils::Expiration;
+
+    #[test]
+    fn spawn_and_register() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::ContractInfo { contract_addr } if contract_addr == "cosmos2contract" => {
+                let mut info = ContractInfoResponse::default();
+                info.code_id = 7;
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&info).unwrap()))
+            }
+            _ => panic!("unexpected query: {:?}", query),
+        });
+        let msg = InstantiateMsg {
+            fee_bps: 25,
+            ..instantiate_msg()
+        };
+        let info = mock_info("deployer", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // the instance is written for the sender, with the factory config
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(80, "ETH")),
+            expires: Expiration::AtHeight(200_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("alice", &coins(2, "BTC"));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Spawn(terms)).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(SPAWN_REPLY_ID, res.messages[0].id);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate2 {
+                admin,
+                code_id,
+                msg,
+                funds,
+                salt,
+                ..
+            }) => {
+                assert_eq!(Some("deployer".to_string()), *admin);
+                assert_eq!(7, *code_id);
+                assert_eq!(coins(2, "BTC"), *funds);
+                assert_eq!(Binary::from(1u64.to_be_bytes()), *salt);
+                let init: InstantiateMsg = cosmwasm_std::from_json(msg).unwrap();
+                assert_eq!(Some("alice".to_string()), init.creator);
+                assert_eq!(25, init.fee_bps);
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+
+        // the reply registers the new address
+        let event = Event::new("instantiate").add_attribute("_contract_address", "instance");
+        let msg = Reply {
+            id: SPAWN_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![event],
+                data: None,
+            }),
+        };
+        let _ = reply(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let res = query_instances_by_creator(deps.as_ref(), "alice".to_string(), None, None);
+        assert_eq!(
+            vec![InstanceInfo {
+                instance_id: 1,
+                address: cosmwasm_std::Addr::unchecked("instance"),
+            }],
+            res.unwrap().instances
+        );
+        let res = query_instances_by_creator(deps.as_ref(), "bob".to_string(), None, None);
+        assert!(res.unwrap().instances.is_empty());
+    }
+}
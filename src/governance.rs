@@ -0,0 +1,133 @@
+//! Governance of the parameters traders rely on.
+//!
+//! The fees, the oracle, the denom whitelist and the timelock each have their own message. With
+//! a DAO contract configured at instantiation only the DAO can send them, and the admin can no
+//! longer change these parameters through `UpdateConfig`, so control over them rests with
+//! on-chain governance rather than a single key. Without a DAO the admin sends them. Changes go
+//! through the timelock either way, and with a DAO only the DAO can cancel queued ones.
+
+use cosmwasm_std::{DepsMut, Env, MessageInfo, Response};
+
+use crate::contract::update_config;
+use crate::error::ContractError;
+use crate::msg::UpdateConfigMsg;
+use crate::state::CONFIG;
+
+/// Whether `msg` changes any of the parameters the DAO governs.
+pub(crate) fn governed(msg: &UpdateConfigMsg) -> bool {
+    msg.fee_bps.is_some()
+        || msg.fee_collector.is_some()
+        || msg.transfer_fee.is_some()
+        || msg.referral_bps.is_some()
+        || msg.insurance_bps.is_some()
+        || msg.oracle.is_some()
+        || msg.allowed_denoms.is_some()
+        || msg.config_timelock.is_some()
+}
+
+/// The DAO, or the admin without one, changes governed parameters
+pub fn handle_governance_update(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    action: &str,
+    changes: UpdateConfigMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != *config.dao.as_ref().unwrap_or(&config.admin) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let attributes = update_config(deps, &env, config, changes)?;
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attributes(attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+
+    #[test]
+    fn dao_governs_parameters() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            dao: Some("dao".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let admin = mock_info("creator", &[]);
+        let dao = mock_info("dao", &[]);
+
+        // the admin keeps the rest of the config, but not the governed parameters
+        let changes = UpdateConfigMsg {
+            oracle: Some("oracle".to_string()),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(changes);
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let changes = UpdateConfigMsg {
+            archive_retention: Some(60),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(changes);
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateFees {
+            fee_bps: Some(100),
+            fee_collector: Some("treasury".to_string()),
+            transfer_fee: None,
+            referral_bps: None,
+            insurance_bps: None,
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), dao.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateOracle {
+            oracle: "oracle".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), dao.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateWhitelist {
+            allowed_denoms: vec!["BTC".to_string(), "ETH".to_string()],
+        };
+        let _ = execute(deps.as_mut(), mock_env(), dao.clone(), msg).unwrap();
+
+        // so is the timelock, which the admin could otherwise lift to skip it
+        let changes = UpdateConfigMsg {
+            config_timelock: Some(0),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(changes);
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let msg = ExecuteMsg::UpdateTimelock {
+            config_timelock: 3_600,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), dao.clone(), msg).unwrap();
+
+        let config = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(100, config.fee_bps);
+        assert_eq!("treasury", config.fee_collector);
+        assert_eq!(Some("oracle".to_string()), config.oracle.map(String::from));
+        assert_eq!(vec!["BTC", "ETH"], config.allowed_denoms);
+        assert_eq!(60, config.archive_retention);
+        assert_eq!(3_600, config.config_timelock);
+
+        // and only the DAO can cancel what it queued
+        let msg = ExecuteMsg::UpdateOracle {
+            oracle: "other_oracle".to_string(),
+        };
+        let _ = execute(deps.as_mut(), mock_env(), dao.clone(), msg).unwrap();
+        let cancel = ExecuteMsg::CancelQueued { queued_id: 1 };
+        let err = execute(deps.as_mut(), mock_env(), admin, cancel.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), dao, cancel).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use cosmwasm_std::{
+    to_json_binary, Addr, Coin, CosmosMsg, Decimal, QuerierWrapper, StdResult, WasmMsg,
+};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{
+    AdminConfigResponse, CanExecuteResponse, ConfigResponse, ExecuteMsg, FairValueResponse,
+    IntrinsicValueResponse, OptionsResponse, QueryMsg,
+};
+
+/// OptionContract is a wrapper around Addr that provides typed messages and queries
+/// for other contracts talking to a deployed option contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OptionContract(pub Addr);
+
+impl OptionContract {
+    pub fn addr(&self) -> Addr {
+        self.0.clone()
+    }
+
+    /// Builds the message executing `msg` on the contract, sending along `funds`
+    pub fn call<T: Into<ExecuteMsg>>(&self, msg: T, funds: Vec<Coin>) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr: self.addr().into(),
+            msg: to_json_binary(&msg.into())?,
+            funds,
+        }
+        .into())
+    }
+
+    fn query<T: DeserializeOwned>(&self, querier: &QuerierWrapper, msg: QueryMsg) -> StdResult<T> {
+        querier.query_wasm_smart(self.addr(), &msg)
+    }
+
+    pub fn admin_config(&self, querier: &QuerierWrapper) -> StdResult<AdminConfigResponse> {
+        self.query(querier, QueryMsg::AdminConfig {})
+    }
+
+    /// Terms of the option
+    pub fn config(&self, querier: &QuerierWrapper, option_id: u64) -> StdResult<ConfigResponse> {
+        self.query(querier, QueryMsg::Config { option_id })
+    }
+
+    pub fn can_execute(
+        &self,
+        querier: &QuerierWrapper,
+        option_id: u64,
+        sender: impl Into<String>,
+        funds: Vec<Coin>,
+    ) -> StdResult<CanExecuteResponse> {
+        let msg = QueryMsg::CanExecute {
+            option_id,
+            sender: sender.into(),
+            funds,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn intrinsic_value(
+        &self,
+        querier: &QuerierWrapper,
+        option_id: u64,
+    ) -> StdResult<IntrinsicValueResponse> {
+        self.query(querier, QueryMsg::IntrinsicValue { option_id })
+    }
+
+    pub fn fair_value(
+        &self,
+        querier: &QuerierWrapper,
+        option_id: u64,
+        volatility: Decimal,
+    ) -> StdResult<FairValueResponse> {
+        let msg = QueryMsg::FairValue {
+            option_id,
+            volatility,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn options_by_owner(
+        &self,
+        querier: &QuerierWrapper,
+        owner: impl Into<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<OptionsResponse> {
+        let msg = QueryMsg::OptionsByOwner {
+            owner: owner.into(),
+            start_after,
+            limit,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn options_by_creator(
+        &self,
+        querier: &QuerierWrapper,
+        creator: impl Into<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<OptionsResponse> {
+        let msg = QueryMsg::OptionsByCreator {
+            creator: creator.into(),
+            start_after,
+            limit,
+        };
+        self.query(querier, msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_dependencies;
+    use cosmwasm_std::{coins, from_json, ContractResult, SystemResult, WasmQuery};
+
+    #[test]
+    fn typed_queries() {
+        let mut deps = mock_dependencies();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "options" => {
+                let res = match from_json(msg).unwrap() {
+                    QueryMsg::CanExecute { sender, .. } if sender == "owner" => {
+                        CanExecuteResponse::Ok {}
+                    }
+                    QueryMsg::CanExecute { .. } => CanExecuteResponse::NotOwner {},
+                    msg => panic!("unexpected query: {:?}", msg),
+                };
+                SystemResult::Ok(ContractResult::Ok(to_json_binary(&res).unwrap()))
+            }
+            _ => panic!("unexpected query: {:?}", query),
+        });
+        let contract = OptionContract(Addr::unchecked("options"));
+        let querier = deps.as_ref().querier;
+
+        let res = contract.can_execute(&querier, 1, "owner", coins(40, "ETH"));
+        assert_eq!(CanExecuteResponse::Ok {}, res.unwrap());
+        let res = contract.can_execute(&querier, 1, "someone", coins(40, "ETH"));
+        assert_eq!(CanExecuteResponse::NotOwner {}, res.unwrap());
+    }
+
+    #[test]
+    fn call() {
+        let contract = OptionContract(Addr::unchecked("options"));
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let res = contract.call(msg.clone(), coins(40, "ETH")).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "options".to_string(),
+                msg: to_json_binary(&msg).unwrap(),
+                funds: coins(40, "ETH"),
+            }),
+            res
+        );
+    }
+}
@@ -0,0 +1,658 @@
+//! Moves ownership of an option to another chain running this contract, and back.
+//!
+//! Like ICS-721, the option itself never leaves: it is escrowed here, owned by the contract,
+//! while the other chain mints a voucher for it. Sending the voucher back releases the option
+//! to whoever the voucher holder names. Collateral and settlement always stay on the chain the
+//! option was written on.
+
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    from_json, to_json_binary, Addr, Binary, Coin, DepsMut, Empty, Env, IbcBasicResponse,
+    IbcChannel, IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg, IbcMsg, IbcOrder,
+    IbcPacket, IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse,
+    IbcTimeout, MessageInfo, Response, StdResult,
+};
+use cw_utils::Expiration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::blacklist;
+use crate::contract::{assert_not_paused, change_owner, check_can_send, option_attributes};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::events::OptionTransferred;
+use crate::state::{
+    options, save_option, Asset, IbcEscrow, State, Voucher, CONFIG, IBC_CHANNELS, IBC_ESCROWS,
+    NOTIONALS, VOUCHERS,
+};
+
+pub const IBC_VERSION: &str = "simple-option-1";
+
+/// Seconds a packet has to be relayed before it times out, unless the sender picks one
+pub const DEFAULT_TIMEOUT: u64 = 600;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionPacket {
+    /// The option was escrowed on the sending chain, mint a voucher for `receiver`
+    Transfer {
+        option_id: u64,
+        sender: String,
+        receiver: String,
+        collateral: Vec<Coin>,
+        counter_offer: Asset,
+        expires: Expiration,
+    },
+    /// The voucher was given up on the sending chain, release the option to `receiver`
+    Return {
+        option_id: u64,
+        sender: String,
+        receiver: String,
+    },
+}
+
+/// Acknowledgement written for every packet, the same shape as cw20-ics20 uses
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ack {
+    Result(Binary),
+    Error(String),
+}
+
+fn ack_success() -> StdResult<Binary> {
+    to_json_binary(&Ack::Result(b"1".into()))
+}
+
+fn ack_fail(err: String) -> StdResult<Binary> {
+    to_json_binary(&Ack::Error(err))
+}
+
+fn packet_timeout(env: &Env, timeout: Option<u64>) -> IbcTimeout {
+    env.block
+        .time
+        .plus_seconds(timeout.unwrap_or(DEFAULT_TIMEOUT))
+        .into()
+}
+
+fn assert_channel(deps: &DepsMut, channel_id: &str) -> Result<(), ContractError> {
+    if !IBC_CHANNELS.has(deps.storage, channel_id) {
+        return Err(ContractError::UnknownChannel {
+            channel_id: channel_id.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Owner, or an approved spender, escrows the option and sends it to `receiver` over `channel_id`.
+pub fn handle_ibc_transfer(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    channel_id: String,
+    receiver: String,
+    timeout: Option<u64>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    assert_channel(&deps, &channel_id)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    // the voucher could be passed on to anyone on the other chain
+    if state.allowed_taker.is_some() {
+        return Err(ContractError::TakerNotAllowed {});
+    }
+    if let Expiration::Never {} = state.expires {
+        return Err(ContractError::PerpetualOption {});
+    }
+    if state.nft.is_some() {
+        return Err(ContractError::NftCollateral {});
+    }
+    if !state.cw20_collateral.is_empty() {
+        return Err(ContractError::Cw20Collateral {});
+    }
+    if !state.alt_counter_offers.is_empty() {
+        return Err(ContractError::AltCounterOffers {});
+    }
+    if NOTIONALS.has(deps.storage, option_id) {
+        return Err(ContractError::MarginedOption {});
+    }
+
+    // the owner gets it back if the packet fails
+    let escrow = IbcEscrow {
+        channel_id: channel_id.clone(),
+        owner: state.owner.clone(),
+    };
+    IBC_ESCROWS.save(deps.storage, option_id, &escrow)?;
+    let event = change_owner(&mut state, option_id, env.contract.address.clone());
+    save_option(deps.storage, option_id, &state)?;
+
+    let packet = OptionPacket::Transfer {
+        option_id,
+        sender: info.sender.to_string(),
+        receiver: receiver.clone(),
+        collateral: state.collateral.clone(),
+        counter_offer: state.counter_offer.clone(),
+        expires: state.expires,
+    };
+    let msg = IbcMsg::SendPacket {
+        channel_id: channel_id.clone(),
+        data: to_json_binary(&packet)?,
+        timeout: packet_timeout(&env, timeout),
+    };
+
+    Ok(Response::new()
+        .add_message(msg)
+        .add_event(event.into())
+        .add_attribute("action", "ibc_transfer")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("receiver", receiver)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Holder of a voucher hands it to `recipient` on this chain.
+pub fn handle_transfer_voucher(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel_id: String,
+    option_id: u64,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut voucher = VOUCHERS.load(deps.storage, (&channel_id, option_id))?;
+    if info.sender != voucher.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    voucher.owner = deps.api.addr_validate(&recipient)?;
+    VOUCHERS.save(deps.storage, (&channel_id, option_id), &voucher)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_voucher")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("owner", voucher.owner))
+}
+
+/// Holder of a voucher sends it back, releasing the option to `receiver` on its home chain.
+/// The voucher stays locked here until the other chain acknowledges.
+pub fn handle_return_voucher(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    channel_id: String,
+    option_id: u64,
+    receiver: String,
+    timeout: Option<u64>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    assert_channel(&deps, &channel_id)?;
+    let mut voucher = VOUCHERS.load(deps.storage, (&channel_id, option_id))?;
+    if info.sender != voucher.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    voucher.owner = env.contract.address.clone();
+    VOUCHERS.save(deps.storage, (&channel_id, option_id), &voucher)?;
+
+    let packet = OptionPacket::Return {
+        option_id,
+        sender: info.sender.to_string(),
+        receiver: receiver.clone(),
+    };
+    let msg = IbcMsg::SendPacket {
+        channel_id: channel_id.clone(),
+        data: to_json_binary(&packet)?,
+        timeout: packet_timeout(&env, timeout),
+    };
+
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "return_voucher")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("receiver", receiver))
+}
+
+fn check_order_and_version(
+    channel: &IbcChannel,
+    counterparty_version: Option<&str>,
+) -> Result<(), ContractError> {
+    // a lost packet on an ordered channel would close it, and strand every escrowed option
+    if channel.order != IbcOrder::Unordered {
+        return Err(ContractError::OnlyUnorderedChannel {});
+    }
+    for version in std::iter::once(channel.version.as_str()).chain(counterparty_version) {
+        if version != IBC_VERSION {
+            return Err(ContractError::InvalidIbcVersion {
+                expected: IBC_VERSION.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_open(
+    _deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelOpenMsg,
+) -> Result<(), ContractError> {
+    check_order_and_version(msg.channel(), msg.counterparty_version())
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_connect(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelConnectMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    check_order_and_version(channel, msg.counterparty_version())?;
+    IBC_CHANNELS.save(deps.storage, &channel.endpoint.channel_id, &Empty {})?;
+
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_connect")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+/// Stops sending over a closed channel. Options already escrowed for it stay with the contract,
+/// as a voucher may still exist on the other side.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_close(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcChannelCloseMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    IBC_CHANNELS.remove(deps.storage, &channel.endpoint.channel_id);
+
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_close")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+/// Never fails, a bad packet is answered with an error acknowledgement so the sender can undo it.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_receive(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcPacketReceiveMsg,
+) -> Result<IbcReceiveResponse, ContractError> {
+    match do_packet_receive(deps, &msg.packet) {
+        Ok(res) => Ok(res),
+        Err(err) => Ok(IbcReceiveResponse::new()
+            .set_ack(ack_fail(err.to_string())?)
+            .add_attribute("action", "ibc_receive")
+            .add_attribute("error", err.to_string())),
+    }
+}
+
+fn do_packet_receive(
+    deps: DepsMut,
+    packet: &IbcPacket,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let channel_id = packet.dest.channel_id.as_str();
+    match from_json(&packet.data)? {
+        OptionPacket::Transfer {
+            option_id,
+            receiver,
+            collateral,
+            counter_offer,
+            expires,
+            ..
+        } => {
+            if VOUCHERS.has(deps.storage, (channel_id, option_id)) {
+                return Err(ContractError::VoucherExists {});
+            }
+            let owner = deps.api.addr_validate(&receiver)?;
+            let voucher = Voucher {
+                owner,
+                collateral,
+                counter_offer,
+                expires,
+            };
+            VOUCHERS.save(deps.storage, (channel_id, option_id), &voucher)?;
+
+            Ok(IbcReceiveResponse::new()
+                .set_ack(ack_success()?)
+                .add_attribute("action", "receive_voucher")
+                .add_attribute("channel_id", channel_id)
+                .add_attribute("option_id", option_id.to_string())
+                .add_attribute("owner", voucher.owner))
+        }
+        OptionPacket::Return {
+            option_id,
+            receiver,
+            ..
+        } => {
+            let escrow = IBC_ESCROWS.load(deps.storage, option_id)?;
+            // only the chain holding the voucher can release it
+            if escrow.channel_id != channel_id {
+                return Err(ContractError::Unauthorized {});
+            }
+            let receiver = deps.api.addr_validate(&receiver)?;
+            // refused, so the sending chain gives the voucher back
+            if let Some(address) = blacklist::listed(deps.storage, &[&receiver]) {
+                let event = blacklist::blocked_event(
+                    "receive_option",
+                    "option_id",
+                    option_id,
+                    address.as_str(),
+                );
+                return Ok(IbcReceiveResponse::new()
+                    .set_ack(ack_fail(format!("{} is blacklisted", address))?)
+                    .add_event(event)
+                    .add_attribute("action", "blocked")
+                    .add_attribute("channel_id", channel_id));
+            }
+            assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &receiver)?;
+            let (state, event) = release(deps, option_id, receiver)?;
+
+            Ok(IbcReceiveResponse::new()
+                .set_ack(ack_success()?)
+                .add_event(event.into())
+                .add_attribute("action", "receive_option")
+                .add_attribute("channel_id", channel_id)
+                .add_attributes(option_attributes(option_id, &state)))
+        }
+    }
+}
+
+/// Takes the option out of escrow and gives it to `owner`.
+fn release(
+    deps: DepsMut,
+    option_id: u64,
+    owner: Addr,
+) -> Result<(State, OptionTransferred), ContractError> {
+    let mut state: State = options().load(deps.storage, option_id)?;
+    let event = change_owner(&mut state, option_id, owner);
+    save_option(deps.storage, option_id, &state)?;
+    IBC_ESCROWS.remove(deps.storage, option_id);
+    Ok((state, event))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_ack(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcPacketAckMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    match from_json(&msg.acknowledgement.data)? {
+        Ack::Result(_) => on_packet_success(deps, &msg.original_packet),
+        Ack::Error(err) => on_packet_failure(deps, &msg.original_packet, err),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_timeout(
+    deps: DepsMut,
+    _env: Env,
+    msg: IbcPacketTimeoutMsg,
+) -> Result<IbcBasicResponse, ContractError> {
+    on_packet_failure(deps, &msg.packet, "timeout".to_string())
+}
+
+fn on_packet_success(deps: DepsMut, packet: &IbcPacket) -> Result<IbcBasicResponse, ContractError> {
+    let channel_id = packet.src.channel_id.as_str();
+    let res = IbcBasicResponse::new().add_attribute("action", "ibc_ack");
+    match from_json(&packet.data)? {
+        // the voucher now exists on the other side, the option stays in escrow
+        OptionPacket::Transfer { option_id, .. } => {
+            Ok(res.add_attribute("option_id", option_id.to_string()))
+        }
+        OptionPacket::Return { option_id, .. } => {
+            VOUCHERS.remove(deps.storage, (channel_id, option_id));
+            Ok(res.add_attribute("option_id", option_id.to_string()))
+        }
+    }
+}
+
+/// Undoes whatever the packet locked up, giving it back to whoever sent it.
+fn on_packet_failure(
+    deps: DepsMut,
+    packet: &IbcPacket,
+    err: String,
+) -> Result<IbcBasicResponse, ContractError> {
+    let channel_id = packet.src.channel_id.as_str();
+    match from_json(&packet.data)? {
+        OptionPacket::Transfer { option_id, .. } => {
+            let escrow = IBC_ESCROWS.load(deps.storage, option_id)?;
+            let (state, event) = release(deps, option_id, escrow.owner)?;
+            Ok(IbcBasicResponse::new()
+                .add_event(event.into())
+                .add_attribute("action", "ibc_transfer_failed")
+                .add_attribute("error", err)
+                .add_attributes(option_attributes(option_id, &state)))
+        }
+        OptionPacket::Return {
+            option_id, sender, ..
+        } => {
+            let mut voucher = VOUCHERS.load(deps.storage, (channel_id, option_id))?;
+            voucher.owner = deps.api.addr_validate(&sender)?;
+            VOUCHERS.save(deps.storage, (channel_id, option_id), &voucher)?;
+            Ok(IbcBasicResponse::new()
+                .add_attribute("action", "return_voucher_failed")
+                .add_attribute("error", err)
+                .add_attribute("option_id", option_id.to_string())
+                .add_attribute("owner", voucher.owner))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ConfigResponse, ExecuteMsg, QueryMsg, VoucherResponse};
+    use crate::testing;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_ibc_channel_connect_ack, mock_ibc_channel_open_try,
+        mock_ibc_packet_ack, mock_ibc_packet_recv, mock_ibc_packet_timeout, mock_info, MockApi,
+        MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{coins, CosmosMsg, IbcAcknowledgement, OwnedDeps};
+
+    const CHANNEL: &str = "channel-0";
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = testing::setup();
+
+        let msg = mock_ibc_channel_connect_ack(CHANNEL, IbcOrder::Unordered, IBC_VERSION);
+        let _ = ibc_channel_connect(deps.as_mut(), mock_env(), msg).unwrap();
+        deps
+    }
+
+    fn owner_of(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> Addr {
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 1 }).unwrap();
+        let config: ConfigResponse = from_json(res).unwrap();
+        config.state.owner
+    }
+
+    fn sent_packet(res: &Response) -> Binary {
+        match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data.clone(),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn channel_handshake() {
+        let mut deps = mock_dependencies();
+        let msg = mock_ibc_channel_open_try(CHANNEL, IbcOrder::Unordered, IBC_VERSION);
+        ibc_channel_open(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let msg = mock_ibc_channel_open_try(CHANNEL, IbcOrder::Ordered, IBC_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::OnlyUnorderedChannel {}));
+
+        let msg = mock_ibc_channel_open_try(CHANNEL, IbcOrder::Unordered, "ics20-1");
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidIbcVersion { .. }));
+    }
+
+    #[test]
+    fn transfer_and_return() {
+        let mut deps = setup();
+
+        // only the owner can send it, and only over a connected channel
+        let msg = ExecuteMsg::IbcTransfer {
+            option_id: 1,
+            channel_id: CHANNEL.to_string(),
+            receiver: "remote".to_string(),
+            timeout: None,
+        };
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let unknown = ExecuteMsg::IbcTransfer {
+            option_id: 1,
+            channel_id: "channel-9".to_string(),
+            receiver: "remote".to_string(),
+            timeout: None,
+        };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, unknown).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownChannel { .. }));
+
+        // the option is escrowed while the packet is in flight
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let data = sent_packet(&res);
+        assert_eq!(mock_env().contract.address, owner_of(&deps));
+
+        // the other chain mints a voucher, here that is the same contract
+        let res = ibc_packet_receive(
+            deps.as_mut(),
+            mock_env(),
+            mock_ibc_packet_recv(CHANNEL, &from_json::<OptionPacket>(&data).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(ack_success().unwrap(), res.acknowledgement);
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Voucher {
+                channel_id: CHANNEL.to_string(),
+                option_id: 1,
+            },
+        )
+        .unwrap();
+        let voucher: VoucherResponse = from_json(res).unwrap();
+        let voucher = voucher.unwrap();
+        assert_eq!("remote", voucher.owner.as_str());
+        assert_eq!(coins(1, "BTC"), voucher.collateral);
+
+        // a second transfer for the same option is refused
+        let packet = from_json::<OptionPacket>(&data).unwrap();
+        let res = ibc_packet_receive(
+            deps.as_mut(),
+            mock_env(),
+            mock_ibc_packet_recv(CHANNEL, &packet).unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            from_json(res.acknowledgement).unwrap(),
+            Ack::Error(_)
+        ));
+
+        // the ack keeps the option in escrow
+        let ack = IbcAcknowledgement::new(ack_success().unwrap());
+        let msg = mock_ibc_packet_ack(CHANNEL, &packet, ack).unwrap();
+        let _ = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(mock_env().contract.address, owner_of(&deps));
+
+        // the voucher holder sends it back to bob
+        let msg = ExecuteMsg::ReturnVoucher {
+            channel_id: CHANNEL.to_string(),
+            option_id: 1,
+            receiver: "bob".to_string(),
+            timeout: None,
+        };
+        let info = mock_info("remote", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let packet: OptionPacket = from_json(sent_packet(&res)).unwrap();
+
+        let msg = mock_ibc_packet_recv(CHANNEL, &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(ack_success().unwrap(), res.acknowledgement);
+        assert_eq!("bob", owner_of(&deps).as_str());
+
+        // once acknowledged, the voucher is gone
+        let ack = IbcAcknowledgement::new(ack_success().unwrap());
+        let msg = mock_ibc_packet_ack(CHANNEL, &packet, ack).unwrap();
+        let _ = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(!VOUCHERS.has(&deps.storage, (CHANNEL, 1)));
+        assert!(!IBC_ESCROWS.has(&deps.storage, 1));
+    }
+
+    #[test]
+    fn return_to_blacklisted_is_refused() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::IbcTransfer {
+            option_id: 1,
+            channel_id: CHANNEL.to_string(),
+            receiver: "remote".to_string(),
+            timeout: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let packet: OptionPacket = from_json(sent_packet(&res)).unwrap();
+        let msg = mock_ibc_packet_recv(CHANNEL, &packet).unwrap();
+        let _ = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        testing::blacklist(&mut deps, "sanctioned");
+
+        let msg = ExecuteMsg::ReturnVoucher {
+            channel_id: CHANNEL.to_string(),
+            option_id: 1,
+            receiver: "sanctioned".to_string(),
+            timeout: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("remote", &[]), msg).unwrap();
+        let packet: OptionPacket = from_json(sent_packet(&res)).unwrap();
+
+        // the option stays in escrow
+        let msg = mock_ibc_packet_recv(CHANNEL, &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(mock_env().contract.address, owner_of(&deps));
+        assert!(IBC_ESCROWS.has(&deps.storage, 1));
+
+        // and the error ack refunds the voucher to its holder
+        let ack = IbcAcknowledgement::new(res.acknowledgement);
+        let msg = mock_ibc_packet_ack(CHANNEL, &packet, ack).unwrap();
+        let _ = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        let voucher = VOUCHERS.load(&deps.storage, (CHANNEL, 1)).unwrap();
+        assert_eq!("remote", voucher.owner.as_str());
+    }
+
+    #[test]
+    fn failed_transfer_is_undone() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::IbcTransfer {
+            option_id: 1,
+            channel_id: CHANNEL.to_string(),
+            receiver: "remote".to_string(),
+            timeout: Some(60),
+        };
+        let info = mock_info("creator", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        let packet: OptionPacket = from_json(sent_packet(&res)).unwrap();
+
+        let msg_timeout = mock_ibc_packet_timeout(CHANNEL, &packet).unwrap();
+        let _ = ibc_packet_timeout(deps.as_mut(), mock_env(), msg_timeout).unwrap();
+        assert_eq!("creator", owner_of(&deps).as_str());
+        assert!(!IBC_ESCROWS.has(&deps.storage, 1));
+
+        // an error ack returns it as well
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let packet: OptionPacket = from_json(sent_packet(&res)).unwrap();
+        let ack = IbcAcknowledgement::new(ack_fail("no".to_string()).unwrap());
+        let msg = mock_ibc_packet_ack(CHANNEL, &packet, ack).unwrap();
+        let _ = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!("creator", owner_of(&deps).as_str());
+    }
+}
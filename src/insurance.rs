@@ -0,0 +1,178 @@
+//! Insurance fund topping up payouts the collateral of an option falls short of.
+//!
+//! The fund is paid the configured share of every native protocol fee. Whenever the margin of a
+//! margined option is less than its payout, on cash settlement or liquidation, the fund makes
+//! up the difference as far as it holds the collateral denom. The admin can withdraw from it.
+
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    BankMsg, Coin, Coins, Deps, DepsMut, MessageInfo, Response, StdError, StdResult, Storage,
+    Uint128,
+};
+
+use crate::contract::split_bps;
+use crate::error::ContractError;
+use crate::msg::InsuranceFundResponse;
+use crate::state::{Config, CONFIG, INSURANCE_FUND};
+
+/// Pays the insurance share of `fee` into the fund, returning the rest.
+pub(crate) fn take_share(
+    storage: &mut dyn Storage,
+    config: &Config,
+    fee: Vec<Coin>,
+) -> StdResult<Vec<Coin>> {
+    let (share, rest) = split_bps(fee, config.insurance_bps);
+    if !share.is_empty() {
+        let mut fund = Coins::try_from(INSURANCE_FUND.may_load(storage)?.unwrap_or_default())?;
+        for coin in share {
+            fund.add(coin)?;
+        }
+        INSURANCE_FUND.save(storage, &fund.into_vec())?;
+    }
+    Ok(rest)
+}
+
+/// Takes as much of `shortfall` of `denom` out of the fund as it holds, returning the amount.
+pub(crate) fn cover(
+    storage: &mut dyn Storage,
+    shortfall: Uint128,
+    denom: &str,
+) -> StdResult<Uint128> {
+    if shortfall.is_zero() {
+        return Ok(shortfall);
+    }
+    let mut fund = Coins::try_from(INSURANCE_FUND.may_load(storage)?.unwrap_or_default())?;
+    let covered = shortfall.min(fund.amount_of(denom));
+    if !covered.is_zero() {
+        fund.sub(Coin::new(covered.u128(), denom))?;
+        INSURANCE_FUND.save(storage, &fund.into_vec())?;
+    }
+    Ok(covered)
+}
+
+/// Admin withdraws `amount` from the fund, to themselves unless they name a recipient
+pub fn handle_withdraw_insurance(
+    deps: DepsMut,
+    info: MessageInfo,
+    amount: Vec<Coin>,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if amount.is_empty() || amount.iter().any(|c| c.amount.is_zero()) {
+        return Err(ContractError::InvalidInsuranceWithdrawal {});
+    }
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender,
+    };
+
+    let mut fund = Coins::try_from(INSURANCE_FUND.may_load(deps.storage)?.unwrap_or_default())
+        .map_err(StdError::from)?;
+    for coin in amount.iter().cloned() {
+        fund.sub(coin)
+            .map_err(|_| ContractError::InvalidInsuranceWithdrawal {})?;
+    }
+    INSURANCE_FUND.save(deps.storage, &fund.to_vec())?;
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount,
+        })
+        .add_attribute("action", "withdraw_insurance")
+        .add_attribute("recipient", recipient)
+        .add_attribute("fund", fund.to_string()))
+}
+
+pub fn query_insurance_fund(deps: Deps) -> StdResult<InsuranceFundResponse> {
+    Ok(INSURANCE_FUND.may_load(deps.storage)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::coins;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::CosmosMsg;
+
+    #[test]
+    fn fund_and_withdraw() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            fee_bps: 1_000,
+            fee_collector: Some("collector".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let config = UpdateConfigMsg {
+            insurance_bps: Some(5_000),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // half of the 4 ETH fee stays in the fund
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".into(),
+                amount: coins(2, "ETH"),
+            }),
+            res.messages[2].msg
+        );
+        assert_eq!(
+            coins(2, "ETH"),
+            query_insurance_fund(deps.as_ref()).unwrap()
+        );
+
+        // shortfalls are covered as far as the fund goes
+        let covered = cover(&mut deps.storage, Uint128::new(3), "ETH").unwrap();
+        assert_eq!(Uint128::new(2), covered);
+        assert!(query_insurance_fund(deps.as_ref()).unwrap().is_empty());
+        let config = CONFIG.load(&deps.storage).unwrap();
+        let rest = take_share(&mut deps.storage, &config, coins(4, "ETH")).unwrap();
+        assert_eq!(coins(2, "ETH"), rest);
+
+        // only the admin withdraws, and only what the fund holds
+        let withdraw = |amount: u128| ExecuteMsg::WithdrawInsurance {
+            amount: coins(amount, "ETH"),
+            recipient: Some("treasury".to_string()),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            withdraw(2),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let admin = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), withdraw(3)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidInsuranceWithdrawal {}));
+        let res = execute(deps.as_mut(), mock_env(), admin, withdraw(2)).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".into(),
+                amount: coins(2, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert!(query_insurance_fund(deps.as_ref()).unwrap().is_empty());
+    }
+}
@@ -1,7 +1,39 @@
+pub mod auction;
+pub mod blacklist;
+pub mod circuit_breaker;
 pub mod contract;
+pub mod cowrite;
+pub mod crank;
+pub mod denom;
+pub mod eligibility;
 pub mod error;
+pub mod escrow;
+pub mod events;
+pub mod factory;
+pub mod governance;
+pub mod helpers;
+pub mod ibc;
+pub mod insurance;
+pub mod limits;
+pub mod margin;
 pub mod msg;
+pub mod oracle;
+pub mod orderbook;
+pub mod payouts;
+pub mod perpetual;
+pub mod pricing;
+pub mod raw;
+pub mod recovery;
+pub mod rfq;
+pub mod series;
+pub mod shares;
+pub mod staking;
 pub mod state;
-
-#[cfg(target_arch = "wasm32")]
-cosmwasm_std::create_entry_points!(contract);
+pub mod strategy;
+#[cfg(test)]
+mod testing;
+pub mod timelock;
+pub mod tokenfactory;
+pub mod twap;
+pub mod vault;
+pub mod yield_strategy;
@@ -0,0 +1,133 @@
+//! Limits keeping the option store from being flooded with dust.
+//!
+//! New options need collateral worth at least the configured minimum, and every address can
+//! only have so many options it wrote open at once.
+
+use cosmwasm_std::{Addr, Coin, Order, QuerierWrapper, Storage, Uint128};
+use cw20::Cw20CoinVerified;
+
+use crate::error::ContractError;
+use crate::oracle::query_price;
+use crate::state::{options, Config};
+
+/// Fails if the collateral is worth less than the configured minimum. Coins of the minimum's
+/// denom count at face value, others at the oracle price if there is an oracle. What the
+/// oracle can't price counts for nothing, and NFT collateral isn't held to the minimum.
+pub(crate) fn assert_min_collateral(
+    querier: &QuerierWrapper,
+    config: &Config,
+    funds: &[Coin],
+    cw20_collateral: &[Cw20CoinVerified],
+) -> Result<(), ContractError> {
+    let min = match &config.min_collateral {
+        Some(min) => min,
+        None => return Ok(()),
+    };
+    if funds.is_empty() && cw20_collateral.is_empty() {
+        return Ok(());
+    }
+    let value_of = |denom: &str, amount: Uint128| match &config.oracle {
+        _ if denom == min.denom => amount,
+        Some(oracle) => query_price(querier, oracle, denom, &min.denom)
+            .map(|rate| amount.mul_floor(rate))
+            .unwrap_or_default(),
+        None => Uint128::zero(),
+    };
+    let value: Uint128 = funds
+        .iter()
+        .map(|c| value_of(&c.denom, c.amount))
+        .chain(
+            cw20_collateral
+                .iter()
+                .map(|c| value_of(c.address.as_str(), c.amount)),
+        )
+        .sum();
+    if value < min.amount {
+        return Err(ContractError::CollateralTooSmall { min: min.clone() });
+    }
+    Ok(())
+}
+
+/// Fails if `creator` already has as many open options as the configured cap.
+pub(crate) fn assert_open_limit(
+    storage: &dyn Storage,
+    config: &Config,
+    creator: &Addr,
+) -> Result<(), ContractError> {
+    let max = config.max_open_options;
+    if max == 0 {
+        return Ok(());
+    }
+    let open = options()
+        .idx
+        .creator
+        .prefix(creator.clone())
+        .keys(storage, None, None, Order::Ascending)
+        .take(max as usize)
+        .count();
+    if open as u64 >= max {
+        return Err(ContractError::TooManyOpenOptions { max });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CreateMsg, ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::state::{Asset, OptionStyle, Settlement};
+    use crate::testing::{instantiate_msg, mock_oracle};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, Decimal};
+    use cw_utils::Expiration;
+
+    #[test]
+    fn minimum_collateral_and_open_cap() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(50));
+        let msg = InstantiateMsg {
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let config = UpdateConfigMsg {
+            min_collateral: Some(coin(100, "USD")),
+            max_open_options: Some(2),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let create = ExecuteMsg::Create(CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            alt_counter_offers: vec![],
+            strike: None,
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            allowed_taker: None,
+            owner: None,
+        });
+        // 150 BTC at half a USD falls short, 100 USD along makes up for it
+        let info = mock_info("creator", &coins(150, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, create.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CollateralTooSmall { .. }));
+        let info = mock_info("creator", &[coin(150, "BTC"), coin(25, "USD")]);
+        let _ = execute(deps.as_mut(), mock_env(), info, create.clone()).unwrap();
+
+        // the creator has two options open now, others can still write theirs
+        let info = mock_info("creator", &coins(100, "USD"));
+        let err = execute(deps.as_mut(), mock_env(), info, create.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::TooManyOpenOptions { max: 2 }));
+        let info = mock_info("other", &coins(100, "USD"));
+        let _ = execute(deps.as_mut(), mock_env(), info, create).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+//! Cash settled options written on margin instead of full collateral.
+//!
+//! The creator of a margined option names the notional, the collateral it pays out on, and
+//! only posts part of it as margin, which is what the option holds as collateral. It settles
+//! like any cash settled option on its notional, paying out no more than the margin. The
+//! margin has to cover the maintenance requirement at the oracle price: what the option would
+//! pay out right now plus the configured share of the notional. Once it no longer does, anyone
+//! can liquidate the option, seizing the whole margin for the owner.
+
+use cosmwasm_std::{
+    BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage, Uint128,
+};
+use cw_utils::Expiration;
+
+use crate::contract::{
+    archive_option, assert_not_paused, create_option, option_attributes, priced_amount, MAX_BPS,
+};
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::insurance;
+use crate::msg::{CreateMsg, MarginResponse};
+use crate::oracle::query_collateral_price;
+use crate::shares::keep_proceeds;
+use crate::state::{options, remove_option, Closure, Config, Settlement, State, CONFIG, NOTIONALS};
+
+/// Notional of a margined option, to load before the option is closed.
+pub(crate) fn notional(storage: &dyn Storage, option_id: u64) -> StdResult<Option<Uint128>> {
+    NOTIONALS.may_load(storage, option_id)
+}
+
+/// What the option would pay out on its notional at the oracle price right now.
+fn intrinsic_value(
+    deps: Deps,
+    config: &Config,
+    state: &State,
+    notional: Uint128,
+) -> Result<Uint128, ContractError> {
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), state)?;
+    let strike = priced_amount(&state.counter_offer);
+    let kept = strike
+        .checked_div_ceil(spot)
+        .unwrap_or(notional)
+        .min(notional);
+    Ok(notional - kept)
+}
+
+/// Margin the option needs: its intrinsic value plus the maintenance share of the notional.
+fn requirement(config: &Config, intrinsic: Uint128, notional: Uint128) -> Uint128 {
+    intrinsic + notional.multiply_ratio(config.maintenance_margin_bps, MAX_BPS)
+}
+
+/// Fails if the option is margined and its margin is below the maintenance requirement.
+pub(crate) fn assert_margin(
+    deps: Deps,
+    option_id: u64,
+    state: &State,
+) -> Result<(), ContractError> {
+    let notional = match NOTIONALS.may_load(deps.storage, option_id)? {
+        Some(notional) => notional,
+        None => return Ok(()),
+    };
+    let config = CONFIG.load(deps.storage)?;
+    let intrinsic = intrinsic_value(deps, &config, state, notional)?;
+    let requirement = requirement(&config, intrinsic, notional);
+    let margin = state.collateral[0].amount;
+    if margin < requirement {
+        return Err(ContractError::MarginTooLow {
+            margin,
+            requirement,
+        });
+    }
+    Ok(())
+}
+
+/// Forgets the notional of an option being closed.
+pub(crate) fn release(storage: &mut dyn Storage, option_id: u64) {
+    NOTIONALS.remove(storage, option_id);
+}
+
+/// Anyone can write a cash settled option on `notional` of the collateral denom, the coin
+/// sent being the margin held against it.
+pub fn handle_create_margined(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    terms: CreateMsg,
+    notional: Uint128,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let margin = match info.funds.as_slice() {
+        [coin] => coin.amount,
+        _ => return Err(ContractError::InvalidMargin {}),
+    };
+    // tokens can't be told apart by the notional, and the strike would follow the margin
+    if config.maintenance_margin_bps == 0
+        || config.tokenfactory
+        || notional <= margin
+        || terms.settlement != Settlement::Cash
+        || terms.strike.is_some()
+        || terms.roll_on_expiry
+        || matches!(terms.expires, Expiration::Never {})
+    {
+        return Err(ContractError::InvalidMargin {});
+    }
+
+    let (option_id, state) = create_option(deps.branch(), &env, info, terms)?;
+    NOTIONALS.save(deps.storage, option_id, &notional)?;
+    assert_margin(deps.as_ref(), option_id, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_margined")
+        .add_attribute("notional", notional)
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Anyone can close an unexpired margined option below its maintenance requirement, paying its
+/// whole margin to the owner
+pub fn handle_liquidate_margin(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let notional = NOTIONALS.load(deps.storage, option_id)?;
+    // expired options settle or are burned as usual
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let intrinsic = intrinsic_value(deps.as_ref(), &config, &state, notional)?;
+    let requirement = requirement(&config, intrinsic, notional);
+    let margin = &state.collateral[0];
+    if margin.amount >= requirement {
+        return Err(ContractError::MarginCovered {});
+    }
+    // the insurance fund makes up what the margin falls short of the payout
+    let shortfall = intrinsic.saturating_sub(margin.amount);
+    let covered = insurance::cover(deps.storage, shortfall, &margin.denom)?;
+    let seized = Coin::new((margin.amount + covered).u128(), &margin.denom);
+
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Liquidated,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+
+    let res = Response::new()
+        .add_message(BankMsg::Send {
+            to_address: state.owner.to_string(),
+            amount: vec![seized],
+        })
+        .add_attribute("action", "liquidate_margin")
+        .add_event(archived.into())
+        .add_attribute("requirement", requirement)
+        .add_attribute("insured", covered)
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+pub fn query_margin(deps: Deps, option_id: u64) -> StdResult<MarginResponse> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let notional = NOTIONALS.load(deps.storage, option_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    let margin = state.collateral[0].amount;
+    let intrinsic = intrinsic_value(deps, &config, &state, notional)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let requirement = requirement(&config, intrinsic, notional);
+    Ok(MarginResponse {
+        notional,
+        margin,
+        requirement,
+        liquidatable: margin < requirement,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::state::{Asset, OptionStyle};
+    use crate::testing::{instantiate_msg, mock_oracle};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, CosmosMsg, Decimal};
+
+    #[test]
+    fn margin_and_liquidation() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier, Decimal::percent(4_000));
+        let msg = InstantiateMsg {
+            settlement: Settlement::Cash,
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // a strike of 40 on 10 BTC, a tenth of it kept as margin
+        let create = |margin: u128| {
+            let terms = CreateMsg {
+                counter_offer: Asset::Native(coins(400, "ETH")),
+                expires: Expiration::AtHeight(100_000),
+                premium: vec![],
+                settlement: Settlement::Cash,
+                style: OptionStyle::American,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: Some("owner".to_string()),
+            };
+            let msg = ExecuteMsg::CreateMargined {
+                terms,
+                notional: Uint128::new(10),
+            };
+            (mock_info("writer", &coins(margin, "BTC")), msg)
+        };
+        let (info, msg) = create(2);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidMargin {}));
+        let config = UpdateConfigMsg {
+            maintenance_margin_bps: Some(1_000),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let (info, msg) = create(10);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidMargin {}));
+        let (info, msg) = create(2);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let (info, msg) = create(4);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // out of the money, the margin only needs to cover the maintenance share
+        let liquidate = ExecuteMsg::LiquidateMargin { option_id: 2 };
+        let anyone = mock_info("anyone", &[]);
+        let err =
+            execute(deps.as_mut(), mock_env(), anyone.clone(), liquidate.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::MarginCovered {}));
+
+        // at 50 the option is worth 2 BTC on top of it
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let res = query_margin(deps.as_ref(), 2).unwrap();
+        assert_eq!(
+            MarginResponse {
+                notional: Uint128::new(10),
+                margin: Uint128::new(2),
+                requirement: Uint128::new(3),
+                liquidatable: true,
+            },
+            res
+        );
+        // not while paused
+        let creator = mock_info("creator", &[]);
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            creator.clone(),
+            ExecuteMsg::Pause {},
+        )
+        .unwrap();
+        let err =
+            execute(deps.as_mut(), mock_env(), anyone.clone(), liquidate.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Paused {}));
+        let _ = execute(deps.as_mut(), mock_env(), creator, ExecuteMsg::Unpause {}).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), anyone, liquidate).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(2, "BTC"),
+            }),
+            res.messages[0].msg
+        );
+        assert!(!NOTIONALS.has(&deps.storage, 2));
+
+        // executing pays out on the notional, the creator keeps the rest of the margin
+        let msg = ExecuteMsg::Execute {
+            option_id: 3,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "writer".into(),
+                    amount: coins(2, "BTC"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".into(),
+                    amount: coins(2, "BTC"),
+                }),
+            ],
+            msgs
+        );
+    }
+}
@@ -1,31 +1,1055 @@
-use crate::state::State;
-use cosmwasm_std::{Coin, HumanAddr};
+use crate::state::{
+    Asset, Auction, Barrier, Buyback, Config, DenomTrace, Extension, FailedPayout, Funding,
+    LastPrice, OptionStyle, PremiumEscrow, QueuedConfig, Quote, QuoteRequest, ReferralStats,
+    Series, Settlement, SettlementRecord, ShareInfo, Stake, State, Stats, Strategy, TradeOrder,
+    TransferFee, TransferOffer, Vault, Voucher, WritingPool, YieldDeposit,
+};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp, Uint128};
+use cw20::{Cw20Coin, Cw20ReceiveMsg};
+use cw721::Cw721ReceiveMsg;
+use cw_utils::Expiration;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct InitMsg {
+pub struct InstantiateMsg {
     // owner and creator come from env
     // collateral comes from env
+    pub counter_offer: Asset,
+    /// Other payments the creator accepts instead of the counter_offer, physical settlement only
+    #[serde(default)]
+    pub alt_counter_offers: Vec<Asset>,
+    /// Price of the collateral in the counter_offer denom. If set, the counter_offer due is worked
+    /// out from the collateral at exercise instead of fixed, single coins only.
+    #[serde(default)]
+    pub strike: Option<Decimal>,
+    pub expires: Expiration,
+    /// Price a buyer has to pay the creator to become owner, empty if not for sale
+    #[serde(default)]
+    pub premium: Vec<Coin>,
+    /// Physical (default), cash, binary or averaging settlement, all but physical need a
+    /// configured oracle
+    #[serde(default)]
+    pub settlement: Settlement,
+    /// American (default) options are exercised before expiry, European ones right after
+    #[serde(default)]
+    pub style: OptionStyle,
+    /// Share of the sale price, in basis points, paid to the creator whenever the option is resold
+    #[serde(default)]
+    pub royalty_bps: u64,
+    /// Unexercised, the option is rolled over to a later expiry instead of being burned
+    #[serde(default)]
+    pub roll_on_expiry: bool,
+    /// Anyone can knock the option out, returning the collateral to the creator, once the
+    /// oracle price of the collateral crosses the barrier
+    pub barrier: Option<Barrier>,
+    /// Blocks or seconds, matching the expiry, a European option can still be executed after
+    /// expiry before the creator can burn it. Defaults to 100 blocks or 600 seconds.
+    pub settlement_window: Option<u64>,
+    /// American options can only be executed from this point until expiry,
+    /// though they can be traded before it
+    pub exercisable_from: Option<Expiration>,
+    /// Only this address, besides the creator, can buy or be transferred the option
+    pub allowed_taker: Option<String>,
+    /// Owner the option is written to, defaults to the creator
+    pub owner: Option<String>,
+    /// Share of the collateral, in basis points, paid to keepers burning expired options
+    #[serde(default)]
+    pub burn_reward_bps: u64,
+    /// Protocol fee, in basis points, taken from the counter_offer on execution
+    #[serde(default)]
+    pub fee_bps: u64,
+    /// Receives the protocol fees, defaults to the instantiator
+    pub fee_collector: Option<String>,
+    /// Can update the contract config, defaults to the instantiator
+    pub admin: Option<String>,
+    /// Can pause the contract, defaults to the admin
+    pub guardian: Option<String>,
+    /// DAO contract governing the fees, oracle and denom whitelist instead of the admin
+    pub dao: Option<String>,
+    /// Denoms accepted as collateral and counter_offer, empty means any
+    #[serde(default)]
+    pub allowed_denoms: Vec<String>,
+    /// Price feed used to value collateral in terms of the counter_offer
+    pub oracle: Option<String>,
+    /// Seconds settlement records are kept before anyone can prune them, 0 keeps them forever
+    #[serde(default)]
+    pub archive_retention: u64,
+    /// Represents new options by an x/tokenfactory denom, traded through the bank
+    #[serde(default)]
+    pub tokenfactory: bool,
+    /// Writes the option on behalf of `creator`, defaults to the instantiator.
+    /// The factory uses it for the instances it spawns.
+    pub creator: Option<String>,
+}
+
+/// Terms of a new option, the collateral is sent along with the message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreateMsg {
+    pub counter_offer: Asset,
+    /// Other payments the creator accepts instead of the counter_offer, physical settlement only
+    #[serde(default)]
+    pub alt_counter_offers: Vec<Asset>,
+    /// Price of the collateral in the counter_offer denom. If set, the counter_offer due is worked
+    /// out from the collateral at exercise instead of fixed, single coins only.
+    #[serde(default)]
+    pub strike: Option<Decimal>,
+    pub expires: Expiration,
+    /// Price a buyer has to pay the creator to become owner, empty if not for sale
+    #[serde(default)]
+    pub premium: Vec<Coin>,
+    /// Physical (default), cash, binary or averaging settlement, all but physical need a
+    /// configured oracle
+    #[serde(default)]
+    pub settlement: Settlement,
+    /// American (default) options are exercised before expiry, European ones right after
+    #[serde(default)]
+    pub style: OptionStyle,
+    /// Share of the sale price, in basis points, paid to the creator whenever the option is resold
+    #[serde(default)]
+    pub royalty_bps: u64,
+    /// Unexercised, the option is rolled over to a later expiry instead of being burned
+    #[serde(default)]
+    pub roll_on_expiry: bool,
+    /// Anyone can knock the option out, returning the collateral to the creator, once the
+    /// oracle price of the collateral crosses the barrier
+    pub barrier: Option<Barrier>,
+    /// Blocks or seconds, matching the expiry, a European option can still be executed after
+    /// expiry before the creator can burn it. Defaults to 100 blocks or 600 seconds.
+    pub settlement_window: Option<u64>,
+    /// American options can only be executed from this point until expiry,
+    /// though they can be traded before it
+    pub exercisable_from: Option<Expiration>,
+    /// Only this address, besides the creator, can buy or be transferred the option
+    pub allowed_taker: Option<String>,
+    /// Owner the option is written to, defaults to the creator
+    pub owner: Option<String>,
+}
+
+/// Collateral and counter_offer of one leg of a strategy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegTerms {
+    pub collateral: Vec<Coin>,
     pub counter_offer: Vec<Coin>,
-    pub expires: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum HandleMsg {
-    /// Owner can transfer to a new owner
-    Transfer { recipient: HumanAddr },
-    /// Owner can post counter_offer on unexpired option to execute and get the collateral
-    Execute {},
+#[allow(clippy::large_enum_variant)]
+pub enum ExecuteMsg {
+    /// Anyone can write a new option, sending the collateral along with the message
+    Create(CreateMsg),
+    /// Anyone can write an option in a new instance of this contract, sending the collateral
+    /// along with the message. The instance is registered under the sender.
+    Spawn(CreateMsg),
+    /// Owner can transfer to a new owner (cw721)
+    TransferNft { recipient: String, token_id: String },
+    /// Owner can transfer to a contract and trigger an action on it (cw721)
+    SendNft {
+        contract: String,
+        token_id: String,
+        msg: Binary,
+    },
+    /// Approved spender can move the option away from `owner`, failing if it since changed hands
+    TransferFrom {
+        owner: String,
+        recipient: String,
+        token_id: String,
+    },
+    /// Moves several options at once as `(option_id, recipient)` pairs, each checked like
+    /// `TransferNft`. Fails as a whole if any of them cannot be transferred.
+    TransferBatch { transfers: Vec<(u64, String)> },
+    /// Owner can move the option to `receiver` on another chain over `channel_id`.
+    /// The collateral stays locked here until the option comes back.
+    IbcTransfer {
+        option_id: u64,
+        channel_id: String,
+        receiver: String,
+        /// Seconds until the transfer times out, 10 minutes by default
+        timeout: Option<u64>,
+    },
+    /// Holder of a voucher for an option on another chain can hand it to `recipient` here
+    TransferVoucher {
+        channel_id: String,
+        option_id: u64,
+        recipient: String,
+    },
+    /// Holder of a voucher can send it back, giving the option to `receiver` on its home chain
+    ReturnVoucher {
+        channel_id: String,
+        option_id: u64,
+        receiver: String,
+        timeout: Option<u64>,
+    },
+    /// Owner can offer the option to `recipient`, who has to claim it before `expires`
+    OfferTransfer {
+        option_id: u64,
+        recipient: String,
+        expires: Expiration,
+    },
+    /// Recipient of a transfer offer accepts it and becomes the owner
+    ClaimTransfer { option_id: u64 },
+    /// Owner can allow `spender` to transfer or execute the option, until `expires` (cw721)
+    Approve {
+        spender: String,
+        token_id: String,
+        expires: Option<Expiration>,
+    },
+    /// Owner can remove a previous `Approve` (cw721)
+    Revoke { spender: String, token_id: String },
+    /// Owner can allow `operator` to manage all of their options, until `expires` (cw721)
+    ApproveAll {
+        operator: String,
+        expires: Option<Expiration>,
+    },
+    /// Owner can remove a previous `ApproveAll` (cw721)
+    RevokeAll { operator: String },
+    /// Owner can put the option up for sale, an empty price takes it off the market
+    List { option_id: u64, price: Vec<Coin> },
+    /// Anyone can pay the asking price of a listed option to become its owner.
+    /// A `referrer` gets the configured share of the transfer fee.
+    Buy {
+        option_id: u64,
+        referrer: Option<String>,
+    },
+    /// Creator, while still the owner, can auction the option off for `duration` seconds,
+    /// starting at `reserve`
+    StartAuction {
+        option_id: u64,
+        reserve: Coin,
+        duration: u64,
+    },
+    /// Anyone can outbid the highest bid of a running auction, which gets refunded
+    Bid { option_id: u64 },
+    /// Anyone can close an ended auction, the highest bidder gets the option
+    CloseAuction { option_id: u64 },
+    /// Owner can sell the option at a price falling from `start_price` to `floor_price`
+    /// over `duration` seconds
+    StartDutchAuction {
+        option_id: u64,
+        start_price: Coin,
+        floor_price: Coin,
+        duration: u64,
+    },
+    /// Owner can take the option out of the Dutch auction
+    CancelDutchAuction { option_id: u64 },
+    /// Anyone can pay the current price of a Dutch auction to become the owner
+    BuyDutch { option_id: u64 },
+    /// Anyone but the owner can bid the single coin sent for the option, until `expires`
+    PlaceBid { option_id: u64, expires: Expiration },
+    /// Owner can offer the option at `price` until `expires`, handing it to the contract meanwhile
+    PlaceAsk {
+        option_id: u64,
+        price: Coin,
+        expires: Expiration,
+    },
+    /// Takes the other side of an order: the owner sells into a bid, anyone pays an ask
+    FillOrder { order_id: u64 },
+    /// Maker can cancel an order, anyone once it expired, returning the escrow to the maker
+    CancelOrder { order_id: u64 },
+    /// Anyone but the owner can escrow the premium sent as an offer for the option,
+    /// which the owner can accept until `deadline`
+    EscrowPremium {
+        option_id: u64,
+        deadline: Expiration,
+    },
+    /// Owner sells the option for the escrowed premium
+    AcceptEscrow { escrow_id: u64 },
+    /// Buyer can take back their escrow, anyone once the deadline passed
+    RefundEscrow { escrow_id: u64 },
+    /// Anyone can refund up to `limit` escrows past their deadline
+    RefundExpiredEscrows { limit: Option<u32> },
+    /// Anyone can ask writers to quote a premium for an option with these terms,
+    /// until `deadline`
+    RequestQuote {
+        counter_offer: Asset,
+        collateral: Vec<Coin>,
+        expires: Expiration,
+        #[serde(default)]
+        settlement: Settlement,
+        #[serde(default)]
+        style: OptionStyle,
+        deadline: Expiration,
+    },
+    /// Buyer can cancel their request, anyone once the deadline passed
+    CancelQuoteRequest { request_id: u64 },
+    /// Anyone can quote a premium on an open request, sending the requested collateral
+    SubmitQuote { request_id: u64, premium: Vec<Coin> },
+    /// Writer can withdraw their quote, anyone once its request closed, refunding the collateral
+    WithdrawQuote { quote_id: u64 },
+    /// Buyer pays the quoted premium to have the writer's option written to them
+    AcceptQuote { quote_id: u64 },
+    /// Owner can post counter_offer on unexpired option to execute and get the collateral.
+    /// Anything sent on top of the counter_offer is refunded.
+    /// The collateral goes to `recipient` if set, or else to the owner.
+    Execute {
+        option_id: u64,
+        recipient: Option<String>,
+        /// Gets the configured share of the protocol fee
+        referrer: Option<String>,
+        /// Fails once past this point, so a delayed transaction doesn't execute at a bad time
+        deadline: Option<Expiration>,
+        /// Fails if a cash settlement pays out less collateral, after the protocol fee
+        min_payout: Option<Uint128>,
+    },
+    /// Owner can execute a `fraction` of the option, paying that share of the counter_offer.
+    /// The rest of the option stays live.
+    ExecutePartial {
+        option_id: u64,
+        fraction: Decimal,
+        recipient: Option<String>,
+    },
+    /// Owner can execute several options at once, sending the sum of their counter_offers.
+    /// Anything sent on top is refunded, the collateral of each goes to its owner.
+    ExecuteBatch { option_ids: Vec<u64> },
+    /// Owner can divide an option into `parts` equal options with the same terms,
+    /// replacing the original
+    Split { option_id: u64, parts: u32 },
+    /// Owner can combine options with the same creator, expiry and denoms into the first one,
+    /// summing their amounts
+    Merge { option_ids: Vec<u64> },
+    /// Creator can send more collateral to back a live option
+    AddCollateral { option_id: u64 },
+    /// Creator can take back part of the collateral while still owning the option,
+    /// which also ends any listing
+    WithdrawCollateral { option_id: u64, amount: Vec<Coin> },
+    /// Creator still owning the option can close it, getting all the collateral back
+    Cancel { option_id: u64 },
+    /// Creator or owner can propose to close the option early, returning the collateral
+    ProposeTermination { option_id: u64 },
+    /// The other side of a termination proposal agrees, closing the option
+    AcceptTermination { option_id: u64 },
+    /// Creator can offer the owner a later expiry, sending along an optional fee for the owner.
+    /// Replaces and refunds any previous proposal.
+    ProposeExtension { option_id: u64, expires: Expiration },
+    /// Owner agrees to the proposed expiry and receives the fee
+    AcceptExtension { option_id: u64 },
+    /// Creator withdraws a proposed extension and gets the fee back
+    CancelExtension { option_id: u64 },
+    /// Creator can offer to buy the option back from its owner, sending along `price`.
+    /// Replaces and refunds any previous offer.
+    OfferBuyback { option_id: u64, price: Vec<Coin> },
+    /// Owner takes the buyback price, the collateral returns to the creator and the option closes
+    AcceptBuyback { option_id: u64 },
+    /// Creator withdraws a buyback offer and gets the price back
+    CancelBuyback { option_id: u64 },
+    /// Anyone can delete up to `limit` settlement records of options closed before `older_than`,
+    /// once that is past the retention period
+    PruneArchive {
+        older_than: Timestamp,
+        limit: Option<u32>,
+    },
     /// Burn will release collateral if expired
-    Burn {},
+    Burn { option_id: u64 },
+    /// Anyone can roll an expired option marked `roll_on_expiry` over to a new expiry, the
+    /// configured duration past the current block, for the keeper reward
+    Roll { option_id: u64 },
+    /// Anyone can settle up to `limit` expired options, burning physical ones and executing
+    /// the rest for their owners, for the crank reward on each
+    Crank { limit: u32 },
+    /// Anyone can add the funds sent to the pool crank rewards are paid from
+    FundCrankPool {},
+    /// Anyone can void an option whose barrier the oracle price crossed before expiry, returning
+    /// the collateral to the creator for the keeper reward
+    KnockOut { option_id: u64 },
+    /// Anyone can settle an expired binary option at the current oracle price
+    SettleBinary { option_id: u64 },
+    /// Anyone can sample the oracle price for an averaging option during its sampling window
+    SamplePrice { option_id: u64 },
+    /// Anyone can write an option that never expires, whose owner pays `funding_rate` to the
+    /// creator every block out of a balance they deposit
+    CreatePerpetual {
+        counter_offer: Asset,
+        funding_rate: Coin,
+        #[serde(default)]
+        premium: Vec<Coin>,
+        #[serde(default)]
+        settlement: Settlement,
+    },
+    /// Anyone can write an option on a basket of the coins sent and cw20 tokens, which the
+    /// contract takes from them under the allowances they granted it. Only physical settlement
+    /// is possible.
+    CreateBasket {
+        terms: CreateMsg,
+        cw20_collateral: Vec<Cw20Coin>,
+    },
+    /// Anyone can write a European option on the staking denom whose collateral is delegated to
+    /// `validator` until it is unstaked, the rewards going to the creator
+    CreateStaked { terms: CreateMsg, validator: String },
+    /// Creator can start unbonding the collateral of a staked option, anyone else once expiry
+    /// is less than the unbonding period away. It can settle once the collateral is back.
+    Unstake { option_id: u64 },
+    /// Anyone can write a cash settled option paying out on `notional` of the collateral denom,
+    /// sending only a margin of it that has to cover the maintenance requirement
+    CreateMargined { terms: CreateMsg, notional: Uint128 },
+    /// Anyone can close an unexpired margined option below its maintenance requirement,
+    /// seizing the margin for the owner
+    LiquidateMargin { option_id: u64 },
+    /// Admin withdraws `amount` from the insurance fund, to themselves unless they name a
+    /// recipient
+    WithdrawInsurance {
+        amount: Vec<Coin>,
+        recipient: Option<String>,
+    },
+    /// Admin sends `amount` of `denom` the contract holds beyond what it tracks for options,
+    /// such as coins sent to it by mistake, to `recipient`
+    RecoverFunds {
+        denom: String,
+        amount: Uint128,
+        recipient: String,
+    },
+    /// Anyone can retry a cw20 payout that failed on settlement, paying its recipient
+    RetryPayout { claim_id: u64 },
+    /// Admin sends everything the contract holds beyond what it tracks for options to the fee
+    /// collector
+    SweepSurplus {},
+    /// Anyone can count up to `limit` more entries into the stats and tracked funds rebuilt
+    /// after a migration
+    RebuildStats { limit: u32 },
+    /// Admin bars addresses from taking or exercising options, or lifts the bar
+    UpdateBlacklist {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Anyone can record the oracle price of the pair the option is priced in. A move past the
+    /// maximum deviation halts cash settlement of the pair for the cooldown.
+    UpdatePrice { option_id: u64 },
+    /// Admin clears the last price of the pair the option is priced in, lifting any halt
+    ResetCircuitBreaker { option_id: u64 },
+    /// Anyone can pay the creator of a staked option the rewards earned so far
+    ClaimStakingRewards { option_id: u64 },
+    /// Creator deposits the collateral of an unexpired option into the configured yield
+    /// strategy, keeping the yield. It has to be withdrawn before the option settles.
+    DepositYield { option_id: u64 },
+    /// Creator or owner withdraws the collateral from the yield strategy, anyone else once
+    /// the option expired. The creator is paid the yield.
+    WithdrawYield { option_id: u64 },
+    /// Owner of a perpetual option adds the funding rate denom sent to their balance
+    DepositFunding { option_id: u64 },
+    /// Owner of a perpetual option takes back `amount` of their balance
+    WithdrawFunding { option_id: u64, amount: Uint128 },
+    /// Anyone can pay the creator of a perpetual option the funding due so far
+    SettleFunding { option_id: u64 },
+    /// Anyone can close a perpetual option whose owner ran out of funding,
+    /// returning the collateral to the creator
+    Liquidate { option_id: u64 },
+    /// Creator can split their side of the option into `shares` of a new cw20 token,
+    /// whose holders claim what the option pays out once it is closed
+    Fractionalize { option_id: u64, shares: Uint128 },
+    /// Anyone can add a single coin of the collateral denom to an option its creator still
+    /// owns, becoming one of its writers. Writers share what the option pays out pro-rata.
+    CoWrite { option_id: u64 },
+    /// Writer claims their part of what a closed co-written option paid out
+    ClaimCoWritten { option_id: u64 },
+    /// Anyone can write a grid of options, one for every strike and expiry, splitting the
+    /// collateral sent evenly between them. Each strike is the counter_offer of its options.
+    CreateSeries {
+        strikes: Vec<Coin>,
+        expiries: Vec<Expiration>,
+        #[serde(default)]
+        style: OptionStyle,
+    },
+    /// Anyone can write 2 to 30 options as legs of one strategy, sending the collateral of all
+    /// of them. The legs move and are executed together, by the strategy owner.
+    CreateStrategy {
+        legs: Vec<LegTerms>,
+        expires: Expiration,
+        #[serde(default)]
+        style: OptionStyle,
+        owner: Option<String>,
+    },
+    /// Strategy owner can transfer the strategy to a new owner
+    TransferStrategy { strategy_id: u64, recipient: String },
+    /// Strategy owner executes all legs at once, sending the counter_offer they owe
+    /// net of the collateral they receive
+    ExecuteStrategy { strategy_id: u64 },
+    /// Admin opens a covered call vault pooling `denom`, whose options `writer` writes
+    /// asking `strike` of `strike_denom` per unit of the asset
+    CreateVault {
+        writer: String,
+        denom: String,
+        strike_denom: String,
+        strike: Decimal,
+    },
+    /// Admin sets the strike of the vault's next options
+    SetVaultStrike { vault_id: u64, strike: Decimal },
+    /// Anyone can deposit the vault asset for shares while none of its options are open
+    DepositVault { vault_id: u64 },
+    /// Depositor redeems `shares` for their part of the idle asset while none of the vault's
+    /// options are open, claiming their earnings along
+    WithdrawVault { vault_id: u64, shares: Uint128 },
+    /// Depositor claims what their shares earned from premiums and counter_offers
+    ClaimVaultEarnings { vault_id: u64 },
+    /// Writer writes an option on `amount` of the idle asset at the vault strike, listed for
+    /// `premium`. The vault keeps the premium, the counter_offer, and the collateral on burn.
+    WriteVaultOption {
+        vault_id: u64,
+        amount: Uint128,
+        expires: Expiration,
+        premium: Vec<Coin>,
+    },
+    /// Entry point for cw20 counter_offer payments, wrapping a `ReceiveMsg`
+    Receive(Cw20ReceiveMsg),
+    /// Entry point for NFT collateral, wrapping a `ReceiveNftMsg`
+    ReceiveNft(Cw721ReceiveMsg),
+    /// Admin can change the contract config. With a timelock configured, changes to the fees,
+    /// the oracle, the denom whitelist and the timelock are queued instead.
+    UpdateConfig(UpdateConfigMsg),
+    /// Governance changes the fees, unset ones are left as they are
+    UpdateFees {
+        fee_bps: Option<u64>,
+        fee_collector: Option<String>,
+        transfer_fee: Option<TransferFee>,
+        referral_bps: Option<u64>,
+        insurance_bps: Option<u64>,
+    },
+    /// Governance changes the price feed
+    UpdateOracle { oracle: String },
+    /// Governance changes the denoms accepted as collateral and counter_offer, empty means any
+    UpdateWhitelist { allowed_denoms: Vec<String> },
+    /// Governance changes how long config changes are queued for, in seconds
+    UpdateTimelock { config_timelock: u64 },
+    /// Anyone can apply a queued config change once the timelock elapsed
+    ExecuteQueued { queued_id: u64 },
+    /// Governance can drop a queued config change
+    CancelQueued { queued_id: u64 },
+    /// Admin can register where the IBC voucher for `base_denom` over `path` comes from,
+    /// so queries show it by name
+    RegisterDenomTrace { path: String, base_denom: String },
+    /// Guardian can halt creation, transfers and execution, expired options can still be burned
+    Pause {},
+    /// Guardian can lift a pause
+    Unpause {},
+}
+
+/// Changes to the contract config, unset fields are left as they are
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct UpdateConfigMsg {
+    pub admin: Option<String>,
+    pub guardian: Option<String>,
+    pub burn_reward_bps: Option<u64>,
+    pub fee_bps: Option<u64>,
+    pub fee_collector: Option<String>,
+    pub allowed_denoms: Option<Vec<String>>,
+    pub oracle: Option<String>,
+    pub archive_retention: Option<u64>,
+    pub tokenfactory: Option<bool>,
+    pub share_code_id: Option<u64>,
+    pub transfer_fee: Option<TransferFee>,
+    pub fee_exempt: Option<Vec<String>>,
+    pub referral_bps: Option<u64>,
+    pub roll_duration: Option<u64>,
+    pub burn_grace_period: Option<u64>,
+    pub crank_reward: Option<Vec<Coin>>,
+    pub unbonding_period: Option<u64>,
+    pub yield_strategy: Option<String>,
+    pub maintenance_margin_bps: Option<u64>,
+    pub insurance_bps: Option<u64>,
+    pub max_price_deviation_bps: Option<u64>,
+    pub breaker_cooldown: Option<u64>,
+    pub config_timelock: Option<u64>,
+    pub allowlist: Option<String>,
+    /// A zero amount lifts the minimum
+    pub min_collateral: Option<Coin>,
+    pub max_open_options: Option<u64>,
+}
+
+/// Messages that can be embedded in a cw20 `Send` to this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Owner can send a cw20 counter_offer on unexpired option to execute and get the collateral,
+    /// or have it sent to `recipient`
+    Execute {
+        option_id: u64,
+        recipient: Option<String>,
+        /// Gets the configured share of the protocol fee
+        referrer: Option<String>,
+        /// Fails once past this point, so a delayed transaction doesn't execute at a bad time
+        deadline: Option<Expiration>,
+        /// Fails if a cash settlement pays out less collateral, after the protocol fee
+        min_payout: Option<Uint128>,
+    },
+    /// Holders of a fractionalized option's shares send them to claim their part of the proceeds
+    ClaimShares { option_id: u64 },
+    /// Owner can send a share of a cw20 counter_offer to execute a `fraction` of the option
+    ExecutePartial {
+        option_id: u64,
+        fraction: Decimal,
+        recipient: Option<String>,
+    },
+}
+
+/// Messages that can be embedded in a cw721 `SendNft` to this contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveNftMsg {
+    /// Anyone can write an option collateralized by the NFT sent, which goes to whoever
+    /// executes it and back to the creator on burn. Only physical settlement is possible.
+    Create(CreateMsg),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+/// Messages only the chain itself can send, such as through a governance proposal
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    /// Closes the option and returns the collateral to the creator,
+    /// for options that can no longer be settled normally, e.g. with a broken oracle
+    ForceSettle { option_id: u64 },
+    /// Pauses or unpauses the contract, like the guardian can
+    SetPaused { paused: bool },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    Config {},
+    /// Returns the contract wide config
+    AdminConfig {},
+    Config {
+        option_id: u64,
+    },
+    /// Returns the number of live options and the amounts they hold
+    Stats {},
+    /// Returns how the option was closed, if it was
+    Archived {
+        option_id: u64,
+    },
+    /// Returns the auction running for the option, if any
+    Auction {
+        option_id: u64,
+    },
+    /// Lists the open orders for the option, ordered by order id
+    Orders {
+        option_id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the premium escrows for the option, ordered by escrow id
+    Escrows {
+        option_id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns the quote request, if still open, and its quotes ordered by quote id
+    Quotes {
+        request_id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Returns what the address earned referring trades
+    ReferralStats {
+        address: String,
+    },
+    /// Returns the current price of the option's Dutch auction
+    CurrentPrice {
+        option_id: u64,
+    },
+    /// Returns the share token of a fractionalized option and its unclaimed proceeds, if any
+    Shares {
+        option_id: u64,
+    },
+    /// Returns the writers' pool of a co-written option and its unclaimed proceeds, if any
+    WritingPool {
+        option_id: u64,
+    },
+    /// Returns how much collateral `writer` put into a co-written option
+    Contribution {
+        option_id: u64,
+        writer: String,
+    },
+    /// Returns the legs of the series, whether or not their options are still open
+    Series {
+        id: u64,
+    },
+    /// Returns the funding of a perpetual option, and what its owner owes right now
+    Funding {
+        option_id: u64,
+    },
+    /// Returns how many prices were sampled for an averaging option, and their average
+    PriceSamples {
+        option_id: u64,
+    },
+    /// Returns the strategy, with what executing its open legs takes and pays out
+    Strategy {
+        strategy_id: u64,
+    },
+    /// Returns the delegation of a staked option and the rewards it has yet to pay the creator
+    Stake {
+        option_id: u64,
+    },
+    /// Returns the yield strategy shares the collateral of the option is deposited for, if any
+    YieldDeposit {
+        option_id: u64,
+    },
+    /// Returns the notional of a margined option, with its margin and the maintenance requirement
+    /// at the oracle price
+    Margin {
+        option_id: u64,
+    },
+    /// Returns the last price of the pair the option is priced in, if any
+    LastPrice {
+        option_id: u64,
+    },
+    /// Returns what the insurance fund holds
+    InsuranceFund {},
+    /// Compares the native balances of the contract with what it tracks for options
+    Reconcile {},
+    /// Returns the cw20 payouts that failed on settlement, to be retried
+    FailedPayouts {},
+    /// Returns the storage key of the option and the raw bytes under it, see `raw` for the
+    /// layout readable through `WasmQuery::Raw`
+    RawOption {
+        option_id: u64,
+    },
+    /// Returns whether the address is blacklisted
+    Blacklisted {
+        address: String,
+    },
+    /// Returns the config changes waiting out the timelock
+    QueuedConfigs {},
+    /// Returns what is left in the crank reward pool
+    CrankPool {},
+    /// Returns the vault, if any
+    Vault {
+        vault_id: u64,
+    },
+    /// Returns the shares of `depositor` in the vault and what they earned since last claiming
+    VaultPosition {
+        vault_id: u64,
+        depositor: String,
+    },
+    /// Returns the voucher for an option that lives on the other end of `channel_id`, if any
+    Voucher {
+        channel_id: String,
+        option_id: u64,
+    },
+    /// Returns the current owner of the option (cw721)
+    OwnerOf {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    /// Returns the pending transfer offer for the option, if any
+    TransferOffer {
+        option_id: u64,
+    },
+    /// Returns the pending expiry extension for the option, if any
+    Extension {
+        option_id: u64,
+    },
+    /// Returns the pending buyback offer for the option, if any
+    Buyback {
+        option_id: u64,
+    },
+    /// Simulates `sender` executing the option with `funds`, and why it would fail
+    CanExecute {
+        option_id: u64,
+        sender: String,
+        funds: Vec<Coin>,
+    },
+    /// Values the option at the oracle spot price
+    IntrinsicValue {
+        option_id: u64,
+    },
+    /// Estimates what the option is worth with Black-Scholes, given the annualised `volatility`
+    /// of the collateral against the counter_offer
+    FairValue {
+        option_id: u64,
+        volatility: Decimal,
+    },
+    /// Returns the spenders approved for the option (cw721)
+    Approvals {
+        token_id: String,
+        include_expired: Option<bool>,
+    },
+    /// Lists the operators approved by `owner`, ordered by address (cw721)
+    AllOperators {
+        owner: String,
+        include_expired: Option<bool>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the option terms as nft metadata (cw721)
+    NftInfo {
+        token_id: String,
+    },
+    /// Lists the options held by `owner`, ordered by id
+    OptionsByOwner {
+        owner: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the options expiring between `start` and `end` (inclusive), ordered by expiry.
+    /// Both bounds must be heights or both times.
+    ExpiringBetween {
+        start: Expiration,
+        end: Expiration,
+        limit: Option<u32>,
+    },
+    /// Lists the options written by `creator`, ordered by id
+    OptionsByCreator {
+        creator: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Lists the instances spawned for `creator`, ordered by instance id
+    InstancesByCreator {
+        creator: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    #[serde(flatten)]
+    pub state: State,
+    /// Collateral and counter_offer denoms, with IBC vouchers resolved where known
+    pub denoms: Vec<DenomInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomInfo {
+    pub denom: String,
+    /// Base denom of an IBC voucher with a registered trace, otherwise the denom itself
+    pub display: String,
+    pub trace: Option<DenomTrace>,
+}
+
+pub type AdminConfigResponse = Config;
+
+pub type StatsResponse = Stats;
+
+pub type TransferOfferResponse = Option<TransferOffer>;
+
+pub type ExtensionResponse = Option<Extension>;
+
+pub type BuybackResponse = Option<Buyback>;
+
+pub type ArchivedResponse = Option<SettlementRecord>;
+
+pub type VoucherResponse = Option<Voucher>;
+
+pub type SharesResponse = Option<ShareInfo>;
+
+pub type WritingPoolResponse = Option<WritingPool>;
+
+pub type ContributionResponse = Uint128;
+
+pub type SeriesResponse = Series;
+
+pub type VaultResponse = Option<Vault>;
+
+pub type CrankPoolResponse = Vec<Coin>;
+
+pub type InsuranceFundResponse = Vec<Coin>;
+
+pub type BlacklistedResponse = bool;
+
+pub type LastPriceResponse = Option<LastPrice>;
+
+pub type QueuedConfigsResponse = Vec<QueuedConfig>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FailedPayoutInfo {
+    pub claim_id: u64,
+    pub payout: FailedPayout,
+}
+
+pub type FailedPayoutsResponse = Vec<FailedPayoutInfo>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RawOptionResponse {
+    pub key: Binary,
+    /// The option's `State` as stored, unset if no option is live under the key
+    pub value: Option<Binary>,
+}
+
+pub type YieldDepositResponse = Option<YieldDeposit>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReconcileResponse {
+    pub balances: Vec<Coin>,
+    /// Held for options, their trades and the contract's pools
+    pub tracked: Vec<Coin>,
+    /// Balances beyond the tracked funds
+    pub surpluses: Vec<Coin>,
+    /// Tracked funds beyond the balances, such as delegated collateral
+    pub shortfalls: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MarginResponse {
+    pub notional: Uint128,
+    pub margin: Uint128,
+    pub requirement: Uint128,
+    /// Whether the margin is below the requirement, so the option can be liquidated
+    pub liquidatable: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakeResponse {
+    pub stake: Stake,
+    /// Rewards earned since the creator was last paid
+    pub rewards: Coin,
 }
 
-pub type ConfigResponse = State;
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StrategyResponse {
+    pub strategy: Strategy,
+    /// Collateral of the open legs
+    pub collateral: Vec<Coin>,
+    /// Net collateral the owner receives on execution
+    pub receive: Vec<Coin>,
+    /// Net counter_offer the owner has to send to execute
+    pub pay: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceSamplesResponse {
+    pub samples: u32,
+    /// None until a price is sampled
+    pub average: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FundingResponse {
+    pub funding: Funding,
+    pub owed: Uint128,
+    pub exhausted: bool,
+    /// Block height the balance lasts until
+    pub funded_until: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultPositionResponse {
+    pub shares: Uint128,
+    pub earnings: Vec<Coin>,
+}
+
+pub type AuctionResponse = Option<Auction>;
+
+pub type ReferralStatsResponse = ReferralStats;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderInfo {
+    pub order_id: u64,
+    pub order: TradeOrder,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowInfo {
+    pub escrow_id: u64,
+    pub escrow: PremiumEscrow,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EscrowsResponse {
+    pub escrows: Vec<EscrowInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuoteInfo {
+    pub quote_id: u64,
+    pub quote: Quote,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuotesResponse {
+    pub request: Option<QuoteRequest>,
+    pub quotes: Vec<QuoteInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CurrentPriceResponse {
+    pub price: Coin,
+    pub floor_price: Coin,
+    /// When the price reaches the floor
+    pub end: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CanExecuteResponse {
+    /// Executing would succeed
+    Ok {},
+    /// The contract is paused
+    Paused {},
+    /// Sender is neither the owner nor approved
+    NotOwner {},
+    /// The address, the sender or whoever the collateral would go to, is blacklisted
+    Blocked { address: Addr },
+    /// The exercise period ended
+    Expired { expired: Expiration },
+    /// The exercise period has not started yet
+    NotYetExercisable { expires: Expiration },
+    /// Funds do not cover what executing takes
+    WrongFunds { expected: Asset },
+    /// Executing would fail for another reason, such as a binary settlement, a missed funding
+    /// payment or a tripped circuit breaker
+    Other { reason: String },
+}
+
+/// Prices are in counter_offer per unit of collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IntrinsicValueResponse {
+    pub spot: Decimal,
+    pub strike: Decimal,
+    /// What executing now is worth to the owner, in counter_offer
+    pub payoff: Uint128,
+    pub in_the_money: bool,
+}
+
+/// Prices are in counter_offer per unit of collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FairValueResponse {
+    pub spot: Decimal,
+    pub strike: Decimal,
+    /// Time left to expiry, heights are converted at an assumed block time
+    pub years: Decimal,
+    pub price_per_unit: Decimal,
+    /// Estimated value of the whole option, in counter_offer
+    pub fair_value: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionInfo {
+    pub option_id: u64,
+    pub option: State,
+    pub denoms: Vec<DenomInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionsResponse {
+    pub options: Vec<OptionInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExpiringOption {
+    pub option_id: u64,
+    pub creator: Addr,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExpiringResponse {
+    pub options: Vec<ExpiringOption>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstanceInfo {
+    pub instance_id: u64,
+    pub address: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstancesResponse {
+    pub instances: Vec<InstanceInfo>,
+}
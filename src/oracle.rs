@@ -0,0 +1,149 @@
+use cosmwasm_std::{Addr, Decimal, QuerierWrapper, StdResult};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContractError;
+use crate::state::{Asset, State};
+
+/// Query interface the configured oracle contract has to implement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleQueryMsg {
+    /// Returns how many units of `quote` one unit of `base` is worth
+    Price { base: String, quote: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceResponse {
+    pub rate: Decimal,
+}
+
+/// Fetches the price of one unit of `base` in units of `quote`.
+/// Cw20 tokens are identified by their contract address.
+pub fn query_price(
+    querier: &QuerierWrapper,
+    oracle: &Addr,
+    base: &str,
+    quote: &str,
+) -> StdResult<Decimal> {
+    let msg = OracleQueryMsg::Price {
+        base: base.to_string(),
+        quote: quote.to_string(),
+    };
+    let res: PriceResponse = querier.query_wasm_smart(oracle, &msg)?;
+    Ok(res.rate)
+}
+
+/// Fetches the price of the option's collateral denom in terms of its counter_offer denom.
+/// Only options with a single collateral and counter_offer denom can be priced.
+pub fn query_collateral_price(
+    querier: &QuerierWrapper,
+    oracle: Option<&Addr>,
+    state: &State,
+) -> Result<Decimal, ContractError> {
+    let oracle = oracle.ok_or(ContractError::NoOracle {})?;
+    let (base, quote) = price_pair(state)?;
+    Ok(query_price(querier, oracle, &base, &quote)?)
+}
+
+/// Returns the `(collateral, counter_offer)` denoms the option is priced in.
+pub fn price_pair(state: &State) -> Result<(String, String), ContractError> {
+    if !state.cw20_collateral.is_empty() {
+        return Err(ContractError::NotPriceable {});
+    }
+    match (&state.collateral[..], &state.counter_offer) {
+        ([collateral], Asset::Native(coins)) if coins.len() == 1 => {
+            Ok((collateral.denom.clone(), coins[0].denom.clone()))
+        }
+        ([collateral], Asset::Cw20(token)) => {
+            Ok((collateral.denom.clone(), token.address.to_string()))
+        }
+        _ => Err(ContractError::NotPriceable {}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{OptionStyle, Settlement};
+    use cosmwasm_std::testing::{mock_dependencies, MockQuerier};
+    use cosmwasm_std::{
+        coins, from_json, to_json_binary, ContractResult, SystemResult, Uint128, WasmQuery,
+    };
+    use cw20::Cw20CoinVerified;
+    use cw_utils::Expiration;
+
+    fn mock_oracle(querier: &mut MockQuerier) {
+        querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, msg } if contract_addr == "oracle" => {
+                let OracleQueryMsg::Price { base, quote } = from_json(msg).unwrap();
+                let rate = match (base.as_str(), quote.as_str()) {
+                    ("BTC", "ETH") => Decimal::percent(1_500),
+                    ("BTC", "token") => Decimal::percent(4_000_000),
+                    _ => Decimal::zero(),
+                };
+                SystemResult::Ok(ContractResult::Ok(
+                    to_json_binary(&PriceResponse { rate }).unwrap(),
+                ))
+            }
+            _ => panic!("unexpected query: {:?}", query),
+        });
+    }
+
+    fn option(collateral: &[(u128, &str)], counter_offer: Asset) -> State {
+        State {
+            creator: Addr::unchecked("creator"),
+            owner: Addr::unchecked("owner"),
+            collateral: collateral
+                .iter()
+                .flat_map(|(amount, denom)| coins(*amount, *denom))
+                .collect(),
+            counter_offer,
+            expires: Expiration::AtHeight(100_000),
+            price: vec![],
+            settlement: Settlement::Cash,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            approvals: vec![],
+            denom: None,
+            nft: None,
+            cw20_collateral: vec![],
+        }
+    }
+
+    #[test]
+    fn collateral_price() {
+        let mut deps = mock_dependencies();
+        mock_oracle(&mut deps.querier);
+        let querier = deps.as_ref().querier;
+        let oracle = Addr::unchecked("oracle");
+
+        let state = option(&[(1, "BTC")], Asset::Native(coins(40, "ETH")));
+        let price = query_collateral_price(&querier, Some(&oracle), &state).unwrap();
+        assert_eq!(price, Decimal::percent(1_500));
+
+        let token = Asset::Cw20(Cw20CoinVerified {
+            address: Addr::unchecked("token"),
+            amount: Uint128::new(40_000),
+        });
+        let state = option(&[(1, "BTC")], token);
+        let price = query_collateral_price(&querier, Some(&oracle), &state).unwrap();
+        assert_eq!(price, Decimal::percent(4_000_000));
+
+        // needs an oracle
+        let err = query_collateral_price(&querier, None, &state).unwrap_err();
+        assert!(matches!(err, ContractError::NoOracle {}));
+
+        // ambiguous which denom to price
+        let state = option(&[(1, "BTC"), (2, "ATOM")], Asset::Native(coins(40, "ETH")));
+        let err = query_collateral_price(&querier, Some(&oracle), &state).unwrap_err();
+        assert!(matches!(err, ContractError::NotPriceable {}));
+    }
+}
@@ -0,0 +1,465 @@
+//! Limit orders for reselling options.
+//!
+//! Makers escrow what they give up: a bid holds the price in the contract, an ask hands the
+//! option to the contract. A taker fills an order at its price, and the maker can cancel it at
+//! any time. Once an order expires it can no longer be filled, and anyone can cancel it to send
+//! the escrow back to its maker.
+
+use cosmwasm_std::{BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult};
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
+
+use crate::blacklist;
+use crate::contract::{
+    assert_not_paused, change_owner, check_can_send, check_taker, option_attributes, pay_sale,
+    DEFAULT_LIMIT, MAX_LIMIT,
+};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::msg::{OrderInfo, OrdersResponse};
+use crate::shares::keep_proceeds;
+use crate::state::{
+    next_id, options, orders, remove_indexed, save_indexed, save_option, Asset, OrderSide, State,
+    TradeOrder, CONFIG, ORDER_COUNT,
+};
+
+fn order_attributes(res: Response, order_id: u64, order: &TradeOrder) -> Response {
+    let side = match order.side {
+        OrderSide::Bid => "bid",
+        OrderSide::Ask => "ask",
+    };
+    res.add_attribute("order_id", order_id.to_string())
+        .add_attribute("side", side)
+        .add_attribute("maker", &order.maker)
+        .add_attribute("price", order.price.to_string())
+}
+
+fn single_coin(funds: &[Coin]) -> Result<Coin, ContractError> {
+    match funds {
+        [coin] if !coin.amount.is_zero() => Ok(coin.clone()),
+        _ => Err(ContractError::InvalidOrder {}),
+    }
+}
+
+/// Anyone but the owner can bid for the option, escrowing the single coin sent as the price.
+pub fn handle_place_bid(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender == state.owner {
+        return Err(ContractError::InvalidOrder {});
+    }
+    check_taker(&state, &info.sender)?;
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OrderExpired {});
+    }
+    let order = TradeOrder {
+        maker: info.sender.clone(),
+        option_id,
+        side: OrderSide::Bid,
+        price: single_coin(&info.funds)?,
+        expires,
+    };
+    let order_id = next_id(deps.storage, &ORDER_COUNT)?;
+    save_indexed(deps.storage, &orders(), order_id, &order)?;
+
+    let res = Response::new().add_attribute("action", "place_bid");
+    Ok(
+        order_attributes(res, order_id, &order)
+            .add_attributes(option_attributes(option_id, &state)),
+    )
+}
+
+/// Owner, or an approved spender, offers the option at `price`, the contract holds it meanwhile.
+pub fn handle_place_ask(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    price: Coin,
+    expires: Expiration,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::OrderExpired {});
+    }
+    let price = single_coin(&[price])?;
+
+    let order = TradeOrder {
+        maker: state.owner.clone(),
+        option_id,
+        side: OrderSide::Ask,
+        price,
+        expires,
+    };
+    let order_id = next_id(deps.storage, &ORDER_COUNT)?;
+    save_indexed(deps.storage, &orders(), order_id, &order)?;
+    let event = change_owner(&mut state, option_id, env.contract.address);
+    save_option(deps.storage, option_id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "place_ask")
+        .add_event(event.into());
+    Ok(
+        order_attributes(res, order_id, &order)
+            .add_attributes(option_attributes(option_id, &state)),
+    )
+}
+
+/// Takes the other side of an order at its price.
+/// A bid is filled by the option's owner, an ask by anyone paying the price.
+pub fn handle_fill_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let order = orders().load(deps.storage, order_id)?;
+    if order.expires.is_expired(&env.block) {
+        return Err(ContractError::OrderExpired {});
+    }
+    let mut state: State = options().load(deps.storage, order.option_id)?;
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+
+    // the option goes to the buyer, the price to the seller
+    let (buyer, seller) = match order.side {
+        OrderSide::Bid => {
+            check_can_send(deps.as_ref(), &env, &info.sender, &state)?;
+            if !info.funds.is_empty() {
+                return Err(ContractError::InvalidOrder {});
+            }
+            (order.maker.clone(), state.owner.clone())
+        }
+        OrderSide::Ask => {
+            if info.funds != [order.price.clone()] {
+                return Err(ContractError::PriceMismatch {
+                    offer: info.funds,
+                    expected: vec![order.price],
+                });
+            }
+            (info.sender.clone(), order.maker.clone())
+        }
+    };
+    check_taker(&state, &buyer)?;
+    // a bid that cannot be filled closes, giving the maker their price back
+    let (payer, paid) = match order.side {
+        OrderSide::Bid => (&order.maker, vec![order.price.clone()]),
+        OrderSide::Ask => (&info.sender, info.funds.clone()),
+    };
+    let blocked = blacklist::blocked_attempt(
+        deps.storage,
+        "fill_order",
+        order.option_id,
+        &[&info.sender, &buyer],
+        payer,
+        Asset::Native(paid),
+    )?;
+    if let Some(res) = blocked {
+        if order.side == OrderSide::Bid {
+            remove_indexed(deps.storage, &orders(), order_id)?;
+        }
+        return Ok(order_attributes(res, order_id, &order));
+    }
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &buyer)?;
+    remove_indexed(deps.storage, &orders(), order_id)?;
+    let event = change_owner(&mut state, order.option_id, buyer);
+    save_option(deps.storage, order.option_id, &state)?;
+
+    let res = Response::new()
+        .add_attribute("action", "fill_order")
+        .add_attribute("taker", info.sender)
+        .add_event(event.into());
+    let res = pay_sale(
+        deps.storage,
+        &env,
+        res,
+        &state,
+        seller,
+        vec![order.price.clone()],
+        None,
+    )?;
+    let res = order_attributes(res, order_id, &order)
+        .add_attributes(option_attributes(order.option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, order.option_id, res)?)
+}
+
+/// Maker can cancel an order at any time, anyone once it expired. The escrow goes back to the
+/// maker, unless an ask's option was closed meanwhile. A blacklisted maker's ask stays open, as
+/// the option cannot go back to them.
+pub fn handle_cancel_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    let order = orders().load(deps.storage, order_id)?;
+    if info.sender != order.maker && !order.expires.is_expired(&env.block) {
+        return Err(ContractError::Unauthorized {});
+    }
+    if order.side == OrderSide::Ask {
+        let blocked = blacklist::blocked_attempt(
+            deps.storage,
+            "cancel_order",
+            order.option_id,
+            &[&order.maker],
+            &info.sender,
+            Asset::Native(info.funds.clone()),
+        )?;
+        if let Some(res) = blocked {
+            return Ok(res);
+        }
+    }
+    remove_indexed(deps.storage, &orders(), order_id)?;
+
+    let res = Response::new().add_attribute("action", "cancel_order");
+    let res = release_escrow(deps, res, &order)?;
+    Ok(order_attributes(res, order_id, &order))
+}
+
+fn release_escrow(deps: DepsMut, res: Response, order: &TradeOrder) -> StdResult<Response> {
+    match order.side {
+        OrderSide::Bid => Ok(res.add_message(BankMsg::Send {
+            to_address: order.maker.to_string(),
+            amount: vec![order.price.clone()],
+        })),
+        OrderSide::Ask => {
+            // an expired option may have been burned while listed
+            match options().may_load(deps.storage, order.option_id)? {
+                Some(mut state) => {
+                    let event = change_owner(&mut state, order.option_id, order.maker.clone());
+                    save_option(deps.storage, order.option_id, &state)?;
+                    Ok(res.add_event(event.into()))
+                }
+                None => Ok(res),
+            }
+        }
+    }
+}
+
+/// Orders for the option, by order id
+pub fn query_orders(
+    deps: Deps,
+    option_id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let orders = orders()
+        .idx
+        .option
+        .prefix(option_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(order_id, order)| OrderInfo { order_id, order }))
+        .collect::<StdResult<_>>()?;
+    Ok(OrdersResponse { orders })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ConfigResponse, ExecuteMsg, QueryMsg};
+    use crate::testing::{blacklist, setup};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coin, coins, from_json, CosmosMsg, OwnedDeps};
+
+    fn owner(deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>) -> String {
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 1 }).unwrap();
+        let config: ConfigResponse = from_json(res).unwrap();
+        config.state.owner.into_string()
+    }
+
+    fn send(to: &str, amount: Coin) -> CosmosMsg {
+        BankMsg::Send {
+            to_address: to.into(),
+            amount: vec![amount],
+        }
+        .into()
+    }
+
+    #[test]
+    fn fill_ask_and_bid() {
+        let mut deps = setup();
+        let expires = Expiration::AtHeight(mock_env().block.height + 100);
+
+        // the creator lists the option, the contract holds it
+        let ask = ExecuteMsg::PlaceAsk {
+            option_id: 1,
+            price: coin(5, "ETH"),
+            expires,
+        };
+        let info = mock_info("someone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, ask.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("creator", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, ask).unwrap();
+        assert_eq!(mock_env().contract.address.as_str(), owner(&deps));
+
+        let fill = ExecuteMsg::FillOrder { order_id: 1 };
+        let info = mock_info("alice", &coins(4, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, fill.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::PriceMismatch { .. }));
+        let info = mock_info("alice", &coins(5, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, fill.clone()).unwrap();
+        assert_eq!(send("creator", coin(5, "ETH")), res.messages[0].msg);
+        assert_eq!("alice", owner(&deps));
+
+        // filled orders are gone
+        let info = mock_info("bob", &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, fill).unwrap_err();
+
+        // bob bids, the owner sells into the bid
+        let bid = ExecuteMsg::PlaceBid {
+            option_id: 1,
+            expires,
+        };
+        let info = mock_info("alice", &coins(7, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, bid.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidOrder {}));
+        let info = mock_info("bob", &coins(7, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, bid).unwrap();
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Orders {
+                option_id: 1,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let orders: OrdersResponse = from_json(res).unwrap();
+        assert_eq!(1, orders.orders.len());
+        assert_eq!(2, orders.orders[0].order_id);
+        assert_eq!(OrderSide::Bid, orders.orders[0].order.side);
+
+        let fill = ExecuteMsg::FillOrder { order_id: 2 };
+        let info = mock_info("bob", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, fill.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, fill).unwrap();
+        assert_eq!(send("alice", coin(7, "ETH")), res.messages[0].msg);
+        assert_eq!("bob", owner(&deps));
+    }
+
+    #[test]
+    fn blacklisted_buyers() {
+        let mut deps = setup();
+        let expires = Expiration::AtHeight(mock_env().block.height + 100);
+        let bid = ExecuteMsg::PlaceBid {
+            option_id: 1,
+            expires,
+        };
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &coins(7, "ETH")),
+            bid,
+        )
+        .unwrap();
+        blacklist(&mut deps, "bob");
+        blacklist(&mut deps, "sanctioned");
+
+        // selling into a listed bidder's bid closes it and refunds the bid
+        let fill = ExecuteMsg::FillOrder { order_id: 1 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), fill).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(send("bob", coin(7, "ETH")), res.messages[0].msg);
+        assert!(!orders().has(&deps.storage, 1));
+        assert_eq!("creator", owner(&deps));
+
+        // a listed taker of an ask gets the price back, the ask stays open
+        let ask = ExecuteMsg::PlaceAsk {
+            option_id: 1,
+            price: coin(5, "ETH"),
+            expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ask).unwrap();
+        let fill = ExecuteMsg::FillOrder { order_id: 2 };
+        let info = mock_info("sanctioned", &coins(5, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, fill).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(send("sanctioned", coin(5, "ETH")), res.messages[0].msg);
+        assert!(orders().has(&deps.storage, 2));
+        assert_eq!(mock_env().contract.address.as_str(), owner(&deps));
+    }
+
+    #[test]
+    fn blacklisted_maker_keeps_ask_open() {
+        let mut deps = setup();
+        let expires = Expiration::AtHeight(mock_env().block.height + 100);
+        let ask = ExecuteMsg::PlaceAsk {
+            option_id: 1,
+            price: coin(5, "ETH"),
+            expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ask).unwrap();
+        blacklist(&mut deps, "creator");
+
+        // the option cannot go back, so cancelling only refunds what was sent
+        let cancel = ExecuteMsg::CancelOrder { order_id: 1 };
+        let info = mock_info("creator", &coins(1, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, cancel).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(send("creator", coin(1, "ETH")), res.messages[0].msg);
+        assert!(orders().has(&deps.storage, 1));
+        assert_eq!(mock_env().contract.address.as_str(), owner(&deps));
+    }
+
+    #[test]
+    fn cancel_and_expire() {
+        let mut deps = setup();
+        let expires = Expiration::AtHeight(mock_env().block.height + 100);
+        let info = mock_info("alice", &coins(7, "ETH"));
+        let bid = ExecuteMsg::PlaceBid {
+            option_id: 1,
+            expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, bid).unwrap();
+        let info = mock_info("creator", &[]);
+        let ask = ExecuteMsg::PlaceAsk {
+            option_id: 1,
+            price: coin(9, "ETH"),
+            expires,
+        };
+        let _ = execute(deps.as_mut(), mock_env(), info, ask).unwrap();
+
+        // only the maker cancels a live order, the bid is refunded
+        let cancel = ExecuteMsg::CancelOrder { order_id: 1 };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, cancel.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, cancel).unwrap();
+        assert_eq!(send("alice", coin(7, "ETH")), res.messages[0].msg);
+
+        // expired orders cannot be filled, anyone can hand the option back
+        let mut env = mock_env();
+        env.block.height += 100;
+        let info = mock_info("bob", &coins(9, "ETH"));
+        let fill = ExecuteMsg::FillOrder { order_id: 2 };
+        let err = execute(deps.as_mut(), env.clone(), info, fill).unwrap_err();
+        assert!(matches!(err, ContractError::OrderExpired {}));
+        let info = mock_info("bob", &[]);
+        let cancel = ExecuteMsg::CancelOrder { order_id: 2 };
+        let res = execute(deps.as_mut(), env, info, cancel).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!("creator", owner(&deps));
+    }
+}
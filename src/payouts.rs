@@ -0,0 +1,218 @@
+//! Cw20 payouts of settlements that can fail without losing them.
+//!
+//! A token contract can reject a transfer, say because it paused or blocked the recipient. To
+//! keep that from failing the settlement, and everything else the recipient is owed with it,
+//! settlements send cw20 payouts as submessages replying on error. The payouts of the current
+//! transaction are kept until the next one, and the reply turns a failed payout into a claim.
+//! Anyone can retry a claim, which pays the recipient.
+
+use cosmwasm_std::{
+    from_json, to_json_binary, Addr, CosmosMsg, Deps, DepsMut, Env, Order, Reply, ReplyOn,
+    Response, StdResult, Storage, SubMsg, SubMsgResult, WasmMsg,
+};
+use cw20::{Cw20CoinVerified, Cw20ExecuteMsg};
+
+use crate::error::ContractError;
+use crate::msg::{FailedPayoutInfo, FailedPayoutsResponse};
+use crate::state::{
+    next_id, FailedPayout, PendingPayouts, FAILED_PAYOUTS, PAYOUT_COUNT, PENDING_PAYOUTS,
+};
+
+/// Reply ids of payouts, offset by their index among the payouts of the transaction
+pub const PAYOUT_REPLY_ID: u64 = 1 << 32;
+
+/// Sends the cw20 transfers among the messages of a settlement as submessages replying on
+/// error, keeping what they pay for the reply.
+pub(crate) fn guard_payouts(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+    mut res: Response,
+) -> StdResult<Response> {
+    let tx_index = env.transaction.as_ref().map(|tx| tx.index);
+    let mut pending = PENDING_PAYOUTS
+        .may_load(storage)?
+        .filter(|p| p.height == env.block.height && p.tx_index == tx_index)
+        .unwrap_or(PendingPayouts {
+            height: env.block.height,
+            tx_index,
+            payouts: vec![],
+        });
+    let before = pending.payouts.len();
+    for sub in res.messages.iter_mut() {
+        let (contract_addr, msg) = match &sub.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) if sub.reply_on == ReplyOn::Never => (contract_addr, msg),
+            _ => continue,
+        };
+        if let Ok(Cw20ExecuteMsg::Transfer { recipient, amount }) = from_json(msg) {
+            let id = PAYOUT_REPLY_ID + pending.payouts.len() as u64;
+            pending.payouts.push(FailedPayout {
+                option_id,
+                recipient: Addr::unchecked(recipient),
+                token: Cw20CoinVerified {
+                    address: Addr::unchecked(contract_addr),
+                    amount,
+                },
+            });
+            *sub = SubMsg::reply_on_error(sub.msg.clone(), id);
+        }
+    }
+    if pending.payouts.len() > before {
+        PENDING_PAYOUTS.save(storage, &pending)?;
+    }
+    Ok(res)
+}
+
+/// Records the payout that failed as a claim. Payouts only reply on error, there is nothing
+/// to record for one that went through.
+pub fn handle_payout_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let error = match msg.result {
+        SubMsgResult::Err(error) => error,
+        SubMsgResult::Ok(_) => return Ok(Response::new()),
+    };
+    let index = (msg.id - PAYOUT_REPLY_ID) as usize;
+    let pending = PENDING_PAYOUTS.load(deps.storage)?;
+    let payout = pending
+        .payouts
+        .into_iter()
+        .nth(index)
+        .ok_or(ContractError::PayoutNotFound {})?;
+    let claim_id = next_id(deps.storage, &PAYOUT_COUNT)?;
+    FAILED_PAYOUTS.save(deps.storage, claim_id, &payout)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "payout_failed")
+        .add_attribute("claim_id", claim_id.to_string())
+        .add_attribute("option_id", payout.option_id.to_string())
+        .add_attribute("recipient", payout.recipient)
+        .add_attribute("error", error))
+}
+
+/// Anyone can retry a failed payout, paying its recipient
+pub fn handle_retry_payout(deps: DepsMut, claim_id: u64) -> Result<Response, ContractError> {
+    let payout = FAILED_PAYOUTS
+        .may_load(deps.storage, claim_id)?
+        .ok_or(ContractError::PayoutNotFound {})?;
+    FAILED_PAYOUTS.remove(deps.storage, claim_id);
+    let transfer = WasmMsg::Execute {
+        contract_addr: payout.token.address.to_string(),
+        msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+            recipient: payout.recipient.to_string(),
+            amount: payout.token.amount,
+        })?,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_message(transfer)
+        .add_attribute("action", "retry_payout")
+        .add_attribute("claim_id", claim_id.to_string())
+        .add_attribute("recipient", payout.recipient))
+}
+
+pub fn query_failed_payouts(deps: Deps) -> StdResult<FailedPayoutsResponse> {
+    FAILED_PAYOUTS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(claim_id, payout)| FailedPayoutInfo { claim_id, payout }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, query, reply};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReceiveMsg};
+    use crate::state::{options, Asset};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, from_json, SubMsgResponse, Uint128};
+    use cw20::Cw20ReceiveMsg;
+
+    #[test]
+    fn failed_payout_becomes_claim() {
+        let mut deps = mock_dependencies();
+        let counter_offer = Cw20CoinVerified {
+            address: Addr::unchecked("token"),
+            amount: Uint128::new(40),
+        };
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Cw20(counter_offer.clone()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(1, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // the counter_offer paid to the creator replies if the token rejects it
+        let wrapper = Cw20ReceiveMsg {
+            sender: "creator".to_string(),
+            amount: Uint128::new(40),
+            msg: to_json_binary(&ReceiveMsg::Execute {
+                option_id: 1,
+                recipient: None,
+                referrer: None,
+                deadline: None,
+                min_payout: None,
+            })
+            .unwrap(),
+        };
+        let msg = ExecuteMsg::Receive(wrapper);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("token", &[]), msg).unwrap();
+        assert_eq!(ReplyOn::Error, res.messages[0].reply_on);
+        assert_eq!(PAYOUT_REPLY_ID, res.messages[0].id);
+        assert_eq!(ReplyOn::Never, res.messages[1].reply_on);
+        assert!(!options().has(&deps.storage, 1));
+
+        // a payout that went through records nothing
+        let paid = Reply {
+            id: PAYOUT_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), paid).unwrap();
+        assert!(res.attributes.is_empty());
+        assert!(FAILED_PAYOUTS.is_empty(&deps.storage));
+
+        let failed = Reply {
+            id: PAYOUT_REPLY_ID,
+            result: SubMsgResult::Err("transfers paused".to_string()),
+        };
+        let _ = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let bin = query(deps.as_ref(), mock_env(), QueryMsg::FailedPayouts {}).unwrap();
+        let claims: FailedPayoutsResponse = from_json(bin).unwrap();
+        let payout = FailedPayout {
+            option_id: 1,
+            recipient: Addr::unchecked("creator"),
+            token: counter_offer,
+        };
+        assert_eq!(
+            vec![FailedPayoutInfo {
+                claim_id: 1,
+                payout: payout.clone(),
+            }],
+            claims
+        );
+
+        // anyone can retry, once
+        let retry = ExecuteMsg::RetryPayout { claim_id: 1 };
+        let anyone = mock_info("anyone", &[]);
+        let res = execute(deps.as_mut(), mock_env(), anyone.clone(), retry.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".into(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "creator".into(),
+                    amount: Uint128::new(40),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+        let err = execute(deps.as_mut(), mock_env(), anyone, retry).unwrap_err();
+        assert!(matches!(err, ContractError::PayoutNotFound {}));
+    }
+}
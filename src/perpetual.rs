@@ -0,0 +1,417 @@
+//! Perpetual options, which never expire and are kept alive by funding.
+//!
+//! The owner pays the creator a funding rate every block out of a balance they post. Funding is
+//! brought up to date whenever the option is touched, and a new owner takes over from the block
+//! it is first touched after changing hands, the previous owner getting back what they left.
+//! Once the balance can't cover the funding due, anyone can liquidate the option, closing it and
+//! returning the collateral to the creator.
+
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    coins, Addr, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage,
+    Uint128,
+};
+use cw_utils::Expiration;
+
+use crate::contract::{archive_option, assert_not_paused, option_attributes, write_option};
+use crate::denom::validate_denom;
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::msg::{CreateMsg, FundingResponse};
+use crate::shares::keep_proceeds;
+use crate::state::{
+    options, remove_held, remove_option, save_held, Asset, Closure, Funding, OptionStyle,
+    Settlement, State, CONFIG, FUNDING,
+};
+
+fn owed(funding: &Funding, height: u64) -> Uint128 {
+    funding.rate.amount * Uint128::from(height.saturating_sub(funding.paid_until))
+}
+
+/// Whether the balance of the owner paying for the option falls short of the funding due,
+/// the creator never pays themselves.
+fn exhausted(funding: &Funding, state: &State, height: u64) -> bool {
+    funding.payer != state.creator && owed(funding, height) > funding.balance
+}
+
+fn send(to: &Addr, amount: Uint128, denom: &str) -> Option<BankMsg> {
+    (!amount.is_zero()).then(|| BankMsg::Send {
+        to_address: to.to_string(),
+        amount: coins(amount.u128(), denom),
+    })
+}
+
+/// Pays the creator the funding due up to the current block, out of the balance. If the option
+/// changed hands, the previous payer gets back the rest and the owner pays from now on.
+fn accrue(funding: &mut Funding, state: &State, env: &Env) -> Vec<BankMsg> {
+    let denom = funding.rate.denom.clone();
+    let mut msgs = vec![];
+    if funding.payer != state.creator {
+        let due = owed(funding, env.block.height).min(funding.balance);
+        funding.balance -= due;
+        msgs.extend(send(&state.creator, due, &denom));
+    }
+    funding.paid_until = env.block.height;
+    if funding.payer != state.owner {
+        msgs.extend(send(&funding.payer, funding.balance, &denom));
+        funding.balance = Uint128::zero();
+        funding.payer = state.owner.clone();
+    }
+    msgs
+}
+
+/// Anyone can write an option without expiry, whose owner pays `funding_rate` every block.
+pub fn handle_create_perpetual(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    counter_offer: Asset,
+    funding_rate: Coin,
+    premium: Vec<Coin>,
+    settlement: Settlement,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    if funding_rate.amount.is_zero() {
+        return Err(ContractError::InvalidFunding {});
+    }
+    validate_denom(&funding_rate.denom)?;
+    // the token holder could not be charged
+    if CONFIG.load(deps.storage)?.tokenfactory {
+        return Err(ContractError::TokenizedOption {});
+    }
+
+    let terms = CreateMsg {
+        counter_offer,
+        expires: Expiration::Never {},
+        premium,
+        settlement,
+        style: OptionStyle::American,
+        royalty_bps: 0,
+        roll_on_expiry: false,
+        barrier: None,
+        settlement_window: None,
+        exercisable_from: None,
+        alt_counter_offers: vec![],
+        strike: None,
+        allowed_taker: None,
+        owner: None,
+    };
+    let creator = info.sender.clone();
+    let (option_id, state) = write_option(deps.branch(), &env, info, terms, None, vec![])?;
+    let funding = Funding {
+        rate: funding_rate,
+        balance: Uint128::zero(),
+        paid_until: env.block.height,
+        payer: creator,
+    };
+    save_held(deps.storage, &FUNDING, option_id, &funding)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_perpetual")
+        .add_attribute("funding_rate", funding.rate.to_string())
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Owner adds the funding rate denom sent to their balance
+pub fn handle_deposit_funding(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let mut funding = FUNDING.load(deps.storage, option_id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let amount = match info.funds.as_slice() {
+        [coin] if coin.denom == funding.rate.denom && !coin.amount.is_zero() => coin.amount,
+        _ => return Err(ContractError::InvalidFunding {}),
+    };
+
+    // a new owner takes over first, then the deposit must cover what is already due
+    let mut msgs = vec![];
+    if funding.payer != state.owner {
+        msgs = accrue(&mut funding, &state, &env);
+    }
+    funding.balance += amount;
+    if exhausted(&funding, &state, env.block.height) {
+        return Err(ContractError::FundingExhausted {});
+    }
+    msgs.extend(accrue(&mut funding, &state, &env));
+    save_held(deps.storage, &FUNDING, option_id, &funding)?;
+
+    let res = Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "deposit_funding")
+        .add_attribute("balance", funding.balance)
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+/// Owner takes back `amount` of their balance, once the funding due is paid
+pub fn handle_withdraw_funding(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let mut funding = FUNDING.load(deps.storage, option_id)?;
+    if info.sender != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if exhausted(&funding, &state, env.block.height) {
+        return Err(ContractError::FundingExhausted {});
+    }
+    let mut msgs = accrue(&mut funding, &state, &env);
+    if amount.is_zero() || amount > funding.balance {
+        return Err(ContractError::InvalidFunding {});
+    }
+    funding.balance -= amount;
+    msgs.extend(send(&info.sender, amount, &funding.rate.denom));
+    save_held(deps.storage, &FUNDING, option_id, &funding)?;
+
+    let res = Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "withdraw_funding")
+        .add_attribute("balance", funding.balance)
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+/// Anyone can bring the funding of an option up to date, paying the creator what is due and
+/// handing the funding over to a new owner
+pub fn handle_settle_funding(
+    deps: DepsMut,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let mut funding = FUNDING.load(deps.storage, option_id)?;
+    if exhausted(&funding, &state, env.block.height) {
+        return Err(ContractError::FundingExhausted {});
+    }
+    let msgs = accrue(&mut funding, &state, &env);
+    save_held(deps.storage, &FUNDING, option_id, &funding)?;
+
+    let res = Response::new()
+        .add_messages(msgs)
+        .add_attribute("action", "settle_funding")
+        .add_attribute("balance", funding.balance)
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+/// Anyone can close an option whose owner ran out of funding, the creator gets the collateral
+/// back along with what was left of the balance
+pub fn handle_liquidate(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let funding = FUNDING.load(deps.storage, option_id)?;
+    if !exhausted(&funding, &state, env.block.height) {
+        return Err(ContractError::FundingCovered {});
+    }
+
+    let archived = archive_option(
+        deps.storage,
+        &env,
+        option_id,
+        &state,
+        Some(&info.sender),
+        Closure::Liquidated,
+        None,
+    )?;
+    remove_option(deps.storage, option_id)?;
+    let paid = settle_funding(deps.storage, &env, option_id, &state)?;
+
+    let res = Response::new()
+        .add_message(BankMsg::Send {
+            to_address: state.creator.to_string(),
+            amount: state.collateral.clone(),
+        })
+        .add_messages(paid)
+        .add_attribute("action", "liquidate")
+        .add_event(archived.into())
+        .add_attributes(option_attributes(option_id, &state));
+    Ok(keep_proceeds(deps.storage, &env, option_id, res)?)
+}
+
+/// Errors if the option is perpetual and its owner ran out of funding.
+pub(crate) fn assert_funded(
+    storage: &dyn Storage,
+    env: &Env,
+    option_id: u64,
+    state: &State,
+) -> Result<(), ContractError> {
+    match FUNDING.may_load(storage, option_id)? {
+        Some(funding) if exhausted(&funding, state, env.block.height) => {
+            Err(ContractError::FundingExhausted {})
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pays the funding due on an option being closed and refunds the rest of the balance to its
+/// payer. Nothing for options that are not perpetual.
+pub(crate) fn settle_funding(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+    state: &State,
+) -> StdResult<Vec<BankMsg>> {
+    let mut funding = match FUNDING.may_load(storage, option_id)? {
+        Some(funding) => funding,
+        None => return Ok(vec![]),
+    };
+    let mut msgs = accrue(&mut funding, state, env);
+    msgs.extend(send(&funding.payer, funding.balance, &funding.rate.denom));
+    remove_held(storage, &FUNDING, option_id)?;
+    Ok(msgs)
+}
+
+pub fn query_funding(deps: Deps, env: Env, option_id: u64) -> StdResult<FundingResponse> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let funding = FUNDING.load(deps.storage, option_id)?;
+    let owed = match funding.payer != state.creator {
+        true => owed(&funding, env.block.height),
+        false => Uint128::zero(),
+    };
+    // blocks the balance lasts past the last payment
+    let funded_blocks = (funding.balance / funding.rate.amount).u128();
+    let funded_blocks = u64::try_from(funded_blocks).unwrap_or(u64::MAX);
+    Ok(FundingResponse {
+        exhausted: exhausted(&funding, &state, env.block.height),
+        owed,
+        funded_until: funding.paid_until.saturating_add(funded_blocks),
+        funding,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::execute;
+    use crate::msg::ExecuteMsg;
+    use crate::testing::setup;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+    use cosmwasm_std::{coin, CosmosMsg};
+
+    fn env_at(blocks: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height += blocks;
+        env
+    }
+
+    fn sent(to: &str, amount: Vec<Coin>) -> CosmosMsg {
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.into(),
+            amount,
+        })
+    }
+
+    fn paid(res: Response) -> Vec<CosmosMsg> {
+        res.messages.into_iter().map(|m| m.msg).collect()
+    }
+
+    #[test]
+    fn funding_and_liquidation() {
+        let mut deps = setup();
+
+        let create = |rate: u128| ExecuteMsg::CreatePerpetual {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            funding_rate: coin(rate, "uatom"),
+            premium: vec![],
+            settlement: Settlement::Physical,
+        };
+        let maker = mock_info("maker", &coins(10, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), maker.clone(), create(0)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidFunding {}));
+        for _ in 0..2 {
+            let _ = execute(deps.as_mut(), mock_env(), maker.clone(), create(2)).unwrap();
+        }
+        for token_id in ["2", "3"] {
+            let transfer = ExecuteMsg::TransferNft {
+                recipient: "owner".to_string(),
+                token_id: token_id.to_string(),
+            };
+            let _ = execute(deps.as_mut(), mock_env(), maker.clone(), transfer).unwrap();
+        }
+        let state = options().load(&deps.storage, 2).unwrap();
+        assert_eq!(Expiration::Never {}, state.expires);
+
+        // the owner funds ten blocks, and takes back what is left after five
+        let deposit = ExecuteMsg::DepositFunding { option_id: 2 };
+        let info = mock_info("owner", &coins(20, "uatom"));
+        let _ = execute(deps.as_mut(), mock_env(), info, deposit).unwrap();
+        let funding = query_funding(deps.as_ref(), env_at(5), 2).unwrap();
+        assert_eq!(Uint128::new(10), funding.owed);
+        assert_eq!(mock_env().block.height + 10, funding.funded_until);
+        assert!(!funding.exhausted);
+
+        let liquidate = ExecuteMsg::Liquidate { option_id: 2 };
+        let keeper = mock_info("keeper", &[]);
+        let err = execute(deps.as_mut(), env_at(5), keeper.clone(), liquidate.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::FundingCovered {}));
+        let withdraw = ExecuteMsg::WithdrawFunding {
+            option_id: 2,
+            amount: Uint128::new(5),
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env_at(5), info.clone(), withdraw).unwrap();
+        assert_eq!(
+            vec![
+                sent("maker", coins(10, "uatom")),
+                sent("owner", coins(5, "uatom")),
+            ],
+            paid(res)
+        );
+
+        // once funding runs out the option can't be executed, only liquidated
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 2,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err = execute(deps.as_mut(), env_at(10), info, execute_msg).unwrap_err();
+        assert!(matches!(err, ContractError::FundingExhausted {}));
+        let res = execute(deps.as_mut(), env_at(10), keeper, liquidate).unwrap();
+        assert_eq!(
+            vec![
+                sent("maker", coins(10, "BTC")),
+                sent("maker", coins(5, "uatom")),
+            ],
+            paid(res)
+        );
+        assert!(!options().has(&deps.storage, 2));
+        assert!(!FUNDING.has(&deps.storage, 2));
+
+        // executing pays the funding due and refunds the rest of the balance
+        let deposit = ExecuteMsg::DepositFunding { option_id: 3 };
+        let info = mock_info("owner", &coins(100, "uatom"));
+        let _ = execute(deps.as_mut(), mock_env(), info, deposit).unwrap();
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 3,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), env_at(3), info, execute_msg).unwrap();
+        let msgs = paid(res);
+        assert!(msgs.contains(&sent("maker", coins(6, "uatom"))));
+        assert!(msgs.contains(&sent("owner", coins(94, "uatom"))));
+        assert!(!FUNDING.has(&deps.storage, 3));
+    }
+}
@@ -0,0 +1,232 @@
+//! Black-Scholes estimate of what an option is worth, for quoting premiums.
+//!
+//! Interest rates are taken as zero, in which case an American call is worth the same as a
+//! European one. The math is done in signed fixed point with 12 decimals, which is plenty for
+//! a quote but not meant for settlement.
+
+use cosmwasm_std::{BlockInfo, Decimal, Uint128};
+use cw_utils::Expiration;
+
+/// Assumed block time, to turn heights into time to expiry
+pub const SECONDS_PER_BLOCK: u64 = 6;
+
+const SECONDS_PER_YEAR: u64 = 31_557_600;
+
+const ONE: i128 = 1_000_000_000_000;
+const LN_2: i128 = 693_147_180_560;
+const INV_SQRT_2PI: i128 = 398_942_280_401;
+
+/// Time left until `expires` in years, zero once expired
+pub fn years_to_expiry(expires: &Expiration, block: &BlockInfo) -> Decimal {
+    let seconds = match expires {
+        Expiration::AtHeight(height) => height.saturating_sub(block.height) * SECONDS_PER_BLOCK,
+        Expiration::AtTime(time) => time.seconds().saturating_sub(block.time.seconds()),
+        // options cannot be written without an expiry
+        Expiration::Never {} => 0,
+    };
+    Decimal::from_ratio(seconds, SECONDS_PER_YEAR)
+}
+
+/// Value of a call on one unit at `strike`, given the `spot` price, annualised `volatility`
+/// and `years` to expiry. Falls back to the intrinsic value when there is no time or volatility.
+pub fn call_value(spot: Decimal, strike: Decimal, volatility: Decimal, years: Decimal) -> Decimal {
+    let intrinsic = spot.checked_sub(strike).unwrap_or_default();
+    if spot.is_zero() || strike.is_zero() || volatility.is_zero() || years.is_zero() {
+        return intrinsic;
+    }
+
+    // d1 = (ln(S/K) + σ²T/2) / σ√T, d2 = d1 - σ√T
+    let (spot, strike) = (to_fixed(spot), to_fixed(strike));
+    let vol_sqrt_t = mul(to_fixed(volatility), sqrt(to_fixed(years)));
+    if vol_sqrt_t == 0 {
+        return intrinsic;
+    }
+    let d1 = div(
+        ln(div(spot, strike)) + mul(vol_sqrt_t, vol_sqrt_t) / 2,
+        vol_sqrt_t,
+    );
+    let d2 = d1 - vol_sqrt_t;
+
+    let value = mul(spot, norm_cdf(d1)) - mul(strike, norm_cdf(d2));
+    // rounding can never make the option worth less than exercising it right away
+    from_fixed(value).max(intrinsic)
+}
+
+/// Value of the whole option, in counter_offer, for `amount` units of collateral
+pub fn option_value(amount: Uint128, per_unit: Decimal) -> Uint128 {
+    amount.mul_floor(per_unit)
+}
+
+fn to_fixed(value: Decimal) -> i128 {
+    // Decimal has 18 decimals
+    (value.atomics().u128() / 1_000_000) as i128
+}
+
+fn from_fixed(value: i128) -> Decimal {
+    Decimal::from_atomics(value.max(0) as u128, 12).unwrap_or(Decimal::MAX)
+}
+
+fn mul(a: i128, b: i128) -> i128 {
+    a * b / ONE
+}
+
+fn div(a: i128, b: i128) -> i128 {
+    a * ONE / b
+}
+
+fn sqrt(x: i128) -> i128 {
+    // Newton's method on the scaled value, sqrt(x * ONE) keeps the scale
+    let n = x * ONE;
+    if n <= 0 {
+        return 0;
+    }
+    let mut guess = n;
+    let mut next = (guess + 1) / 2;
+    while next < guess {
+        guess = next;
+        next = (guess + n / guess) / 2;
+    }
+    guess
+}
+
+/// Natural logarithm, for `x > 0`
+fn ln(x: i128) -> i128 {
+    // x = m * 2^k with m in [1, 2)
+    let mut m = x;
+    let mut k = 0;
+    while m >= 2 * ONE {
+        m /= 2;
+        k += 1;
+    }
+    while m < ONE {
+        m *= 2;
+        k -= 1;
+    }
+    // ln(m) = 2 * atanh((m - 1) / (m + 1))
+    let y = div(m - ONE, m + ONE);
+    let y2 = mul(y, y);
+    let mut term = y;
+    let mut sum = 0;
+    let mut n = 1;
+    while term != 0 {
+        sum += term / n;
+        term = mul(term, y2);
+        n += 2;
+    }
+    2 * sum + k * LN_2
+}
+
+fn exp(x: i128) -> i128 {
+    // e^x = 2^k * e^r with |r| <= ln(2) / 2
+    let k = (x + LN_2 / 2).div_euclid(LN_2);
+    let r = x - k * LN_2;
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+    while term != 0 {
+        term = mul(term, r) / n;
+        sum += term;
+        n += 1;
+    }
+    if k >= 0 {
+        sum << k.min(60)
+    } else {
+        sum >> (-k).min(127)
+    }
+}
+
+/// Standard normal cumulative distribution, after Abramowitz and Stegun 26.2.17
+fn norm_cdf(x: i128) -> i128 {
+    if x < 0 {
+        return ONE - norm_cdf(-x);
+    }
+    // the tail is below the precision anyway
+    if x > 12 * ONE {
+        return ONE;
+    }
+    const P: i128 = 231_641_900_000;
+    const B: [i128; 5] = [
+        319_381_530_000,
+        -356_563_782_000,
+        1_781_477_937_000,
+        -1_821_255_978_000,
+        1_330_274_429_000,
+    ];
+    let t = div(ONE, ONE + mul(P, x));
+    let mut poly = 0;
+    for b in B.iter().rev() {
+        poly = mul(poly + b, t);
+    }
+    let density = mul(INV_SQRT_2PI, exp(-mul(x, x) / 2));
+    ONE - mul(density, poly)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_env;
+    use std::str::FromStr;
+
+    fn assert_close(expected: &str, actual: Decimal) {
+        let expected = Decimal::from_str(expected).unwrap();
+        let diff = if actual > expected {
+            actual - expected
+        } else {
+            expected - actual
+        };
+        assert!(
+            diff < Decimal::from_str("0.0005").unwrap(),
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn black_scholes() {
+        assert_close(
+            "7.96557",
+            call_value(dec("100"), dec("100"), dec("0.2"), dec("1")),
+        );
+        assert_close(
+            "14.74568",
+            call_value(dec("110"), dec("100"), dec("0.3"), dec("0.5")),
+        );
+        assert_close(
+            "13.19559",
+            call_value(dec("50"), dec("40"), dec("0.8"), dec("0.25")),
+        );
+        assert_close(
+            "0.08316",
+            call_value(dec("1"), dec("2"), dec("0.5"), dec("2")),
+        );
+
+        // no time left is just the intrinsic value
+        assert_eq!(
+            dec("10"),
+            call_value(dec("110"), dec("100"), dec("0.3"), dec("0"))
+        );
+        assert_eq!(
+            dec("0"),
+            call_value(dec("90"), dec("100"), dec("0"), dec("1"))
+        );
+    }
+
+    #[test]
+    fn time_to_expiry() {
+        let env = mock_env();
+        let blocks = SECONDS_PER_YEAR / SECONDS_PER_BLOCK;
+        let expires = Expiration::AtHeight(env.block.height + blocks);
+        assert_eq!(Decimal::one(), years_to_expiry(&expires, &env.block));
+
+        let expires = Expiration::AtTime(env.block.time.plus_seconds(SECONDS_PER_YEAR / 2));
+        assert_eq!(Decimal::percent(50), years_to_expiry(&expires, &env.block));
+
+        let expires = Expiration::AtHeight(env.block.height - 1);
+        assert_eq!(Decimal::zero(), years_to_expiry(&expires, &env.block));
+    }
+}
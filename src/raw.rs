@@ -0,0 +1,79 @@
+//! Reading options straight from storage.
+//!
+//! Everything is stored as JSON, under keys other contracts can query with `WasmQuery::Raw`
+//! without going through this crate's messages:
+//!
+//! | key | value |
+//! |-----|-------|
+//! | `admin_config` | the contract `Config` |
+//! | `option_count` | the id of the last option written |
+//! | `0x0006` `option` id | the `State` of a live option |
+//! | `0x0007` `archive` id | the `SettlementRecord` of a closed one |
+//!
+//! Map keys start with the length of their namespace as two big-endian bytes, followed by the
+//! namespace and the option id as eight big-endian bytes. Nothing is stored under the key of
+//! an option that does not exist or was closed. The layout only changes with a migration.
+
+use cosmwasm_std::{Binary, Deps, StdResult};
+
+use crate::msg::RawOptionResponse;
+use crate::state::options;
+
+/// Storage key of the option, as passed to `WasmQuery::Raw`
+pub fn option_key(option_id: u64) -> Vec<u8> {
+    options().key(option_id).to_vec()
+}
+
+/// Returns the key of the option and the bytes stored under it
+pub fn query_raw_option(deps: Deps, option_id: u64) -> StdResult<RawOptionResponse> {
+    let key = option_key(option_id);
+    let value = deps.storage.get(&key).map(Binary::from);
+    Ok(RawOptionResponse {
+        key: key.into(),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ExecuteMsg, QueryMsg};
+    use crate::state::State;
+    use crate::testing::setup;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+    use cosmwasm_std::{coins, from_json, Storage};
+
+    #[test]
+    fn raw_option_layout() {
+        let mut deps = setup();
+
+        // the documented key, holding the same state the contract loads
+        let mut key = vec![0, 6];
+        key.extend_from_slice(b"option");
+        key.extend_from_slice(&1u64.to_be_bytes());
+        assert_eq!(key, option_key(1));
+        let raw = QueryMsg::RawOption { option_id: 1 };
+        let res: RawOptionResponse =
+            from_json(query(deps.as_ref(), mock_env(), raw.clone()).unwrap()).unwrap();
+        assert_eq!(Binary::from(key), res.key);
+        let state: State = from_json(res.value.unwrap()).unwrap();
+        assert_eq!(options().load(&deps.storage, 1).unwrap(), state);
+        let count: u64 = from_json(deps.storage.get(b"option_count").unwrap()).unwrap();
+        assert_eq!(1, count);
+
+        // gone once the option is executed
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let res: RawOptionResponse =
+            from_json(query(deps.as_ref(), mock_env(), raw).unwrap()).unwrap();
+        assert_eq!(None, res.value);
+    }
+}
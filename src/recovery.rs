@@ -0,0 +1,318 @@
+//! Recovery of funds sent to the contract outside of any option.
+//!
+//! Whatever the contract holds beyond what it tracks for options and their trades is a
+//! surplus, such as coins bank sent to its address by mistake. The admin can sweep surpluses,
+//! never the funds backing options, either naming the recipient or to the fee collector as
+//! the treasury. Only native coins are tracked: delegated collateral and collateral deposited
+//! for yield count as held, so surpluses are never overstated and show up as shortfalls.
+
+use cosmwasm_std::{
+    BankMsg, Coin, Coins, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage,
+    Uint128,
+};
+use cw_storage_plus::Map;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::contract::fmt_coins;
+use crate::error::ContractError;
+use crate::msg::ReconcileResponse;
+use crate::staking;
+use crate::state::{
+    move_tracked, options, orders, premium_escrows, quotes, update_stats, Held, Ledger, AUCTIONS,
+    BUYBACKS, CONFIG, CRANK_POOL, EXTENSIONS, FUNDING, INSURANCE_FUND, SHARES, STATS_REBUILD,
+    TRACKED, WRITING_POOLS,
+};
+use crate::vault;
+
+/// Native coins the contract holds for options, their trades and its pools.
+pub(crate) fn tracked_funds(storage: &dyn Storage) -> StdResult<Coins> {
+    if STATS_REBUILD.exists(storage) {
+        return Err(StdError::generic_err("the tracked funds are being rebuilt"));
+    }
+    let held = TRACKED
+        .may_load(storage)?
+        .into_iter()
+        .chain(CRANK_POOL.may_load(storage)?)
+        .chain(INSURANCE_FUND.may_load(storage)?)
+        .flatten();
+    let mut tracked = Coins::default();
+    for coin in held {
+        tracked.add(coin)?;
+    }
+    Ok(tracked)
+}
+
+fn held_by<T>(storage: &dyn Storage, map: &Map<u64, T>, id: u64) -> StdResult<Vec<Coin>>
+where
+    T: Held + Serialize + DeserializeOwned,
+{
+    Ok(map
+        .may_load(storage, id)?
+        .map_or_else(Vec::new, |entry| entry.held()))
+}
+
+/// Counts entry `id` of `ledger` into the stats and tracked funds, as a rebuild reaches it.
+pub(crate) fn count_entry(storage: &mut dyn Storage, ledger: Ledger, id: u64) -> StdResult<()> {
+    let mut held = vec![];
+    match ledger {
+        Ledger::Options => {
+            if let Some(state) = options().may_load(storage, id)? {
+                update_stats(storage, None, Some(&state))?;
+            }
+            held.extend(held_by(storage, &EXTENSIONS, id)?);
+            held.extend(held_by(storage, &BUYBACKS, id)?);
+            held.extend(held_by(storage, &AUCTIONS, id)?);
+            held.extend(held_by(storage, &SHARES, id)?);
+            held.extend(held_by(storage, &WRITING_POOLS, id)?);
+            held.extend(held_by(storage, &FUNDING, id)?);
+            held.extend(staking::unpaid_rewards(storage, id)?);
+        }
+        Ledger::Orders => held.extend(
+            orders()
+                .may_load(storage, id)?
+                .into_iter()
+                .flat_map(|o| o.held()),
+        ),
+        Ledger::PremiumEscrows => held.extend(
+            premium_escrows()
+                .may_load(storage, id)?
+                .into_iter()
+                .flat_map(|e| e.held()),
+        ),
+        Ledger::Quotes => held.extend(
+            quotes()
+                .may_load(storage, id)?
+                .into_iter()
+                .flat_map(|q| q.held()),
+        ),
+        Ledger::Vaults => held.extend(vault::held(storage, id)?),
+    }
+    move_tracked(storage, &[], &held)
+}
+
+/// What the contract holds of `denom` beyond the tracked funds.
+fn surplus(deps: Deps, env: &Env, denom: &str) -> StdResult<Uint128> {
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom)?
+        .amount;
+    let tracked = tracked_funds(deps.storage)?.amount_of(denom);
+    Ok(balance.saturating_sub(tracked))
+}
+
+/// Admin sends `amount` of `denom` the contract holds beyond the tracked funds to `recipient`
+pub fn handle_recover_funds(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    denom: String,
+    amount: Uint128,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if STATS_REBUILD.exists(deps.storage) {
+        return Err(ContractError::StatsRebuilding {});
+    }
+    let recipient = deps.api.addr_validate(&recipient)?;
+    let surplus = surplus(deps.as_ref(), &env, &denom)?;
+    if amount.is_zero() || amount > surplus {
+        return Err(ContractError::ExceedsSurplus {
+            surplus: Coin::new(surplus.u128(), denom),
+        });
+    }
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: vec![Coin::new(amount.u128(), &denom)],
+        })
+        .add_attribute("action", "recover_funds")
+        .add_attribute("recipient", recipient)
+        .add_attribute("amount", Coin::new(amount.u128(), denom).to_string()))
+}
+
+/// Admin sends everything the contract holds beyond the tracked funds to the fee collector
+pub fn handle_sweep_surplus(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    if STATS_REBUILD.exists(deps.storage) {
+        return Err(ContractError::StatsRebuilding {});
+    }
+    let surpluses = query_reconcile(deps.as_ref(), env)?.surpluses;
+    if surpluses.is_empty() {
+        return Err(ContractError::NoSurplus {});
+    }
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: config.fee_collector.to_string(),
+            amount: surpluses.clone(),
+        })
+        .add_attribute("action", "sweep_surplus")
+        .add_attribute("recipient", config.fee_collector)
+        .add_attribute("amount", fmt_coins(&surpluses)))
+}
+
+pub fn query_reconcile(deps: Deps, env: Env) -> StdResult<ReconcileResponse> {
+    let balances = deps.querier.query_all_balances(&env.contract.address)?;
+    let tracked = tracked_funds(deps.storage)?;
+    let mut surpluses = vec![];
+    for coin in &balances {
+        let surplus = coin.amount.saturating_sub(tracked.amount_of(&coin.denom));
+        if !surplus.is_zero() {
+            surpluses.push(Coin::new(surplus.u128(), &coin.denom));
+        }
+    }
+    let shortfalls = tracked
+        .iter()
+        .filter_map(|coin| {
+            let held = balances
+                .iter()
+                .find(|b| b.denom == coin.denom)
+                .map_or(Uint128::zero(), |b| b.amount);
+            let shortfall = coin.amount.saturating_sub(held);
+            (!shortfall.is_zero()).then(|| Coin::new(shortfall.u128(), &coin.denom))
+        })
+        .collect();
+    Ok(ReconcileResponse {
+        balances,
+        tracked: tracked.into_vec(),
+        surpluses,
+        shortfalls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, migrate};
+    use crate::msg::{ExecuteMsg, MigrateMsg};
+    use crate::testing::{instantiate_msg, setup};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, CosmosMsg};
+    use cw_utils::Expiration;
+
+    #[test]
+    fn recovers_only_the_surplus() {
+        let mut deps = mock_dependencies_with_balance(&coins(5, "BTC"));
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(2, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // 2 of the 5 BTC held back the option
+        let recover = |amount: u128| ExecuteMsg::RecoverFunds {
+            denom: "BTC".to_string(),
+            amount: Uint128::new(amount),
+            recipient: "sender".to_string(),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            recover(3),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let admin = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), recover(4)).unwrap_err();
+        assert!(matches!(err, ContractError::ExceedsSurplus { .. }));
+        let res = execute(deps.as_mut(), mock_env(), admin.clone(), recover(3)).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sender".into(),
+                amount: coins(3, "BTC"),
+            }),
+            res.messages[0].msg
+        );
+
+        // the rest of the surplus is reported and swept to the fee collector
+        deps.querier.update_balance(
+            mock_env().contract.address,
+            vec![coin(4, "BTC"), coin(1, "ETH")],
+        );
+        let reconciled = query_reconcile(deps.as_ref(), mock_env()).unwrap();
+        assert_eq!(coins(2, "BTC"), reconciled.tracked);
+        assert_eq!(vec![coin(2, "BTC"), coin(1, "ETH")], reconciled.surpluses);
+        assert!(reconciled.shortfalls.is_empty());
+        let sweep = ExecuteMsg::SweepSurplus {};
+        let res = execute(deps.as_mut(), mock_env(), admin.clone(), sweep.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: vec![coin(2, "BTC"), coin(1, "ETH")],
+            }),
+            res.messages[0].msg
+        );
+        deps.querier
+            .update_balance(mock_env().contract.address, coins(2, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), admin, sweep).unwrap_err();
+        assert!(matches!(err, ContractError::NoSurplus {}));
+    }
+
+    #[test]
+    fn sweeps_once_the_tracked_funds_are_rebuilt() {
+        let mut deps = setup();
+        deps.querier
+            .update_balance(mock_env().contract.address, coins(3, "BTC"));
+        let _ = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        // until the option is counted again its collateral could be taken for a surplus
+        let admin = mock_info("creator", &[]);
+        let sweep = ExecuteMsg::SweepSurplus {};
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), sweep.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::StatsRebuilding {}));
+        assert!(query_reconcile(deps.as_ref(), mock_env()).is_err());
+
+        let rebuild = ExecuteMsg::RebuildStats { limit: 10 };
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), rebuild).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), admin, sweep).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(2, "BTC"),
+            }),
+            res.messages[0].msg
+        );
+    }
+
+    #[test]
+    fn tracks_funds_as_they_move() {
+        let mut deps = setup();
+        let tracked = |deps: Deps| tracked_funds(deps.storage).unwrap().into_vec();
+        assert_eq!(coins(1, "BTC"), tracked(deps.as_ref()));
+
+        // an escrowed premium is tracked until it is refunded
+        let escrow = ExecuteMsg::EscrowPremium {
+            option_id: 1,
+            deadline: Expiration::AtHeight(99_000),
+        };
+        let buyer = mock_info("buyer", &coins(5, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), buyer, escrow).unwrap();
+        assert_eq!(vec![coin(1, "BTC"), coin(5, "ETH")], tracked(deps.as_ref()));
+        let refund = ExecuteMsg::RefundEscrow { escrow_id: 1 };
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("buyer", &[]), refund).unwrap();
+        assert_eq!(coins(1, "BTC"), tracked(deps.as_ref()));
+
+        // and so is the collateral, until the option is exercised
+        let exercise = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let owner = mock_info("creator", &coins(40, "ETH"));
+        let _ = execute(deps.as_mut(), mock_env(), owner, exercise).unwrap();
+        assert!(tracked(deps.as_ref()).is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+//! Request-for-quote: buyers ask for an option, writers compete to write it.
+//!
+//! A buyer posts the terms they want, open for quotes until a deadline. Writers respond with a
+//! premium, escrowing the requested collateral. The buyer accepts one quote by paying its
+//! premium, which writes the option with the quoting writer as creator and the buyer as owner.
+//! Quotes left over, or on a request that closed, are withdrawn back to their writers.
+
+use cosmwasm_std::{BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult};
+use cw_storage_plus::Bound;
+use cw_utils::Expiration;
+
+use crate::blacklist;
+use crate::contract::{
+    assert_not_paused, create_option, option_attributes, DEFAULT_LIMIT, MAX_LIMIT,
+};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::msg::{CreateMsg, QuoteInfo, QuotesResponse};
+use crate::state::{
+    next_id, quotes, remove_indexed, save_indexed, Asset, OptionStyle, Quote, QuoteRequest,
+    Settlement, CONFIG, QUOTE_COUNT, QUOTE_REQUESTS, REQUEST_COUNT,
+};
+
+fn open_request(deps: Deps, env: &Env, request_id: u64) -> Result<QuoteRequest, ContractError> {
+    match QUOTE_REQUESTS.may_load(deps.storage, request_id)? {
+        Some(request) if !request.deadline.is_expired(&env.block) => Ok(request),
+        _ => Err(ContractError::QuoteRequestClosed {}),
+    }
+}
+
+/// Anyone can ask writers for an option with the given terms, collecting quotes until `deadline`
+#[allow(clippy::too_many_arguments)]
+pub fn handle_request_quote(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    counter_offer: Asset,
+    collateral: Vec<Coin>,
+    expires: Expiration,
+    settlement: Settlement,
+    style: OptionStyle,
+    deadline: Expiration,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    // writing the option checks the terms, this only rejects requests nobody can quote
+    if collateral.is_empty() || collateral.iter().any(|c| c.amount.is_zero()) {
+        return Err(ContractError::InvalidCollateral {});
+    }
+    if deadline.is_expired(&env.block) {
+        return Err(ContractError::QuoteRequestClosed {});
+    }
+    if CONFIG.load(deps.storage)?.tokenfactory {
+        return Err(ContractError::TokenizedOption {});
+    }
+
+    let request = QuoteRequest {
+        buyer: info.sender,
+        counter_offer,
+        collateral,
+        expires,
+        settlement,
+        style,
+        deadline,
+    };
+    let request_id = next_id(deps.storage, &REQUEST_COUNT)?;
+    QUOTE_REQUESTS.save(deps.storage, request_id, &request)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "request_quote")
+        .add_attribute("request_id", request_id.to_string())
+        .add_attribute("buyer", request.buyer))
+}
+
+/// Buyer can close their request at any time, anyone once the deadline passed.
+/// Quotes on it are left for their writers to withdraw.
+pub fn handle_cancel_quote_request(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    request_id: u64,
+) -> Result<Response, ContractError> {
+    let request = QUOTE_REQUESTS.load(deps.storage, request_id)?;
+    if info.sender != request.buyer && !request.deadline.is_expired(&env.block) {
+        return Err(ContractError::Unauthorized {});
+    }
+    QUOTE_REQUESTS.remove(deps.storage, request_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_quote_request")
+        .add_attribute("request_id", request_id.to_string()))
+}
+
+/// Anyone can quote a premium on an open request, sending exactly the requested collateral
+pub fn handle_submit_quote(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    request_id: u64,
+    premium: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let request = open_request(deps.as_ref(), &env, request_id)?;
+    if info.funds != request.collateral {
+        return Err(ContractError::InvalidCollateral {});
+    }
+
+    let quote = Quote {
+        request_id,
+        writer: info.sender,
+        premium,
+        collateral: info.funds,
+    };
+    let quote_id = next_id(deps.storage, &QUOTE_COUNT)?;
+    save_indexed(deps.storage, &quotes(), quote_id, &quote)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "submit_quote")
+        .add_attribute("request_id", request_id.to_string())
+        .add_attribute("quote_id", quote_id.to_string())
+        .add_attribute("writer", quote.writer))
+}
+
+/// Writer can withdraw their quote at any time, anyone once its request closed.
+/// The escrowed collateral goes back to the writer.
+pub fn handle_withdraw_quote(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    quote_id: u64,
+) -> Result<Response, ContractError> {
+    let quote = quotes().load(deps.storage, quote_id)?;
+    let open = open_request(deps.as_ref(), &env, quote.request_id).is_ok();
+    if info.sender != quote.writer && open {
+        return Err(ContractError::Unauthorized {});
+    }
+    remove_indexed(deps.storage, &quotes(), quote_id)?;
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: quote.writer.to_string(),
+            amount: quote.collateral,
+        })
+        .add_attribute("action", "withdraw_quote")
+        .add_attribute("quote_id", quote_id.to_string())
+        .add_attribute("writer", quote.writer))
+}
+
+/// Buyer accepts a quote on their open request by paying its premium to the writer.
+/// The option is written from the escrowed collateral and owned by the buyer, the request closes.
+pub fn handle_accept_quote(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    quote_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let quote = quotes().load(deps.storage, quote_id)?;
+    let request = open_request(deps.as_ref(), &env, quote.request_id)?;
+    if info.sender != request.buyer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(address) = blacklist::listed(deps.storage, &[&request.buyer]) {
+        let event =
+            blacklist::blocked_event("accept_quote", "quote_id", quote_id, address.as_str());
+        let paid = Asset::Native(info.funds);
+        return Ok(blacklist::blocked_response(event, &info.sender, paid)?);
+    }
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &request.buyer)?;
+    if info.funds != quote.premium {
+        return Err(ContractError::PriceMismatch {
+            offer: info.funds,
+            expected: quote.premium,
+        });
+    }
+    remove_indexed(deps.storage, &quotes(), quote_id)?;
+    QUOTE_REQUESTS.remove(deps.storage, quote.request_id);
+
+    let writer = MessageInfo {
+        sender: quote.writer.clone(),
+        funds: quote.collateral,
+    };
+    let msg = CreateMsg {
+        counter_offer: request.counter_offer,
+        expires: request.expires,
+        premium: vec![],
+        settlement: request.settlement,
+        style: request.style,
+        royalty_bps: 0,
+        roll_on_expiry: false,
+        barrier: None,
+        settlement_window: None,
+        exercisable_from: None,
+        alt_counter_offers: vec![],
+        strike: None,
+        allowed_taker: None,
+        owner: Some(request.buyer.into_string()),
+    };
+    let (option_id, state) = create_option(deps, &env, writer, msg)?;
+
+    let mut res = Response::new();
+    if !quote.premium.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: quote.writer.to_string(),
+            amount: quote.premium,
+        });
+    }
+    Ok(res
+        .add_attribute("action", "accept_quote")
+        .add_attribute("request_id", quote.request_id.to_string())
+        .add_attribute("quote_id", quote_id.to_string())
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// The request, while it was not accepted or cancelled, and the quotes on it by quote id
+pub fn query_quotes(
+    deps: Deps,
+    request_id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<QuotesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let quotes = quotes()
+        .idx
+        .request
+        .prefix(request_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(quote_id, quote)| QuoteInfo { quote_id, quote }))
+        .collect::<StdResult<_>>()?;
+    Ok(QuotesResponse {
+        request: QUOTE_REQUESTS.may_load(deps.storage, request_id)?,
+        quotes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ConfigResponse, ExecuteMsg, QueryMsg};
+    use crate::state::options;
+    use crate::testing;
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coins, from_json, CosmosMsg, OwnedDeps};
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = testing::setup();
+
+        let msg = ExecuteMsg::RequestQuote {
+            counter_offer: Asset::Native(coins(30, "ETH")),
+            collateral: coins(1, "BTC"),
+            expires: Expiration::AtHeight(100_000),
+            settlement: Settlement::Physical,
+            style: OptionStyle::American,
+            deadline: Expiration::AtHeight(mock_env().block.height + 100),
+        };
+        let info = mock_info("buyer", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        deps
+    }
+
+    fn quote(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, writer: &str, premium: u128) {
+        let msg = ExecuteMsg::SubmitQuote {
+            request_id: 1,
+            premium: coins(premium, "ETH"),
+        };
+        let info = mock_info(writer, &coins(1, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    fn refund(to: &str) -> CosmosMsg {
+        BankMsg::Send {
+            to_address: to.into(),
+            amount: coins(1, "BTC"),
+        }
+        .into()
+    }
+
+    #[test]
+    fn blacklisted_buyer() {
+        let mut deps = setup();
+        quote(&mut deps, "alice", 3);
+        testing::blacklist(&mut deps, "buyer");
+
+        // nothing is written, the premium is refunded and the quote stays up
+        let accept = ExecuteMsg::AcceptQuote { quote_id: 1 };
+        let info = mock_info("buyer", &coins(3, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, accept).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "buyer".into(),
+                amount: coins(3, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert!(quotes().has(&deps.storage, 1));
+        assert!(!options().has(&deps.storage, 2));
+    }
+
+    #[test]
+    fn accept_quote() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::SubmitQuote {
+            request_id: 1,
+            premium: coins(3, "ETH"),
+        };
+        let info = mock_info("alice", &coins(2, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidCollateral {}));
+        quote(&mut deps, "alice", 3);
+        quote(&mut deps, "bob", 2);
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Quotes {
+                request_id: 1,
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let quotes: QuotesResponse = from_json(res).unwrap();
+        assert_eq!(2, quotes.quotes.len());
+        assert_eq!("buyer", quotes.request.unwrap().buyer.as_str());
+
+        // only the buyer accepts, paying the premium to the writer
+        let accept = ExecuteMsg::AcceptQuote { quote_id: 2 };
+        let info = mock_info("bob", &coins(2, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("buyer", &coins(1, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, accept.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::PriceMismatch { .. }));
+        let info = mock_info("buyer", &coins(2, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, accept).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".into(),
+                amount: coins(2, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config { option_id: 2 }).unwrap();
+        let config: ConfigResponse = from_json(res).unwrap();
+        assert_eq!("bob", config.state.creator.as_str());
+        assert_eq!("buyer", config.state.owner.as_str());
+        assert_eq!(coins(1, "BTC"), config.state.collateral);
+        assert_eq!(Asset::Native(coins(30, "ETH")), config.state.counter_offer);
+
+        // the request is closed, anyone can send the other quote back
+        let accept = ExecuteMsg::AcceptQuote { quote_id: 1 };
+        let info = mock_info("buyer", &coins(3, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, accept).unwrap_err();
+        assert!(matches!(err, ContractError::QuoteRequestClosed {}));
+        let info = mock_info("anyone", &[]);
+        let msg = ExecuteMsg::WithdrawQuote { quote_id: 1 };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(refund("alice"), res.messages[0].msg);
+    }
+
+    #[test]
+    fn request_deadline() {
+        let mut deps = setup();
+        quote(&mut deps, "alice", 3);
+
+        // writers can withdraw open quotes, nobody else
+        let msg = ExecuteMsg::WithdrawQuote { quote_id: 1 };
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(refund("alice"), res.messages[0].msg);
+        quote(&mut deps, "alice", 3);
+
+        // past the deadline no quotes are taken or accepted, and anyone can clean up
+        let mut env = mock_env();
+        env.block.height += 100;
+        let msg = ExecuteMsg::SubmitQuote {
+            request_id: 1,
+            premium: coins(2, "ETH"),
+        };
+        let info = mock_info("bob", &coins(1, "BTC"));
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::QuoteRequestClosed {}));
+        let info = mock_info("buyer", &coins(3, "ETH"));
+        let msg = ExecuteMsg::AcceptQuote { quote_id: 2 };
+        let err = execute(deps.as_mut(), env.clone(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::QuoteRequestClosed {}));
+
+        let cancel = ExecuteMsg::CancelQuoteRequest { request_id: 1 };
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), cancel.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), env.clone(), info.clone(), cancel).unwrap();
+        let msg = ExecuteMsg::WithdrawQuote { quote_id: 2 };
+        let res = execute(deps.as_mut(), env, info, msg).unwrap();
+        assert_eq!(refund("alice"), res.messages[0].msg);
+    }
+}
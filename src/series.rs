@@ -0,0 +1,148 @@
+//! Series of options written in one go over a grid of strikes and expiries.
+
+use cosmwasm_std::{Coin, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128};
+use cw_utils::Expiration;
+
+use crate::contract::{assert_not_paused, create_option, split_share, MAX_PARTS};
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::msg::{CreateMsg, SeriesResponse};
+use crate::state::{
+    next_id, Asset, OptionStyle, Series, SeriesLeg, Settlement, SERIES, SERIES_COUNT,
+};
+use crate::tokenfactory;
+
+/// Writes an option for every strike and expiry, the first leg also takes the rounding
+/// remainder of the collateral.
+pub fn handle_create_series(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    strikes: Vec<Coin>,
+    expiries: Vec<Expiration>,
+    style: OptionStyle,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let legs = strikes.len() * expiries.len();
+    if legs == 0 || legs > MAX_PARTS as usize || strikes.iter().any(|s| s.amount.is_zero()) {
+        return Err(ContractError::InvalidSeries {});
+    }
+    let legs = legs as u32;
+    if info.funds.iter().any(|c| c.amount < Uint128::from(legs)) {
+        return Err(ContractError::InvalidSeries {});
+    }
+
+    let mut series = Series {
+        creator: info.sender.clone(),
+        legs: vec![],
+    };
+    let mut res = Response::new().add_attribute("action", "create_series");
+    for expires in &expiries {
+        for strike in &strikes {
+            let index = series.legs.len() as u32;
+            let collateral = info
+                .funds
+                .iter()
+                .map(|c| Coin::new(split_share(c.amount, legs, index).u128(), &c.denom))
+                .collect();
+            let writer = MessageInfo {
+                sender: info.sender.clone(),
+                funds: collateral,
+            };
+            let terms = CreateMsg {
+                counter_offer: Asset::Native(vec![strike.clone()]),
+                expires: *expires,
+                premium: vec![],
+                settlement: Settlement::Physical,
+                style,
+                royalty_bps: 0,
+                roll_on_expiry: false,
+                barrier: None,
+                settlement_window: None,
+                exercisable_from: None,
+                alt_counter_offers: vec![],
+                strike: None,
+                allowed_taker: None,
+                owner: None,
+            };
+            let (option_id, state) = create_option(deps.branch(), &env, writer, terms)?;
+            res = res
+                .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+                .add_event(OptionCreated::new(option_id, &state).into());
+            series.legs.push(SeriesLeg {
+                option_id,
+                strike: strike.clone(),
+                expires: *expires,
+            });
+        }
+    }
+    let series_id = next_id(deps.storage, &SERIES_COUNT)?;
+    SERIES.save(deps.storage, series_id, &series)?;
+
+    let option_ids: Vec<String> = series
+        .legs
+        .iter()
+        .map(|leg| leg.option_id.to_string())
+        .collect();
+    Ok(res
+        .add_attribute("series_id", series_id.to_string())
+        .add_attribute("creator", series.creator)
+        .add_attribute("option_ids", option_ids.join(",")))
+}
+
+pub fn query_series(deps: Deps, id: u64) -> StdResult<SeriesResponse> {
+    SERIES.load(deps.storage, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, query};
+    use crate::msg::{ExecuteMsg, QueryMsg};
+    use crate::state::options;
+    use crate::testing::setup;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json};
+
+    #[test]
+    fn strike_expiry_grid() {
+        let mut deps = setup();
+
+        let strikes = vec![coin(30, "ETH"), coin(40, "ETH")];
+        let expiries = vec![Expiration::AtHeight(50_000), Expiration::AtHeight(60_000)];
+        let msg = ExecuteMsg::CreateSeries {
+            strikes,
+            expiries,
+            style: OptionStyle::American,
+        };
+        // every leg needs some collateral
+        let info = mock_info("maker", &coins(3, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSeries {}));
+        let info = mock_info("maker", &coins(10, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Series { id: 1 }).unwrap();
+        let series: SeriesResponse = from_json(res).unwrap();
+        assert_eq!("maker", series.creator.as_str());
+        let legs: Vec<_> = series
+            .legs
+            .iter()
+            .map(|leg| (leg.option_id, leg.strike.amount.u128(), leg.expires))
+            .collect();
+        assert_eq!(
+            vec![
+                (2, 30, Expiration::AtHeight(50_000)),
+                (3, 40, Expiration::AtHeight(50_000)),
+                (4, 30, Expiration::AtHeight(60_000)),
+                (5, 40, Expiration::AtHeight(60_000)),
+            ],
+            legs
+        );
+        let state = options().load(&deps.storage, 5).unwrap();
+        assert_eq!(Asset::Native(coins(40, "ETH")), state.counter_offer);
+        assert_eq!(coins(2, "BTC"), state.collateral);
+        let first = options().load(&deps.storage, 2).unwrap();
+        assert_eq!(coins(4, "BTC"), first.collateral);
+    }
+}
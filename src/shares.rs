@@ -0,0 +1,385 @@
+//! Splits the writer's side of an option into cw20 shares.
+//!
+//! Fractionalizing hands the option's creator role to the contract and mints the shares to the
+//! creator. Whatever the option would have paid its creator, the counter_offer on execution or
+//! the collateral on burn, stays in the contract as proceeds. Once the option is closed, holders
+//! send their shares back to claim their part of the proceeds, and the shares are burned.
+
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    from_json, to_json_binary, Addr, BankMsg, Coin, Coins, CosmosMsg, Deps, DepsMut, Env,
+    MessageInfo, Reply, Response, StdError, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+};
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg, MinterResponse};
+use serde::Serialize;
+
+use crate::contract::{assert_not_paused, option_attributes};
+use crate::error::ContractError;
+use crate::msg::SharesResponse;
+use crate::state::{
+    options, remove_held, save_held, save_option, ShareInfo, State, CONFIG, PENDING_SHARES, SHARES,
+    VAULT_OPTIONS, WRITING_POOLS,
+};
+use crate::vault::keep_vault_proceeds;
+
+pub const SHARES_REPLY_ID: u64 = 2;
+
+const SHARE_DECIMALS: u8 = 6;
+
+/// What cw20-base takes to instantiate, the contract does not depend on it
+#[derive(Serialize)]
+struct Cw20InstantiateMsg {
+    name: String,
+    symbol: String,
+    decimals: u8,
+    initial_balances: Vec<Cw20Coin>,
+    mint: Option<MinterResponse>,
+}
+
+/// Creator splits their side of the option into `shares`, minted to them on a new cw20 token.
+pub fn handle_fractionalize(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+    shares: Uint128,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let code_id = config
+        .share_code_id
+        .ok_or(ContractError::SharesNotEnabled {})?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    if shares.is_zero() {
+        return Err(ContractError::InvalidShares {});
+    }
+    // the contract can't hold the NFT for the holders
+    if state.nft.is_some() {
+        return Err(ContractError::NftCollateral {});
+    }
+
+    // the contract takes over as creator, so payments to the creator stay here for the holders
+    state.creator = env.contract.address.clone();
+    save_option(deps.storage, option_id, &state)?;
+    PENDING_SHARES.save(deps.storage, &(option_id, shares))?;
+
+    let init = Cw20InstantiateMsg {
+        name: format!("simple-option {} shares", option_id),
+        symbol: "OSHARE".to_string(),
+        decimals: SHARE_DECIMALS,
+        initial_balances: vec![Cw20Coin {
+            address: info.sender.to_string(),
+            amount: shares,
+        }],
+        mint: None,
+    };
+    let msg = WasmMsg::Instantiate {
+        admin: None,
+        code_id,
+        msg: to_json_binary(&init)?,
+        funds: vec![],
+        label: format!("simple-option {} shares", option_id),
+    };
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(msg, SHARES_REPLY_ID))
+        .add_attribute("action", "fractionalize")
+        .add_attribute("shares", shares)
+        .add_attribute("holder", info.sender)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Registers the share token created by `handle_fractionalize`.
+pub fn handle_shares_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let (option_id, supply) = PENDING_SHARES.load(deps.storage)?;
+    PENDING_SHARES.remove(deps.storage);
+
+    let res = msg.result.into_result().map_err(StdError::generic_err)?;
+    let token = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "_contract_address")
+        .ok_or_else(|| StdError::generic_err("share token address not found"))?;
+    let token = deps.api.addr_validate(&token.value)?;
+    let info = ShareInfo {
+        token: token.clone(),
+        supply,
+        proceeds: vec![],
+        cw20_proceeds: vec![],
+    };
+    save_held(deps.storage, &SHARES, option_id, &info)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_shares")
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("token", token))
+}
+
+/// Books what `res` pays the creator of a fractionalized, co-written or vault option as proceeds,
+/// instead of sending it from the contract to itself. Responses of other options are left as
+/// they are.
+pub fn keep_proceeds(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+    res: Response,
+) -> StdResult<Response> {
+    if let Some(mut info) = SHARES.may_load(storage, option_id)? {
+        let res = take_proceeds(env, res, &mut info.proceeds, &mut info.cw20_proceeds)?;
+        save_held(storage, &SHARES, option_id, &info)?;
+        return Ok(res);
+    }
+    if let Some(mut pool) = WRITING_POOLS.may_load(storage, option_id)? {
+        let res = take_proceeds(env, res, &mut pool.proceeds, &mut pool.cw20_proceeds)?;
+        save_held(storage, &WRITING_POOLS, option_id, &pool)?;
+        return Ok(res);
+    }
+    keep_vault_proceeds(storage, env, option_id, res)
+}
+
+/// Whether what the option pays its creator is booked under its id, so it must keep it.
+pub(crate) fn books_proceeds(storage: &dyn Storage, option_id: u64) -> bool {
+    SHARES.has(storage, option_id)
+        || WRITING_POOLS.has(storage, option_id)
+        || VAULT_OPTIONS.has(storage, option_id)
+}
+
+/// Moves the payments of `res` to the contract itself into `proceeds`.
+pub(crate) fn take_proceeds(
+    env: &Env,
+    mut res: Response,
+    proceeds: &mut Vec<Coin>,
+    cw20_proceeds: &mut Vec<Cw20CoinVerified>,
+) -> StdResult<Response> {
+    let contract = env.contract.address.as_str();
+    let mut native = Coins::try_from(std::mem::take(proceeds))?;
+    let mut kept = vec![];
+    for sub in std::mem::take(&mut res.messages) {
+        match &sub.msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) if to_address == contract => {
+                for coin in amount {
+                    native.add(coin.clone())?;
+                }
+                continue;
+            }
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) => {
+                if let Ok(Cw20ExecuteMsg::Transfer { recipient, amount }) = from_json(msg) {
+                    if recipient == contract {
+                        add_cw20(cw20_proceeds, contract_addr, amount);
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        kept.push(sub);
+    }
+    *proceeds = native.into_vec();
+    res.messages = kept;
+    Ok(res)
+}
+
+fn add_cw20(proceeds: &mut Vec<Cw20CoinVerified>, token: &str, amount: Uint128) {
+    match proceeds.iter_mut().find(|c| c.address == token) {
+        Some(coin) => coin.amount += amount,
+        None => proceeds.push(Cw20CoinVerified {
+            address: Addr::unchecked(token),
+            amount,
+        }),
+    }
+}
+
+/// Pays `recipient` the `amount / total` part of the proceeds, taking it out of them.
+/// Each claim takes the same share of what is left, so the last one gets any rounding dust.
+pub(crate) fn pay_portion(
+    proceeds: &mut Vec<Coin>,
+    cw20_proceeds: &mut Vec<Cw20CoinVerified>,
+    amount: Uint128,
+    total: Uint128,
+    recipient: &Addr,
+) -> StdResult<Response> {
+    let portion = |coin: Uint128| coin.multiply_ratio(amount, total);
+    let mut paid = vec![];
+    for coin in proceeds.iter_mut() {
+        let part = portion(coin.amount);
+        coin.amount -= part;
+        if !part.is_zero() {
+            paid.push(Coin::new(part.u128(), &coin.denom));
+        }
+    }
+    let mut res = Response::new();
+    for coin in cw20_proceeds.iter_mut() {
+        let part = portion(coin.amount);
+        coin.amount -= part;
+        if !part.is_zero() {
+            res = res.add_message(WasmMsg::Execute {
+                contract_addr: coin.address.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount: part,
+                })?,
+                funds: vec![],
+            });
+        }
+    }
+    if !paid.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: paid,
+        });
+    }
+    proceeds.retain(|c| !c.amount.is_zero());
+    cw20_proceeds.retain(|c| !c.amount.is_zero());
+    Ok(res)
+}
+
+/// Pays `holder` its part of the proceeds for the `amount` of shares it sent, burning them.
+pub fn handle_claim(
+    deps: DepsMut,
+    token: Addr,
+    holder: Addr,
+    amount: Uint128,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let mut info = SHARES.load(deps.storage, option_id)?;
+    if token != info.token {
+        return Err(ContractError::Unauthorized {});
+    }
+    if options().has(deps.storage, option_id) {
+        return Err(ContractError::OptionStillOpen {});
+    }
+
+    let res = pay_portion(
+        &mut info.proceeds,
+        &mut info.cw20_proceeds,
+        amount,
+        info.supply,
+        &holder,
+    )?;
+    info.supply -= amount;
+    if info.supply.is_zero() {
+        remove_held(deps.storage, &SHARES, option_id)?;
+    } else {
+        save_held(deps.storage, &SHARES, option_id, &info)?;
+    }
+
+    Ok(res
+        .add_message(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Burn { amount })?,
+            funds: vec![],
+        })
+        .add_attribute("action", "claim_shares")
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("holder", holder)
+        .add_attribute("shares", amount))
+}
+
+pub fn query_shares(deps: Deps, option_id: u64) -> StdResult<SharesResponse> {
+    SHARES.may_load(deps.storage, option_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, reply};
+    use crate::msg::{ExecuteMsg, ReceiveMsg, UpdateConfigMsg};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, Event, SubMsgResponse, SubMsgResult};
+    use cw20::Cw20ReceiveMsg;
+
+    #[test]
+    fn fractionalize_and_claim() {
+        let mut deps = mock_dependencies();
+        let msg = instantiate_msg();
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // needs a cw20 code to instantiate
+        let msg = ExecuteMsg::Fractionalize {
+            option_id: 1,
+            shares: Uint128::new(100),
+        };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::SharesNotEnabled {}));
+        let config = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            share_code_id: Some(9),
+            ..UpdateConfigMsg::default()
+        });
+        let _ = execute(deps.as_mut(), mock_env(), info.clone(), config).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(SHARES_REPLY_ID, res.messages[0].id);
+        let event = Event::new("instantiate").add_attribute("_contract_address", "shares");
+        let msg = Reply {
+            id: SHARES_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![event],
+                data: None,
+            }),
+        };
+        let _ = reply(deps.as_mut(), mock_env(), msg).unwrap();
+
+        // executing keeps the counter_offer here instead of paying the creator
+        let msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("creator", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(10, "BTC"),
+            })],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        let info = query_shares(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(coins(40, "ETH"), info.proceeds);
+
+        // holders claim their part, and only with the share token
+        let claim = |holder: &str, amount: u128| {
+            let msg = Cw20ReceiveMsg {
+                sender: holder.to_string(),
+                amount: Uint128::new(amount),
+                msg: to_json_binary(&ReceiveMsg::ClaimShares { option_id: 1 }).unwrap(),
+            };
+            ExecuteMsg::Receive(msg)
+        };
+        let info = mock_info("other", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, claim("alice", 25)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        let info = mock_info("shares", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), claim("alice", 25)).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".into(),
+                amount: coins(10, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        let res = execute(deps.as_mut(), mock_env(), info, claim("bob", 75)).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".into(),
+                amount: coins(30, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert_eq!(None, query_shares(deps.as_ref(), 1).unwrap());
+    }
+}
@@ -0,0 +1,453 @@
+//! Options whose collateral is staked while they are open.
+//!
+//! On chains where the collateral is the staking denom, a creator can have it delegated to a
+//! validator when the option is written, earning them staking rewards until it is unstaked.
+//! Unbonding takes the chain's unbonding period, so staked options are European and expire
+//! later than that: anyone can unstake once expiry is less than the period away, and the
+//! option settles like any other once the collateral is back.
+//!
+//! All stakes with a validator share one delegation. Whenever it changes or its rewards are
+//! withdrawn, the rewards accumulated are spread over the collateral delegated, and every
+//! stake is paid what its collateral earned since it was last paid.
+
+use std::slice;
+
+use cosmwasm_std::{
+    Addr, BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, MessageInfo,
+    Response, StakingMsg, StdResult, Storage, Uint128,
+};
+use cw_utils::Expiration;
+
+use crate::contract::{assert_not_paused, create_option, option_attributes};
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::msg::{CreateMsg, StakeResponse};
+use crate::state::{
+    counted, move_tracked, options, save_option, Ledger, OptionStyle, Stake, State, ValidatorStake,
+    CONFIG, STAKES, VALIDATOR_STAKES,
+};
+use crate::tokenfactory;
+
+/// Fails while the collateral of the option is delegated or unbonding.
+pub(crate) fn assert_liquid(
+    storage: &dyn Storage,
+    env: &Env,
+    option_id: u64,
+) -> Result<(), ContractError> {
+    match STAKES.may_load(storage, option_id)? {
+        None => Ok(()),
+        // unbonding completes at the end of the block, so the collateral is back the block after
+        Some(Stake {
+            unbonds_at: Some(time),
+            ..
+        }) if env.block.time > time => Ok(()),
+        Some(_) => Err(ContractError::CollateralStaked {}),
+    }
+}
+
+/// Forgets the stake of an option being closed, which must not hold its collateral anymore.
+pub(crate) fn release(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+) -> Result<(), ContractError> {
+    assert_liquid(storage, env, option_id)?;
+    STAKES.remove(storage, option_id);
+    Ok(())
+}
+
+/// Fails while a rebuild is counting the options, as rewards accrue to every stake at once.
+fn assert_counted(storage: &dyn Storage) -> Result<(), ContractError> {
+    if !counted(storage, Ledger::Options, u64::MAX)? {
+        return Err(ContractError::StatsRebuilding {});
+    }
+    Ok(())
+}
+
+/// Spreads the rewards the delegation to `validator` accumulated over the collateral delegated,
+/// returning them with the stakes. They are withdrawn by whatever message next touches the
+/// delegation, and tracked until they are paid.
+fn accrue(
+    deps: Deps,
+    env: &Env,
+    validator: &str,
+    denom: &str,
+) -> StdResult<(ValidatorStake, Coin)> {
+    let mut stakes = VALIDATOR_STAKES
+        .may_load(deps.storage, validator)?
+        .unwrap_or_default();
+    let delegation = deps
+        .querier
+        .query_delegation(&env.contract.address, validator)?;
+    let mut accrued = Coin::new(0, denom);
+    if let Some(delegation) = delegation {
+        let rewards = delegation
+            .accumulated_rewards
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map_or(Uint128::zero(), |coin| coin.amount);
+        if !stakes.total.is_zero() {
+            stakes.reward_index += Decimal::from_ratio(rewards, stakes.total);
+            accrued.amount = rewards;
+        }
+    }
+    Ok((stakes, accrued))
+}
+
+/// What the stake earned since it was last paid, up to `index`.
+fn earned(stake: &Stake, index: Decimal) -> StdResult<Uint128> {
+    Ok(stake.amount.amount * index.checked_sub(stake.reward_index)?)
+}
+
+/// Pays the creator what the stake earned up to `index`.
+fn pay_rewards(
+    stake: &mut Stake,
+    index: Decimal,
+    creator: &Addr,
+) -> StdResult<(Coin, Option<BankMsg>)> {
+    let earned = earned(stake, index)?;
+    stake.reward_index = index;
+    let rewards = Coin::new(earned.u128(), &stake.amount.denom);
+    let msg = (!earned.is_zero()).then(|| BankMsg::Send {
+        to_address: creator.to_string(),
+        amount: vec![rewards.clone()],
+    });
+    Ok((rewards, msg))
+}
+
+/// Rewards withdrawn from the delegation that are yet to be paid to the creator of the option's
+/// stake. Unbonding stakes were paid when they were unstaked and earn nothing since.
+pub(crate) fn unpaid_rewards(storage: &dyn Storage, option_id: u64) -> StdResult<Vec<Coin>> {
+    let stake = match STAKES.may_load(storage, option_id)? {
+        Some(stake) if stake.unbonds_at.is_none() => stake,
+        _ => return Ok(vec![]),
+    };
+    let index = VALIDATOR_STAKES
+        .may_load(storage, &stake.validator)?
+        .map_or(stake.reward_index, |stakes| stakes.reward_index);
+    let earned = earned(&stake, index)?;
+    Ok(vec![Coin::new(earned.u128(), &stake.amount.denom)])
+}
+
+/// Writes a European option on the staking denom and delegates its collateral to `validator`.
+pub fn handle_create_staked(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    terms: CreateMsg,
+    validator: String,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    assert_counted(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let denom = deps.querier.query_bonded_denom()?;
+    let collateral = match info.funds.as_slice() {
+        [coin] if coin.denom == denom => coin.clone(),
+        _ => return Err(ContractError::InvalidStaking {}),
+    };
+    // the collateral must be back by the time the option settles, and can't be knocked out early
+    let unbonds_in_time = match terms.expires {
+        Expiration::AtTime(time) => env.block.time.plus_seconds(config.unbonding_period) < time,
+        _ => false,
+    };
+    if config.unbonding_period == 0
+        || !unbonds_in_time
+        || terms.style != OptionStyle::European
+        || terms.barrier.is_some()
+        || terms.roll_on_expiry
+        || deps.querier.query_validator(&validator)?.is_none()
+    {
+        return Err(ContractError::InvalidStaking {});
+    }
+
+    let (option_id, state) = create_option(deps.branch(), &env, info, terms)?;
+    let (mut stakes, accrued) = accrue(deps.as_ref(), &env, &validator, &denom)?;
+    move_tracked(deps.storage, &[], &[accrued])?;
+    stakes.total += collateral.amount;
+    VALIDATOR_STAKES.save(deps.storage, &validator, &stakes)?;
+    let stake = Stake {
+        validator: validator.clone(),
+        amount: collateral.clone(),
+        reward_index: stakes.reward_index,
+        unbonds_at: None,
+    };
+    STAKES.save(deps.storage, option_id, &stake)?;
+
+    Ok(Response::new()
+        .add_messages(tokenfactory::mint_msgs(&env, option_id, &state))
+        .add_message(StakingMsg::Delegate {
+            validator: validator.clone(),
+            amount: collateral,
+        })
+        .add_attribute("action", "create_staked")
+        .add_attribute("validator", validator)
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Undelegates the collateral of a staked option, paying the creator the rewards earned. If
+/// the validator was slashed, the option is left with what comes back.
+pub fn handle_unstake(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_counted(deps.storage)?;
+    let config = CONFIG.load(deps.storage)?;
+    let mut state: State = options().load(deps.storage, option_id)?;
+    let mut stake = STAKES.load(deps.storage, option_id)?;
+    if stake.unbonds_at.is_some() {
+        return Err(ContractError::InvalidStaking {});
+    }
+    // anyone can make sure the collateral is back by expiry
+    let unstake_from = match state.expires {
+        Expiration::AtTime(time) => time.minus_seconds(config.unbonding_period),
+        // extended to a height, so it can't be told how long is left
+        _ => env.block.time,
+    };
+    if info.sender != state.creator && env.block.time < unstake_from {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let (mut stakes, accrued) = accrue(deps.as_ref(), &env, &stake.validator, &stake.amount.denom)?;
+    let (rewards, paid) = pay_rewards(&mut stake, stakes.reward_index, &state.creator)?;
+    move_tracked(deps.storage, slice::from_ref(&rewards), &[accrued])?;
+    let delegated = deps
+        .querier
+        .query_delegation(&env.contract.address, &stake.validator)?
+        .map_or(Uint128::zero(), |delegation| delegation.amount.amount);
+    let unbonding = stake
+        .amount
+        .amount
+        .multiply_ratio(delegated.min(stakes.total), stakes.total);
+    let slashed = stake.amount.amount - unbonding;
+    if !slashed.is_zero() {
+        state.collateral[0].amount -= slashed;
+        save_option(deps.storage, option_id, &state)?;
+    }
+    stakes.total -= stake.amount.amount;
+    VALIDATOR_STAKES.save(deps.storage, &stake.validator, &stakes)?;
+    stake.unbonds_at = Some(env.block.time.plus_seconds(config.unbonding_period));
+    STAKES.save(deps.storage, option_id, &stake)?;
+
+    // undelegating withdraws the rewards first
+    let mut res = Response::new();
+    if !unbonding.is_zero() {
+        res = res.add_message(StakingMsg::Undelegate {
+            validator: stake.validator.clone(),
+            amount: Coin::new(unbonding.u128(), &stake.amount.denom),
+        });
+    }
+    Ok(res
+        .add_messages(paid)
+        .add_attribute("action", "unstake")
+        .add_attribute("rewards", rewards.to_string())
+        .add_attribute("slashed", slashed)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Withdraws the rewards of the validator's delegation, paying the creator what the option's
+/// collateral earned.
+pub fn handle_claim_staking_rewards(
+    deps: DepsMut,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_counted(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let mut stake = STAKES.load(deps.storage, option_id)?;
+    if stake.unbonds_at.is_some() {
+        return Err(ContractError::InvalidStaking {});
+    }
+    let (stakes, accrued) = accrue(deps.as_ref(), &env, &stake.validator, &stake.amount.denom)?;
+    VALIDATOR_STAKES.save(deps.storage, &stake.validator, &stakes)?;
+    let (rewards, paid) = pay_rewards(&mut stake, stakes.reward_index, &state.creator)?;
+    move_tracked(deps.storage, slice::from_ref(&rewards), &[accrued])?;
+    STAKES.save(deps.storage, option_id, &stake)?;
+
+    let withdraw = CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+        validator: stake.validator,
+    });
+    Ok(Response::new()
+        .add_message(withdraw)
+        .add_messages(paid)
+        .add_attribute("action", "claim_staking_rewards")
+        .add_attribute("rewards", rewards.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+pub fn query_stake(deps: Deps, env: Env, option_id: u64) -> StdResult<StakeResponse> {
+    let stake = STAKES.load(deps.storage, option_id)?;
+    let rewards = match stake.unbonds_at {
+        Some(_) => Coin::new(0, &stake.amount.denom),
+        None => {
+            let index = accrue(deps, &env, &stake.validator, &stake.amount.denom)?
+                .0
+                .reward_index;
+            let creator = options().load(deps.storage, option_id)?.creator;
+            pay_rewards(&mut stake.clone(), index, &creator)?.0
+        }
+    };
+    Ok(StakeResponse { stake, rewards })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::execute;
+    use crate::msg::{ExecuteMsg, UpdateConfigMsg};
+    use crate::state::{Asset, Settlement};
+    use crate::testing::setup;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, FullDelegation, Validator};
+
+    #[test]
+    fn stake_and_unstake() {
+        let mut deps = setup();
+        let validator = Validator {
+            address: "validator".to_string(),
+            commission: Decimal::percent(5),
+            max_commission: Decimal::percent(10),
+            max_change_rate: Decimal::percent(1),
+        };
+        deps.querier.update_staking("ustake", &[validator], &[]);
+
+        let now = mock_env().block.time;
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(40, "ETH")),
+            expires: Expiration::AtTime(now.plus_seconds(1_000)),
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style: OptionStyle::European,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let create = |terms: CreateMsg| ExecuteMsg::CreateStaked {
+            terms,
+            validator: "validator".to_string(),
+        };
+        // staking is off until the unbonding period is set
+        let info = mock_info("writer", &coins(100, "ustake"));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            create(terms.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStaking {}));
+        let config = UpdateConfigMsg {
+            unbonding_period: Some(100),
+            ..UpdateConfigMsg::default()
+        };
+        let admin = mock_info("creator", &[]);
+        let _ = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin,
+            ExecuteMsg::UpdateConfig(config),
+        )
+        .unwrap();
+        // too close to expiry to unbond in time
+        let soon = CreateMsg {
+            expires: Expiration::AtTime(now.plus_seconds(100)),
+            ..terms.clone()
+        };
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), create(soon)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStaking {}));
+        let res = execute(deps.as_mut(), mock_env(), info, create(terms)).unwrap();
+        assert_eq!(
+            CosmosMsg::Staking(StakingMsg::Delegate {
+                validator: "validator".to_string(),
+                amount: coin(100, "ustake"),
+            }),
+            res.messages[0].msg
+        );
+
+        // the creator gets the rewards
+        let delegation = |rewards: u128| FullDelegation {
+            delegator: mock_env().contract.address,
+            validator: "validator".to_string(),
+            amount: coin(100, "ustake"),
+            can_redelegate: coin(100, "ustake"),
+            accumulated_rewards: coins(rewards, "ustake"),
+        };
+        deps.querier
+            .update_staking("ustake", &[], &[delegation(10)]);
+        let res = query_stake(deps.as_ref(), mock_env(), 2).unwrap();
+        assert_eq!(coin(10, "ustake"), res.rewards);
+        let claim = ExecuteMsg::ClaimStakingRewards { option_id: 2 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), claim).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward {
+                    validator: "validator".to_string(),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "writer".into(),
+                    amount: coins(10, "ustake"),
+                }),
+            ],
+            msgs
+        );
+
+        // only the creator can unstake until expiry is an unbonding period away
+        deps.querier.update_staking("ustake", &[], &[delegation(4)]);
+        let unstake = ExecuteMsg::Unstake { option_id: 2 };
+        let info = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), unstake.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let mut env = mock_env();
+        env.block.time = now.plus_seconds(900);
+        let res = execute(deps.as_mut(), env.clone(), info, unstake).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Staking(StakingMsg::Undelegate {
+                    validator: "validator".to_string(),
+                    amount: coin(100, "ustake"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "writer".into(),
+                    amount: coins(4, "ustake"),
+                }),
+            ],
+            msgs
+        );
+
+        // the option settles once the collateral is back
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 2,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("writer", &coins(40, "ETH"));
+        env.block.time = now.plus_seconds(1_000);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            info.clone(),
+            execute_msg.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::CollateralStaked {}));
+        env.block.time = now.plus_seconds(1_001);
+        let _ = execute(deps.as_mut(), env, info, execute_msg).unwrap();
+        assert!(!STAKES.has(&deps.storage, 2));
+        // the validator keeps its reward index for later stakes
+        let stakes = VALIDATOR_STAKES.load(&deps.storage, "validator").unwrap();
+        assert!(stakes.total.is_zero());
+        assert_eq!(Decimal::percent(14), stakes.reward_index);
+    }
+}
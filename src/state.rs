@@ -1,24 +1,1249 @@
 use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Coin, HumanAddr, Storage};
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use cosmwasm_std::{
+    Addr, Coin, Coins, Decimal, Empty, StdError, StdResult, Storage, Timestamp, Uint128,
+};
+use cw20::Cw20CoinVerified;
+use cw721::Approval;
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use cw_utils::Expiration;
 
-pub static CONFIG_KEY: &[u8] = b"config";
+use crate::msg::UpdateConfigMsg;
+
+/// Asset that can be used as a counter_offer, either native coins or a cw20 token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Asset {
+    Native(Vec<Coin>),
+    Cw20(Cw20CoinVerified),
+}
+
+/// Native coins and cw20 tokens held together, such as the collateral of an option
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AssetList {
+    pub native: Vec<Coin>,
+    pub cw20: Vec<Cw20CoinVerified>,
+}
+
+impl AssetList {
+    pub fn is_empty(&self) -> bool {
+        self.native.is_empty() && self.cw20.is_empty()
+    }
+}
+
+/// How an option is settled when the owner executes it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Settlement {
+    /// Owner pays the counter_offer and receives the collateral
+    #[default]
+    Physical,
+    /// Owner pays nothing and receives the in-the-money part of the collateral,
+    /// valued by the oracle. The creator keeps the rest.
+    Cash,
+    /// Not executed, anyone settles the option after expiry at the oracle price of the moment.
+    /// The owner receives the whole collateral if the price is past the strike in `direction`,
+    /// the creator keeps it otherwise.
+    Binary { direction: BarrierDirection },
+    /// Like cash, against the average oracle price sampled by keepers during the `window`
+    /// blocks or seconds, matching the expiry, before expiry. The spot price is used when
+    /// nothing was sampled. The option has to be European.
+    Average { window: u64 },
+}
+
+/// When the owner may execute an option
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionStyle {
+    /// Any time before expiry
+    #[default]
+    American,
+    /// Only during the settlement window that opens at expiry
+    European,
+}
+
+/// Which way the oracle price has to cross a target, a barrier or the strike of a binary option
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BarrierDirection {
+    /// Once the collateral is worth `price` or more
+    Up,
+    /// Once the collateral is worth `price` or less
+    Down,
+}
+
+/// Collateral price, in units of the counter_offer, at which the option is voided
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Barrier {
+    pub price: Decimal,
+    pub direction: BarrierDirection,
+}
+
+impl Barrier {
+    pub fn crossed(&self, spot: Decimal) -> bool {
+        match self.direction {
+            BarrierDirection::Up => spot >= self.price,
+            BarrierDirection::Down => spot <= self.price,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
-    pub creator: HumanAddr,
-    pub owner: HumanAddr,
+    pub creator: Addr,
+    pub owner: Addr,
+    pub collateral: Vec<Coin>,
+    pub counter_offer: Asset,
+    /// Accepted instead of the counter_offer, whichever the executor pays
+    #[serde(default)]
+    pub alt_counter_offers: Vec<Asset>,
+    /// Price of the collateral in the counter_offer denom, which the counter_offer follows
+    #[serde(default)]
+    pub strike: Option<Decimal>,
+    pub expires: Expiration,
+    /// Asking price paid to the owner by whoever buys the option, empty if not for sale.
+    /// Starts out as the creator's premium and is cleared on every change of owner.
+    pub price: Vec<Coin>,
+    #[serde(default)]
+    pub settlement: Settlement,
+    #[serde(default)]
+    pub style: OptionStyle,
+    /// Share of the sale price, in basis points, paid to the creator on resales
+    #[serde(default)]
+    pub royalty_bps: u64,
+    /// Rolled over to a later expiry once expired unexercised, instead of being burned
+    #[serde(default)]
+    pub roll_on_expiry: bool,
+    /// Voided once the oracle price crosses it, if set
+    #[serde(default)]
+    pub barrier: Option<Barrier>,
+    /// Blocks or seconds, matching the expiry, a European option stays exercisable after
+    /// expiry, the contract default if unset
+    #[serde(default)]
+    pub settlement_window: Option<u64>,
+    /// Not exercisable before this point, if set
+    #[serde(default)]
+    pub exercisable_from: Option<Expiration>,
+    /// Only owner besides the creator the option can have, anyone if unset
+    #[serde(default)]
+    pub allowed_taker: Option<Addr>,
+    /// Spenders the owner allowed to transfer or execute this option, cleared on every change of owner
+    #[serde(default)]
+    pub approvals: Vec<Approval>,
+    /// Factory denom of a tokenized option, whose holder can execute it.
+    /// The contract is the owner of tokenized options.
+    #[serde(default)]
+    pub denom: Option<String>,
+    /// Held as the collateral instead of coins, if set
+    #[serde(default)]
+    pub nft: Option<NftCollateral>,
+    /// Cw20 tokens held as collateral along with the coins
+    #[serde(default)]
+    pub cw20_collateral: Vec<Cw20CoinVerified>,
+}
+
+impl State {
+    /// Coins and tokens of the collateral, not counting an NFT
+    pub fn collateral_list(&self) -> AssetList {
+        AssetList {
+            native: self.collateral.clone(),
+            cw20: self.cw20_collateral.clone(),
+        }
+    }
+
+    /// Sets the counter_offer due for the collateral at the strike price, rounded up.
+    pub fn apply_strike(&mut self) {
+        let price = match self.strike {
+            Some(price) => price,
+            None => return,
+        };
+        if let ([collateral], Asset::Native(due)) =
+            (self.collateral.as_slice(), &mut self.counter_offer)
+        {
+            for coin in due.iter_mut() {
+                coin.amount = collateral.amount.mul_ceil(price);
+            }
+        }
+    }
+}
+
+/// NFT an option is collateralized by
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NftCollateral {
+    pub collection: Addr,
+    pub token_id: String,
+}
+
+pub struct OptionIndexes<'a> {
+    pub owner: MultiIndex<'a, Addr, State, u64>,
+    pub creator: MultiIndex<'a, Addr, State, u64>,
+    pub expires: MultiIndex<'a, (u8, u64), State, u64>,
+}
+
+impl<'a> IndexList<State> for OptionIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<State>> + '_> {
+        let v: Vec<&dyn Index<State>> = vec![&self.owner, &self.creator, &self.expires];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Contract wide settings, independent of any single option
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    /// Can update this config
+    pub admin: Addr,
+    /// Can pause and unpause the contract
+    pub guardian: Addr,
+    /// Only address that can change the fees, oracle and denom whitelist, unset leaves them to
+    /// the admin
+    #[serde(default)]
+    pub dao: Option<Addr>,
+    /// While paused, options cannot be created, transferred or executed
+    pub paused: bool,
+    /// Share of the collateral, in basis points, paid to whoever burns another creator's option
+    pub burn_reward_bps: u64,
+    /// Protocol fee, in basis points, taken from the counter_offer on execution
+    pub fee_bps: u64,
+    /// Receives the protocol fees
+    pub fee_collector: Addr,
+    /// Denoms accepted as collateral and counter_offer, empty means any
+    pub allowed_denoms: Vec<String>,
+    /// Price feed used to value collateral in terms of the counter_offer
+    pub oracle: Option<Addr>,
+    /// Seconds settlement records are kept before anyone can prune them, 0 keeps them forever
+    #[serde(default)]
+    pub archive_retention: u64,
+    /// Represents new options by an x/tokenfactory denom, only for chains with the module
+    #[serde(default)]
+    pub tokenfactory: bool,
+    /// Code id of cw20-base, for share tokens of fractionalized options. Unset disables them.
+    #[serde(default)]
+    pub share_code_id: Option<u64>,
+    /// Fee paid to the fee collector whenever an option changes hands
+    #[serde(default)]
+    pub transfer_fee: TransferFee,
+    /// Addresses, such as marketplace contracts, whose transfers and sales pay no transfer fee
+    #[serde(default)]
+    pub fee_exempt: Vec<Addr>,
+    /// Share of the protocol fees, in basis points, paid to the referrer of a trade
+    #[serde(default)]
+    pub referral_bps: u64,
+    /// How far past the current block rolled options are set to expire, in blocks for
+    /// options expiring at a height and seconds for those expiring at a time. Zero disables
+    /// rolling.
+    #[serde(default)]
+    pub roll_duration: u64,
+    /// How long past expiry, or the end of the settlement window of European options, burning
+    /// waits. In blocks or seconds, matching the expiry.
+    #[serde(default)]
+    pub burn_grace_period: u64,
+    /// Paid out of the crank pool to keepers for every option they crank, nothing if empty
+    #[serde(default)]
+    pub crank_reward: Vec<Coin>,
+    /// Seconds the chain takes to unbond, staked options must expire later than this.
+    /// Zero disables staking.
+    #[serde(default)]
+    pub unbonding_period: u64,
+    /// Contract the collateral of options can be deposited into for yield, unset disables it
+    #[serde(default)]
+    pub yield_strategy: Option<Addr>,
+    /// Share of the notional, in basis points, margined options need on top of what they would
+    /// pay out. Zero disables margined options.
+    #[serde(default)]
+    pub maintenance_margin_bps: u64,
+    /// Share of native protocol fees, in basis points, paid into the insurance fund
+    #[serde(default)]
+    pub insurance_bps: u64,
+    /// Largest move of the oracle price, in basis points of the last one, cash settlement goes
+    /// through at. Zero disables the circuit breaker.
+    #[serde(default)]
+    pub max_price_deviation_bps: u64,
+    /// Seconds cash settlement of a pair is halted after a larger move
+    #[serde(default)]
+    pub breaker_cooldown: u64,
+    /// Seconds changes to the fees, oracle and denom whitelist are queued for before they can
+    /// be executed. Zero applies them right away.
+    #[serde(default)]
+    pub config_timelock: u64,
+    /// Contract asked whether an address may take options on every transfer and sale,
+    /// unset lets anyone take them
+    #[serde(default)]
+    pub allowlist: Option<Addr>,
+    /// Least the collateral of new options has to be worth, valued by the oracle in other
+    /// denoms. Unset allows any amount.
+    #[serde(default)]
+    pub min_collateral: Option<Coin>,
+    /// Most options an address can have written and still open, zero allows any number
+    #[serde(default)]
+    pub max_open_options: u64,
+}
+
+/// Fee charged when an option changes hands, nothing by default
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct TransferFee {
+    /// Paid by the sender along with every transfer
+    pub flat: Vec<Coin>,
+    /// Share of the price, in basis points, taken from every sale
+    pub bps: u64,
+}
+
+impl Config {
+    /// Administered by `admin` alone, with no fees, rewards or extensions enabled
+    pub fn new(admin: Addr) -> Self {
+        Config {
+            admin: admin.clone(),
+            guardian: admin.clone(),
+            dao: None,
+            paused: false,
+            burn_reward_bps: 0,
+            fee_bps: 0,
+            fee_collector: admin,
+            allowed_denoms: vec![],
+            oracle: None,
+            archive_retention: 0,
+            tokenfactory: false,
+            share_code_id: None,
+            transfer_fee: TransferFee::default(),
+            fee_exempt: vec![],
+            referral_bps: 0,
+            roll_duration: 0,
+            burn_grace_period: 0,
+            crank_reward: vec![],
+            unbonding_period: 0,
+            yield_strategy: None,
+            maintenance_margin_bps: 0,
+            insurance_bps: 0,
+            max_price_deviation_bps: 0,
+            breaker_cooldown: 0,
+            config_timelock: 0,
+            allowlist: None,
+            min_collateral: None,
+            max_open_options: 0,
+        }
+    }
+}
+
+pub const CONFIG: Item<Config> = Item::new("admin_config");
+
+/// Options are keyed by their big-endian id so ranges come back in creation order.
+pub fn options<'a>() -> IndexedMap<'a, u64, State, OptionIndexes<'a>> {
+    let indexes = OptionIndexes {
+        owner: MultiIndex::new(|_pk, s| s.owner.clone(), "option", "option__owner"),
+        creator: MultiIndex::new(|_pk, s| s.creator.clone(), "option", "option__creator"),
+        expires: MultiIndex::new(
+            |_pk, s| expiration_key(&s.expires),
+            "option",
+            "option__expires",
+        ),
+    };
+    IndexedMap::new("option", indexes)
+}
+pub const OPTION_COUNT: Item<u64> = Item::new("option_count");
+
+/// Running totals over the live options, cw20 collateral and counter_offers are counted by
+/// contract address
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Stats {
+    pub active_options: u64,
     pub collateral: Vec<Coin>,
     pub counter_offer: Vec<Coin>,
-    pub expires: u64,
 }
 
-pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
-    singleton(storage, CONFIG_KEY)
+pub const STATS: Item<Stats> = Item::new("stats");
+
+/// Native coins the contract holds for options, their trades and its pools, other than the
+/// crank pool and insurance fund. Kept up as they move in and out rather than added up.
+pub const TRACKED: Item<Vec<Coin>> = Item::new("tracked");
+
+/// Kinds of entries holding tracked funds, in the order a rebuild counts them. Entries about
+/// an option, like its auction or funding, are counted with it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ledger {
+    Options,
+    Orders,
+    PremiumEscrows,
+    Quotes,
+    Vaults,
+}
+
+impl Ledger {
+    /// Counter of the ids of its entries
+    pub fn counter(self) -> Item<'static, u64> {
+        match self {
+            Ledger::Options => OPTION_COUNT,
+            Ledger::Orders => ORDER_COUNT,
+            Ledger::PremiumEscrows => ESCROW_COUNT,
+            Ledger::Quotes => QUOTE_COUNT,
+            Ledger::Vaults => VAULT_COUNT,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Ledger::Options => "options",
+            Ledger::Orders => "orders",
+            Ledger::PremiumEscrows => "premium_escrows",
+            Ledger::Quotes => "quotes",
+            Ledger::Vaults => "vaults",
+        }
+    }
+
+    /// Ledger a rebuild counts next
+    pub fn next(self) -> Option<Ledger> {
+        match self {
+            Ledger::Options => Some(Ledger::Orders),
+            Ledger::Orders => Some(Ledger::PremiumEscrows),
+            Ledger::PremiumEscrows => Some(Ledger::Quotes),
+            Ledger::Quotes => Some(Ledger::Vaults),
+            Ledger::Vaults => None,
+        }
+    }
+}
+
+/// Ledger and id of the last entry counted while the stats and tracked funds are rebuilt after
+/// a migration. Later entries are left out of them until the rebuild reaches them.
+pub const STATS_REBUILD: Item<(Ledger, u64)> = Item::new("stats_rebuild");
+
+/// Whether the entry is in the stats and tracked funds, which it is unless a rebuild has yet
+/// to reach it
+pub fn counted(storage: &dyn Storage, ledger: Ledger, id: u64) -> StdResult<bool> {
+    Ok(STATS_REBUILD.may_load(storage)?.map_or(true, |(at, last)| {
+        ledger < at || (ledger == at && id <= last)
+    }))
+}
+
+/// Adds `held` to the tracked funds and takes `released` out of them.
+pub fn move_tracked(storage: &mut dyn Storage, released: &[Coin], held: &[Coin]) -> StdResult<()> {
+    let mut tracked = to_coins(TRACKED.may_load(storage)?.unwrap_or_default())?;
+    for coin in held {
+        tracked.add(coin.clone())?;
+    }
+    for coin in released {
+        tracked.sub(coin.clone())?;
+    }
+    TRACKED.save(storage, &tracked.into_vec())
+}
+
+/// Moves the funds entry `id` of `ledger` released or took in, unless it is yet to be counted
+pub fn track(
+    storage: &mut dyn Storage,
+    ledger: Ledger,
+    id: u64,
+    released: &[Coin],
+    held: &[Coin],
+) -> StdResult<()> {
+    if counted(storage, ledger, id)? {
+        move_tracked(storage, released, held)?;
+    }
+    Ok(())
+}
+
+/// Entries holding tracked funds. They should only be written through `save_held` and
+/// `remove_held`, or their indexed counterparts.
+pub trait Held {
+    const LEDGER: Ledger;
+
+    /// Native coins the contract holds for the entry
+    fn held(&self) -> Vec<Coin>;
+}
+
+fn held_before<T: Held>(old: Option<T>) -> Vec<Coin> {
+    old.map_or_else(Vec::new, |old| old.held())
+}
+
+/// Stores the entry, keeping the tracked funds in line with it.
+pub fn save_held<T>(
+    storage: &mut dyn Storage,
+    map: &Map<u64, T>,
+    id: u64,
+    value: &T,
+) -> StdResult<()>
+where
+    T: Held + Serialize + DeserializeOwned,
+{
+    let old = held_before(map.may_load(storage, id)?);
+    track(storage, T::LEDGER, id, &old, &value.held())?;
+    map.save(storage, id, value)
+}
+
+/// Removes the entry, if any, from the store and the tracked funds.
+pub fn remove_held<T>(storage: &mut dyn Storage, map: &Map<u64, T>, id: u64) -> StdResult<()>
+where
+    T: Held + Serialize + DeserializeOwned,
+{
+    let old = held_before(map.may_load(storage, id)?);
+    track(storage, T::LEDGER, id, &old, &[])?;
+    map.remove(storage, id);
+    Ok(())
+}
+
+/// `save_held` for indexed entries
+pub fn save_indexed<'a, T, I>(
+    storage: &mut dyn Storage,
+    map: &IndexedMap<'a, u64, T, I>,
+    id: u64,
+    value: &T,
+) -> StdResult<()>
+where
+    T: Held + Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    let old = held_before(map.may_load(storage, id)?);
+    track(storage, T::LEDGER, id, &old, &value.held())?;
+    map.save(storage, id, value)
+}
+
+/// `remove_held` for indexed entries
+pub fn remove_indexed<'a, T, I>(
+    storage: &mut dyn Storage,
+    map: &IndexedMap<'a, u64, T, I>,
+    id: u64,
+) -> StdResult<()>
+where
+    T: Held + Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    let old = held_before(map.may_load(storage, id)?);
+    track(storage, T::LEDGER, id, &old, &[])?;
+    map.remove(storage, id)
+}
+
+impl Held for Extension {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        self.fee.clone()
+    }
+}
+
+impl Held for Buyback {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        self.price.clone()
+    }
+}
+
+impl Held for Auction {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        self.bid.iter().map(|bid| bid.amount.clone()).collect()
+    }
+}
+
+impl Held for ShareInfo {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        self.proceeds.clone()
+    }
+}
+
+impl Held for WritingPool {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        self.proceeds.clone()
+    }
+}
+
+impl Held for Funding {
+    const LEDGER: Ledger = Ledger::Options;
+
+    fn held(&self) -> Vec<Coin> {
+        vec![Coin::new(self.balance.u128(), &self.rate.denom)]
+    }
+}
+
+impl Held for TradeOrder {
+    const LEDGER: Ledger = Ledger::Orders;
+
+    /// Bids escrow their price, asks the option
+    fn held(&self) -> Vec<Coin> {
+        match self.side {
+            OrderSide::Bid => vec![self.price.clone()],
+            OrderSide::Ask => vec![],
+        }
+    }
+}
+
+impl Held for PremiumEscrow {
+    const LEDGER: Ledger = Ledger::PremiumEscrows;
+
+    fn held(&self) -> Vec<Coin> {
+        self.premium.clone()
+    }
+}
+
+impl Held for Quote {
+    const LEDGER: Ledger = Ledger::Quotes;
+
+    fn held(&self) -> Vec<Coin> {
+        self.collateral.clone()
+    }
+}
+
+/// The idle asset only, the earnings depositors are owed are tracked as they are booked and paid
+impl Held for Vault {
+    const LEDGER: Ledger = Ledger::Vaults;
+
+    fn held(&self) -> Vec<Coin> {
+        vec![Coin::new(self.idle.u128(), &self.denom)]
+    }
+}
+
+/// Stores the option, keeping the stats in line with it.
+/// Options should only be written through here and `remove_option`.
+pub fn save_option(storage: &mut dyn Storage, option_id: u64, state: &State) -> StdResult<()> {
+    if counted(storage, Ledger::Options, option_id)? {
+        let old = options().may_load(storage, option_id)?;
+        update_stats(storage, old.as_ref(), Some(state))?;
+    }
+    options().save(storage, option_id, state)
+}
+
+/// Deletes the option, along with any termination request or transfer offer left on it
+pub fn remove_option(storage: &mut dyn Storage, option_id: u64) -> StdResult<()> {
+    let old = options().load(storage, option_id)?;
+    if counted(storage, Ledger::Options, option_id)? {
+        update_stats(storage, Some(&old), None)?;
+    }
+    TERMINATIONS.remove(storage, option_id);
+    TRANSFER_OFFERS.remove(storage, option_id);
+    options().remove(storage, option_id)
+}
+
+/// Takes `old` out of the stats and tracked funds and adds `new` to them.
+pub fn update_stats(
+    storage: &mut dyn Storage,
+    old: Option<&State>,
+    new: Option<&State>,
+) -> StdResult<()> {
+    let stats = STATS.may_load(storage)?.unwrap_or_default();
+    let mut active_options = stats.active_options;
+    let mut collateral = to_coins(stats.collateral)?;
+    let mut counter_offer = to_coins(stats.counter_offer)?;
+    if let Some(old) = old {
+        active_options = active_options
+            .checked_sub(1)
+            .ok_or_else(|| StdError::generic_err("no active option to remove from the stats"))?;
+        for coin in old.collateral.iter().cloned().chain(cw20_coins(old)) {
+            collateral.sub(coin)?;
+        }
+        for coin in asset_coins(&old.counter_offer) {
+            counter_offer.sub(coin)?;
+        }
+    }
+    if let Some(new) = new {
+        active_options += 1;
+        for coin in new.collateral.iter().cloned().chain(cw20_coins(new)) {
+            collateral.add(coin)?;
+        }
+        for coin in asset_coins(&new.counter_offer) {
+            counter_offer.add(coin)?;
+        }
+    }
+    let stats = Stats {
+        active_options,
+        collateral: collateral.into_vec(),
+        counter_offer: counter_offer.into_vec(),
+    };
+    STATS.save(storage, &stats)?;
+    let released = old.map_or(&[][..], |old| &old.collateral);
+    let held = new.map_or(&[][..], |new| &new.collateral);
+    move_tracked(storage, released, held)
+}
+
+fn to_coins(coins: Vec<Coin>) -> StdResult<Coins> {
+    let mut total = Coins::default();
+    for coin in coins {
+        total.add(coin)?;
+    }
+    Ok(total)
+}
+
+fn asset_coins(asset: &Asset) -> Vec<Coin> {
+    match asset {
+        Asset::Native(coins) => coins.clone(),
+        Asset::Cw20(coin) => vec![cw20_coin(coin)],
+    }
+}
+
+fn cw20_coins(state: &State) -> impl Iterator<Item = Coin> + '_ {
+    state.cw20_collateral.iter().map(cw20_coin)
+}
+
+fn cw20_coin(coin: &Cw20CoinVerified) -> Coin {
+    Coin::new(coin.amount.u128(), coin.address.as_str())
+}
+
+/// Ownership the owner offered to `recipient`, who has to claim it before `expires`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransferOffer {
+    /// Owner at the time of the offer, the offer lapses if the option changes hands
+    pub from: Addr,
+    pub recipient: Addr,
+    pub expires: Expiration,
+}
+
+pub const TRANSFER_OFFERS: Map<u64, TransferOffer> = Map::new("transfer_offers");
+
+/// Creator or owner that proposed to unwind an option early, waiting on the other side
+pub const TERMINATIONS: Map<u64, Addr> = Map::new("terminations");
+
+/// Later expiry the creator offered the owner, along with a fee held until accepted
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Extension {
+    pub creator: Addr,
+    pub expires: Expiration,
+    pub fee: Vec<Coin>,
+}
+
+pub const EXTENSIONS: Map<u64, Extension> = Map::new("extensions");
+
+/// Price the creator escrowed to buy back and close an option
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Buyback {
+    pub creator: Addr,
+    pub price: Vec<Coin>,
+}
+
+pub const BUYBACKS: Map<u64, Buyback> = Map::new("buybacks");
+
+/// How an option was closed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Closure {
+    Executed,
+    Burned,
+    Terminated,
+    Cancelled,
+    BoughtBack,
+    ForceSettled,
+    Liquidated,
+    KnockedOut,
+    Settled,
+    /// Split into smaller options
+    Split,
+    /// Merged with other options
+    Merged,
+}
+
+impl Closure {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Closure::Executed => "executed",
+            Closure::Burned => "burned",
+            Closure::Terminated => "terminated",
+            Closure::Cancelled => "cancelled",
+            Closure::BoughtBack => "bought_back",
+            Closure::ForceSettled => "force_settled",
+            Closure::Liquidated => "liquidated",
+            Closure::KnockedOut => "knocked_out",
+            Closure::Settled => "settled",
+            Closure::Split => "split",
+            Closure::Merged => "merged",
+        }
+    }
+}
+
+/// What is kept of an option once it is closed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SettlementRecord {
+    pub creator: Addr,
+    /// Owner at the time it was closed
+    pub owner: Addr,
+    /// Sender of the closing message, none when the chain closed it
+    pub closed_by: Option<Addr>,
+    pub closure: Closure,
+    pub height: u64,
+    pub time: Timestamp,
+    /// Collateral the option held
+    pub collateral: Vec<Coin>,
+    /// Counter_offer paid on execution, or the price of a buyback
+    pub payment: Option<Asset>,
+}
+
+pub struct ArchiveIndexes<'a> {
+    pub time: MultiIndex<'a, u64, SettlementRecord, u64>,
+}
+
+impl<'a> IndexList<SettlementRecord> for ArchiveIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<SettlementRecord>> + '_> {
+        let v: Vec<&dyn Index<SettlementRecord>> = vec![&self.time];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Records of closed options by option id, indexed by closing time in nanoseconds for pruning.
+pub fn archive<'a>() -> IndexedMap<'a, u64, SettlementRecord, ArchiveIndexes<'a>> {
+    let indexes = ArchiveIndexes {
+        time: MultiIndex::new(|_pk, r| r.time.nanos(), "archive", "archive__time"),
+    };
+    IndexedMap::new("archive", indexes)
+}
+
+/// Highest bid of an auction, escrowed by the contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Bid {
+    pub bidder: Addr,
+    pub amount: Coin,
+}
+
+/// English auction for an option the contract holds on behalf of `seller`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Auction {
+    pub seller: Addr,
+    /// Lowest first bid, later bids are in the same denom
+    pub reserve: Coin,
+    pub end: Timestamp,
+    pub bid: Option<Bid>,
+}
+
+pub const AUCTIONS: Map<u64, Auction> = Map::new("auctions");
+
+/// Declining price sale, only valid while `seller` owns the option
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DutchAuction {
+    pub seller: Addr,
+    pub start_price: Coin,
+    pub floor_price: Coin,
+    pub start: Timestamp,
+    /// From here on the price stays at the floor
+    pub end: Timestamp,
+}
+
+pub const DUTCH_AUCTIONS: Map<u64, DutchAuction> = Map::new("dutch_auctions");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderSide {
+    /// Maker buys the option, the price is escrowed
+    Bid,
+    /// Maker sells the option, the option is escrowed
+    Ask,
+}
+
+/// Limit order on an option, filled at `price` until `expires`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TradeOrder {
+    pub maker: Addr,
+    pub option_id: u64,
+    pub side: OrderSide,
+    pub price: Coin,
+    pub expires: Expiration,
+}
+
+pub struct OrderIndexes<'a> {
+    pub option: MultiIndex<'a, u64, TradeOrder, u64>,
+}
+
+impl<'a> IndexList<TradeOrder> for OrderIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<TradeOrder>> + '_> {
+        let v: Vec<&dyn Index<TradeOrder>> = vec![&self.option];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Open orders by order id, indexed by option
+pub fn orders<'a>() -> IndexedMap<'a, u64, TradeOrder, OrderIndexes<'a>> {
+    let indexes = OrderIndexes {
+        option: MultiIndex::new(|_pk, o| o.option_id, "orders", "orders__option"),
+    };
+    IndexedMap::new("orders", indexes)
+}
+
+pub const ORDER_COUNT: Item<u64> = Item::new("order_count");
+
+/// Premium a buyer offers for an option, held until the owner accepts or `deadline` passes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PremiumEscrow {
+    pub buyer: Addr,
+    pub option_id: u64,
+    pub premium: Vec<Coin>,
+    pub deadline: Expiration,
+}
+
+pub struct EscrowIndexes<'a> {
+    pub option: MultiIndex<'a, u64, PremiumEscrow, u64>,
+    pub deadline: MultiIndex<'a, (u8, u64), PremiumEscrow, u64>,
+}
+
+impl<'a> IndexList<PremiumEscrow> for EscrowIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<PremiumEscrow>> + '_> {
+        let v: Vec<&dyn Index<PremiumEscrow>> = vec![&self.option, &self.deadline];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Premium escrows by escrow id, indexed by option and by deadline
+pub fn premium_escrows<'a>() -> IndexedMap<'a, u64, PremiumEscrow, EscrowIndexes<'a>> {
+    let indexes = EscrowIndexes {
+        option: MultiIndex::new(|_pk, e| e.option_id, "escrows", "escrows__option"),
+        deadline: MultiIndex::new(
+            |_pk, e| expiration_key(&e.deadline),
+            "escrows",
+            "escrows__deadline",
+        ),
+    };
+    IndexedMap::new("escrows", indexes)
+}
+
+pub const ESCROW_COUNT: Item<u64> = Item::new("escrow_count");
+
+/// Terms a buyer asks writers to quote on, open for quotes until `deadline`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QuoteRequest {
+    pub buyer: Addr,
+    pub counter_offer: Asset,
+    pub collateral: Vec<Coin>,
+    pub expires: Expiration,
+    pub settlement: Settlement,
+    pub style: OptionStyle,
+    pub deadline: Expiration,
+}
+
+pub const QUOTE_REQUESTS: Map<u64, QuoteRequest> = Map::new("quote_requests");
+
+pub const REQUEST_COUNT: Item<u64> = Item::new("request_count");
+
+/// Writer's offer to write the requested option for `premium`, holding the collateral
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Quote {
+    pub request_id: u64,
+    pub writer: Addr,
+    pub premium: Vec<Coin>,
+    pub collateral: Vec<Coin>,
+}
+
+pub struct QuoteIndexes<'a> {
+    pub request: MultiIndex<'a, u64, Quote, u64>,
+}
+
+impl<'a> IndexList<Quote> for QuoteIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Quote>> + '_> {
+        let v: Vec<&dyn Index<Quote>> = vec![&self.request];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Quotes by quote id, indexed by request
+pub fn quotes<'a>() -> IndexedMap<'a, u64, Quote, QuoteIndexes<'a>> {
+    let indexes = QuoteIndexes {
+        request: MultiIndex::new(|_pk, q| q.request_id, "quotes", "quotes__request"),
+    };
+    IndexedMap::new("quotes", indexes)
+}
+
+pub const QUOTE_COUNT: Item<u64> = Item::new("quote_count");
+
+/// Protocol fees paid out to a referrer, cw20 tokens are counted by contract address
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct ReferralStats {
+    /// Trades that paid the referrer a share of their fees
+    pub referrals: u64,
+    pub earned: Vec<Coin>,
+    pub earned_cw20: Vec<Cw20CoinVerified>,
+}
+
+pub const REFERRALS: Map<&Addr, ReferralStats> = Map::new("referrals");
+
+/// Share token of a fractionalized option, and what the option paid out for its holders
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShareInfo {
+    pub token: Addr,
+    /// Shares not yet claimed
+    pub supply: Uint128,
+    pub proceeds: Vec<Coin>,
+    pub cw20_proceeds: Vec<Cw20CoinVerified>,
+}
+
+pub const SHARES: Map<u64, ShareInfo> = Map::new("shares");
+
+/// Writers pooling the collateral of a co-written option, and what it paid out for them
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WritingPool {
+    /// Creator of the option, others can only join while they own it
+    pub lead: Addr,
+    /// Contributions not yet claimed
+    pub total: Uint128,
+    pub proceeds: Vec<Coin>,
+    pub cw20_proceeds: Vec<Cw20CoinVerified>,
+}
+
+pub const WRITING_POOLS: Map<u64, WritingPool> = Map::new("writing_pools");
+
+/// Collateral each writer put into a co-written option
+pub const CONTRIBUTIONS: Map<(u64, &Addr), Uint128> = Map::new("contributions");
+
+/// Option of a series, with the terms it was written at
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SeriesLeg {
+    pub option_id: u64,
+    pub strike: Coin,
+    pub expires: Expiration,
+}
+
+/// Grid of options written in one go, one per strike and expiry
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Series {
+    pub creator: Addr,
+    pub legs: Vec<SeriesLeg>,
+}
+
+pub const SERIES: Map<u64, Series> = Map::new("series");
+
+pub const SERIES_COUNT: Item<u64> = Item::new("series_count");
+
+/// Options written together, owned and executed as one position
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Strategy {
+    pub creator: Addr,
+    pub owner: Addr,
+    /// Legs, which stay listed once closed
+    pub option_ids: Vec<u64>,
+}
+
+pub const STRATEGIES: Map<u64, Strategy> = Map::new("strategies");
+
+pub const STRATEGY_COUNT: Item<u64> = Item::new("strategy_count");
+
+/// Delegation of the collateral of a staked option. `reward_index` is what the validator's
+/// delegations earned per unit when the stake was last paid its rewards.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Stake {
+    pub validator: String,
+    pub amount: Coin,
+    pub reward_index: Decimal,
+    /// When the undelegated collateral is back, unset while it is delegated
+    pub unbonds_at: Option<Timestamp>,
+}
+
+pub const STAKES: Map<u64, Stake> = Map::new("stakes");
+
+/// Shares of the yield strategy the collateral of an option was deposited for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct YieldDeposit {
+    pub strategy: Addr,
+    pub shares: Uint128,
+}
+
+pub const YIELD_DEPOSITS: Map<u64, YieldDeposit> = Map::new("yield_deposits");
+
+/// Option whose collateral is being deposited for yield, until the strategy reports the shares
+pub const PENDING_YIELD_DEPOSIT: Item<u64> = Item::new("pending_yield_deposit");
+
+/// Shares of an option being redeemed, with the balance of its collateral denom beforehand and
+/// what was sent along to make up a loss
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingWithdrawal {
+    pub option_id: u64,
+    pub sender: Addr,
+    pub balance: Uint128,
+    pub top_up: Uint128,
+}
+
+pub const PENDING_YIELD_WITHDRAWAL: Item<PendingWithdrawal> = Item::new("pending_yield_withdrawal");
+
+/// Notional of margined options, in their collateral denom, the collateral being the margin
+pub const NOTIONALS: Map<u64, Uint128> = Map::new("notionals");
+
+/// Collateral the contract delegates to a validator and the rewards earned per unit of it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct ValidatorStake {
+    pub total: Uint128,
+    pub reward_index: Decimal,
+}
+
+pub const VALIDATOR_STAKES: Map<&str, ValidatorStake> = Map::new("validator_stakes");
+
+/// Funds crank rewards are paid from
+pub const CRANK_POOL: Item<Vec<Coin>> = Item::new("crank_pool");
+
+/// Expiration key and id of the last option the crank looked at, where the next crank picks up.
+/// Unset once the crank has gone through every expired option.
+pub const CRANK_CURSOR: Item<((u8, u64), u64)> = Item::new("crank_cursor");
+
+/// Oracle price a pair last settled at or was updated to, with any halt of cash settlement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LastPrice {
+    pub rate: Decimal,
+    pub halted_until: Option<Timestamp>,
+}
+
+/// Last prices keyed by `(collateral, counter_offer)` denom
+pub const LAST_PRICES: Map<(&str, &str), LastPrice> = Map::new("last_prices");
+
+/// Addresses that can't take or exercise options
+pub const BLACKLIST: Map<&Addr, Empty> = Map::new("blacklist");
+
+/// Funds topping up payouts the collateral falls short of, out of protocol fees
+pub const INSURANCE_FUND: Item<Vec<Coin>> = Item::new("insurance_fund");
+
+/// Config change waiting out the timelock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueuedConfig {
+    pub id: u64,
+    pub changes: UpdateConfigMsg,
+    pub executable_at: Timestamp,
+}
+
+pub const QUEUED_CONFIGS: Map<u64, QueuedConfig> = Map::new("queued_configs");
+
+pub const QUEUED_COUNT: Item<u64> = Item::new("queued_count");
+
+/// Cw20 payout of a settlement, kept as a claim if the transfer failed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FailedPayout {
+    pub option_id: u64,
+    pub recipient: Addr,
+    pub token: Cw20CoinVerified,
+}
+
+/// Cw20 payouts sent by the transaction at `tx_index` of the block at `height`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingPayouts {
+    pub height: u64,
+    pub tx_index: Option<u32>,
+    pub payouts: Vec<FailedPayout>,
+}
+
+pub const PENDING_PAYOUTS: Item<PendingPayouts> = Item::new("pending_payouts");
+
+/// Claims of failed payouts by claim id
+pub const FAILED_PAYOUTS: Map<u64, FailedPayout> = Map::new("failed_payouts");
+
+pub const PAYOUT_COUNT: Item<u64> = Item::new("payout_count");
+
+/// Oracle prices sampled for an averaging option, kept once it is closed
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PriceSamples {
+    pub sum: Decimal,
+    pub count: u32,
+    /// Height of the last sample, at most one is taken a block
+    pub last_height: u64,
+}
+
+pub const PRICE_SAMPLES: Map<u64, PriceSamples> = Map::new("price_samples");
+
+/// Funding of a perpetual option, `payer` pays `rate` every block since `paid_until`
+/// out of `balance`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Funding {
+    pub rate: Coin,
+    pub balance: Uint128,
+    pub paid_until: u64,
+    pub payer: Addr,
+}
+
+pub const FUNDING: Map<u64, Funding> = Map::new("funding");
+
+/// What a vault share earned of `denom` so far
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Earning {
+    pub denom: String,
+    pub per_share: Decimal,
+}
+
+/// Covered call vault pooling deposits of an asset, which its writer writes options on
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Vault {
+    pub writer: Addr,
+    pub denom: String,
+    /// Denom of the counter_offer, asked at `strike` per unit of the asset
+    pub strike_denom: String,
+    pub strike: Decimal,
+    /// Asset not backing an option
+    pub idle: Uint128,
+    /// Asset backing the open options
+    pub locked: Uint128,
+    pub shares: Uint128,
+    /// Premiums and counter_offers earned, other than in the asset itself
+    pub earned: Vec<Earning>,
+}
+
+pub const VAULTS: Map<u64, Vault> = Map::new("vaults");
+
+pub const VAULT_COUNT: Item<u64> = Item::new("vault_count");
+
+/// Shares of a vault depositor
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct VaultPosition {
+    pub shares: Uint128,
+    /// What a share had earned when the depositor last claimed
+    pub claimed: Vec<Earning>,
+}
+
+pub const VAULT_POSITIONS: Map<(u64, &Addr), VaultPosition> = Map::new("vault_positions");
+
+/// Vault and asset amount backing each open vault option
+pub const VAULT_OPTIONS: Map<u64, (u64, Uint128)> = Map::new("vault_options");
+
+/// Option and share supply of the share token being instantiated
+pub const PENDING_SHARES: Item<(u64, Uint128)> = Item::new("pending_shares");
+
+/// Where an IBC voucher comes from, as in the ibc-go transfer module
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DenomTrace {
+    /// Port and channel pairs the token went through, e.g. `transfer/channel-0`
+    pub path: String,
+    pub base_denom: String,
+}
+
+/// Traces registered by the admin, keyed by `ibc/HASH` denom
+pub const DENOM_TRACES: Map<&str, DenomTrace> = Map::new("denom_traces");
+
+/// Channels opened with this contract on another chain
+pub const IBC_CHANNELS: Map<&str, Empty> = Map::new("ibc_channels");
+
+/// Option locked here while its ownership is held on another chain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IbcEscrow {
+    pub channel_id: String,
+    /// Owner before it was sent, who gets it back if the transfer fails
+    pub owner: Addr,
+}
+
+pub const IBC_ESCROWS: Map<u64, IbcEscrow> = Map::new("ibc_escrows");
+
+/// Ownership of an option whose collateral is held on another chain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Voucher {
+    pub owner: Addr,
+    pub collateral: Vec<Coin>,
+    pub counter_offer: Asset,
+    pub expires: Expiration,
+}
+
+/// Vouchers keyed by `(channel_id, option_id)`, where the id is the one on the other chain
+pub const VOUCHERS: Map<(&str, u64), Voucher> = Map::new("vouchers");
+
+/// Operators allowed to manage all options of an owner, keyed by `(owner, operator)`
+pub const OPERATORS: Map<(&Addr, &Addr), Expiration> = Map::new("operators");
+
+/// Option contracts spawned by this one as a factory, keyed by `(creator, instance_id)`
+pub const INSTANCES: Map<(&Addr, u64), Addr> = Map::new("instances");
+pub const INSTANCE_COUNT: Item<u64> = Item::new("instance_count");
+/// Creator and id of the instance being spawned, until its address is known
+pub const PENDING_INSTANCE: Item<(Addr, u64)> = Item::new("pending_instance");
+
+/// Sortable index key for an expiration, heights and times are kept in separate ranges.
+/// Times are stored in nanoseconds.
+pub fn expiration_key(expires: &Expiration) -> (u8, u64) {
+    match expires {
+        Expiration::AtHeight(height) => (0, *height),
+        Expiration::AtTime(time) => (1, time.nanos()),
+        Expiration::Never {} => (2, 0),
+    }
 }
 
-pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
-    singleton_read(storage, CONFIG_KEY)
+/// Bumps `counter` and returns the id to use for whatever it counts. Ids start at 1.
+pub fn next_id(storage: &mut dyn Storage, counter: &Item<u64>) -> StdResult<u64> {
+    let id = counter.may_load(storage)?.unwrap_or_default() + 1;
+    counter.save(storage, &id)?;
+    Ok(id)
 }
@@ -0,0 +1,446 @@
+//! Strategies, options written together as legs of a single position.
+//!
+//! The legs are owned by the contract and move only as the strategy does. Executing the strategy
+//! executes every leg at once, netting what the owner pays against what they receive in each
+//! denom: a leg's counter_offer can be paid out of another leg's collateral, and only the
+//! shortfall is sent along. Each creator is still paid the full counter_offer of their leg.
+
+use std::convert::TryFrom;
+
+use cosmwasm_std::{
+    Addr, BankMsg, Coin, Coins, Deps, DepsMut, Env, Event, MessageInfo, Response, StdError,
+    StdResult, Storage,
+};
+use cw_utils::Expiration;
+
+use crate::blacklist;
+use crate::contract::{
+    archive_option, assert_not_paused, check_exercisable, create_option, protocol_fee_msgs,
+    split_bps, MAX_PARTS,
+};
+use crate::eligibility::assert_eligible;
+use crate::error::ContractError;
+use crate::events::{OptionCreated, OptionExercised};
+use crate::msg::{CreateMsg, LegTerms, StrategyResponse};
+use crate::shares::keep_proceeds;
+use crate::state::{
+    next_id, options, remove_option, Asset, Closure, OptionStyle, Settlement, State, Strategy,
+    CONFIG, STRATEGIES, STRATEGY_COUNT,
+};
+
+fn sum(coins: impl IntoIterator<Item = Coin>) -> StdResult<Coins> {
+    let mut total = Coins::default();
+    for coin in coins {
+        total.add(coin)?;
+    }
+    Ok(total)
+}
+
+/// What the owner receives of the collateral and has to pay on top of it, in every denom,
+/// when executing all of `legs` at once.
+fn net(legs: &[State]) -> StdResult<(Vec<Coin>, Vec<Coin>)> {
+    let collateral = sum(legs.iter().flat_map(|leg| leg.collateral.clone()))?;
+    let counter_offer = sum(legs.iter().flat_map(|leg| match &leg.counter_offer {
+        Asset::Native(coins) => coins.clone(),
+        Asset::Cw20(_) => unreachable!("strategy legs have native counter_offers"),
+    }))?;
+    let mut receive = vec![];
+    let mut pay = vec![];
+    let mut denoms: Vec<String> = collateral.denoms().into_iter().collect();
+    denoms.extend(counter_offer.denoms());
+    denoms.sort();
+    denoms.dedup();
+    for denom in denoms {
+        let (has, owes) = (
+            collateral.amount_of(&denom),
+            counter_offer.amount_of(&denom),
+        );
+        if has > owes {
+            receive.push(Coin::new((has - owes).u128(), &denom));
+        } else if owes > has {
+            pay.push(Coin::new((owes - has).u128(), &denom));
+        }
+    }
+    Ok((receive, pay))
+}
+
+/// Anyone can write a strategy, sending exactly the collateral of all its legs. The legs share
+/// their expiry and style and are written to `owner`, the sender by default.
+pub fn handle_create_strategy(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    legs: Vec<LegTerms>,
+    expires: Expiration,
+    style: OptionStyle,
+    owner: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    if !(2..=MAX_PARTS as usize).contains(&legs.len()) {
+        return Err(ContractError::InvalidStrategy {});
+    }
+    // token holders could execute the legs one by one
+    if CONFIG.load(deps.storage)?.tokenfactory {
+        return Err(ContractError::TokenizedOption {});
+    }
+    let needed = sum(legs.iter().flat_map(|leg| leg.collateral.clone()))?;
+    let sent = Coins::try_from(info.funds).map_err(StdError::from)?;
+    if needed != sent {
+        return Err(ContractError::InvalidStrategy {});
+    }
+    let owner = match owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+
+    let mut strategy = Strategy {
+        creator: info.sender.clone(),
+        owner,
+        option_ids: vec![],
+    };
+    let mut events = vec![];
+    for leg in legs {
+        let writer = MessageInfo {
+            sender: info.sender.clone(),
+            funds: leg.collateral,
+        };
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(leg.counter_offer),
+            expires,
+            premium: vec![],
+            settlement: Settlement::Physical,
+            style,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: Some(env.contract.address.to_string()),
+        };
+        let (option_id, state) = create_option(deps.branch(), &env, writer, terms)?;
+        events.push(Event::from(OptionCreated::new(option_id, &state)));
+        strategy.option_ids.push(option_id);
+    }
+    let strategy_id = next_id(deps.storage, &STRATEGY_COUNT)?;
+    STRATEGIES.save(deps.storage, strategy_id, &strategy)?;
+
+    let option_ids: Vec<String> = strategy.option_ids.iter().map(u64::to_string).collect();
+    Ok(Response::new()
+        .add_attribute("action", "create_strategy")
+        .add_attribute("strategy_id", strategy_id.to_string())
+        .add_attribute("creator", strategy.creator)
+        .add_attribute("owner", strategy.owner)
+        .add_attribute("option_ids", option_ids.join(","))
+        .add_events(events))
+}
+
+/// The response to the sender's attempt at `action` if they or `recipient` are blacklisted
+fn blocked(
+    storage: &dyn Storage,
+    info: &MessageInfo,
+    action: &str,
+    strategy_id: u64,
+    recipient: &Addr,
+) -> StdResult<Option<Response>> {
+    match blacklist::listed(storage, &[&info.sender, recipient]) {
+        Some(address) => {
+            let event =
+                blacklist::blocked_event(action, "strategy_id", strategy_id, address.as_str());
+            let paid = Asset::Native(info.funds.clone());
+            blacklist::blocked_response(event, &info.sender, paid).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Owner passes the whole strategy on to `recipient`
+pub fn handle_transfer_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    strategy_id: u64,
+    recipient: String,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut strategy = STRATEGIES.load(deps.storage, strategy_id)?;
+    if info.sender != strategy.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let recipient = deps.api.addr_validate(&recipient)?;
+    if let Some(res) = blocked(
+        deps.storage,
+        &info,
+        "transfer_strategy",
+        strategy_id,
+        &recipient,
+    )? {
+        return Ok(res);
+    }
+    strategy.owner = recipient;
+    assert_eligible(&deps.querier, &CONFIG.load(deps.storage)?, &strategy.owner)?;
+    STRATEGIES.save(deps.storage, strategy_id, &strategy)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_strategy")
+        .add_attribute("strategy_id", strategy_id.to_string())
+        .add_attribute("owner", strategy.owner))
+}
+
+/// Owner executes every leg at once, sending only the net shortfall of counter_offer
+pub fn handle_execute_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    strategy_id: u64,
+) -> Result<Response, ContractError> {
+    let strategy = STRATEGIES.load(deps.storage, strategy_id)?;
+    if info.sender != strategy.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if let Some(res) = blocked(
+        deps.storage,
+        &info,
+        "execute_strategy",
+        strategy_id,
+        &info.sender,
+    )? {
+        return Ok(res);
+    }
+    let mut legs = vec![];
+    for option_id in &strategy.option_ids {
+        // a leg burned after expiry leaves nothing to execute
+        let state = options()
+            .may_load(deps.storage, *option_id)?
+            .ok_or(ContractError::StrategyClosed {})?;
+        check_exercisable(
+            deps.as_ref(),
+            &env,
+            &env.contract.address,
+            *option_id,
+            &state,
+        )?;
+        legs.push(state);
+    }
+    let (receive, pay) = net(&legs)?;
+    if sum(info.funds.clone())? != sum(pay.clone())? {
+        return Err(ContractError::CounterOfferMismatch {
+            offer: Asset::Native(info.funds),
+            expected: Asset::Native(pay),
+        });
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let mut res = Response::new()
+        .add_attribute("action", "execute_strategy")
+        .add_attribute("strategy_id", strategy_id.to_string());
+    for (option_id, state) in strategy.option_ids.iter().copied().zip(legs) {
+        let archived = archive_option(
+            deps.storage,
+            &env,
+            option_id,
+            &state,
+            Some(&info.sender),
+            Closure::Executed,
+            Some(state.counter_offer.clone()),
+        )?;
+        remove_option(deps.storage, option_id)?;
+        let payout = state.collateral_list();
+        let exercised = OptionExercised::new(option_id, &info.sender, &info.sender, &state, payout);
+        res = res.add_event(exercised.into()).add_event(archived.into());
+        let counter_offer = match state.counter_offer {
+            Asset::Native(coins) => coins,
+            Asset::Cw20(_) => unreachable!("strategy legs have native counter_offers"),
+        };
+        let (fee, counter_offer) = split_bps(counter_offer, config.fee_bps);
+        let fee = (!fee.is_empty()).then_some(Asset::Native(fee));
+        let paid = Response::new()
+            .add_message(BankMsg::Send {
+                to_address: state.creator.to_string(),
+                amount: counter_offer,
+            })
+            .add_messages(protocol_fee_msgs(deps.storage, &config, None, fee)?);
+        let paid = keep_proceeds(deps.storage, &env, option_id, paid)?;
+        res = res.add_submessages(paid.messages);
+    }
+    if !receive.is_empty() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: receive,
+        });
+    }
+    Ok(res)
+}
+
+pub fn query_strategy(deps: Deps, strategy_id: u64) -> StdResult<StrategyResponse> {
+    let strategy = STRATEGIES.load(deps.storage, strategy_id)?;
+    let legs = strategy
+        .option_ids
+        .iter()
+        .filter_map(|id| options().may_load(deps.storage, *id).transpose())
+        .collect::<StdResult<Vec<State>>>()?;
+    let collateral = sum(legs.iter().flat_map(|leg| leg.collateral.clone()))?;
+    let (receive, pay) = net(&legs)?;
+    Ok(StrategyResponse {
+        strategy,
+        collateral: collateral.into_vec(),
+        receive,
+        pay,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::execute;
+    use crate::msg::ExecuteMsg;
+    use crate::testing::{blacklist, setup};
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coin, coins, CosmosMsg, OwnedDeps};
+
+    /// A call and a put on 1 BTC, owned by `owner`
+    fn strategy(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, owner: &str) {
+        let msg = ExecuteMsg::CreateStrategy {
+            legs: vec![
+                LegTerms {
+                    collateral: coins(1, "BTC"),
+                    counter_offer: coins(40, "ETH"),
+                },
+                LegTerms {
+                    collateral: coins(50, "ETH"),
+                    counter_offer: coins(1, "BTC"),
+                },
+            ],
+            expires: Expiration::AtHeight(100_000),
+            style: OptionStyle::American,
+            owner: Some(owner.to_string()),
+        };
+        let info = mock_info("maker", &[coin(1, "BTC"), coin(50, "ETH")]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    #[test]
+    fn blacklisted_transfer() {
+        let mut deps = setup();
+        strategy(&mut deps, "owner");
+        blacklist(&mut deps, "sanctioned");
+
+        let transfer = ExecuteMsg::TransferStrategy {
+            strategy_id: 1,
+            recipient: "sanctioned".to_string(),
+        };
+        let info = mock_info("owner", &coins(1, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, transfer).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(1, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        let strategy = query_strategy(deps.as_ref(), 1).unwrap();
+        assert_eq!("owner", strategy.strategy.owner);
+    }
+
+    #[test]
+    fn blacklisted_execute() {
+        let mut deps = setup();
+        strategy(&mut deps, "sanctioned");
+        blacklist(&mut deps, "sanctioned");
+
+        let msg = ExecuteMsg::ExecuteStrategy { strategy_id: 1 };
+        let info = mock_info("sanctioned", &coins(40, "ETH"));
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!("blacklist_blocked", res.events[0].ty);
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "sanctioned".into(),
+                amount: coins(40, "ETH"),
+            }),
+            res.messages[0].msg
+        );
+        assert!(options().has(&deps.storage, 2));
+    }
+
+    #[test]
+    fn executes_legs_net() {
+        let mut deps = setup();
+
+        // a call buying 1 BTC for 40 ETH, and a put selling it for 50 ETH
+        let msg = ExecuteMsg::CreateStrategy {
+            legs: vec![
+                LegTerms {
+                    collateral: coins(1, "BTC"),
+                    counter_offer: coins(40, "ETH"),
+                },
+                LegTerms {
+                    collateral: coins(50, "ETH"),
+                    counter_offer: coins(1, "BTC"),
+                },
+            ],
+            expires: Expiration::AtHeight(100_000),
+            style: OptionStyle::American,
+            owner: Some("owner".to_string()),
+        };
+        let info = mock_info("maker", &[coin(1, "BTC"), coin(40, "ETH")]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStrategy {}));
+        let info = mock_info("maker", &[coin(1, "BTC"), coin(50, "ETH")]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let strategy = query_strategy(deps.as_ref(), 1).unwrap();
+        assert_eq!(vec![2, 3], strategy.strategy.option_ids);
+        assert_eq!(vec![coin(1, "BTC"), coin(50, "ETH")], strategy.collateral);
+        assert_eq!(coins(10, "ETH"), strategy.receive);
+        assert!(strategy.pay.is_empty());
+
+        // the legs only move together
+        let execute_leg = ExecuteMsg::Execute {
+            option_id: 2,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, execute_leg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let transfer = ExecuteMsg::TransferStrategy {
+            strategy_id: 1,
+            recipient: "bob".to_string(),
+        };
+        let info = mock_info("owner", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, transfer).unwrap();
+
+        // the put pays for the call, nothing needs to be sent
+        let msg = ExecuteMsg::ExecuteStrategy { strategy_id: 1 };
+        let info = mock_info("owner", &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let info = mock_info("bob", &coins(40, "ETH"));
+        let err = execute(deps.as_mut(), mock_env(), info, msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CounterOfferMismatch { .. }));
+        let info = mock_info("bob", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), msg.clone()).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        let sent = |to: &str, amount| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.into(),
+                amount,
+            })
+        };
+        assert_eq!(
+            vec![
+                sent("maker", coins(40, "ETH")),
+                sent("maker", coins(1, "BTC")),
+                sent("bob", coins(10, "ETH")),
+            ],
+            msgs
+        );
+        assert!(!options().has(&deps.storage, 2));
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::StrategyClosed {}));
+    }
+}
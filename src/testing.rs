@@ -0,0 +1,73 @@
+//! Fixtures shared by the unit tests.
+
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{
+    coins, to_json_binary, ContractResult, Decimal, OwnedDeps, SystemResult, WasmQuery,
+};
+use cw_utils::Expiration;
+
+use crate::contract::{execute, instantiate};
+use crate::msg::{ExecuteMsg, InstantiateMsg};
+use crate::oracle::PriceResponse;
+use crate::state::{Asset, OptionStyle, Settlement};
+
+/// An American option of 40 ETH, expiring at height 100_000, with every feature and fee off.
+/// Tests override the fields they need with `..instantiate_msg()`.
+pub fn instantiate_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        counter_offer: Asset::Native(coins(40, "ETH")),
+        expires: Expiration::AtHeight(100_000),
+        premium: vec![],
+        settlement: Settlement::Physical,
+        style: OptionStyle::American,
+        royalty_bps: 0,
+        roll_on_expiry: false,
+        barrier: None,
+        settlement_window: None,
+        exercisable_from: None,
+        alt_counter_offers: vec![],
+        strike: None,
+        allowed_taker: None,
+        owner: None,
+        burn_reward_bps: 0,
+        fee_bps: 0,
+        fee_collector: None,
+        admin: None,
+        guardian: None,
+        dao: None,
+        allowed_denoms: vec![],
+        oracle: None,
+        archive_retention: 0,
+        tokenfactory: false,
+        creator: None,
+    }
+}
+
+/// The contract instantiated by `creator` with `instantiate_msg`, writing option 1 for 1 BTC
+pub fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+    let mut deps = mock_dependencies();
+    let info = mock_info("creator", &coins(1, "BTC"));
+    let _ = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg()).unwrap();
+    deps
+}
+
+/// Puts `addr` on the blacklist, as the admin of `setup`
+pub fn blacklist(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, addr: &str) {
+    let msg = ExecuteMsg::UpdateBlacklist {
+        add: vec![addr.to_string()],
+        remove: vec![],
+    };
+    let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+}
+
+/// Answers price queries to the `oracle` contract with `rate`
+pub fn mock_oracle(querier: &mut MockQuerier, rate: Decimal) {
+    querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, .. } if contract_addr == "oracle" => SystemResult::Ok(
+            ContractResult::Ok(to_json_binary(&PriceResponse { rate }).unwrap()),
+        ),
+        _ => panic!("unexpected query: {:?}", query),
+    });
+}
@@ -0,0 +1,195 @@
+//! Timelock on the config changes traders rely on.
+//!
+//! With a timelock configured, admin changes to the fees and who is exempt from them, the burn
+//! reward, the maintenance margin, the oracle, the denom whitelist or the timelock itself are
+//! queued instead of applied, and can only be executed once the timelock elapsed. Everyone
+//! gets that long to exit before parameters change under them.
+//! The admin, or the DAO when there is one, can cancel a queued change until it is executed.
+
+use cosmwasm_std::{
+    Api, Attribute, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage,
+};
+
+use crate::contract::apply_config_update;
+use crate::error::ContractError;
+use crate::msg::{QueuedConfigsResponse, UpdateConfigMsg};
+use crate::state::{next_id, Config, QueuedConfig, CONFIG, QUEUED_CONFIGS, QUEUED_COUNT};
+
+/// Takes the changes behind the timelock out of `msg`, if there are any.
+pub(crate) fn take_timelocked(msg: &mut UpdateConfigMsg) -> Option<UpdateConfigMsg> {
+    let timelocked = UpdateConfigMsg {
+        fee_bps: msg.fee_bps.take(),
+        fee_collector: msg.fee_collector.take(),
+        fee_exempt: msg.fee_exempt.take(),
+        transfer_fee: msg.transfer_fee.take(),
+        referral_bps: msg.referral_bps.take(),
+        insurance_bps: msg.insurance_bps.take(),
+        burn_reward_bps: msg.burn_reward_bps.take(),
+        maintenance_margin_bps: msg.maintenance_margin_bps.take(),
+        oracle: msg.oracle.take(),
+        allowed_denoms: msg.allowed_denoms.take(),
+        config_timelock: msg.config_timelock.take(),
+        ..UpdateConfigMsg::default()
+    };
+    (timelocked != UpdateConfigMsg::default()).then_some(timelocked)
+}
+
+/// Queues `changes` until the timelock elapsed, returning the attributes describing them.
+/// They are checked against the current config right away.
+pub(crate) fn queue(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    env: &Env,
+    config: &Config,
+    changes: UpdateConfigMsg,
+) -> Result<Vec<Attribute>, ContractError> {
+    apply_config_update(api, &mut config.clone(), changes.clone())?;
+    let id = next_id(storage, &QUEUED_COUNT)?;
+    let queued = QueuedConfig {
+        id,
+        changes,
+        executable_at: env.block.time.plus_seconds(config.config_timelock),
+    };
+    QUEUED_CONFIGS.save(storage, id, &queued)?;
+    Ok(vec![
+        Attribute::new("queued_id", id.to_string()),
+        Attribute::new("executable_at", queued.executable_at.to_string()),
+    ])
+}
+
+/// Anyone can apply a queued config change once the timelock elapsed
+pub fn handle_execute_queued(
+    deps: DepsMut,
+    env: Env,
+    queued_id: u64,
+) -> Result<Response, ContractError> {
+    let queued = QUEUED_CONFIGS.load(deps.storage, queued_id)?;
+    if env.block.time < queued.executable_at {
+        return Err(ContractError::TimelockNotElapsed {
+            executable_at: queued.executable_at,
+        });
+    }
+    let mut config = CONFIG.load(deps.storage)?;
+    apply_config_update(deps.api, &mut config, queued.changes)?;
+    CONFIG.save(deps.storage, &config)?;
+    QUEUED_CONFIGS.remove(deps.storage, queued_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "execute_queued")
+        .add_attribute("queued_id", queued_id.to_string()))
+}
+
+/// The DAO, or the admin without one, drops a queued config change
+pub fn handle_cancel_queued(
+    deps: DepsMut,
+    info: MessageInfo,
+    queued_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != *config.dao.as_ref().unwrap_or(&config.admin) {
+        return Err(ContractError::Unauthorized {});
+    }
+    QUEUED_CONFIGS.load(deps.storage, queued_id)?;
+    QUEUED_CONFIGS.remove(deps.storage, queued_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_queued")
+        .add_attribute("queued_id", queued_id.to_string()))
+}
+
+pub fn query_queued_configs(deps: Deps) -> StdResult<QueuedConfigsResponse> {
+    QUEUED_CONFIGS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, queued)| queued))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::execute;
+    use crate::msg::ExecuteMsg;
+    use crate::testing::setup;
+    use cosmwasm_std::testing::{mock_env, mock_info};
+
+    #[test]
+    fn queue_execute_and_cancel() {
+        let mut deps = setup();
+        let admin = mock_info("creator", &[]);
+        let update = |changes: UpdateConfigMsg| ExecuteMsg::UpdateConfig(changes);
+
+        // without a timelock changes apply right away, including setting one of up to a year
+        let changes = UpdateConfigMsg {
+            config_timelock: Some(365 * 24 * 3600 + 1),
+            ..UpdateConfigMsg::default()
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), update(changes)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidTimelock { .. }));
+        let changes = UpdateConfigMsg {
+            config_timelock: Some(3_600),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), update(changes)).unwrap();
+        assert_eq!(3_600, CONFIG.load(&deps.storage).unwrap().config_timelock);
+
+        // fee changes are queued, the rest still applies, and invalid changes aren't queued
+        let changes = UpdateConfigMsg {
+            fee_bps: Some(10_001),
+            ..UpdateConfigMsg::default()
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), update(changes)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidBps { .. }));
+        let changes = UpdateConfigMsg {
+            fee_bps: Some(100),
+            burn_reward_bps: Some(50),
+            archive_retention: Some(60),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), update(changes)).unwrap();
+        let config = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(
+            (0, 0, 60),
+            (
+                config.fee_bps,
+                config.burn_reward_bps,
+                config.archive_retention
+            )
+        );
+        let queued = query_queued_configs(deps.as_ref()).unwrap();
+        assert_eq!(1, queued.len());
+        assert_eq!(Some(100), queued[0].changes.fee_bps);
+        assert_eq!(Some(50), queued[0].changes.burn_reward_bps);
+        assert_eq!(None, queued[0].changes.archive_retention);
+
+        // anyone executes it, but only once the timelock elapsed
+        let anyone = mock_info("anyone", &[]);
+        let execute_queued = |queued_id| ExecuteMsg::ExecuteQueued { queued_id };
+        let err =
+            execute(deps.as_mut(), mock_env(), anyone.clone(), execute_queued(1)).unwrap_err();
+        assert!(matches!(err, ContractError::TimelockNotElapsed { .. }));
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(3_600);
+        let _ = execute(
+            deps.as_mut(),
+            later.clone(),
+            anyone.clone(),
+            execute_queued(1),
+        )
+        .unwrap();
+        assert_eq!(100, CONFIG.load(&deps.storage).unwrap().fee_bps);
+        assert!(query_queued_configs(deps.as_ref()).unwrap().is_empty());
+
+        // only the admin cancels
+        let changes = UpdateConfigMsg {
+            oracle: Some("oracle".to_string()),
+            ..UpdateConfigMsg::default()
+        };
+        let _ = execute(deps.as_mut(), mock_env(), admin.clone(), update(changes)).unwrap();
+        let cancel = ExecuteMsg::CancelQueued { queued_id: 2 };
+        let err = execute(deps.as_mut(), mock_env(), anyone.clone(), cancel.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), admin, cancel).unwrap();
+        let _ = execute(deps.as_mut(), later, anyone, execute_queued(2)).unwrap_err();
+        assert_eq!(None, CONFIG.load(&deps.storage).unwrap().oracle);
+    }
+}
@@ -0,0 +1,135 @@
+//! Represents options by an x/tokenfactory denom, so they can be traded as regular bank tokens.
+//!
+//! A tokenized option is owned by the contract itself, whoever holds its single
+//! `factory/{contract}/option{id}` token can execute it by sending the token along with the
+//! counter_offer. The token is burned when the option is executed.
+
+use cosmwasm_std::{Binary, Coin, CosmosMsg, Env};
+
+use crate::error::ContractError;
+use crate::state::{Asset, State};
+
+const TYPE_URL_PREFIX: &str = "/osmosis.tokenfactory.v1beta1";
+
+pub fn subdenom(option_id: u64) -> String {
+    format!("option{}", option_id)
+}
+
+pub fn option_denom(env: &Env, option_id: u64) -> String {
+    format!("factory/{}/{}", env.contract.address, subdenom(option_id))
+}
+
+/// Creates the option's denom and mints its token to the creator, nothing for untokenized options
+pub fn mint_msgs(env: &Env, option_id: u64, state: &State) -> Vec<CosmosMsg> {
+    let denom = match &state.denom {
+        Some(denom) => denom,
+        None => return vec![],
+    };
+    let contract = env.contract.address.as_str();
+
+    let mut create = Proto::default();
+    create.string(1, contract).string(2, &subdenom(option_id));
+    let mut mint = Proto::default();
+    mint.string(1, contract)
+        .message(2, &coin(denom))
+        .string(3, state.creator.as_str());
+    vec![create.into_msg("MsgCreateDenom"), mint.into_msg("MsgMint")]
+}
+
+/// Burns the token the holder sent in to execute the option
+pub fn burn_msg(env: &Env, denom: &str) -> CosmosMsg {
+    let contract = env.contract.address.as_str();
+    let mut burn = Proto::default();
+    burn.string(1, contract)
+        .message(2, &coin(denom))
+        .string(3, contract);
+    burn.into_msg("MsgBurn")
+}
+
+/// Takes the option's token out of `payment`, which has to hold it for tokenized options
+pub fn take_token(state: &State, payment: Asset) -> Result<Asset, ContractError> {
+    let denom = match &state.denom {
+        Some(denom) => denom,
+        None => return Ok(payment),
+    };
+    let missing = || ContractError::OptionTokenRequired {
+        denom: denom.clone(),
+    };
+    match payment {
+        Asset::Native(mut coins) => {
+            let pos = coins
+                .iter()
+                .position(|c| &c.denom == denom && c.amount.u128() == 1)
+                .ok_or_else(missing)?;
+            coins.remove(pos);
+            Ok(Asset::Native(coins))
+        }
+        Asset::Cw20(_) => Err(missing()),
+    }
+}
+
+fn coin(denom: &str) -> Proto {
+    let coin = Coin::new(1, denom);
+    let mut proto = Proto::default();
+    proto
+        .string(1, &coin.denom)
+        .string(2, &coin.amount.to_string());
+    proto
+}
+
+/// Just enough protobuf encoding for the tokenfactory messages, which only hold strings
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn bytes(&mut self, field: u64, bytes: &[u8]) -> &mut Self {
+        // wire type 2, length delimited
+        self.varint(field << 3 | 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn string(&mut self, field: u64, value: &str) -> &mut Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(&mut self, field: u64, value: &Proto) -> &mut Self {
+        self.bytes(field, &value.0)
+    }
+
+    fn into_msg(self, name: &str) -> CosmosMsg {
+        CosmosMsg::Stargate {
+            type_url: format!("{}.{}", TYPE_URL_PREFIX, name),
+            value: Binary::from(self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let mut proto = Proto::default();
+        proto.string(1, "ab").message(2, &coin("c"));
+        assert_eq!(
+            vec![0x0a, 2, b'a', b'b', 0x12, 6, 0x0a, 1, b'c', 0x12, 1, b'1'],
+            proto.0
+        );
+
+        // lengths over 127 take two bytes
+        let mut proto = Proto::default();
+        proto.string(3, &"x".repeat(200));
+        assert_eq!(vec![0x1a, 0xc8, 0x01], proto.0[..3].to_vec());
+    }
+}
@@ -0,0 +1,213 @@
+//! Averaging settlement, where options settle against the average of the oracle prices keepers
+//! sampled in a window before expiry instead of a single spot reading.
+
+use cosmwasm_std::{Decimal, Deps, DepsMut, Env, Response, StdResult};
+use cw_utils::Expiration;
+
+use crate::contract::{assert_not_paused, option_attributes};
+use crate::error::ContractError;
+use crate::msg::PriceSamplesResponse;
+use crate::oracle::query_collateral_price;
+use crate::state::{options, Config, PriceSamples, Settlement, State, CONFIG, PRICE_SAMPLES};
+
+/// Opening of the sampling window, `window` blocks or seconds before expiry.
+fn window_start(expires: &Expiration, window: u64) -> Expiration {
+    match expires {
+        Expiration::AtHeight(height) => Expiration::AtHeight(height.saturating_sub(window)),
+        Expiration::AtTime(time) => Expiration::AtTime(time.minus_seconds(window)),
+        Expiration::Never {} => Expiration::Never {},
+    }
+}
+
+/// Anyone can add the current oracle price to the samples of an averaging option,
+/// once a block while its sampling window is open
+pub fn handle_sample_price(
+    deps: DepsMut,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    let window = match state.settlement {
+        Settlement::Average { window } => window,
+        _ => return Err(ContractError::Unauthorized {}),
+    };
+    let start = window_start(&state.expires, window);
+    if !start.is_expired(&env.block) || state.expires.is_expired(&env.block) {
+        return Err(ContractError::OutsideSamplingWindow {
+            start,
+            end: state.expires,
+        });
+    }
+    let mut samples = PRICE_SAMPLES
+        .may_load(deps.storage, option_id)?
+        .unwrap_or_default();
+    if samples.count > 0 && samples.last_height == env.block.height {
+        return Err(ContractError::AlreadySampled {});
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let spot = query_collateral_price(&deps.querier, config.oracle.as_ref(), &state)?;
+    samples.sum += spot;
+    samples.count += 1;
+    samples.last_height = env.block.height;
+    PRICE_SAMPLES.save(deps.storage, option_id, &samples)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "sample_price")
+        .add_attribute("spot", spot.to_string())
+        .add_attribute("samples", samples.count.to_string())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+fn average(samples: &PriceSamples) -> Option<Decimal> {
+    (samples.count > 0).then(|| samples.sum / Decimal::from_ratio(samples.count, 1u32))
+}
+
+/// Price an option settles against, the average of its samples for averaging options,
+/// or the spot price when there are none.
+pub(crate) fn settlement_price(
+    deps: Deps,
+    config: &Config,
+    option_id: u64,
+    state: &State,
+) -> Result<Decimal, ContractError> {
+    if let Settlement::Average { .. } = state.settlement {
+        let samples = PRICE_SAMPLES.may_load(deps.storage, option_id)?;
+        if let Some(average) = samples.as_ref().and_then(average) {
+            return Ok(average);
+        }
+    }
+    query_collateral_price(&deps.querier, config.oracle.as_ref(), state)
+}
+
+pub fn query_price_samples(deps: Deps, option_id: u64) -> StdResult<PriceSamplesResponse> {
+    let samples = PRICE_SAMPLES
+        .may_load(deps.storage, option_id)?
+        .unwrap_or_default();
+    Ok(PriceSamplesResponse {
+        samples: samples.count,
+        average: average(&samples),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate};
+    use crate::msg::{CreateMsg, ExecuteMsg, InstantiateMsg};
+    use crate::state::{Asset, OptionStyle};
+    use crate::testing::{instantiate_msg, mock_oracle};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coins, BankMsg, CosmosMsg};
+
+    fn env_at(height: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    }
+
+    #[test]
+    fn settles_on_average() {
+        let mut deps = mock_dependencies();
+        let settlement = Settlement::Average { window: 100 };
+        let msg = InstantiateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            settlement,
+            style: OptionStyle::European,
+            owner: Some("owner".to_string()),
+            oracle: Some("oracle".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // American options could be executed before the average is in
+        let terms = CreateMsg {
+            counter_offer: Asset::Native(coins(400, "ETH")),
+            expires: Expiration::AtHeight(100_000),
+            premium: vec![],
+            settlement,
+            style: OptionStyle::American,
+            royalty_bps: 0,
+            roll_on_expiry: false,
+            barrier: None,
+            settlement_window: None,
+            exercisable_from: None,
+            alt_counter_offers: vec![],
+            strike: None,
+            allowed_taker: None,
+            owner: None,
+        };
+        let info = mock_info("creator", &coins(10, "BTC"));
+        let err = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Create(terms)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidAveraging {}));
+
+        let sample = ExecuteMsg::SamplePrice { option_id: 1 };
+        let keeper = mock_info("keeper", &[]);
+        mock_oracle(&mut deps.querier, Decimal::percent(5_000));
+        let err = execute(
+            deps.as_mut(),
+            env_at(99_899),
+            keeper.clone(),
+            sample.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::OutsideSamplingWindow { .. }));
+        let _ = execute(
+            deps.as_mut(),
+            env_at(99_900),
+            keeper.clone(),
+            sample.clone(),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env_at(99_900),
+            keeper.clone(),
+            sample.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::AlreadySampled {}));
+        mock_oracle(&mut deps.querier, Decimal::percent(7_000));
+        let _ = execute(
+            deps.as_mut(),
+            env_at(99_950),
+            keeper.clone(),
+            sample.clone(),
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), env_at(100_000), keeper, sample).unwrap_err();
+        assert!(matches!(err, ContractError::OutsideSamplingWindow { .. }));
+
+        let samples = query_price_samples(deps.as_ref(), 1).unwrap();
+        assert_eq!(2, samples.samples);
+        assert_eq!(Some(Decimal::percent(6_000)), samples.average);
+
+        // a crash at expiry doesn't matter, 10 BTC at 60 ETH leaves 3 BTC over the 400 ETH strike
+        mock_oracle(&mut deps.querier, Decimal::percent(3_000));
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), env_at(100_000), info, execute_msg).unwrap();
+        let msgs: Vec<CosmosMsg> = res.messages.into_iter().map(|m| m.msg).collect();
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "creator".into(),
+                    amount: coins(7, "BTC"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "owner".into(),
+                    amount: coins(3, "BTC"),
+                }),
+            ],
+            msgs
+        );
+    }
+}
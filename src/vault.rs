@@ -0,0 +1,506 @@
+//! Covered call vaults.
+//!
+//! Depositors pool an asset for shares of the vault. Its writer writes options on the idle
+//! asset at the strike set by the admin, created by the contract itself and listed for a
+//! premium. Whatever those options pay their creator stays in the vault: the collateral coming
+//! back on burn returns to the idle asset, premiums and counter_offers in other denoms are
+//! earned by the shares and claimed by depositors. Deposits and withdrawals wait for the
+//! options written in a round to close, so the idle asset is all a share is redeemed for.
+
+use std::slice;
+
+use cosmwasm_std::{
+    coins, Addr, BankMsg, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    StdResult, Storage, Uint128,
+};
+use cw_utils::Expiration;
+
+use crate::contract::{assert_not_paused, create_option, option_attributes};
+use crate::denom::validate_denom;
+use crate::error::ContractError;
+use crate::events::OptionCreated;
+use crate::msg::{CreateMsg, VaultPositionResponse, VaultResponse};
+use crate::shares::take_proceeds;
+use crate::state::{
+    next_id, options, save_held, track, Asset, Earning, Held, Ledger, OptionStyle, Settlement,
+    Vault, VaultPosition, CONFIG, VAULTS, VAULT_COUNT, VAULT_OPTIONS, VAULT_POSITIONS,
+};
+
+fn assert_admin(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    if *sender != CONFIG.load(deps.storage)?.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+/// What the shares of `position` earned since it last claimed, marking it claimed.
+fn claim_earnings(vault: &Vault, position: &mut VaultPosition) -> Vec<Coin> {
+    let earnings = vault
+        .earned
+        .iter()
+        .filter_map(|earning| {
+            let claimed = position
+                .claimed
+                .iter()
+                .find(|c| c.denom == earning.denom)
+                .map_or(Decimal::zero(), |c| c.per_share);
+            let amount = position.shares * (earning.per_share - claimed);
+            (!amount.is_zero()).then(|| Coin::new(amount.u128(), &earning.denom))
+        })
+        .collect();
+    position.claimed = vault.earned.clone();
+    earnings
+}
+
+/// What the vault holds for its depositors: the idle asset and the earnings not yet claimed.
+pub(crate) fn held(storage: &dyn Storage, vault_id: u64) -> StdResult<Vec<Coin>> {
+    let vault = match VAULTS.may_load(storage, vault_id)? {
+        Some(vault) => vault,
+        None => return Ok(vec![]),
+    };
+    let mut held = vault.held();
+    for item in VAULT_POSITIONS
+        .prefix(vault_id)
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (_, mut position) = item?;
+        held.extend(claim_earnings(&vault, &mut position));
+    }
+    Ok(held)
+}
+
+fn pay(res: Response, to: &Addr, amount: Vec<Coin>) -> Response {
+    if amount.is_empty() {
+        return res;
+    }
+    res.add_message(BankMsg::Send {
+        to_address: to.to_string(),
+        amount,
+    })
+}
+
+fn vault_attributes(res: Response, vault_id: u64, vault: &Vault) -> Response {
+    res.add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("idle", vault.idle)
+        .add_attribute("locked", vault.locked)
+        .add_attribute("shares", vault.shares)
+}
+
+/// Admin opens a vault pooling `denom`, whose options `writer` writes
+pub fn handle_create_vault(
+    deps: DepsMut,
+    info: MessageInfo,
+    writer: String,
+    denom: String,
+    strike_denom: String,
+    strike: Decimal,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info.sender)?;
+    validate_denom(&denom)?;
+    validate_denom(&strike_denom)?;
+    if denom == strike_denom || strike.is_zero() {
+        return Err(ContractError::InvalidVault {});
+    }
+
+    let vault = Vault {
+        writer: deps.api.addr_validate(&writer)?,
+        denom,
+        strike_denom,
+        strike,
+        idle: Uint128::zero(),
+        locked: Uint128::zero(),
+        shares: Uint128::zero(),
+        earned: vec![],
+    };
+    let vault_id = next_id(deps.storage, &VAULT_COUNT)?;
+    save_held(deps.storage, &VAULTS, vault_id, &vault)?;
+
+    let res = Response::new()
+        .add_attribute("action", "create_vault")
+        .add_attribute("writer", &vault.writer)
+        .add_attribute("strike", strike.to_string());
+    Ok(vault_attributes(res, vault_id, &vault))
+}
+
+/// Admin sets the strike the vault's next options are written at
+pub fn handle_set_vault_strike(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    strike: Decimal,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info.sender)?;
+    let mut vault = VAULTS.load(deps.storage, vault_id)?;
+    if strike.is_zero() {
+        return Err(ContractError::InvalidVault {});
+    }
+    vault.strike = strike;
+    save_held(deps.storage, &VAULTS, vault_id, &vault)?;
+
+    let res = Response::new()
+        .add_attribute("action", "set_vault_strike")
+        .add_attribute("strike", strike.to_string());
+    Ok(vault_attributes(res, vault_id, &vault))
+}
+
+/// Mints shares for the asset sent, at the idle asset per share
+pub fn handle_deposit_vault(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut vault = VAULTS.load(deps.storage, vault_id)?;
+    if !vault.locked.is_zero() {
+        return Err(ContractError::VaultRoundOpen {});
+    }
+    let amount = match info.funds.as_slice() {
+        [coin] if coin.denom == vault.denom => coin.amount,
+        _ => return Err(ContractError::InvalidDeposit {}),
+    };
+    let shares = if vault.shares.is_zero() {
+        amount
+    } else if vault.idle.is_zero() {
+        // the shares left are only owed earnings, they can't price new ones
+        return Err(ContractError::InvalidDeposit {});
+    } else {
+        amount.multiply_ratio(vault.shares, vault.idle)
+    };
+    if shares.is_zero() {
+        return Err(ContractError::InvalidDeposit {});
+    }
+
+    let key = (vault_id, &info.sender);
+    let mut position = VAULT_POSITIONS
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    let earnings = claim_earnings(&vault, &mut position);
+    track(deps.storage, Ledger::Vaults, vault_id, &earnings, &[])?;
+    position.shares += shares;
+    vault.shares += shares;
+    vault.idle += amount;
+    VAULT_POSITIONS.save(deps.storage, key, &position)?;
+    save_held(deps.storage, &VAULTS, vault_id, &vault)?;
+
+    let res = Response::new()
+        .add_attribute("action", "deposit_vault")
+        .add_attribute("depositor", &info.sender)
+        .add_attribute("minted", shares);
+    let res = pay(res, &info.sender, earnings);
+    Ok(vault_attributes(res, vault_id, &vault))
+}
+
+/// Burns `shares` of the sender for their part of the idle asset, along with their earnings
+pub fn handle_withdraw_vault(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    shares: Uint128,
+) -> Result<Response, ContractError> {
+    let mut vault = VAULTS.load(deps.storage, vault_id)?;
+    if !vault.locked.is_zero() {
+        return Err(ContractError::VaultRoundOpen {});
+    }
+    let key = (vault_id, &info.sender);
+    let mut position = VAULT_POSITIONS
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    if shares.is_zero() || shares > position.shares {
+        return Err(ContractError::InsufficientShares {});
+    }
+
+    let mut paid = claim_earnings(&vault, &mut position);
+    track(deps.storage, Ledger::Vaults, vault_id, &paid, &[])?;
+    let amount = vault.idle.multiply_ratio(shares, vault.shares);
+    if !amount.is_zero() {
+        paid.push(Coin::new(amount.u128(), &vault.denom));
+    }
+    position.shares -= shares;
+    vault.shares -= shares;
+    vault.idle -= amount;
+    if position.shares.is_zero() {
+        VAULT_POSITIONS.remove(deps.storage, key);
+    } else {
+        VAULT_POSITIONS.save(deps.storage, key, &position)?;
+    }
+    save_held(deps.storage, &VAULTS, vault_id, &vault)?;
+
+    let res = Response::new()
+        .add_attribute("action", "withdraw_vault")
+        .add_attribute("depositor", &info.sender)
+        .add_attribute("burned", shares);
+    let res = pay(res, &info.sender, paid);
+    Ok(vault_attributes(res, vault_id, &vault))
+}
+
+/// Pays the sender what their shares earned since they last claimed
+pub fn handle_claim_vault_earnings(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    let vault = VAULTS.load(deps.storage, vault_id)?;
+    let key = (vault_id, &info.sender);
+    let mut position = VAULT_POSITIONS.load(deps.storage, key)?;
+    let earnings = claim_earnings(&vault, &mut position);
+    track(deps.storage, Ledger::Vaults, vault_id, &earnings, &[])?;
+    VAULT_POSITIONS.save(deps.storage, key, &position)?;
+
+    let res = Response::new()
+        .add_attribute("action", "claim_vault_earnings")
+        .add_attribute("depositor", &info.sender);
+    let res = pay(res, &info.sender, earnings);
+    Ok(vault_attributes(res, vault_id, &vault))
+}
+
+/// Writer writes a covered call on `amount` of the idle asset, created and listed by the
+/// contract so the vault is paid what the option pays its creator and seller
+pub fn handle_write_vault_option(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    vault_id: u64,
+    amount: Uint128,
+    expires: Expiration,
+    premium: Vec<Coin>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let mut vault = VAULTS.load(deps.storage, vault_id)?;
+    if info.sender != vault.writer {
+        return Err(ContractError::Unauthorized {});
+    }
+    let counter_offer = amount * vault.strike;
+    let no_premium = premium.is_empty() || premium.iter().any(|c| c.amount.is_zero());
+    if amount.is_zero() || amount > vault.idle || counter_offer.is_zero() || no_premium {
+        return Err(ContractError::InvalidVaultWrite {});
+    }
+
+    let writer = MessageInfo {
+        sender: env.contract.address.clone(),
+        funds: coins(amount.u128(), &vault.denom),
+    };
+    let terms = CreateMsg {
+        counter_offer: Asset::Native(coins(counter_offer.u128(), &vault.strike_denom)),
+        expires,
+        premium,
+        settlement: Settlement::Physical,
+        style: OptionStyle::American,
+        royalty_bps: 0,
+        roll_on_expiry: false,
+        barrier: None,
+        settlement_window: None,
+        exercisable_from: None,
+        alt_counter_offers: vec![],
+        strike: None,
+        allowed_taker: None,
+        owner: None,
+    };
+    let (option_id, state) = create_option(deps.branch(), &env, writer, terms)?;
+    vault.idle -= amount;
+    vault.locked += amount;
+    save_held(deps.storage, &VAULTS, vault_id, &vault)?;
+    VAULT_OPTIONS.save(deps.storage, option_id, &(vault_id, amount))?;
+
+    let res = Response::new().add_attribute("action", "write_vault_option");
+    Ok(vault_attributes(res, vault_id, &vault)
+        .add_event(OptionCreated::new(option_id, &state).into())
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Books what `res` pays the contract for a vault option into its vault, releasing the
+/// locked asset once the option is closed. Responses of other options are left as they are.
+pub(crate) fn keep_vault_proceeds(
+    storage: &mut dyn Storage,
+    env: &Env,
+    option_id: u64,
+    res: Response,
+) -> StdResult<Response> {
+    let (vault_id, written) = match VAULT_OPTIONS.may_load(storage, option_id)? {
+        Some(written) => written,
+        None => return Ok(res),
+    };
+    let mut vault = VAULTS.load(storage, vault_id)?;
+    // vault options ask native coins, nothing comes back in cw20
+    let (mut proceeds, mut cw20_proceeds) = (vec![], vec![]);
+    let res = take_proceeds(env, res, &mut proceeds, &mut cw20_proceeds)?;
+    for coin in proceeds {
+        if coin.denom == vault.denom {
+            vault.idle += coin.amount;
+            continue;
+        }
+        track(
+            storage,
+            Ledger::Vaults,
+            vault_id,
+            &[],
+            slice::from_ref(&coin),
+        )?;
+        // writing takes idle asset, so there are shares while vault options are open
+        let per_share = Decimal::from_ratio(coin.amount, vault.shares);
+        match vault.earned.iter_mut().find(|e| e.denom == coin.denom) {
+            Some(earning) => earning.per_share += per_share,
+            None => vault.earned.push(Earning {
+                denom: coin.denom,
+                per_share,
+            }),
+        }
+    }
+    if !options().has(storage, option_id) {
+        vault.locked -= written;
+        VAULT_OPTIONS.remove(storage, option_id);
+    }
+    save_held(storage, &VAULTS, vault_id, &vault)?;
+    Ok(res)
+}
+
+pub fn query_vault(deps: Deps, vault_id: u64) -> StdResult<VaultResponse> {
+    VAULTS.may_load(deps.storage, vault_id)
+}
+
+pub fn query_vault_position(
+    deps: Deps,
+    vault_id: u64,
+    depositor: String,
+) -> StdResult<VaultPositionResponse> {
+    let depositor = deps.api.addr_validate(&depositor)?;
+    let vault = VAULTS.load(deps.storage, vault_id)?;
+    let mut position = VAULT_POSITIONS
+        .may_load(deps.storage, (vault_id, &depositor))?
+        .unwrap_or_default();
+    let earnings = claim_earnings(&vault, &mut position);
+    Ok(VaultPositionResponse {
+        shares: position.shares,
+        earnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::execute;
+    use crate::msg::ExecuteMsg;
+    use crate::testing;
+    use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coin, CosmosMsg, OwnedDeps};
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = testing::setup();
+
+        let msg = ExecuteMsg::CreateVault {
+            writer: "writer".to_string(),
+            denom: "BTC".to_string(),
+            strike_denom: "USDC".to_string(),
+            strike: Decimal::percent(2000),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        );
+        assert!(matches!(err.unwrap_err(), ContractError::Unauthorized {}));
+        let _ = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        deps
+    }
+
+    fn deposit(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, who: &str, amount: u128) {
+        let msg = ExecuteMsg::DepositVault { vault_id: 1 };
+        let info = mock_info(who, &coins(amount, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    fn write(deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, amount: u128) {
+        let msg = ExecuteMsg::WriteVaultOption {
+            vault_id: 1,
+            amount: Uint128::new(amount),
+            expires: Expiration::AtHeight(mock_env().block.height + 100),
+            premium: coins(10, "USDC"),
+        };
+        let info = mock_info("writer", &[]);
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    fn sent(res: Response) -> Vec<CosmosMsg> {
+        res.messages.into_iter().map(|m| m.msg).collect()
+    }
+
+    #[test]
+    fn covered_call_rounds() {
+        let mut deps = setup();
+        deposit(&mut deps, "alice", 30);
+        deposit(&mut deps, "bob", 10);
+
+        // the writer writes on the pool, the premium and counter_offer go to the shares
+        write(&mut deps, 20);
+        write(&mut deps, 10);
+        let state = options().load(&deps.storage, 2).unwrap();
+        assert_eq!(Asset::Native(coins(400, "USDC")), state.counter_offer);
+        assert_eq!(mock_env().contract.address, state.owner);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("carol", &coins(10, "BTC")),
+            ExecuteMsg::DepositVault { vault_id: 1 },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::VaultRoundOpen {}));
+
+        let buy = |option_id| ExecuteMsg::Buy {
+            option_id,
+            referrer: None,
+        };
+        let info = mock_info("buyer", &coins(10, "USDC"));
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), buy(2)).unwrap();
+        assert!(res.messages.is_empty());
+        let _ = execute(deps.as_mut(), mock_env(), info, buy(3)).unwrap();
+        let msg = ExecuteMsg::Execute {
+            option_id: 2,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("buyer", &coins(400, "USDC"));
+        let _ = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // the unexercised one comes back to the pool once burned
+        let mut env = mock_env();
+        env.block.height += 100;
+        let msg = ExecuteMsg::Burn { option_id: 3 };
+        let _ = execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        let vault = query_vault(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Uint128::new(20), vault.idle);
+        assert!(vault.locked.is_zero());
+
+        let position = query_vault_position(deps.as_ref(), 1, "bob".to_string()).unwrap();
+        assert_eq!(coins(105, "USDC"), position.earnings);
+        let msg = ExecuteMsg::WithdrawVault {
+            vault_id: 1,
+            shares: Uint128::new(30),
+        };
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".into(),
+                amount: vec![coin(315, "USDC"), coin(15, "BTC")],
+            })],
+            sent(res)
+        );
+
+        // new deposits are priced on the idle asset, earnings already booked stay with bob
+        deposit(&mut deps, "carol", 10);
+        let position = query_vault_position(deps.as_ref(), 1, "carol".to_string()).unwrap();
+        assert_eq!(Uint128::new(20), position.shares);
+        assert!(position.earnings.is_empty());
+        let msg = ExecuteMsg::ClaimVaultEarnings { vault_id: 1 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".into(),
+                amount: coins(105, "USDC"),
+            })],
+            sent(res)
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Collateral put to work in an external yield strategy while the option is open.
+//!
+//! The creator can deposit the collateral of an option into the configured strategy contract,
+//! such as a lending market, which issues the contract shares as a receipt. The collateral
+//! has to be withdrawn again before the option settles: the creator or owner can withdraw it
+//! at any time, anyone else once the option expired. Whatever the shares are worth above the
+//! collateral goes to the creator, so the option pays out as if never deposited. A loss has to
+//! be made up by whoever withdraws, which leaves the option unable to settle until the creator
+//! covers it.
+
+use cosmwasm_std::{
+    from_json, to_json_binary, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Reply, Response,
+    StdError, StdResult, Storage, SubMsg, Uint128, WasmMsg,
+};
+use cw_utils::parse_execute_response_data;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::contract::{assert_not_paused, option_attributes};
+use crate::error::ContractError;
+use crate::msg::YieldDepositResponse;
+use crate::state::{
+    options, PendingWithdrawal, State, YieldDeposit, CONFIG, PENDING_YIELD_DEPOSIT,
+    PENDING_YIELD_WITHDRAWAL, STAKES, YIELD_DEPOSITS,
+};
+
+pub const YIELD_DEPOSIT_REPLY_ID: u64 = 3;
+pub const YIELD_WITHDRAWAL_REPLY_ID: u64 = 4;
+
+/// Interface the configured yield strategy contract has to implement
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum YieldExecuteMsg {
+    /// Takes the funds sent, issuing the sender shares of the strategy
+    Deposit {},
+    /// Burns `shares` of the sender, sending them what they are worth
+    Redeem { shares: Uint128 },
+}
+
+/// Data `Deposit` has to respond with
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DepositResponse {
+    pub shares: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum YieldQueryMsg {
+    /// Returns what redeeming `shares` sends right now, telling whoever withdraws what loss
+    /// to make up
+    PreviewRedeem { shares: Uint128 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PreviewRedeemResponse {
+    pub amount: Coin,
+}
+
+/// Fails while the collateral of the option is deposited in a yield strategy.
+pub(crate) fn assert_withdrawn(storage: &dyn Storage, option_id: u64) -> Result<(), ContractError> {
+    if YIELD_DEPOSITS.has(storage, option_id) {
+        return Err(ContractError::CollateralDeposited {});
+    }
+    Ok(())
+}
+
+/// Creator deposits the collateral of an unexpired option into the configured yield strategy.
+/// The shares are recorded once the strategy reports how many it issued.
+pub fn handle_deposit_yield(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage)?;
+    let state: State = options().load(deps.storage, option_id)?;
+    if info.sender != state.creator {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.expires.is_expired(&env.block) {
+        return Err(ContractError::OptionExpired {
+            expired: state.expires,
+        });
+    }
+    let strategy = CONFIG
+        .load(deps.storage)?
+        .yield_strategy
+        .ok_or(ContractError::InvalidYieldDeposit {})?;
+    // staked collateral is with the validator
+    let collateral = match state.collateral.as_slice() {
+        [coin] if !STAKES.has(deps.storage, option_id) => coin.clone(),
+        _ => return Err(ContractError::InvalidYieldDeposit {}),
+    };
+    assert_withdrawn(deps.storage, option_id)?;
+
+    // the option counts as deposited from here on, the reply fills in the shares
+    let deposit = YieldDeposit {
+        strategy: strategy.clone(),
+        shares: Uint128::zero(),
+    };
+    YIELD_DEPOSITS.save(deps.storage, option_id, &deposit)?;
+    PENDING_YIELD_DEPOSIT.save(deps.storage, &option_id)?;
+    let msg = WasmMsg::Execute {
+        contract_addr: strategy.to_string(),
+        msg: to_json_binary(&YieldExecuteMsg::Deposit {})?,
+        funds: vec![collateral],
+    };
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(msg, YIELD_DEPOSIT_REPLY_ID))
+        .add_attribute("action", "deposit_yield")
+        .add_attribute("strategy", strategy)
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Records the shares the strategy issued for the deposit made by `handle_deposit_yield`.
+pub fn handle_deposit_reply(deps: DepsMut, msg: Reply) -> Result<Response, ContractError> {
+    let option_id = PENDING_YIELD_DEPOSIT.load(deps.storage)?;
+    PENDING_YIELD_DEPOSIT.remove(deps.storage);
+
+    let res = msg.result.into_result().map_err(StdError::generic_err)?;
+    let data = res.data.ok_or(ContractError::InvalidYieldDeposit {})?;
+    let data = parse_execute_response_data(&data)
+        .map_err(|e| StdError::generic_err(e.to_string()))?
+        .data
+        .ok_or(ContractError::InvalidYieldDeposit {})?;
+    let issued: DepositResponse = from_json(data)?;
+    if issued.shares.is_zero() {
+        return Err(ContractError::InvalidYieldDeposit {});
+    }
+    let mut deposit = YIELD_DEPOSITS.load(deps.storage, option_id)?;
+    deposit.shares = issued.shares;
+    YIELD_DEPOSITS.save(deps.storage, option_id, &deposit)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_yield_deposit")
+        .add_attribute("option_id", option_id.to_string())
+        .add_attribute("shares", deposit.shares))
+}
+
+/// Redeems the shares of the option, paying the creator the yield on top of the collateral.
+/// Should the shares redeem for less than the collateral, the difference has to be sent along,
+/// as the option can't settle short of its collateral. Whatever is sent beyond that is refunded.
+pub fn handle_withdraw_yield(
+    deps: DepsMut,
+    info: MessageInfo,
+    env: Env,
+    option_id: u64,
+) -> Result<Response, ContractError> {
+    let state: State = options().load(deps.storage, option_id)?;
+    let deposit = YIELD_DEPOSITS.load(deps.storage, option_id)?;
+    // anyone can get an expired option ready to settle
+    let parties = [&state.creator, &state.owner];
+    if !parties.contains(&&info.sender) && !state.expires.is_expired(&env.block) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let denom = &state.collateral[0].denom;
+    let top_up = match info.funds.as_slice() {
+        [] => Uint128::zero(),
+        [coin] if &coin.denom == denom => coin.amount,
+        _ => return Err(ContractError::InvalidYieldDeposit {}),
+    };
+
+    // what the shares redeem for is what the balance grows by
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom)?
+        .amount;
+    let pending = PendingWithdrawal {
+        option_id,
+        sender: info.sender,
+        balance,
+        top_up,
+    };
+    PENDING_YIELD_WITHDRAWAL.save(deps.storage, &pending)?;
+    YIELD_DEPOSITS.remove(deps.storage, option_id);
+    let msg = WasmMsg::Execute {
+        contract_addr: deposit.strategy.to_string(),
+        msg: to_json_binary(&YieldExecuteMsg::Redeem {
+            shares: deposit.shares,
+        })?,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(msg, YIELD_WITHDRAWAL_REPLY_ID))
+        .add_attribute("action", "withdraw_yield")
+        .add_attributes(option_attributes(option_id, &state)))
+}
+
+/// Settles the redemption made by `handle_withdraw_yield` against the collateral of the option.
+pub fn handle_withdrawal_reply(
+    deps: DepsMut,
+    env: Env,
+    msg: Reply,
+) -> Result<Response, ContractError> {
+    let pending = PENDING_YIELD_WITHDRAWAL.load(deps.storage)?;
+    PENDING_YIELD_WITHDRAWAL.remove(deps.storage);
+    msg.result.into_result().map_err(StdError::generic_err)?;
+
+    let state: State = options().load(deps.storage, pending.option_id)?;
+    let collateral = &state.collateral[0];
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &collateral.denom)?
+        .amount;
+    let redeemed = balance
+        .checked_sub(pending.balance)
+        .map_err(StdError::from)?;
+    let missing = collateral.amount.saturating_sub(redeemed);
+    if pending.top_up < missing {
+        return Err(ContractError::YieldShortfall {
+            missing: Coin::new(missing.u128(), &collateral.denom),
+        });
+    }
+    let earned = redeemed.saturating_sub(collateral.amount);
+    let refund = pending.top_up - missing;
+
+    let mut res = Response::new()
+        .add_attribute("action", "settle_yield_withdrawal")
+        .add_attribute("option_id", pending.option_id.to_string())
+        .add_attribute("earned", earned)
+        .add_attribute("covered", missing);
+    if !earned.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: state.creator.to_string(),
+            amount: vec![Coin::new(earned.u128(), &collateral.denom)],
+        });
+    }
+    if !refund.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: pending.sender.to_string(),
+            amount: vec![Coin::new(refund.u128(), &collateral.denom)],
+        });
+    }
+    Ok(res)
+}
+
+pub fn query_yield_deposit(deps: Deps, option_id: u64) -> StdResult<YieldDepositResponse> {
+    YIELD_DEPOSITS.may_load(deps.storage, option_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::{execute, instantiate, reply};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, UpdateConfigMsg};
+    use crate::testing::instantiate_msg;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MOCK_CONTRACT_ADDR};
+    use cosmwasm_std::{coin, coins, Binary, CosmosMsg, SubMsgResponse, SubMsgResult};
+
+    /// Reply to a deposit the strategy issued `shares` for
+    fn issued(shares: u128) -> Reply {
+        // the data of a MsgExecuteContractResponse, its only field
+        let data = to_json_binary(&DepositResponse {
+            shares: Uint128::new(shares),
+        })
+        .unwrap();
+        let mut response = vec![0x0a, data.len() as u8];
+        response.extend_from_slice(data.as_slice());
+        Reply {
+            id: YIELD_DEPOSIT_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(Binary::from(response)),
+            }),
+        }
+    }
+
+    fn redeemed() -> Reply {
+        Reply {
+            id: YIELD_WITHDRAWAL_REPLY_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            owner: Some("owner".to_string()),
+            ..instantiate_msg()
+        };
+        let info = mock_info("creator", &coins(100, "BTC"));
+        let _ = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // nowhere to deposit until a strategy is configured, and only for the creator
+        let deposit = ExecuteMsg::DepositYield { option_id: 1 };
+        let creator = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), mock_env(), creator.clone(), deposit.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidYieldDeposit {}));
+        let config = UpdateConfigMsg {
+            yield_strategy: Some("market".to_string()),
+            ..UpdateConfigMsg::default()
+        };
+        let msg = ExecuteMsg::UpdateConfig(config);
+        let _ = execute(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap();
+        let owner = mock_info("owner", &[]);
+        let err = execute(deps.as_mut(), mock_env(), owner, deposit.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let res = execute(deps.as_mut(), mock_env(), creator.clone(), deposit.clone()).unwrap();
+        assert_eq!(YIELD_DEPOSIT_REPLY_ID, res.messages[0].id);
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                msg: to_json_binary(&YieldExecuteMsg::Deposit {}).unwrap(),
+                funds: coins(100, "BTC"),
+            }),
+            res.messages[0].msg
+        );
+        // the shares are whatever the strategy says it issued
+        let _ = reply(deps.as_mut(), mock_env(), issued(95)).unwrap();
+        let deposit_info = query_yield_deposit(deps.as_ref(), 1).unwrap().unwrap();
+        assert_eq!(Uint128::new(95), deposit_info.shares);
+
+        // the owner has to withdraw before executing, the creator keeps the yield
+        let execute_msg = ExecuteMsg::Execute {
+            option_id: 1,
+            recipient: None,
+            referrer: None,
+            deadline: None,
+            min_payout: None,
+        };
+        let info = mock_info("owner", &coins(40, "ETH"));
+        let err =
+            execute(deps.as_mut(), mock_env(), info.clone(), execute_msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::CollateralDeposited {}));
+        let withdraw = ExecuteMsg::WithdrawYield { option_id: 1 };
+        let anyone = mock_info("anyone", &[]);
+        let err = execute(deps.as_mut(), mock_env(), anyone, withdraw.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let owner = mock_info("owner", &[]);
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), withdraw.clone()).unwrap();
+        assert_eq!(
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "market".to_string(),
+                msg: to_json_binary(&YieldExecuteMsg::Redeem {
+                    shares: Uint128::new(95),
+                })
+                .unwrap(),
+                funds: vec![],
+            }),
+            res.messages[0].msg
+        );
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(110, "BTC"));
+        let res = reply(deps.as_mut(), mock_env(), redeemed()).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(10, "BTC"),
+            })],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+
+        // a loss has to be made up before the collateral counts as withdrawn
+        let _ = execute(deps.as_mut(), mock_env(), creator, deposit).unwrap();
+        let _ = reply(deps.as_mut(), mock_env(), issued(100)).unwrap();
+        let deposited = YIELD_DEPOSITS.load(&deps.storage, 1).unwrap();
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![]);
+        let _ = execute(deps.as_mut(), mock_env(), owner, withdraw.clone()).unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(90, "BTC"));
+        let err = reply(deps.as_mut(), mock_env(), redeemed()).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::YieldShortfall { missing } if missing == coin(10, "BTC")
+        ));
+
+        // which reverts the withdrawal, anything sent beyond the loss comes back
+        YIELD_DEPOSITS
+            .save(&mut deps.storage, 1, &deposited)
+            .unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(15, "BTC"));
+        let topping_up = mock_info("creator", &coins(15, "BTC"));
+        let _ = execute(deps.as_mut(), mock_env(), topping_up, withdraw).unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(105, "BTC"));
+        let res = reply(deps.as_mut(), mock_env(), redeemed()).unwrap();
+        assert_eq!(
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".into(),
+                amount: coins(5, "BTC"),
+            })],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        assert!(!YIELD_DEPOSITS.has(&deps.storage, 1));
+
+        let res = execute(deps.as_mut(), mock_env(), info, execute_msg).unwrap();
+        assert_eq!(
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "owner".into(),
+                amount: coins(100, "BTC"),
+            }),
+            res.messages[1].msg
+        );
+    }
+}